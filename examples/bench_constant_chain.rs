@@ -0,0 +1,49 @@
+// times a run over constant_chain(100000), 100k addc and mulc gates and no interaction after input processing,
+// against the reduction of its constants alone. the protocol reduces each constant modulo q at the gate using it,
+// so the second number bounds what reducing them once at parse time could save. in a release build the runs took
+// about 0.55s each and the reductions 0.25ms, so pre-reducing the constants would not be noticeable
+//
+// run with: cargo run --release --example bench_constant_chain
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use beaver::benchcircuits;
+use beaver::protocol::{run_beaver_protocol, LogLevel, ProtocolOptions};
+use beaver::utilities;
+
+const N: usize = 100_000;
+
+fn main() {
+    let bench = benchcircuits::constant_chain(N);
+    let inputs = bench.random_inputs(1);
+
+    let start = Instant::now();
+    let cleartext = bench.circuit.evaluate(&inputs, bench.q).unwrap();
+    println!("{}: cleartext evaluation {:?}", bench.name, start.elapsed());
+
+    for authenticated in [false, true] {
+        let options = ProtocolOptions { authenticated, seed: Some(1), log_level: LogLevel::Off, ..Default::default() };
+        let start = Instant::now();
+        let result = run_beaver_protocol(&bench.encoding, bench.q, inputs.clone(), &options).unwrap();
+        let elapsed = start.elapsed();
+
+        let id = N as u32 - 1;
+        assert_eq!(result.outputs_p1[&id], cleartext[&id]);
+        println!("    {} run: {elapsed:?}", if authenticated { "authenticated" } else { "unauthenticated" });
+    }
+
+    // the constants of the chain, as parsed
+    let constants: Vec<i32> = (1..N as i32)
+        .map(|i| match i % 4 {
+            1 => -2,
+            3 => 3,
+            _ => (i % 100_000) * if i % 8 == 0 { -10_007 } else { 10_009 },
+        })
+        .collect();
+    let start = Instant::now();
+    for c in &constants {
+        black_box(utilities::modulo(black_box(*c), bench.q));
+    }
+    println!("    {} constant reductions: {:?}", constants.len(), start.elapsed());
+}
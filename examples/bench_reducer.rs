@@ -0,0 +1,42 @@
+// compares the Barrett Reducer with F32, which divides, on the operations the dealer runs when generating
+// triples: one multiplication and a subtraction per share, over a single modulus. on an x86-64 laptop the
+// Reducer took 1.3x less time than F32 for q = 101 and 2^31 - 1, and 1.75x less for q = 2^32 - 5
+//
+// run with: cargo run --release --example bench_reducer
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use beaver::field::{FieldOps, Reducer, F32};
+use rand::Rng;
+use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+
+const N: usize = 10_000_000;
+
+// a chain of dependent operations over the operands, so that neither context can skip or reorder any of them
+fn measure<F: FieldOps<Elem = u32>>(f: &F, operands: &[(u32, u32)]) -> (Duration, u32) {
+    let start = Instant::now();
+    let mut acc = 0;
+
+    for (a, b) in operands {
+        acc = f.sub(f.mul(black_box(*a), acc ^ *b), *b);
+    }
+
+    (start.elapsed(), black_box(acc))
+}
+
+fn main() {
+    let rng = &mut ChaCha20Rng::seed_from_u64(1);
+
+    for q in [101, 2147483647, u32::MAX - 4] {
+        let operands = (0..N).map(|_| (rng.gen(), rng.gen())).collect::<Vec<(u32, u32)>>();
+
+        let (f32_time, f32_result) = measure(&F32::new(q), &operands);
+        let (reducer_time, reducer_result) = measure(&Reducer::new(q), &operands);
+        assert_eq!(f32_result, reducer_result);
+
+        println!("q = {q}: {N} multiplications and subtractions");
+        println!("    F32:     {f32_time:>12?}");
+        println!("    Reducer: {reducer_time:>12?}, {:.2}x", f32_time.as_secs_f64() / reducer_time.as_secs_f64());
+    }
+}
@@ -0,0 +1,65 @@
+// compares the direct Sharing::subtract of UnauthSharing and AuthSharing with the
+// default add(s1, &s2.complement(f)) in time and heap allocations
+//
+// run with: cargo run --release --example bench_subtract
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use beaver::field::ModField;
+use beaver::sharing::*;
+use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const N: usize = 1_000_000;
+const F: ModField = ModField { q: 2147483647 };
+
+// runs f over every pair and returns the elapsed time and number of allocations
+fn measure<T: Sharing>(pairs: &[(T, T)], f: impl Fn(&T, &T) -> T) -> (Duration, usize) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+
+    for (s1, s2) in pairs {
+        black_box(f(black_box(s1), black_box(s2)));
+    }
+
+    (start.elapsed(), ALLOCATIONS.load(Ordering::Relaxed) - allocations)
+}
+
+fn bench<T: Sharing>(name: &str) {
+    let rng = &mut ChaCha20Rng::seed_from_u64(1);
+    let pairs = (0..N)
+        .map(|i| (T::share(i as u32 % F.q, 5, 7, rng, &F).0, T::share(3 * i as u32 % F.q, 5, 7, rng, &F).0))
+        .collect::<Vec<_>>();
+
+    let (direct_time, direct_allocs) = measure(&pairs, |s1, s2| T::subtract(s1, s2, &F));
+    let (compl_time, compl_allocs) = measure(&pairs, |s1, s2| T::add(s1, &s2.complement(&F), &F));
+
+    println!("{name}: {N} subtractions");
+    println!("    direct:     {direct_time:>12?}, {direct_allocs} allocations");
+    println!("    complement: {compl_time:>12?}, {compl_allocs} allocations");
+}
+
+fn main() {
+    bench::<UnauthSharing>("UnauthSharing");
+    bench::<AuthSharing>("AuthSharing");
+}
@@ -0,0 +1,282 @@
+// Standard parameterised workloads, so that benchmarks and simulations on different machines produce comparable
+// numbers: multiplication chains, wide sums, polynomial evaluation, inner products, a statistics circuit and a
+// chain of constant gates.
+// Each generator returns the circuit together with its encoding (as taken by run_beaver_protocol), a
+// recommended modulus and a way to draw inputs for it. All outputs are revealed to both parties. Random circuits
+// of a given shape can be generated too, for checks beyond these workloads.
+
+use rand::Rng;
+use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+
+use crate::circuit::{Circuit, Inputs};
+use crate::field::{FieldOps, F32};
+use crate::utilities;
+
+// the workloads only add and multiply, so any prime works. a large one makes every value look random
+const RECOMMENDED_Q: u32 = 2147483647;
+
+pub struct BenchCircuit {
+    // e.g. "mul_chain(100)"
+    pub name: String,
+    pub encoding: String,
+    pub circuit: Circuit,
+    pub q: u32,
+}
+
+impl BenchCircuit {
+    fn new(name: String, gates: Vec<String>, outputs: Vec<usize>) -> Self {
+        let outputs = outputs.iter().map(|id| id.to_string()).collect::<Vec<String>>().join(", ");
+        let encoding = format!("{} & {outputs} & {outputs}", gates.join(" | "));
+
+        BenchCircuit {
+            name,
+            // the generators only produce valid encodings
+            circuit: encoding.parse().unwrap(),
+            encoding,
+            q: RECOMMENDED_Q,
+        }
+    }
+
+    // uniformly random inputs modulo q, reproducible from the seed
+    pub fn random_inputs(&self, seed: u64) -> Inputs {
+        let rng = &mut ChaCha20Rng::seed_from_u64(seed);
+        let arity = self.circuit.input_arity();
+
+        let mut random = |n: usize| -> Vec<u32> { (0..n).map(|_| F32::new(self.q).random(rng)).collect() };
+
+        Inputs {
+            p1: (random(arity.p1_first), random(arity.p1_second)),
+            p2: (random(arity.p2_first), random(arity.p2_second)),
+            public: random(self.circuit.num_public_inputs()),
+        }
+    }
+}
+
+// the product of depth + 1 values, multiplied in sequence: depth rounds of multiplications
+pub fn mul_chain(depth: usize) -> BenchCircuit {
+    let depth = depth.max(1);
+
+    let mut gates = vec![String::from("0, P1, mul, P2")];
+    gates.extend((1..depth).map(|i| format!("{i}, {}, mul, P{}", i - 1, i % 2 + 1)));
+
+    BenchCircuit::new(format!("mul_chain({depth})"), gates, vec![depth - 1])
+}
+
+// the sum of n values provided alternately by each party, computed by a single linear gate
+pub fn wide_sum(n: usize) -> BenchCircuit {
+    let n = n.max(1);
+
+    let mut gates = input_gates(n);
+    gates.push(linmap(&[n], &(0..n).collect::<Vec<usize>>(), &[vec![1; n]]));
+
+    BenchCircuit::new(format!("wide_sum({n})"), gates, vec![n])
+}
+
+// the evaluation by Horner's rule of a polynomial of the given degree, whose coefficients P2 provides (from the
+// leading one down), at a point P1 provides
+pub fn polynomial(degree: usize) -> BenchCircuit {
+    let degree = degree.max(1);
+
+    let mut gates = vec![String::from("0, P1, addc, 0")];
+    for i in 0..degree {
+        let acc = if i == 0 { String::from("P2") } else { (2 * i).to_string() };
+        gates.push(format!("{}, {acc}, mul, 0", 2 * i + 1));
+        gates.push(format!("{}, {}, add, P2", 2 * i + 2, 2 * i + 1));
+    }
+
+    BenchCircuit::new(format!("polynomial({degree})"), gates, vec![2 * degree])
+}
+
+// the inner product of a vector of P1 and one of P2, each of length n
+pub fn dot_product(n: usize) -> BenchCircuit {
+    let n = n.max(1);
+
+    let mut gates: Vec<String> = (0..2 * n).map(|i| format!("{i}, P{}, addc, 0", i / n + 1)).collect();
+    gates.push(format!("dot, {}, {}, {}", 2 * n, ids(0..n), ids(n..2 * n)));
+
+    BenchCircuit::new(format!("dot_product({n})"), gates, vec![2 * n])
+}
+
+// the numerators of the mean and variance of n values provided alternately by each party: the sum S of the
+// values and n times the sum of their squares minus S^2
+pub fn statistics(n: usize) -> BenchCircuit {
+    let n = n.max(1);
+
+    let mut gates = input_gates(n);
+    gates.extend((0..n).map(|i| format!("{}, {i}, mul, {i}", n + i)));
+
+    let (sum, sum_squares, sum_squared, variance) = (2 * n, 2 * n + 1, 2 * n + 2, 2 * n + 3);
+    let rows = [[vec![1; n], vec![0; n]].concat(), [vec![0; n], vec![1; n]].concat()];
+    gates.push(linmap(&[sum, sum_squares], &(0..2 * n).collect::<Vec<usize>>(), &rows));
+    gates.push(format!("{sum_squared}, {sum}, mul, {sum}"));
+    gates.push(linmap(&[variance], &[sum_squares, sum_squared], &[vec![n as i32, -1]]));
+
+    BenchCircuit::new(format!("statistics({n})"), gates, vec![sum, variance])
+}
+
+// a value of P1 put through n - 1 constant gates, alternately multiplying by -2 or 3 and adding constants of either
+// sign up to about 2^30 in absolute value. no interaction after input processing, so the cost is all local
+pub fn constant_chain(n: usize) -> BenchCircuit {
+    let n = n.max(1);
+
+    let mut gates = vec![String::from("0, P1, addc, 0")];
+    gates.extend((1..n).map(|i| match i % 4 {
+        1 => format!("{i}, {}, mulc, -2", i - 1),
+        3 => format!("{i}, {}, mulc, 3", i - 1),
+        _ => format!("{i}, {}, addc, {}", i - 1, (i as i32 % 100_000) * if i % 8 == 0 { -10_007 } else { 10_009 }),
+    }));
+
+    BenchCircuit::new(format!("constant_chain({n})"), gates, vec![n - 1])
+}
+
+// the shape of the circuits generated by random. each gate adds or multiplies two wires or a wire and a constant,
+// and each of its inputs is either a party input or an earlier gate
+#[derive(Clone, Copy, Debug)]
+pub struct CircuitShape {
+    pub gates: usize,
+    // probability of each gate multiplying rather than adding
+    pub mul_ratio: f64,
+    // probability of each gate taking a constant as second input
+    pub constant_ratio: f64,
+    // probability of each gate input being a party input rather than an earlier gate (always so for gate 0)
+    pub input_ratio: f64,
+    // number of gates revealed to both parties, the last one always among them
+    pub outputs: usize,
+}
+
+impl Default for CircuitShape {
+    fn default() -> Self {
+        CircuitShape { gates: 10, mul_ratio: 0.5, constant_ratio: 0.25, input_ratio: 0.3, outputs: 2 }
+    }
+}
+
+// a random valid circuit of the given shape over Z_q, named after its size. unlike the workloads above, it is
+// meant to exercise the protocol on arbitrary circuits rather than to measure it
+pub fn random(rng: &mut ChaCha20Rng, shape: &CircuitShape, q: u32) -> BenchCircuit {
+    let n = shape.gates.max(1);
+
+    let input = |rng: &mut ChaCha20Rng, id: usize| {
+        if id == 0 || rng.gen_bool(shape.input_ratio) {
+            format!("P{}", rng.gen_range(1..=2))
+        } else {
+            rng.gen_range(0..id).to_string()
+        }
+    };
+
+    let gates = (0..n)
+        .map(|id| {
+            let op = if rng.gen_bool(shape.mul_ratio) { "mul" } else { "add" };
+            let i1 = input(rng, id);
+            if rng.gen_bool(shape.constant_ratio) {
+                // constants are parsed as i32
+                format!("{id}, {i1}, {op}c, {}", F32::new(q.min(i32::MAX as u32)).random(rng))
+            } else {
+                format!("{id}, {i1}, {op}, {}", input(rng, id))
+            }
+        })
+        .collect();
+
+    // cannot fail, as at most n - 1 of the n - 1 other gates are drawn
+    let mut outputs = utilities::sample_subset(shape.outputs.clamp(1, n) - 1, n - 1, rng).unwrap();
+    outputs.push(n - 1);
+
+    BenchCircuit { q, ..BenchCircuit::new(format!("random({n})"), gates, outputs) }
+}
+
+// every workload with the given size parameter
+pub fn suite(n: usize) -> Vec<BenchCircuit> {
+    vec![mul_chain(n), wide_sum(n), polynomial(n), dot_product(n), statistics(n), constant_chain(n)]
+}
+
+// gates 0 to n - 1 passing on a value provided by P1 (even ids) or P2 (odd ids)
+fn input_gates(n: usize) -> Vec<String> {
+    (0..n).map(|i| format!("{i}, P{}, addc, 0", i % 2 + 1)).collect()
+}
+
+fn linmap(outputs: &[usize], inputs: &[usize], rows: &[Vec<i32>]) -> String {
+    let rows: Vec<String> = rows.iter().map(|r| r.iter().map(|c| c.to_string()).collect::<Vec<String>>().join(" ")).collect();
+    format!("linmap, {}, {}, {}", ids(outputs.iter().copied()), ids(inputs.iter().copied()), rows.join("; "))
+}
+
+fn ids(ids: impl Iterator<Item = usize>) -> String {
+    ids.map(|id| id.to_string()).collect::<Vec<String>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::circuit::Party;
+    use crate::protocol::{run_beaver_protocol, LogLevel, ProtocolOptions};
+
+    // the value of constant_chain(n) on input x, computed over the integers and reduced at every step
+    fn constant_chain_reference(n: usize, x: u32, q: u32) -> u32 {
+        let q = q as i128;
+        let mut v = x as i128;
+        for i in 1..n {
+            v = match i % 4 {
+                1 => v * -2,
+                3 => v * 3,
+                _ => v + (i as i128 % 100_000) * if i % 8 == 0 { -10_007 } else { 10_009 },
+            }
+            .rem_euclid(q);
+        }
+        v as u32
+    }
+
+    // 100k constant gates, so that any accumulation error of the repeated constant arithmetic shows in the output.
+    // the traversal of a chain this deep also used to overflow the stack
+    #[test]
+    fn constant_chain_of_100k_gates_matches_the_reference() {
+        let n = 100_000;
+        let bench = constant_chain(n);
+        assert_eq!(bench.circuit.get_topology().len(), n);
+        assert_eq!(bench.circuit.num_beaver_triples(), 0);
+
+        let inputs = bench.random_inputs(1);
+        let expected = constant_chain_reference(n, inputs.p1.0[0], bench.q);
+        assert_eq!(bench.circuit.evaluate(&inputs, bench.q).unwrap()[&(n as u32 - 1)], expected);
+
+        for authenticated in [false, true] {
+            let options =
+                ProtocolOptions { authenticated, seed: Some(1), log_level: LogLevel::Off, ..Default::default() };
+            let result = run_beaver_protocol(&bench.encoding, bench.q, inputs.clone(), &options).unwrap();
+            assert_eq!(result.outputs_p1[&(n as u32 - 1)], expected);
+            assert_eq!(result.outputs_p1, result.outputs_p2);
+        }
+    }
+
+    // the smallest instance of every workload, whose outputs are revealed to both parties
+    #[test]
+    fn smallest_workloads_match_the_cleartext() {
+        let suite = suite(1);
+        let names: Vec<&str> = suite.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["mul_chain(1)", "wide_sum(1)", "polynomial(1)", "dot_product(1)", "statistics(1)", "constant_chain(1)"]
+        );
+
+        let options = |authenticated, seed| ProtocolOptions {
+            authenticated,
+            seed: Some(seed),
+            log_level: LogLevel::Off,
+            ..Default::default()
+        };
+        for bench in suite {
+            for seed in 0..3 {
+                let inputs = bench.random_inputs(seed);
+                let cleartext = bench.circuit.evaluate(&inputs, bench.q).unwrap();
+                let expected: HashMap<u32, u32> =
+                    bench.circuit.get_outputs(Party::P1).iter().map(|id| (*id, cleartext[id])).collect();
+
+                for authenticated in [false, true] {
+                    let options = options(authenticated, seed);
+                    let result = run_beaver_protocol(&bench.encoding, bench.q, inputs.clone(), &options).unwrap();
+                    assert_eq!(result.outputs_p1, expected, "{}", bench.name);
+                    assert_eq!(result.outputs_p2, expected, "{}", bench.name);
+                }
+            }
+        }
+    }
+}
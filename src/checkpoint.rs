@@ -0,0 +1,241 @@
+// Checkpoints of a party's evaluation state, stored as a plain text file with one record per line:
+//
+//     position <gates of the topology already processed>
+//     keys <share of k1> <share of k2> <opened key>
+//     epoch <key epoch>, only in sessions
+//     sent <messages sent to the other party so far>
+//     input <p1_first | p1_second | p2_first | p2_second> <gate id> <sharing components>
+//     public <index> <sharing components>
+//     wire <gate id> <sharing components>
+//     triple <components of the sharings of a, b and c>
+//     dot <length> <components of the sharings of the entries of a, then of b, then of c>
+//     trunc <components of the sharings of r and r'>
+//     ltz <number of bits> <components of the sharings of the bits, then of the high part>
+//     eq <number of bits> <components of the sharings of the bits>
+//     revealed <reveal gate id> <public value>
+//     blind <output gate id> <blind, or - if the output is the other party's> <sharing components>
+//     checksum <FNV-1a hash of all the preceding lines, in hexadecimal>
+//
+// The checksum is always the last line, so that a truncated checkpoint is refused instead of resuming with part of
+// the preprocessing material missing.
+// Triples, inner-product tuples, truncation pairs, comparison and equality sharings are listed in the order in which they are stored, i.e. the last
+// one is consumed first.
+
+use std::{collections::HashMap, fs};
+
+use crate::error::BeaverError;
+use crate::protocol::EvalState;
+use crate::sharing::{BeaverSharing, ComparisonSharing, EqualitySharing, InnerProductSharing, Sharing, TruncationSharing};
+use crate::utilities::Fnv1a;
+
+const INPUT_SLOTS: [&str; 4] = ["p1_first", "p1_second", "p2_first", "p2_second"];
+
+pub(crate) fn save<T: Sharing>(
+    path: &str,
+    state: &EvalState<T>,
+    (s_k1, s_k2, key): (u32, u32, u32),
+    epoch: Option<u32>,
+    sent: usize,
+) -> Result<(), BeaverError> {
+    let mut out = format!("position {}\nkeys {s_k1} {s_k2} {key}\n", state.position);
+    if let Some(epoch) = epoch {
+        out.push_str(&format!("epoch {epoch}\n"));
+    }
+    out.push_str(&format!("sent {sent}\n"));
+
+    let slots = [&state.inputs_p1.0, &state.inputs_p1.1, &state.inputs_p2.0, &state.inputs_p2.1];
+
+    for (slot, sharings) in INPUT_SLOTS.iter().zip(slots) {
+        for (id, s) in sorted(sharings) {
+            out.push_str(&format!("input {slot} {id} {}\n", join(&s.components())));
+        }
+    }
+
+    for (i, s) in sorted(&state.public) {
+        out.push_str(&format!("public {i} {}\n", join(&s.components())));
+    }
+
+    for (id, s) in sorted(&state.inner_wires) {
+        out.push_str(&format!("wire {id} {}\n", join(&s.components())));
+    }
+
+    for t in &state.triples {
+        out.push_str(&format!("triple {}\n", join(&t.components())));
+    }
+
+    for t in &state.inner_products {
+        out.push_str(&format!("dot {} {}\n", t.len(), join(&t.components())));
+    }
+
+    for t in &state.truncations {
+        out.push_str(&format!("trunc {}\n", join(&t.components())));
+    }
+
+    for t in &state.comparisons {
+        out.push_str(&format!("ltz {} {}\n", t.len(), join(&t.components())));
+    }
+
+    for t in &state.equalities {
+        out.push_str(&format!("eq {} {}\n", t.len(), join(&t.components())));
+    }
+
+    for (id, v) in sorted(&state.revealed) {
+        out.push_str(&format!("revealed {id} {v}\n"));
+    }
+
+    for (id, (s, r)) in sorted(&state.output_blinds) {
+        let r = r.map_or(String::from("-"), |r| r.to_string());
+        out.push_str(&format!("blind {id} {r} {}\n", join(&s.components())));
+    }
+
+    out.push_str(&format!("checksum {}\n", checksum(&out)));
+
+    fs::write(path, out).map_err(|e| BeaverError::Io(format!("Failed to write checkpoint {path}: {e}")))
+}
+
+#[allow(clippy::type_complexity)]
+pub(crate) fn load<T: Sharing>(
+    path: &str,
+) -> Result<(EvalState<T>, (u32, u32, u32), usize, Option<u32>), BeaverError> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| BeaverError::Io(format!("Failed to read checkpoint {path}: {e}")))?;
+
+    let invalid = |n: usize, line: &str| BeaverError::Parse(format!("Invalid checkpoint line {}: {line}", n + 1));
+
+    let mut position = None;
+    let mut keys = None;
+    let mut epoch = None;
+    let mut sent = None;
+    let mut inputs: [HashMap<u32, T>; 4] = Default::default();
+    let mut public = HashMap::new();
+    let mut inner_wires = HashMap::new();
+    let mut triples = Vec::new();
+    let mut inner_products = Vec::new();
+    let mut truncations = Vec::new();
+    let mut comparisons = Vec::new();
+    let mut equalities = Vec::new();
+    let mut revealed = HashMap::new();
+    let mut output_blinds = HashMap::new();
+
+    // everything up to the last line, which should be the checksum of the rest
+    let trimmed = contents.strip_suffix('\n').unwrap_or(&contents);
+    let (body, last) = trimmed.rsplit_once('\n').map_or(("", trimmed), |(b, l)| (&contents[..b.len() + 1], l));
+    match last.split_whitespace().collect::<Vec<&str>>()[..] {
+        ["checksum", c] if c == checksum(body) => (),
+        ["checksum", _] => {
+            return Err(BeaverError::Parse(format!("Invalid checkpoint {path}: the contents do not match the checksum")))
+        }
+        _ => return Err(BeaverError::Parse(format!("Invalid checkpoint {path}: truncated file, missing the checksum"))),
+    }
+
+    for (n, line) in body.lines().enumerate() {
+        let terms: Vec<&str> = line.split_whitespace().collect();
+        let numbers = |from: usize| -> Result<Vec<u32>, BeaverError> {
+            terms[from..].iter().map(|t| t.parse().map_err(|_| invalid(n, line))).collect()
+        };
+
+        match terms.first() {
+            Some(&"position") => position = Some(*numbers(1)?.first().ok_or(invalid(n, line))? as usize),
+            Some(&"keys") => match numbers(1)?[..] {
+                [s_k1, s_k2, key] => keys = Some((s_k1, s_k2, key)),
+                _ => return Err(invalid(n, line)),
+            },
+            Some(&"epoch") => epoch = Some(*numbers(1)?.first().ok_or(invalid(n, line))?),
+            Some(&"sent") => sent = Some(*numbers(1)?.first().ok_or(invalid(n, line))? as usize),
+            Some(&"input") if terms.len() >= 3 => {
+                let slot = INPUT_SLOTS.iter().position(|s| *s == terms[1]).ok_or(invalid(n, line))?;
+                let c = numbers(2)?;
+                let s = T::from_components(&c[1..]).ok_or(invalid(n, line))?;
+                inputs[slot].insert(c[0], s);
+            }
+            Some(&"public") if terms.len() >= 2 => {
+                let c = numbers(1)?;
+                let s = T::from_components(&c[1..]).ok_or(invalid(n, line))?;
+                public.insert(c[0], s);
+            }
+            Some(&"wire") if terms.len() >= 2 => {
+                let c = numbers(1)?;
+                let s = T::from_components(&c[1..]).ok_or(invalid(n, line))?;
+                inner_wires.insert(c[0], s);
+            }
+            Some(&"triple") => {
+                triples.push(BeaverSharing::from_components(&numbers(1)?).ok_or(invalid(n, line))?);
+            }
+            Some(&"dot") if terms.len() >= 2 => {
+                let c = numbers(1)?;
+                let t = InnerProductSharing::from_components(c[0] as usize, &c[1..]).ok_or(invalid(n, line))?;
+                inner_products.push(t);
+            }
+            Some(&"trunc") => {
+                truncations.push(TruncationSharing::from_components(&numbers(1)?).ok_or(invalid(n, line))?);
+            }
+            Some(&"ltz") if terms.len() >= 2 => {
+                let c = numbers(1)?;
+                let t = ComparisonSharing::from_components(c[0] as usize, &c[1..]).ok_or(invalid(n, line))?;
+                comparisons.push(t);
+            }
+            Some(&"eq") if terms.len() >= 2 => {
+                let c = numbers(1)?;
+                let t = EqualitySharing::from_components(c[0] as usize, &c[1..]).ok_or(invalid(n, line))?;
+                equalities.push(t);
+            }
+            Some(&"revealed") => match numbers(1)?[..] {
+                [id, v] => {
+                    revealed.insert(id, v);
+                }
+                _ => return Err(invalid(n, line)),
+            },
+            Some(&"blind") if terms.len() >= 3 => {
+                let id = terms[1].parse().map_err(|_| invalid(n, line))?;
+                let r = match terms[2] {
+                    "-" => None,
+                    r => Some(r.parse().map_err(|_| invalid(n, line))?),
+                };
+                let s = T::from_components(&numbers(3)?).ok_or(invalid(n, line))?;
+                output_blinds.insert(id, (s, r));
+            }
+            None => (),
+            _ => return Err(invalid(n, line)),
+        }
+    }
+
+    let missing = |field: &str| BeaverError::Parse(format!("Invalid checkpoint {path}: missing {field}"));
+
+    let [p1_first, p1_second, p2_first, p2_second] = inputs;
+
+    Ok((
+        EvalState {
+            position: position.ok_or(missing("position"))?,
+            inputs_p1: (p1_first, p1_second),
+            inputs_p2: (p2_first, p2_second),
+            public,
+            inner_wires,
+            triples,
+            inner_products,
+            truncations,
+            comparisons,
+            equalities,
+            revealed,
+            output_blinds,
+        },
+        keys.ok_or(missing("keys"))?,
+        sent.ok_or(missing("sent"))?,
+        epoch,
+    ))
+}
+
+fn checksum(contents: &str) -> String {
+    let mut h = Fnv1a::new();
+    h.write(contents.as_bytes());
+    format!("{:016x}", h.finish())
+}
+
+fn sorted<T>(sharings: &HashMap<u32, T>) -> Vec<(&u32, &T)> {
+    let mut v: Vec<(&u32, &T)> = sharings.iter().collect();
+    v.sort_by_key(|(id, _)| **id);
+    v
+}
+
+fn join(c: &[u32]) -> String {
+    c.iter().map(|v| v.to_string()).collect::<Vec<String>>().join(" ")
+}
@@ -1,14 +1,18 @@
 // for the unhandled Result values from write and writeln
 #![allow(unused_must_use)]
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::{fmt, str::FromStr};
 
+use crate::field::{FieldOps, F32};
 use crate::utilities;
 
+#[derive(Clone, Copy)]
 pub enum GateOp {
     Add,
     Mul,
+    // 1 if both inputs are equal, 0 otherwise
+    Eq,
 }
 
 impl fmt::Display for GateOp {
@@ -16,11 +20,12 @@ impl fmt::Display for GateOp {
         match self {
             GateOp::Add => write!(f, "+"),
             GateOp::Mul => write!(f, "×"),
+            GateOp::Eq => write!(f, "="),
         }
     }
 }
 
-#[derive(PartialEq, Clone, Copy)]
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
 pub enum Party {
     P1,
     P2,
@@ -35,10 +40,12 @@ impl fmt::Display for Party {
     }
 }
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
 pub enum GateInput {
     Id(u32),
     InputParty(Party),
+    // the value at the given index of the public inputs, which both parties know
+    Public(u32),
 }
 
 impl fmt::Display for GateInput {
@@ -46,6 +53,7 @@ impl fmt::Display for GateInput {
         match self {
             GateInput::Id(i) => write!(f, "{}", i),
             GateInput::InputParty(p) => write!(f, "{}", p),
+            GateInput::Public(i) => write!(f, "pub{}", i),
         }
     }
 }
@@ -57,14 +65,68 @@ impl FromStr for GateInput {
         match s.to_lowercase().as_str() {
             "p1" => Ok(GateInput::InputParty(Party::P1)),
             "p2" => Ok(GateInput::InputParty(Party::P2)),
-            _ => match s.parse::<u32>() {
-                Ok(n) => Ok(GateInput::Id(n)),
-                Err(_) => Err(format!("Invalid gate input format: {s}")),
+            l => match (l.strip_prefix("pub"), s.parse::<u32>()) {
+                (Some(i), _) => i.parse().map(GateInput::Public).map_err(|_| format!("Invalid public input format: {s}")),
+                (None, Ok(n)) => Ok(GateInput::Id(n)),
+                (None, Err(_)) => Err(format!("Invalid gate input format: {s}")),
             },
         }
     }
 }
 
+// the constant of an addc or mulc gate: either fixed in the circuit, the value of a reveal gate, which becomes
+// public during the run, or a public input, written as in the gate inputs
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum GateConstant {
+    Fixed(i32),
+    Revealed(u32),
+    Public(u32),
+}
+
+impl fmt::Display for GateConstant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GateConstant::Fixed(c) => write!(f, "{}", c),
+            GateConstant::Revealed(id) => write!(f, "R{}", id),
+            GateConstant::Public(i) => write!(f, "pub{}", i),
+        }
+    }
+}
+
+impl FromStr for GateConstant {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(i) = s.to_lowercase().strip_prefix("pub") {
+            return i.parse().map(GateConstant::Public).map_err(|_| format!("Invalid public input format: {s}"));
+        }
+
+        match s.strip_prefix(['r', 'R']) {
+            Some(id) => match id.parse::<u32>() {
+                Ok(id) => Ok(GateConstant::Revealed(id)),
+                Err(_) => Err(format!("Invalid revealed gate format: {s}")),
+            },
+            None => match utilities::parse_integer(s, i32::MIN as i64, i32::MAX as i64) {
+                Ok(c) => Ok(GateConstant::Fixed(c as i32)),
+                Err(e) => Err(format!("Invalid constant format: {e}")),
+            },
+        }
+    }
+}
+
+impl GateConstant {
+    // the value of the constant modulo q, with those of the reveal gates looked up in the given map and those of
+    // the public inputs in the given list
+    pub fn resolve(&self, revealed: &HashMap<u32, u32>, public: &[u32], q: u32) -> u32 {
+        match self {
+            GateConstant::Fixed(c) => utilities::modulo(*c, q),
+            GateConstant::Revealed(id) => revealed[id],
+            GateConstant::Public(i) => public[*i as usize] % q,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub enum Gate {
     GateWithoutC {
         id: u32,
@@ -76,7 +138,41 @@ pub enum Gate {
         id: u32,
         op: GateOp,
         i1: GateInput,
-        c: i32,
+        c: GateConstant,
+    },
+    // aborts the protocol unless its input is zero, which it then outputs
+    AssertZero {
+        id: u32,
+        i1: GateInput,
+    },
+    // opens its input to both parties, outputting it unchanged. its value can then be used as a constant
+    Reveal {
+        id: u32,
+        i1: GateInput,
+    },
+    // division of its input (as a signed integer) by 2^f, rounded down or, with small probability, up
+    Truncate {
+        id: u32,
+        i1: GateInput,
+        f: u32,
+    },
+    // 1 if its input (a signed integer in [-2^(k - 1), 2^(k - 1))) is negative, 0 otherwise
+    Ltz {
+        id: u32,
+        i1: GateInput,
+        k: u32,
+    },
+    // linear combination of the outputs of other gates with constant coefficients, i.e. a row of a linmap
+    Linear {
+        id: u32,
+        inputs: Vec<u32>,
+        coeffs: Vec<i32>,
+    },
+    // inner product of the outputs of two equally long lists of gates, consuming one inner-product tuple
+    Dot {
+        id: u32,
+        left: Vec<u32>,
+        right: Vec<u32>,
     },
 }
 
@@ -89,6 +185,26 @@ impl fmt::Display for Gate {
             Gate::GateWithC { id, op, i1, c } => {
                 write!(f, "[{} | {} {} C({}))", id, i1, op, c)
             }
+            Gate::AssertZero { id, i1 } => {
+                write!(f, "[{} | {} = 0)", id, i1)
+            }
+            Gate::Reveal { id, i1 } => {
+                write!(f, "[{} | reveal {})", id, i1)
+            }
+            Gate::Truncate { id, i1, f: shift } => {
+                write!(f, "[{} | {} / 2^{})", id, i1, shift)
+            }
+            Gate::Ltz { id, i1, k } => {
+                write!(f, "[{} | {} < 0 ({} bits))", id, i1, k)
+            }
+            Gate::Linear { id, inputs, coeffs } => {
+                let terms: Vec<String> = coeffs.iter().zip(inputs).map(|(c, i)| format!("C({}) × {}", c, i)).collect();
+                write!(f, "[{} | {})", id, terms.join(" + "))
+            }
+            Gate::Dot { id, left, right } => {
+                let join = |v: &Vec<u32>| v.iter().map(|i| i.to_string()).collect::<Vec<String>>().join(", ");
+                write!(f, "[{} | <({}), ({})>)", id, join(left), join(right))
+            }
         }
     }
 }
@@ -97,8 +213,38 @@ impl FromStr for Gate {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // dot gates list their inputs separated by whitespace: dot, <id>, <left ids>, <right ids>
+        if let [op, id, left, right] = s.split(",").map(|t| t.trim()).collect::<Vec<&str>>()[..] {
+            if op.to_lowercase() == "dot" {
+                let id: u32 = id.parse().map_err(|_| format!("Invalid id format: {id}"))?;
+                let ids = |t: &str| -> Result<Vec<u32>, String> {
+                    t.split_whitespace()
+                        .map(|i| i.parse().map_err(|_| format!("Invalid id format in dot gate: {i}")))
+                        .collect()
+                };
+                let (left, right) = (ids(left)?, ids(right)?);
+
+                if left.is_empty() || left.len() != right.len() {
+                    return Err(format!("Invalid dot gate: expected two non-empty lists of equal length: {}", s.trim()));
+                }
+
+                return Ok(Gate::Dot { id, left, right });
+            }
+        }
+
         let terms: String = s.chars().filter(|c| !c.is_whitespace()).collect();
 
+        // the only gates with a single parameter after the operation
+        if let [id, i1, op] = terms.split(",").collect::<Vec<&str>>()[..] {
+            let op = op.to_lowercase();
+            if op == "assert_zero" || op == "reveal" {
+                let id: u32 = id.parse().map_err(|_| format!("Invalid id format: {id}"))?;
+                let i1: GateInput = i1.parse()?;
+
+                return Ok(if op == "reveal" { Gate::Reveal { id, i1 } } else { Gate::AssertZero { id, i1 } });
+            }
+        }
+
         if let [id, i1, op, opt] = terms.split(",").collect::<Vec<&str>>()[..] {
             let id: u32 = match id.parse() {
                 Ok(n) => n,
@@ -107,6 +253,20 @@ impl FromStr for Gate {
                 }
             };
 
+            if op.to_lowercase() == "truncf" {
+                let f: u32 = opt.parse().map_err(|_| format!("Invalid truncation shift format: {opt}"))?;
+                let i1: GateInput = i1.parse()?;
+
+                return Ok(Gate::Truncate { id, i1, f });
+            }
+
+            if op.to_lowercase() == "ltz" {
+                let k: u32 = opt.parse().map_err(|_| format!("Invalid bit length format: {opt}"))?;
+                let i1: GateInput = i1.parse()?;
+
+                return Ok(Gate::Ltz { id, i1, k });
+            }
+
             let mut constant = false;
 
             let op: GateOp = match op.to_lowercase().as_str() {
@@ -120,6 +280,11 @@ impl FromStr for Gate {
                     constant = true;
                     GateOp::Mul
                 }
+                "eq" => GateOp::Eq,
+                "eqc" => {
+                    constant = true;
+                    GateOp::Eq
+                }
                 _ => {
                     return Err(format!("Invalid gate operation: {op}"));
                 }
@@ -128,12 +293,7 @@ impl FromStr for Gate {
             let i1: GateInput = i1.parse()?;
 
             if constant {
-                let c: i32 = match opt.parse() {
-                    Ok(n) => n,
-                    Err(_) => {
-                        return Err(format!("Invalid constant format: {opt}"));
-                    }
-                };
+                let c: GateConstant = opt.parse()?;
 
                 return Ok(Gate::GateWithC { id, op, i1, c });
             } else {
@@ -150,14 +310,271 @@ impl FromStr for Gate {
 }
 
 impl Gate {
+    // the name of the operation in the circuit encoding
+    pub fn op_name(&self) -> &'static str {
+        match self {
+            Gate::GateWithoutC { op: GateOp::Add, .. } => "add",
+            Gate::GateWithoutC { op: GateOp::Mul, .. } => "mul",
+            Gate::GateWithoutC { op: GateOp::Eq, .. } => "eq",
+            Gate::GateWithC { op: GateOp::Add, .. } => "addc",
+            Gate::GateWithC { op: GateOp::Mul, .. } => "mulc",
+            Gate::GateWithC { op: GateOp::Eq, .. } => "eqc",
+            Gate::AssertZero { .. } => "assert_zero",
+            Gate::Reveal { .. } => "reveal",
+            Gate::Truncate { .. } => "truncf",
+            Gate::Ltz { .. } => "ltz",
+            Gate::Linear { .. } => "linmap",
+            Gate::Dot { .. } => "dot",
+        }
+    }
     fn get_id(&self) -> u32 {
         match self {
             Gate::GateWithoutC { id, .. } => *id,
             Gate::GateWithC { id, .. } => *id,
+            Gate::AssertZero { id, .. } => *id,
+            Gate::Reveal { id, .. } => *id,
+            Gate::Truncate { id, .. } => *id,
+            Gate::Ltz { id, .. } => *id,
+            Gate::Linear { id, .. } => *id,
+            Gate::Dot { id, .. } => *id,
+        }
+    }
+
+    // number of Beaver triples consumed when processing the gate
+    pub fn num_triples(&self) -> usize {
+        match self {
+            Gate::GateWithoutC { op: GateOp::Mul, .. } | Gate::AssertZero { .. } => 1,
+            // a product of all the bit flags of the equality test
+            Gate::GateWithoutC { op: GateOp::Eq, .. } | Gate::GateWithC { op: GateOp::Eq, .. } => {
+                utilities::EQUALITY_BITS as usize - 1
+            }
+            // two multiplications per combination of adjacent bits in the carry computation
+            Gate::Ltz { k, .. } => 2 * k.saturating_sub(2) as usize,
+            _ => 0,
+        }
+    }
+
+    // sharings each party opens to the other to process the gate: two per multiplication (including those of
+    // comparisons and equality tests) and one per masked value otherwise
+    pub fn num_openings(&self) -> usize {
+        2 * self.num_triples()
+            + match self {
+                Gate::Reveal { .. } | Gate::Truncate { .. } | Gate::Ltz { .. } => 1,
+                Gate::GateWithoutC { op: GateOp::Eq, .. } | Gate::GateWithC { op: GateOp::Eq, .. } => 1,
+                Gate::Dot { left, .. } => 2 * left.len(),
+                _ => 0,
+            }
+    }
+
+    // messages each party sends to the other to process the gate, as openings are batched per round except for
+    // the two of a multiplication gate
+    pub fn num_messages(&self) -> usize {
+        match self {
+            Gate::GateWithoutC { op: GateOp::Mul, .. } | Gate::AssertZero { .. } => 2,
+            Gate::Dot { .. } | Gate::Truncate { .. } | Gate::Reveal { .. } => 1,
+            Gate::Ltz { k, .. } => 1 + comparison_levels(*k),
+            Gate::GateWithoutC { op: GateOp::Eq, .. } | Gate::GateWithC { op: GateOp::Eq, .. } => {
+                1 + tree_levels(utilities::EQUALITY_BITS)
+            }
+            _ => 0,
+        }
+    }
+
+    // ids of the gates whose outputs this gate takes as inputs
+    fn input_gate_ids(&self) -> Vec<u32> {
+        let gate_inputs = match self {
+            Gate::GateWithoutC { i1, i2, .. } => vec![i1, i2],
+            Gate::GateWithC { i1, .. }
+            | Gate::AssertZero { i1, .. }
+            | Gate::Reveal { i1, .. }
+            | Gate::Truncate { i1, .. }
+            | Gate::Ltz { i1, .. } => vec![i1],
+            Gate::Linear { inputs, .. } => return inputs.clone(),
+            Gate::Dot { left, right, .. } => return [left.clone(), right.clone()].concat(),
+        };
+
+        let mut ids: Vec<u32> = gate_inputs
+            .into_iter()
+            .filter_map(|i| match i {
+                GateInput::Id(id) => Some(*id),
+                GateInput::InputParty(_) | GateInput::Public(_) => None,
+            })
+            .collect();
+
+        // the gate whose revealed value is used must be evaluated first
+        if let Gate::GateWithC { c: GateConstant::Revealed(r), .. } = self {
+            ids.push(*r);
         }
+
+        ids
     }
 }
 
+// a linmap applies a constant matrix to the outputs of some gates, each row defining a Linear gate:
+//     linmap, <output gate ids>, <input gate ids>, <rows of the matrix>
+// where ids and matrix entries are separated by whitespace and rows by semicolons, e.g.
+//     linmap, 10 11, 0 1 2, 1 1 1; 1 -1 0
+fn parse_linmap(s: &str) -> Result<Vec<Gate>, String> {
+    let ids = |t: &str| -> Result<Vec<u32>, String> {
+        t.split_whitespace()
+            .map(|id| id.parse().map_err(|_| format!("Invalid id format in linmap: {id}")))
+            .collect()
+    };
+
+    if let [_, outputs, inputs, matrix] = s.split(",").collect::<Vec<&str>>()[..] {
+        let outputs = ids(outputs)?;
+        let inputs = ids(inputs)?;
+
+        let rows: Vec<Vec<i32>> = matrix
+            .split(";")
+            .map(|row| {
+                row.split_whitespace()
+                    .map(|c| {
+                        utilities::parse_integer(c, i32::MIN as i64, i32::MAX as i64)
+                            .map(|c| c as i32)
+                            .map_err(|e| format!("Invalid constant format in linmap: {e}"))
+                    })
+                    .collect()
+            })
+            .collect::<Result<_, String>>()?;
+
+        if inputs.is_empty() {
+            return Err(format!("Invalid linmap without input gates: {s}"));
+        }
+
+        if rows.len() != outputs.len() {
+            return Err(format!(
+                "Invalid linmap: the matrix has {} rows for {} output gates: {s}",
+                rows.len(),
+                outputs.len()
+            ));
+        }
+
+        if let Some(row) = rows.iter().find(|row| row.len() != inputs.len()) {
+            return Err(format!(
+                "Invalid linmap: a row of the matrix has {} entries for {} input gates: {s}",
+                row.len(),
+                inputs.len()
+            ));
+        }
+
+        Ok(outputs
+            .into_iter()
+            .zip(rows)
+            .map(|(id, coeffs)| Gate::Linear { id, inputs: inputs.clone(), coeffs })
+            .collect())
+    } else {
+        Err(format!("Invalid number of linmap parameters (should be 4): {s}"))
+    }
+}
+
+// the values provided by each party, split as the circuit's input wires (first and second gate inputs)
+// and in ascending order with respect to the identifier of the gate they correspond to, along with the public
+// inputs both parties know, indexed as in the pub<i> gate inputs
+#[derive(Clone)]
+pub struct Inputs {
+    pub p1: (Vec<u32>, Vec<u32>),
+    pub p2: (Vec<u32>, Vec<u32>),
+    pub public: Vec<u32>,
+}
+
+impl Inputs {
+    pub fn arity(&self) -> InputArity {
+        InputArity {
+            p1_first: self.p1.0.len(),
+            p1_second: self.p1.1.len(),
+            p2_first: self.p2.0.len(),
+            p2_second: self.p2.1.len(),
+        }
+    }
+}
+
+// number of input wires of each kind, i.e. of values each party has to provide
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct InputArity {
+    pub p1_first: usize,
+    pub p1_second: usize,
+    pub p2_first: usize,
+    pub p2_second: usize,
+}
+
+impl InputArity {
+    pub fn total(&self) -> usize {
+        self.p1_first + self.p1_second + self.p2_first + self.p2_second
+    }
+}
+
+// the first or second input of a gate, provided by the given party
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub struct InputWire {
+    pub party: Party,
+    pub first: bool,
+    pub id: u32,
+}
+
+impl InputWire {
+    // as displayed, but with the party referred to by its display name, given those of P1 and P2
+    pub fn named(&self, (name1, name2): (&str, &str)) -> String {
+        format!(
+            "{} input of gate {} ({})",
+            if self.first { "first" } else { "second" },
+            self.id,
+            if self.party == Party::P1 { name1 } else { name2 }
+        )
+    }
+}
+
+impl fmt::Display for InputWire {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.named(("P1", "P2")))
+    }
+}
+
+// a piece of correlated randomness from the dealer. each kind is numbered separately: singletons as the input
+// wires they mask (see input_wires) and everything else in the order in which the gates consume it
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Randomness {
+    Singleton(usize),
+    Triple(usize),
+    InnerProduct(usize),
+    Truncation(usize),
+    ComparisonBits(usize),
+    EqualityBits(usize),
+}
+
+impl fmt::Display for Randomness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Randomness::Singleton(i) => write!(f, "singleton {i}"),
+            Randomness::Triple(i) => write!(f, "triple {i}"),
+            Randomness::InnerProduct(i) => write!(f, "inner product {i}"),
+            Randomness::Truncation(i) => write!(f, "truncation pair {i}"),
+            Randomness::ComparisonBits(i) => write!(f, "comparison bits {i}"),
+            Randomness::EqualityBits(i) => write!(f, "equality bits {i}"),
+        }
+    }
+}
+
+// the correlated randomness processing a gate uses, empty for gates local to each party
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct RandomnessUse {
+    pub gate: u32,
+    pub uses: Vec<Randomness>,
+}
+
+impl fmt::Display for RandomnessUse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.uses.is_empty() {
+            true => write!(f, "gate {}: none", self.gate),
+            false => {
+                let uses: Vec<String> = self.uses.iter().map(|u| u.to_string()).collect();
+                write!(f, "gate {}: {}", self.gate, uses.join(", "))
+            }
+        }
+    }
+}
+
+#[derive(Default)]
 pub struct Circuit {
     gates: HashMap<u32, Gate>,
     outputs_p1: Vec<u32>,
@@ -165,6 +582,8 @@ pub struct Circuit {
     topology: Vec<u32>,
     inputs_p1: (Vec<u32>, Vec<u32>),
     inputs_p2: (Vec<u32>, Vec<u32>),
+    // outputs displayed as signed values (see format_output), marked with a leading - in either output list
+    signed_outputs: HashSet<u32>,
 }
 
 impl fmt::Display for Circuit {
@@ -179,7 +598,7 @@ impl fmt::Display for Circuit {
             "    Outputs for P1: {}",
             self.outputs_p1
                 .iter()
-                .map(|x| x.to_string())
+                .map(|x| if self.signed_outputs.contains(x) { format!("-{x}") } else { x.to_string() })
                 .collect::<Vec<String>>()
                 .join(", ")
         );
@@ -188,7 +607,7 @@ impl fmt::Display for Circuit {
             "    Outputs for P2: {}",
             self.outputs_p2
                 .iter()
-                .map(|x| x.to_string())
+                .map(|x| if self.signed_outputs.contains(x) { format!("-{x}") } else { x.to_string() })
                 .collect::<Vec<String>>()
                 .join(", ")
         );
@@ -208,16 +627,35 @@ impl FromStr for Circuit {
         let mut inputs_p2_first = Vec::new();
         let mut inputs_p2_second = Vec::new();
 
-        if let [gates_str, output1_str, output2_str] = s.split("&").collect::<Vec<&str>>()[..] {
-            let outputs_p1 = utilities::str_u32_to_vec_u32(output1_str)?;
-            let outputs_p2 = utilities::str_u32_to_vec_u32(output2_str)?;
+        let fields = s.split("&").collect::<Vec<&str>>();
+
+        if let [gates_str, output1_str, output2_str] = fields[..] {
+            // either list may be empty or blank, in which case that party receives no outputs
+            let outputs_p1 = utilities::str_to_output_list(output1_str)
+                .map_err(|e| format!("Invalid list of outputs for P1: {e}"))?;
+            let outputs_p2 = utilities::str_to_output_list(output2_str)
+                .map_err(|e| format!("Invalid list of outputs for P2: {e}"))?;
+
+            // an output marked as signed in either list is displayed as such for both parties
+            let signed_outputs: HashSet<u32> =
+                outputs_p1.iter().chain(&outputs_p2).filter(|(_, signed)| *signed).map(|(id, _)| *id).collect();
+            let outputs_p1: Vec<u32> = outputs_p1.into_iter().map(|(id, _)| id).collect();
+            let outputs_p2: Vec<u32> = outputs_p2.into_iter().map(|(id, _)| id).collect();
 
             // processing gates
+            // blank entries, e.g. from a leading, trailing or doubled |, are skipped
             for g_str in gates_str.trim().split("|") {
-                if g_str.len() == 0 {
+                if g_str.trim().is_empty() {
                     continue;
+                }
+
+                let parsed = if g_str.trim().to_lowercase().starts_with("linmap") {
+                    parse_linmap(g_str.trim())?
                 } else {
-                    let g: Gate = g_str.parse()?;
+                    vec![g_str.parse()?]
+                };
+
+                for g in parsed {
                     let id = g.get_id();
 
                     // adding to input list. it is necessary to separate the inputs into two vectors
@@ -236,14 +674,21 @@ impl FromStr for Circuit {
                                 _ => (),
                             }
                         }
-                        Gate::GateWithC { i1, .. } => match *i1 {
+                        Gate::GateWithC { i1, .. }
+                        | Gate::AssertZero { i1, .. }
+                        | Gate::Reveal { i1, .. }
+                        | Gate::Truncate { i1, .. }
+                        | Gate::Ltz { i1, .. } => match *i1 {
                             GateInput::InputParty(Party::P1) => inputs_p1_first.push(id),
                             GateInput::InputParty(Party::P2) => inputs_p2_first.push(id),
                             _ => (),
                         },
+                        Gate::Linear { .. } | Gate::Dot { .. } => (),
                     }
 
-                    gates.insert(id, g);
+                    if gates.insert(id, g).is_some() {
+                        return Err(format!("Gate {id} appears more than once in circuit"));
+                    }
                 }
             }
 
@@ -267,14 +712,114 @@ impl FromStr for Circuit {
                 topology,
                 inputs_p1: (inputs_p1_first, inputs_p1_second),
                 inputs_p2: (inputs_p2_first, inputs_p2_second),
+                signed_outputs,
             })
         } else {
-            Err("Invalid circuit input format, should be: <gates> & <outputs_to_P1> & <outputs_to_P2>".to_string())
+            Err(format!(
+                "Invalid circuit input format, should be: <gates> & <outputs_to_P1> & <outputs_to_P2> (found {} fields separated by &)",
+                fields.len()
+            ))
         }
     }
 }
 
 impl Circuit {
+    // a circuit without gates or outputs, to be built with add_gate and add_output_p1/p2
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // inserts the gate, rejecting it (and leaving the circuit untouched) if its id is taken or it would close a
+    // cycle. gates may take as inputs gates which have not been added yet, as in the circuit encoding
+    pub fn add_gate(&mut self, gate: Gate) -> Result<(), String> {
+        let id = gate.get_id();
+
+        if self.gates.contains_key(&id) {
+            return Err(format!("Gate {id} already exists in circuit"));
+        }
+
+        // existing gates may only reach the new one through forward references to it, so a cycle exists iff the
+        // new gate can reach itself
+        let mut pending = gate.input_gate_ids();
+        let mut visited = HashSet::new();
+
+        while let Some(i) = pending.pop() {
+            if i == id {
+                return Err(format!("Invalid topology: adding gate {id} would create a cycle"));
+            }
+            if visited.insert(i) {
+                if let Some(g) = self.gates.get(&i) {
+                    pending.extend(g.input_gate_ids());
+                }
+            }
+        }
+
+        let add_input = |ids: &mut Vec<u32>| {
+            let pos = ids.partition_point(|i| *i < id);
+            ids.insert(pos, id);
+        };
+
+        match &gate {
+            Gate::GateWithoutC { i1, i2, .. } => {
+                match i1 {
+                    GateInput::InputParty(Party::P1) => add_input(&mut self.inputs_p1.0),
+                    GateInput::InputParty(Party::P2) => add_input(&mut self.inputs_p2.0),
+                    _ => (),
+                }
+                match i2 {
+                    GateInput::InputParty(Party::P1) => add_input(&mut self.inputs_p1.1),
+                    GateInput::InputParty(Party::P2) => add_input(&mut self.inputs_p2.1),
+                    _ => (),
+                }
+            }
+            Gate::GateWithC { i1, .. }
+            | Gate::AssertZero { i1, .. }
+            | Gate::Reveal { i1, .. }
+            | Gate::Truncate { i1, .. }
+            | Gate::Ltz { i1, .. } => match i1 {
+                GateInput::InputParty(Party::P1) => add_input(&mut self.inputs_p1.0),
+                GateInput::InputParty(Party::P2) => add_input(&mut self.inputs_p2.0),
+                _ => (),
+            },
+            Gate::Linear { .. } | Gate::Dot { .. } => (),
+        }
+
+        // the topology is unaffected: gates reachable from the outputs cannot refer to a gate which did not exist
+        self.gates.insert(id, gate);
+
+        Ok(())
+    }
+
+    pub fn add_output_p1(&mut self, id: u32) -> Result<(), String> {
+        self.add_output(id, Party::P1)
+    }
+
+    pub fn add_output_p2(&mut self, id: u32) -> Result<(), String> {
+        self.add_output(id, Party::P2)
+    }
+
+    // marks the gate as an output for the party, provided all the gates it depends on are present
+    fn add_output(&mut self, id: u32, party: Party) -> Result<(), String> {
+        if self.get_outputs(party).contains(&id) {
+            return Err(format!("Gate {id} is already an output for {party}"));
+        }
+
+        let mut outputs_all = self.outputs_p1.clone();
+        outputs_all.extend(&self.outputs_p2);
+        outputs_all.push(id);
+        outputs_all.sort();
+        outputs_all.dedup();
+
+        self.topology = compute_topology(&self.gates, &outputs_all)?;
+
+        match party {
+            Party::P1 => self.outputs_p1.push(id),
+            Party::P2 => self.outputs_p2.push(id),
+        }
+
+        Ok(())
+    }
+
     // vec is returned to guarantee order, which is crucial for synchronisation
     pub fn get_inputs_p1(&self) -> &(Vec<u32>, Vec<u32>) {
         &self.inputs_p1
@@ -284,11 +829,55 @@ impl Circuit {
         &self.inputs_p2
     }
 
+    pub fn input_arity(&self) -> InputArity {
+        InputArity {
+            p1_first: self.inputs_p1.0.len(),
+            p1_second: self.inputs_p1.1.len(),
+            p2_first: self.inputs_p2.0.len(),
+            p2_second: self.inputs_p2.1.len(),
+        }
+    }
+
+    // all input wires: first those of P1 (first, then second gate inputs), then those of P2
+    pub fn input_wires(&self) -> Vec<InputWire> {
+        let wires = |party: Party, first: bool, ids: &Vec<u32>| -> Vec<InputWire> {
+            ids.iter().map(|id| InputWire { party, first, id: *id }).collect()
+        };
+
+        [
+            wires(Party::P1, true, &self.inputs_p1.0),
+            wires(Party::P1, false, &self.inputs_p1.1),
+            wires(Party::P2, true, &self.inputs_p2.0),
+            wires(Party::P2, false, &self.inputs_p2.1),
+        ]
+        .concat()
+    }
+
     pub fn total_input_wires(&self) -> usize {
-        self.inputs_p1.0.len()
-            + self.inputs_p1.1.len()
-            + self.inputs_p2.0.len()
-            + self.inputs_p2.1.len()
+        self.input_arity().total()
+    }
+
+    // length of the public inputs, i.e. one more than the highest pub<i> index in the circuit, be it of a gate
+    // input or of a constant
+    pub fn num_public_inputs(&self) -> usize {
+        self.gates
+            .values()
+            .flat_map(|g| match g {
+                Gate::GateWithoutC { i1, i2, .. } => vec![*i1, *i2],
+                Gate::GateWithC { i1, c: GateConstant::Public(c), .. } => vec![*i1, GateInput::Public(*c)],
+                Gate::GateWithC { i1, .. }
+                | Gate::AssertZero { i1, .. }
+                | Gate::Reveal { i1, .. }
+                | Gate::Truncate { i1, .. }
+                | Gate::Ltz { i1, .. } => vec![*i1],
+                Gate::Linear { .. } | Gate::Dot { .. } => vec![],
+            })
+            .filter_map(|i| match i {
+                GateInput::Public(i) => Some(i as usize + 1),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0)
     }
 
     pub fn get_outputs(&self, party: Party) -> &Vec<u32> {
@@ -298,90 +887,1146 @@ impl Circuit {
         }
     }
 
+    pub fn is_signed_output(&self, id: u32) -> bool {
+        self.signed_outputs.contains(&id)
+    }
+
+    // the value of the given output as displayed to the parties: the residue itself, or its centred representative
+    // in (-q / 2, q / 2] if the output is signed
+    pub fn format_output(&self, id: u32, v: u32, q: u32) -> String {
+        if self.is_signed_output(id) {
+            utilities::centered(v, q).to_string()
+        } else {
+            v.to_string()
+        }
+    }
+
+    // ids of the outputs revealed to one party only, in ascending order
+    pub fn single_party_outputs(&self) -> Vec<u32> {
+        let mut ids: Vec<u32> = self
+            .outputs_p1
+            .iter()
+            .filter(|id| !self.outputs_p2.contains(id))
+            .chain(self.outputs_p2.iter().filter(|id| !self.outputs_p1.contains(id)))
+            .copied()
+            .collect();
+        ids.sort();
+        ids.dedup();
+        ids
+    }
+
     pub fn get_gate(&self, id: &u32) -> Option<&Gate> {
         self.gates.get(id)
     }
 
+    // the gate as displayed, but with each input replaced by its kind, e.g. [5 | (mul-gate 3) × (P1 input wire)],
+    // so that it is clear which inputs are masked wires and which are the outputs of other gates
+    pub fn describe_gate(&self, id: u32) -> String {
+        let gate = match self.gates.get(&id) {
+            Some(g) => g,
+            None => return format!("[{id} | missing gate]"),
+        };
+
+        let source = |i: &u32| match self.gates.get(i) {
+            Some(g) => format!("({}-gate {i})", g.op_name()),
+            None => format!("(missing gate {i})"),
+        };
+        let resolve = |i: &GateInput| match i {
+            GateInput::Id(s_id) => source(s_id),
+            GateInput::InputParty(p) => format!("({p} input wire)"),
+            GateInput::Public(i) => format!("(public input {i})"),
+        };
+        let sources = |v: &Vec<u32>| v.iter().map(source).collect::<Vec<String>>().join(", ");
+
+        match gate {
+            Gate::GateWithoutC { op, i1, i2, .. } => format!("[{id} | {} {op} {}]", resolve(i1), resolve(i2)),
+            Gate::GateWithC { op, i1, c, .. } => format!("[{id} | {} {op} C({c})]", resolve(i1)),
+            Gate::AssertZero { i1, .. } => format!("[{id} | {} = 0]", resolve(i1)),
+            Gate::Reveal { i1, .. } => format!("[{id} | reveal {}]", resolve(i1)),
+            Gate::Truncate { i1, f, .. } => format!("[{id} | {} / 2^{f}]", resolve(i1)),
+            Gate::Ltz { i1, k, .. } => format!("[{id} | {} < 0 ({k} bits)]", resolve(i1)),
+            Gate::Linear { inputs, coeffs, .. } => {
+                let terms: Vec<String> = coeffs.iter().zip(inputs).map(|(c, i)| format!("C({c}) × {}", source(i))).collect();
+                format!("[{id} | {}]", terms.join(" + "))
+            }
+            Gate::Dot { left, right, .. } => format!("[{id} | <({}), ({})>]", sources(left), sources(right)),
+        }
+    }
+
     pub fn get_topology(&self) -> &[u32] {
         &self.topology
     }
+
+    // ids of the multiplication gates, in topology order
+    pub fn mul_gate_ids(&self) -> Vec<u32> {
+        self.topology
+            .iter()
+            .copied()
+            .filter(|id| {
+                matches!(
+                    self.gates.get(id),
+                    Some(Gate::GateWithoutC {
+                        op: GateOp::Mul,
+                        ..
+                    })
+                )
+            })
+            .collect()
+    }
+
+    // ids of the gates consuming Beaver triples (multiplications, zero assertions, comparisons and equality
+    // tests), in topology order
+    pub fn triple_gate_ids(&self) -> Vec<u32> {
+        self.topology
+            .iter()
+            .copied()
+            .filter(|id| self.gates.get(id).is_some_and(|g| g.num_triples() > 0))
+            .collect()
+    }
+
+    pub fn num_beaver_triples(&self) -> usize {
+        self.topology.iter().filter_map(|id| self.gates.get(id)).map(|g| g.num_triples()).sum()
+    }
+
+    // lengths of the dot gates, in topology order, each of which consumes an inner-product tuple of that length
+    pub fn dot_gate_lengths(&self) -> Vec<usize> {
+        self.topology
+            .iter()
+            .filter_map(|id| match self.gates.get(id) {
+                Some(Gate::Dot { left, .. }) => Some(left.len()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // shifts of the truncation gates, in topology order, each of which consumes a truncation pair for that shift
+    pub fn truncation_shifts(&self) -> Vec<u32> {
+        self.topology
+            .iter()
+            .filter_map(|id| match self.gates.get(id) {
+                Some(Gate::Truncate { f, .. }) => Some(*f),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // number of equality tests, each of which consumes the bits of a random value. since these all have the same
+    // size, their order is irrelevant
+    pub fn num_equality_tests(&self) -> usize {
+        self.gates
+            .values()
+            .filter(|g| {
+                matches!(g, Gate::GateWithoutC { op: GateOp::Eq, .. } | Gate::GateWithC { op: GateOp::Eq, .. })
+            })
+            .count()
+    }
+
+    // bit lengths of the comparison gates, in topology order, each of which consumes k - 1 random shared bits
+    pub fn comparison_bit_lengths(&self) -> Vec<u32> {
+        self.topology
+            .iter()
+            .filter_map(|id| match self.gates.get(id) {
+                Some(Gate::Ltz { k, .. }) => Some(*k),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // the correlated randomness used by each gate, in topology order. this only covers the online phase: the key
+    // sharings, the triples sacrificed or audited in preprocessing and the blinds of single-party outputs are left
+    // out
+    pub fn randomness_schedule(&self) -> Vec<RandomnessUse> {
+        let singletons: HashMap<InputWire, usize> =
+            self.input_wires().into_iter().enumerate().map(|(i, w)| (w, i)).collect();
+        let (mut triples, mut inner_products, mut truncations, mut comparisons, mut equalities) = (0, 0, 0, 0, 0);
+
+        let next = |counter: &mut usize| {
+            *counter += 1;
+            *counter - 1
+        };
+
+        self.topology
+            .iter()
+            .map(|id| {
+                // the topology only contains existing gates
+                let g = self.gates.get(id).unwrap();
+                let mut uses = Vec::new();
+
+                let gate_inputs = match g {
+                    Gate::GateWithoutC { i1, i2, .. } => vec![(i1, true), (i2, false)],
+                    Gate::GateWithC { i1, .. }
+                    | Gate::AssertZero { i1, .. }
+                    | Gate::Reveal { i1, .. }
+                    | Gate::Truncate { i1, .. }
+                    | Gate::Ltz { i1, .. } => vec![(i1, true)],
+                    Gate::Linear { .. } | Gate::Dot { .. } => vec![],
+                };
+                for (input, first) in gate_inputs {
+                    if let GateInput::InputParty(party) = input {
+                        uses.push(Randomness::Singleton(singletons[&InputWire { party: *party, first, id: *id }]));
+                    }
+                }
+
+                match g {
+                    Gate::Dot { .. } => uses.push(Randomness::InnerProduct(next(&mut inner_products))),
+                    Gate::Truncate { .. } => uses.push(Randomness::Truncation(next(&mut truncations))),
+                    Gate::Ltz { .. } => uses.push(Randomness::ComparisonBits(next(&mut comparisons))),
+                    Gate::GateWithoutC { op: GateOp::Eq, .. } | Gate::GateWithC { op: GateOp::Eq, .. } => {
+                        uses.push(Randomness::EqualityBits(next(&mut equalities)))
+                    }
+                    _ => {}
+                }
+                uses.extend((0..g.num_triples()).map(|_| Randomness::Triple(next(&mut triples))));
+
+                RandomnessUse { gate: *id, uses }
+            })
+            .collect()
+    }
+
+    // the masking of truncated and compared values only leaves room for inputs of a bounded size, which in turn
+    // bounds the shift of truncations and the bit length of comparisons
+    pub fn check_value_bounds(&self, q: u32) -> Result<(), String> {
+        let k_max = utilities::truncation_bits(q);
+
+        for (id, g) in &self.gates {
+            match g {
+                Gate::Truncate { f, .. } if *f >= k_max => {
+                    return Err(format!(
+                        "Error: the shift of truncation gate {id} should be below {k_max} for q = {q}"
+                    ));
+                }
+                // comparisons divide by a power of two, which has no inverse modulo an even q
+                Gate::Ltz { .. } if q.is_multiple_of(2) => {
+                    return Err(format!("Error: comparison gate {id} requires an odd modulus, not q = {q}"));
+                }
+                Gate::Ltz { k, .. } if *k == 0 || *k > k_max => {
+                    return Err(format!(
+                        "Error: the bit length of comparison gate {id} should be between 1 and {k_max} for q = {q}"
+                    ));
+                }
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    // largest number of multiplication (or dot) gates on a path from the inputs to an output, with comparisons
+    // and equality tests counting as many as the depth of their product trees
+    pub fn multiplicative_depth(&self) -> usize {
+        self.weighted_depth(|g| match g {
+            Gate::GateWithoutC { op: GateOp::Mul, .. } | Gate::Dot { .. } => 1,
+            Gate::Ltz { k, .. } => comparison_levels(*k),
+            Gate::GateWithoutC { op: GateOp::Eq, .. } | Gate::GateWithC { op: GateOp::Eq, .. } => {
+                tree_levels(utilities::EQUALITY_BITS)
+            }
+            _ => 0,
+        })
+    }
+
+    // largest number of sequential openings on a path from the inputs to an output: one per multiplication,
+    // truncation and reveal, two per zero assertion and one plus one per level of the product tree per comparison
+    // and equality test
+    pub fn interactive_depth(&self) -> usize {
+        self.weighted_depth(|g| match g {
+            Gate::GateWithoutC { op: GateOp::Mul, .. }
+            | Gate::Dot { .. }
+            | Gate::Truncate { .. }
+            | Gate::Reveal { .. } => 1,
+            Gate::AssertZero { .. } => 2,
+            Gate::Ltz { k, .. } => 1 + comparison_levels(*k),
+            Gate::GateWithoutC { op: GateOp::Eq, .. } | Gate::GateWithC { op: GateOp::Eq, .. } => {
+                1 + tree_levels(utilities::EQUALITY_BITS)
+            }
+            _ => 0,
+        })
+    }
+
+    // the gates of the topology grouped as early as possible: gates taking only party inputs and fixed constants
+    // form layer 0, and every other gate goes one layer after the latest gate it depends on. the gates of a layer
+    // are thus independent of one another and sorted by id. unlike the interactive depth, every gate counts
+    pub fn layers(&self) -> Vec<Vec<u32>> {
+        let mut levels: HashMap<u32, usize> = HashMap::new();
+        let mut layers: Vec<Vec<u32>> = Vec::new();
+
+        for id in &self.topology {
+            // the topology only contains existing gates whose gate inputs precede them
+            let g = self.gates.get(id).unwrap();
+            let level = g.input_gate_ids().iter().map(|i| levels[i] + 1).max().unwrap_or(0);
+            levels.insert(*id, level);
+
+            if level == layers.len() {
+                layers.push(Vec::new());
+            }
+            layers[level].push(*id);
+        }
+
+        layers.iter_mut().for_each(|l| l.sort_unstable());
+        layers
+    }
+
+    fn weighted_depth(&self, weight: impl Fn(&Gate) -> usize) -> usize {
+        let mut depths: HashMap<u32, usize> = HashMap::new();
+
+        for id in &self.topology {
+            // the topology only contains existing gates whose gate inputs precede them
+            let g = self.gates.get(id).unwrap();
+            let d = g.input_gate_ids().iter().map(|i| depths[i]).max().unwrap_or(0) + weight(g);
+            depths.insert(*id, d);
+        }
+
+        depths.into_values().max().unwrap_or(0)
+    }
+
+    pub fn check_inputs(&self, inputs: &Inputs) -> Result<(), String> {
+        let needed = self.input_arity();
+        let provided = inputs.arity();
+
+        if needed.p1_first != provided.p1_first || needed.p1_second != provided.p1_second {
+            return Err(String::from("Error: number of input values provided by P1 does not match the circuit's needs"));
+        }
+
+        if needed.p2_first != provided.p2_first || needed.p2_second != provided.p2_second {
+            return Err(String::from("Error: number of input values provided by P2 does not match the circuit's needs"));
+        }
+
+        if inputs.public.len() != self.num_public_inputs() {
+            return Err(format!(
+                "Error: {} public input values were provided, but the circuit needs {}",
+                inputs.public.len(),
+                self.num_public_inputs()
+            ));
+        }
+
+        Ok(())
+    }
+
+    // a weak heuristic against gate ids pasted into an input vector: a warning for each value which equals the id
+    // of another gate taking input from the same party through the same slot. values feed the gates in ascending
+    // order of id, and the warnings spell this mapping out. false positives are to be expected with small values
+    pub fn input_id_warnings(&self, inputs: &Inputs) -> Vec<String> {
+        let slots = [
+            (Party::P1, "first", &self.inputs_p1.0, &inputs.p1.0),
+            (Party::P1, "second", &self.inputs_p1.1, &inputs.p1.1),
+            (Party::P2, "first", &self.inputs_p2.0, &inputs.p2.0),
+            (Party::P2, "second", &self.inputs_p2.1, &inputs.p2.1),
+        ];
+
+        let mut warnings = Vec::new();
+        for (party, slot, ids, values) in slots {
+            for (i, (id, v)) in ids.iter().zip(values).enumerate() {
+                if let Some(j) = ids.iter().position(|other| other == v).filter(|j| *j != i) {
+                    warnings.push(format!(
+                        "Warning: value {v} at position {} of the {slot} inputs of {party} equals the id of gate {v}, \
+                         which takes the value at position {} instead. Is it a gate id? The {slot} inputs of {party} \
+                         feed gates {} in this order, so this value goes to gate {id}",
+                        i + 1,
+                        j + 1,
+                        ids.iter().map(|id| id.to_string()).collect::<Vec<String>>().join(", ")
+                    ));
+                }
+            }
+        }
+        warnings
+    }
+
+    // cleartext evaluation of every gate in the topology. this is *not* part of the protocol and offers no
+    // privacy whatsoever: it only serves as a reference for the values the protocol should output
+    pub fn evaluate(&self, inputs: &Inputs, q: u32) -> Result<HashMap<u32, u32>, String> {
+        self.check_inputs(inputs)?;
+        let f = F32::new(q);
+
+        let input_values = |ids: &Vec<u32>, values: &Vec<u32>| -> HashMap<u32, u32> {
+            ids.iter().copied().zip(values.iter().map(|v| v % q)).collect()
+        };
+
+        let p1_first = input_values(&self.inputs_p1.0, &inputs.p1.0);
+        let p1_second = input_values(&self.inputs_p1.1, &inputs.p1.1);
+        let p2_first = input_values(&self.inputs_p2.0, &inputs.p2.0);
+        let p2_second = input_values(&self.inputs_p2.1, &inputs.p2.1);
+
+        let mut values: HashMap<u32, u32> = HashMap::new();
+
+        for id in &self.topology {
+            // the topology only contains existing gates whose gate inputs precede them
+            let v = match self.gates.get(id).unwrap() {
+                Gate::GateWithoutC { op, i1, i2, .. } => {
+                    let v1 = match i1 {
+                        GateInput::Id(s_id) => values[s_id],
+                        GateInput::InputParty(Party::P1) => p1_first[id],
+                        GateInput::InputParty(Party::P2) => p2_first[id],
+                        GateInput::Public(i) => inputs.public[*i as usize] % q,
+                    };
+                    let v2 = match i2 {
+                        GateInput::Id(s_id) => values[s_id],
+                        GateInput::InputParty(Party::P1) => p1_second[id],
+                        GateInput::InputParty(Party::P2) => p2_second[id],
+                        GateInput::Public(i) => inputs.public[*i as usize] % q,
+                    };
+                    match op {
+                        GateOp::Add => f.add(v1, v2),
+                        GateOp::Mul => f.mul(v1, v2),
+                        GateOp::Eq => (v1 == v2) as u32,
+                    }
+                }
+                Gate::GateWithC { op, i1, c, .. } => {
+                    let v1 = match i1 {
+                        GateInput::Id(s_id) => values[s_id],
+                        GateInput::InputParty(Party::P1) => p1_first[id],
+                        GateInput::InputParty(Party::P2) => p2_first[id],
+                        GateInput::Public(i) => inputs.public[*i as usize] % q,
+                    };
+                    // a reveal gate's public value is its cleartext one
+                    let c = c.resolve(&values, &inputs.public, q);
+                    match op {
+                        GateOp::Add => f.add(v1, c),
+                        GateOp::Mul => f.mul(v1, c),
+                        GateOp::Eq => (v1 == c) as u32,
+                    }
+                }
+                Gate::AssertZero { i1, .. } => {
+                    let v1 = match i1 {
+                        GateInput::Id(s_id) => values[s_id],
+                        GateInput::InputParty(Party::P1) => p1_first[id],
+                        GateInput::InputParty(Party::P2) => p2_first[id],
+                        GateInput::Public(i) => inputs.public[*i as usize] % q,
+                    };
+                    if v1 != 0 {
+                        return Err(format!("Assertion failed: the input of gate {id} is not zero"));
+                    }
+                    v1
+                }
+                // the exact result, which the protocol may exceed by 1
+                Gate::Reveal { i1, .. } => match i1 {
+                    GateInput::Id(s_id) => values[s_id],
+                    GateInput::InputParty(Party::P1) => p1_first[id],
+                    GateInput::InputParty(Party::P2) => p2_first[id],
+                    GateInput::Public(i) => inputs.public[*i as usize] % q,
+                },
+                Gate::Truncate { i1, f, .. } => {
+                    let v1 = match i1 {
+                        GateInput::Id(s_id) => values[s_id],
+                        GateInput::InputParty(Party::P1) => p1_first[id],
+                        GateInput::InputParty(Party::P2) => p2_first[id],
+                        GateInput::Public(i) => inputs.public[*i as usize] % q,
+                    };
+                    let signed = if v1 > q / 2 { v1 as i64 - q as i64 } else { v1 as i64 };
+                    (signed >> f).rem_euclid(q as i64) as u32
+                }
+                Gate::Ltz { i1, .. } => {
+                    let v1 = match i1 {
+                        GateInput::Id(s_id) => values[s_id],
+                        GateInput::InputParty(Party::P1) => p1_first[id],
+                        GateInput::InputParty(Party::P2) => p2_first[id],
+                        GateInput::Public(i) => inputs.public[*i as usize] % q,
+                    };
+                    (v1 > q / 2) as u32
+                }
+                Gate::Linear { inputs, coeffs, .. } => inputs.iter().zip(coeffs).fold(0, |acc, (i, c)| {
+                    f.add(acc, f.mul(values[i], utilities::modulo(*c, q)))
+                }),
+                Gate::Dot { left, right, .. } => left.iter().zip(right).fold(0, |acc, (l, r)| {
+                    f.add(acc, f.mul(values[l], values[r]))
+                }),
+            };
+
+            values.insert(*id, v);
+        }
+
+        Ok(values)
+    }
+
+    // for each output gate (of either party), the party inputs it transitively depends on. only the
+    // GateInput::InputParty leaves are collected, so a set {P1} means the output is a function of P1's inputs alone
+    pub fn output_dependencies(&self) -> HashMap<u32, HashSet<GateInput>> {
+        let mut deps: HashMap<u32, HashSet<GateInput>> = HashMap::new();
+
+        for id in &self.topology {
+            // the topology only contains existing gates whose gate inputs precede them
+            let gate_inputs = match self.gates.get(id).unwrap() {
+                Gate::GateWithoutC { i1, i2, .. } => vec![*i1, *i2],
+                Gate::GateWithC { i1, c: GateConstant::Revealed(r), .. } => vec![*i1, GateInput::Id(*r)],
+                Gate::GateWithC { i1, .. }
+                | Gate::AssertZero { i1, .. }
+                | Gate::Reveal { i1, .. }
+                | Gate::Truncate { i1, .. }
+                | Gate::Ltz { i1, .. } => vec![*i1],
+                g @ (Gate::Linear { .. } | Gate::Dot { .. }) => {
+                    g.input_gate_ids().into_iter().map(GateInput::Id).collect()
+                }
+            };
+
+            let mut d = HashSet::new();
+
+            for i in gate_inputs {
+                match i {
+                    GateInput::Id(s_id) => d.extend(deps[&s_id].iter().copied()),
+                    GateInput::InputParty(_) => {
+                        d.insert(i);
+                    }
+                    GateInput::Public(_) => (),
+                }
+            }
+
+            deps.insert(*id, d);
+        }
+
+        self.outputs_p1
+            .iter()
+            .chain(self.outputs_p2.iter())
+            .map(|id| (*id, deps[id].clone()))
+            .collect()
+    }
+
+    // restriction of the cleartext evaluation to the outputs assigned to the given party
+    pub fn evaluate_outputs(&self, inputs: &Inputs, q: u32, party: Party) -> Result<HashMap<u32, u32>, String> {
+        let values = self.evaluate(inputs, q)?;
+
+        Ok(self.get_outputs(party).iter().map(|id| (*id, values[id])).collect())
+    }
+
+    // the smallest circuit computing the given gates, with the same gate ids. each of them remains an output for
+    // the same parties as in this circuit, or becomes one for both if it was not an output. the input lists only
+    // keep the wires of the copied gates, in the same order
+    pub fn subcircuit(&self, output_ids: &[u32]) -> Result<Circuit, String> {
+        let mut outputs_all = output_ids.to_vec();
+        outputs_all.sort();
+        outputs_all.dedup();
+
+        let topology = compute_topology(&self.gates, &outputs_all)?;
+        let gates: HashMap<u32, Gate> = topology.iter().map(|id| (*id, self.gates[id].clone())).collect();
+
+        let (outputs_p1, outputs_p2): (Vec<u32>, Vec<u32>) = (
+            outputs_all.iter().copied().filter(|id| !self.outputs_p2.contains(id) || self.outputs_p1.contains(id)).collect(),
+            outputs_all.iter().copied().filter(|id| !self.outputs_p1.contains(id) || self.outputs_p2.contains(id)).collect(),
+        );
+
+        let keep = |ids: &Vec<u32>| -> Vec<u32> { ids.iter().copied().filter(|id| gates.contains_key(id)).collect() };
+        let inputs_p1 = (keep(&self.inputs_p1.0), keep(&self.inputs_p1.1));
+        let inputs_p2 = (keep(&self.inputs_p2.0), keep(&self.inputs_p2.1));
+
+        Ok(Circuit {
+            gates,
+            outputs_p1,
+            outputs_p2,
+            topology,
+            inputs_p1,
+            inputs_p2,
+            signed_outputs: outputs_all.iter().copied().filter(|id| self.signed_outputs.contains(id)).collect(),
+        })
+    }
+
+    // rewrites each multiplication of two public inputs, whose product both parties can compute, as a mulc of the
+    // first one by the second, which needs no Beaver triple. the topology and input wires are unaffected, as
+    // neither operand is a gate or an input wire. returns the number of gates folded
+    pub fn fold_constants(&mut self) -> usize {
+        let mut folded = 0;
+
+        for g in self.gates.values_mut() {
+            if let Gate::GateWithoutC { id, op: GateOp::Mul, i1: i1 @ GateInput::Public(_), i2: GateInput::Public(c) } = *g {
+                *g = Gate::GateWithC { id, op: GateOp::Mul, i1, c: GateConstant::Public(c) };
+                folded += 1;
+            }
+        }
+
+        folded
+    }
+
+    // rewrites each gate with a constant (addc, mulc or eqc) as the two-input gate with the same operation, for
+    // exporters and analyses which only take these. a fixed constant becomes a new public input, a public input
+    // constant that same input and a revealed one the output of its reveal gate, which holds the same value. the
+    // outputs are unchanged, but each mulc becomes a mul consuming a Beaver triple. returns the fixed constants, in
+    // the order of the public inputs added for them after the existing ones, which must be appended to the public
+    // inputs (reduced modulo q) when evaluating or running the rewritten circuit
+    pub fn desugar_constants(&mut self) -> Vec<i32> {
+        let mut ids: Vec<u32> = self.gates.keys().copied().collect();
+        ids.sort();
+
+        let first = self.num_public_inputs() as u32;
+        let mut constants = Vec::new();
+
+        for id in ids {
+            if let Some(Gate::GateWithC { op, i1, c, .. }) = self.gates.get(&id) {
+                let i2 = match c {
+                    GateConstant::Fixed(c) => {
+                        constants.push(*c);
+                        GateInput::Public(first + constants.len() as u32 - 1)
+                    }
+                    GateConstant::Public(i) => GateInput::Public(*i),
+                    GateConstant::Revealed(r) => GateInput::Id(*r),
+                };
+                self.gates.insert(id, Gate::GateWithoutC { id, op: *op, i1: *i1, i2 });
+            }
+        }
+
+        // every gate still depends on the same ones, so this cannot fail
+        let mut outputs_all = self.outputs_p1.clone();
+        outputs_all.extend(&self.outputs_p2);
+        outputs_all.sort();
+        outputs_all.dedup();
+        self.topology = compute_topology(&self.gates, &outputs_all).unwrap();
+
+        constants
+    }
+}
+
+// the circuit encoding at the start of the lines of a file, which ends at the first empty line after the second &
+// (or at the end of the file). the encoding may be split along any number of lines, with blank lines in between and
+// comments from a # to the end of the line. also returns whether an empty line ended it, i.e. whether data may follow
+pub fn read_encoding<E>(lines: &mut impl Iterator<Item = Result<String, E>>) -> Result<(String, bool), E> {
+    let mut encoding = String::new();
+
+    for l in lines {
+        let l = l?;
+        if l.trim().is_empty() && encoding.matches('&').count() >= 2 {
+            return Ok((encoding, true));
+        }
+        encoding.push_str(l.split('#').next().unwrap_or(""));
+    }
+
+    Ok((encoding, false))
 }
 
+// the gates the outputs depend on, each after its inputs: a depth-first post-order from each output in turn, visiting
+// the inputs of each gate in order. the traversal keeps its own stack, so that arbitrarily deep circuits (e.g. long
+// chains of constant gates) cannot overflow the call stack
 fn compute_topology(gates: &HashMap<u32, Gate>, outputs: &Vec<u32>) -> Result<Vec<u32>, String> {
+    let inputs_of = |target: u32| -> Result<Vec<u32>, String> {
+        let g = gates
+            .get(&target)
+            .ok_or(format!("Invalid topology: necessary gate {target} not found in circuit"))?;
+
+        // a constant gate using a revealed value must use that of a reveal gate
+        if let Gate::GateWithC { c: GateConstant::Revealed(r), .. } = g {
+            if !matches!(gates.get(r), Some(Gate::Reveal { .. })) {
+                return Err(format!("Gate {target} uses the value of gate {r}, which is not a reveal gate"));
+            }
+        }
+
+        let ids = g.input_gate_ids();
+        if ids.contains(&target) {
+            return Err(format!("Gate {} cannot be an input to itself", target));
+        }
+
+        Ok(ids)
+    };
+
     let mut top = Vec::new();
+    let mut done = HashSet::new();
 
     for o in outputs {
-        top = simplify(top, compute_topology_internal(gates, o)?);
+        if done.contains(o) {
+            continue;
+        }
+
+        // the gates being visited, each with its inputs and the position of the next one to visit
+        let mut stack = vec![(*o, inputs_of(*o)?, 0)];
+        let mut on_stack = HashSet::from([*o]);
+
+        while let Some((id, inputs, next)) = stack.last_mut() {
+            match inputs.get(*next).copied() {
+                Some(i) => {
+                    *next += 1;
+                    if on_stack.contains(&i) {
+                        return Err(format!("Invalid topology: it contains a cycle involving gate {}", i));
+                    }
+                    if !done.contains(&i) {
+                        stack.push((i, inputs_of(i)?, 0));
+                        on_stack.insert(i);
+                    }
+                }
+                None => {
+                    let id = *id;
+                    stack.pop();
+                    on_stack.remove(&id);
+                    done.insert(id);
+                    top.push(id);
+                }
+            }
+        }
     }
 
     Ok(top)
 }
 
-fn compute_topology_internal(gates: &HashMap<u32, Gate>, target: &u32) -> Result<Vec<u32>, String> {
-    let mut req = match gates.get(target) {
-        None => {
-            return Err(format!(
-                "Invalid topology: necessary gate {target} not found in circuit"
-            ))
+// number of levels of the tree combining the k - 1 low bits in the carry computation of a comparison
+pub fn comparison_levels(k: u32) -> usize {
+    tree_levels(k.saturating_sub(1))
+}
+
+// number of levels of a binary tree combining n leaves pairwise
+fn tree_levels(n: u32) -> usize {
+    n.max(1).next_power_of_two().trailing_zeros() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gate_inputs_dedup_in_hash_set() {
+        let inputs = [
+            GateInput::Id(3),
+            GateInput::InputParty(Party::P1),
+            GateInput::Public(3),
+            GateInput::Id(3),
+            GateInput::InputParty(Party::P2),
+            GateInput::InputParty(Party::P1),
+            GateInput::Public(3),
+            // parsed from the encoding, as the same input written differently
+            "P1".parse().unwrap(),
+            "3".parse().unwrap(),
+            "PUB3".parse().unwrap(),
+        ];
+
+        // gate 3 and public input 3 are different inputs, despite the same index
+        let set: HashSet<GateInput> = inputs.iter().copied().collect();
+        assert_eq!(set.len(), 4);
+        for i in [GateInput::Id(3), GateInput::InputParty(Party::P1), GateInput::InputParty(Party::P2), GateInput::Public(3)] {
+            assert!(set.contains(&i));
         }
-        // Gates with exactly two input gates
-        Some(Gate::GateWithoutC {
-            i1: GateInput::Id(id1),
-            i2: GateInput::Id(id2),
-            ..
-        }) => {
-            if id1 == target || id2 == target {
-                return Err(format!("Gate {} cannot be an input to itself", target));
-            } else if id1 == id2 {
-                compute_topology_internal(gates, id1)?
-            } else {
-                simplify(
-                    compute_topology_internal(gates, id1)?,
-                    compute_topology_internal(gates, id2)?,
-                )
+        assert!(!set.contains(&GateInput::Id(4)) && !set.contains(&GateInput::Public(4)));
+    }
+
+    // public inputs 6 and 7: gate 0 multiplies them, gate 3 multiplies one by a gate and gate 4 adds them as constants
+    const PUBLIC_PRODUCTS: &str = "0, pub0, mul, pub1 | 1, 0, mul, P1 | 2, 1, add, pub1 | 3, pub0, mul, 2 | 4, 3, addc, pub1 & 4 & 4";
+
+    fn public_products_inputs() -> Inputs {
+        Inputs { p1: (vec![], vec![5]), p2: (vec![], vec![]), public: vec![6, 7] }
+    }
+
+    #[test]
+    fn multiplication_of_public_inputs_is_folded() {
+        let mut circuit: Circuit = PUBLIC_PRODUCTS.parse().unwrap();
+        let inputs = public_products_inputs();
+        let before = circuit.evaluate(&inputs, 101).unwrap();
+        assert_eq!(circuit.num_beaver_triples(), 3);
+        assert_eq!(circuit.num_public_inputs(), 2);
+
+        // only gate 0 has two public inputs
+        assert_eq!(circuit.fold_constants(), 1);
+        assert_eq!(circuit.num_beaver_triples(), 2);
+        assert_eq!(circuit.mul_gate_ids(), [1, 3]);
+        assert_eq!(circuit.describe_gate(0), "[0 | (public input 0) × C(pub1)]");
+        assert_eq!(circuit.get_topology(), [0, 1, 2, 3, 4]);
+
+        // (6 * 7 * 5 + 7) * 6 + 7 = 1309 = 97 mod 101
+        let after = circuit.evaluate(&inputs, 101).unwrap();
+        assert_eq!(after, before);
+        assert_eq!(after[&4], 97);
+
+        // folding again changes nothing
+        assert_eq!(circuit.fold_constants(), 0);
+    }
+
+    #[test]
+    fn public_input_constants_are_parsed() {
+        assert_eq!("pub3".parse::<GateConstant>(), Ok(GateConstant::Public(3)));
+        assert_eq!("PUB0".parse::<GateConstant>(), Ok(GateConstant::Public(0)));
+        assert_eq!(GateConstant::Public(3).to_string(), "pub3");
+        assert!("pub".parse::<GateConstant>().is_err());
+        assert!("pub-1".parse::<GateConstant>().is_err());
+
+        // a public input used only as a constant still counts towards the public inputs
+        let circuit: Circuit = "0, P1, mulc, pub2 & 0 & 0".parse().unwrap();
+        assert_eq!(circuit.num_public_inputs(), 3);
+    }
+
+    #[test]
+    fn desugared_constants_give_the_same_outputs() {
+        // every kind of constant, on gate and input wire operands, including a mulc by a revealed value
+        let encoding = "0, P1, addc, -3 | 1, 0, mulc, 5 | 2, P2, mulc, pub0 | 3, 1, add, 2 | 4, 3, reveal \
+            | 5, 3, mulc, R4 | 6, 5, eqc, 2 | 7, P1, addc, pub1 & 3, 5, 6, 7 & 5, 6";
+        let q = 101;
+        let mut circuit: Circuit = encoding.parse().unwrap();
+        let triples = circuit.num_beaver_triples();
+
+        let cases = [(vec![4, 9], vec![11], vec![2, 3]), (vec![0, 100], vec![0], vec![100, 0]), (vec![52, 1], vec![7], vec![5, 99])];
+        for (p1, p2, public) in cases {
+            let mut circuit: Circuit = encoding.parse().unwrap();
+            let inputs = Inputs { p1: (p1, vec![]), p2: (p2, vec![]), public };
+            let original = circuit.evaluate(&inputs, q).unwrap();
+
+            // -3, 5 and 2 are new public inputs 2, 3 and 4
+            let constants = circuit.desugar_constants();
+            assert_eq!(constants, [-3, 5, 2]);
+            assert_eq!(circuit.num_public_inputs(), 5);
+
+            let mut desugared_inputs = inputs.clone();
+            desugared_inputs.public.extend(constants.iter().map(|c| utilities::modulo(*c, q)));
+            let desugared = circuit.evaluate(&desugared_inputs, q).unwrap();
+
+            for id in [3, 5, 6, 7] {
+                assert_eq!(desugared[&id], original[&id], "gate {id}");
             }
         }
-        // Gates with exactly one input gate
-        Some(Gate::GateWithoutC {
-            i1: GateInput::Id(id),
-            ..
-        })
-        | Some(Gate::GateWithoutC {
-            i2: GateInput::Id(id),
-            ..
-        })
-        | Some(Gate::GateWithC {
-            i1: GateInput::Id(id),
-            ..
-        }) => {
-            if id == target {
-                return Err(format!("Gate {} cannot be an input to itself", target));
-            } else {
-                compute_topology_internal(gates, id)?
+
+        // the three mulc gates now consume a triple each, and the eqc as many as before
+        let constants = circuit.desugar_constants();
+        assert_eq!(circuit.num_beaver_triples(), triples + 3);
+        assert!(circuit.topology.iter().all(|id| !matches!(circuit.gates[id], Gate::GateWithC { .. })));
+        assert_eq!(circuit.describe_gate(5), "[5 | (add-gate 3) × (reveal-gate 4)]");
+        assert_eq!(circuit.describe_gate(0), "[0 | (P1 input wire) + (public input 2)]");
+        assert_eq!(constants.len(), 3);
+
+        // the topology still puts each gate after its inputs, the reveal gate included
+        let position = |id: u32| circuit.get_topology().iter().position(|i| *i == id).unwrap();
+        assert!(position(4) < position(5) && position(3) < position(4));
+    }
+
+    // a diamond: gate 0 feeds two independent gates, which meet again in the output
+    #[test]
+    fn diamond_is_layered_by_its_longest_paths() {
+        let diamond: Circuit = "0, P1, mul, P2 | 1, 0, addc, 3 | 2, 0, mul, P1 | 3, 1, mul, 2 & 3 &".parse().unwrap();
+        assert_eq!(diamond.layers(), [vec![0], vec![1, 2], vec![3]]);
+
+        // one arm longer than the other: the join waits for the longer one, while gate 5 only needs gate 0. the ids
+        // are out of order, but each layer is sorted
+        let lopsided: Circuit =
+            "7, P1, mul, P2 | 4, 7, addc, 3 | 2, 4, mul, P1 | 1, 7, mul, 7 | 5, 7, reveal | 0, 2, add, 1 & 0, 5 &"
+                .parse()
+                .unwrap();
+        let layers = lopsided.layers();
+        assert_eq!(layers, [vec![7], vec![1, 4, 5], vec![2], vec![0]]);
+
+        // every gate is in exactly one layer, after all of its gate inputs
+        let layer_of = |id: u32| layers.iter().position(|l| l.contains(&id)).unwrap();
+        assert_eq!(layers.concat().len(), lopsided.get_topology().len());
+        for id in lopsided.get_topology() {
+            for i in lopsided.gates[id].input_gate_ids() {
+                assert!(layer_of(i) < layer_of(*id), "gate {id} after gate {i}");
             }
         }
-        // Gates with no gate inputs (party-constant or party-party)
-        _ => vec![],
-    };
+    }
 
-    if req.contains(target) {
-        return Err(format!(
-            "Invalid topology: it contains a cycle involving gate {}",
-            target
-        ));
+    // every kind of gate consuming correlated randomness, some with input wires of both parties in both positions
+    #[test]
+    fn randomness_schedule_follows_the_topology() {
+        let circuit: Circuit = "0, P2, mul, P1 | 1, 0, truncf, 2 | 2, P1, ltz, 4 | dot, 3, 0 1, 2 0 | 4, 3, eqc, 5 \
+            | 5, P1, mul, 4 | 6, 5, truncf, 1 | 7, 6, add, 1 & 7 & 4"
+            .parse()
+            .unwrap();
+        assert_eq!(circuit.get_topology(), [0, 1, 2, 3, 4, 5, 6, 7]);
+
+        // the singletons are numbered as the input wires: P1 first (gates 2 and 5), P1 second (gate 0), then P2
+        // first (gate 0)
+        let triples = |r: std::ops::Range<usize>| r.map(Randomness::Triple).collect::<Vec<_>>();
+        let expected = [
+            (0, vec![Randomness::Singleton(3), Randomness::Singleton(2), Randomness::Triple(0)]),
+            (1, vec![Randomness::Truncation(0)]),
+            (2, [vec![Randomness::Singleton(0), Randomness::ComparisonBits(0)], triples(1..5)].concat()),
+            (3, vec![Randomness::InnerProduct(0)]),
+            (4, [vec![Randomness::EqualityBits(0)], triples(5..36)].concat()),
+            (5, vec![Randomness::Singleton(1), Randomness::Triple(36)]),
+            (6, vec![Randomness::Truncation(1)]),
+            (7, vec![]),
+        ]
+        .map(|(gate, uses)| RandomnessUse { gate, uses });
+        let schedule = circuit.randomness_schedule();
+        assert_eq!(schedule, expected);
+
+        // as many of each kind as the circuit consumes in total
+        let triples_used = schedule.iter().flat_map(|u| &u.uses).filter(|r| matches!(r, Randomness::Triple(_)));
+        assert_eq!(triples_used.count(), circuit.num_beaver_triples());
+
+        assert_eq!(schedule[0].to_string(), "gate 0: singleton 3, singleton 2, triple 0");
+        assert_eq!(schedule[3].to_string(), "gate 3: inner product 0");
+        assert_eq!(schedule[7].to_string(), "gate 7: none");
     }
 
-    req.push(*target);
+    #[test]
+    fn output_dependencies_collect_the_input_parties() {
+        let (p1, p2) = (GateInput::InputParty(Party::P1), GateInput::InputParty(Party::P2));
 
-    Ok(req)
-}
+        // public inputs and constants are no dependencies. gate 5 only depends on P1 through the revealed gate 4
+        let circuit: Circuit = "0, P1, add, P1 | 1, 0, mulc, pub0 | 2, P2, mul, pub1 | 3, 1, add, 2 | 4, 1, reveal \
+            | 5, 2, mulc, R4 | 6, pub0, add, pub1 | 7, 4, addc, 3 & 1, 3, 4, 7 & 5, 6"
+            .parse()
+            .unwrap();
+        let deps = circuit.output_dependencies();
+        let expected: HashMap<u32, HashSet<GateInput>> = [
+            (1, vec![p1]),
+            (3, vec![p1, p2]),
+            (4, vec![p1]),
+            (7, vec![p1]),
+            (5, vec![p1, p2]),
+            (6, vec![]),
+        ]
+        .into_iter()
+        .map(|(id, d)| (id, d.into_iter().collect()))
+        .collect();
+        assert_eq!(deps, expected);
+
+        // a circuit where P2 only receives outputs
+        let circuit: Circuit = "0, P1, mul, P1 | 1, 0, add, P1 & 1 & 0, 1".parse().unwrap();
+        let deps = circuit.output_dependencies();
+        assert_eq!(deps.len(), 2);
+        assert!(deps.values().all(|d| *d == HashSet::from([p1])));
+    }
+
+    #[test]
+    fn subcircuit_evaluates_as_the_whole_circuit() {
+        let circuit: Circuit =
+            "0, P1, mul, P2 | 1, P1, add, P2 | 2, 0, mul, P1 | 3, 1, mul, 2 | 4, P2, addc, 7 | 5, 4, mul, 0 & 3 & 5"
+                .parse()
+                .unwrap();
+        let inputs = Inputs { p1: (vec![10, 11], vec![12]), p2: (vec![13], vec![14, 15]), public: vec![] };
+        let values = circuit.evaluate(&inputs, 101).unwrap();
+
+        // gates 1 and 3 are left out, with the wires of gate 1. gate 2 was not an output, so it is one for both
+        let sub = circuit.subcircuit(&[5, 2, 5]).unwrap();
+        let mut topology = sub.get_topology().to_vec();
+        topology.sort();
+        assert_eq!(topology, [0, 2, 4, 5]);
+        assert_eq!(sub.get_outputs(Party::P1), &[2]);
+        assert_eq!(sub.get_outputs(Party::P2), &[2, 5]);
+        assert_eq!(sub.get_inputs_p1(), &(vec![0], vec![2]));
+        assert_eq!(sub.get_inputs_p2(), &(vec![4], vec![0]));
+
+        // the inputs of the remaining wires give the same values
+        let sub_inputs = Inputs { p1: (vec![10], vec![12]), p2: (vec![13], vec![14]), public: vec![] };
+        let sub_values = sub.evaluate(&sub_inputs, 101).unwrap();
+        for id in [0, 2, 4, 5] {
+            assert_eq!(sub_values[&id], values[&id], "gate {id}");
+        }
+        let outputs_p2 = HashMap::from([(2, values[&2]), (5, values[&5])]);
+        assert_eq!(sub.evaluate_outputs(&sub_inputs, 101, Party::P2).unwrap(), outputs_p2);
+
+        assert!(circuit.subcircuit(&[6]).is_err());
+    }
+
+    // the same circuit added gate by gate, in reverse order so that most inputs are forward references, and parsed
+    #[test]
+    fn incremental_building_matches_parsing() {
+        let gates =
+            ["0, P1, mul, P2", "1, 0, addc, 5", "2, P2, mul, P1", "3, 1, add, 2", "4, P1, mulc, 3", "5, 3, mul, 4"];
+        let parsed: Circuit = format!("{} & 5, 3 & 5, 1", gates.join(" | ")).parse().unwrap();
+
+        let mut built = Circuit::new();
+        for g in gates.iter().rev() {
+            built.add_gate(g.parse().unwrap()).unwrap();
+        }
+        for id in [5, 3] {
+            built.add_output_p1(id).unwrap();
+        }
+        for id in [5, 1] {
+            built.add_output_p2(id).unwrap();
+        }
+
+        assert_eq!(built.get_inputs_p1(), parsed.get_inputs_p1());
+        assert_eq!(built.get_inputs_p2(), parsed.get_inputs_p2());
+        assert_eq!(built.get_topology(), parsed.get_topology());
+        for party in [Party::P1, Party::P2] {
+            assert_eq!(built.get_outputs(party), parsed.get_outputs(party));
+        }
+        for id in 0..6 {
+            assert_eq!(built.gates[&id].to_string(), parsed.gates[&id].to_string());
+            assert_eq!(built.describe_gate(id), parsed.describe_gate(id));
+        }
+
+        let inputs = Inputs { p1: (vec![3, 7], vec![8]), p2: (vec![4], vec![9]), public: vec![] };
+        assert_eq!(built.evaluate(&inputs, 101), parsed.evaluate(&inputs, 101));
+
+        // a taken id and a cycle are refused, and the circuit stays as it was
+        assert!(built.add_gate("3, P1, add, P2".parse().unwrap()).is_err());
+        assert!(built.add_gate("6, 5, add, 7".parse().unwrap()).is_ok());
+        assert!(built.add_gate("7, 6, mul, P1".parse().unwrap()).unwrap_err().contains("cycle"));
+        assert_eq!(built.get_inputs_p1(), parsed.get_inputs_p1());
+        assert!(built.add_output_p1(6).is_err());
+        assert_eq!(built.get_outputs(Party::P1), &[5, 3]);
+    }
+
+    #[test]
+    fn input_arity_counts_each_party_and_slot() {
+        // P1 in both slots of gate 0, P2 in both slots of gate 1, single-input gates in the first slot and no input
+        // wires for linear or public operands
+        let circuit: Circuit = "0, P1, mul, P1 | 1, P2, add, P2 | 2, P1, mul, P2 | 3, P2, addc, 4 | 4, P1, reveal \
+            | 5, P2, mul, pub0 | 6, 0, add, P1 | 7, 1, add, 2 & 0, 3, 4, 5 & 6, 7"
+            .parse()
+            .unwrap();
+        let arity = circuit.input_arity();
+        assert_eq!(arity, InputArity { p1_first: 3, p1_second: 2, p2_first: 3, p2_second: 2 });
+        assert_eq!(arity.total(), 10);
+        assert_eq!(circuit.total_input_wires(), 10);
+        assert_eq!(circuit.input_wires().len(), 10);
+
+        let inputs = Inputs { p1: (vec![1, 2, 3], vec![4, 5]), p2: (vec![6, 7, 8], vec![9, 11]), public: vec![10] };
+        assert_eq!(inputs.arity(), arity);
+        assert!(circuit.check_inputs(&inputs).is_ok());
 
-// concatenate two vectors, removing from the second one the elements which are already present in the first one
-fn simplify(mut v1: Vec<u32>, v2: Vec<u32>) -> Vec<u32> {
-    for e in v2.into_iter() {
-        if !v1.contains(&e) {
-            v1.push(e);
+        // one value too many or too few in any slot is refused, naming the party
+        fn slot(i: &mut Inputs, n: usize) -> &mut Vec<u32> {
+            [&mut i.p1.0, &mut i.p1.1, &mut i.p2.0, &mut i.p2.1].into_iter().nth(n).unwrap()
         }
+        for (n, party) in [(0, "P1"), (1, "P1"), (2, "P2"), (3, "P2")] {
+            let mut more = inputs.clone();
+            slot(&mut more, n).push(0);
+            let mut fewer = inputs.clone();
+            slot(&mut fewer, n).pop();
+            for i in [more, fewer] {
+                let e = circuit.check_inputs(&i).unwrap_err();
+                assert!(e.contains(&format!("provided by {party}")), "{e}");
+            }
+        }
+
+        // swapping the slots of a party is refused as well, although the total is the same
+        let swapped = Inputs { p1: (vec![1, 2], vec![3, 4, 5]), ..inputs };
+        assert_eq!(swapped.arity().total(), 10);
+        assert!(circuit.check_inputs(&swapped).is_err());
+    }
+
+    #[test]
+    fn gates_are_described_by_the_kind_of_each_input() {
+        // each combination of gate output, input wire and public input on two-input gates, then every other kind
+        // of gate. gate 16 refers to a gate which does not exist, but is not needed by any output
+        let circuit: Circuit = "0, P1, mul, P2 | 1, 0, add, P1 | 2, P2, mul, 1 | 3, 1, eq, 2 | 4, pub0, add, 3 \
+            | 5, P1, mul, pub1 | 6, pub1, add, pub0 | 7, 4, addc, -3 | 8, P2, mulc, pub2 | 9, 7, reveal \
+            | 10, 8, mulc, R9 | 11, 10, assert_zero | 12, P1, truncf, 4 | 13, 12, ltz, 8 | dot, 14, 0 1, 2 3 \
+            | linmap, 15, 5 6, 2 -1 | 16, 99, add, P1 & 11, 13, 14, 15 &"
+            .parse()
+            .unwrap();
+
+        let expected = [
+            "[0 | (P1 input wire) × (P2 input wire)]",
+            "[1 | (mul-gate 0) + (P1 input wire)]",
+            "[2 | (P2 input wire) × (add-gate 1)]",
+            "[3 | (add-gate 1) = (mul-gate 2)]",
+            "[4 | (public input 0) + (eq-gate 3)]",
+            "[5 | (P1 input wire) × (public input 1)]",
+            "[6 | (public input 1) + (public input 0)]",
+            "[7 | (add-gate 4) + C(-3)]",
+            "[8 | (P2 input wire) × C(pub2)]",
+            "[9 | reveal (addc-gate 7)]",
+            "[10 | (mulc-gate 8) × C(R9)]",
+            "[11 | (mulc-gate 10) = 0]",
+            "[12 | (P1 input wire) / 2^4]",
+            "[13 | (truncf-gate 12) < 0 (8 bits)]",
+            "[14 | <((mul-gate 0), (add-gate 1)), ((mul-gate 2), (eq-gate 3))>]",
+            "[15 | C(2) × (mul-gate 5) + C(-1) × (add-gate 6)]",
+            "[16 | (missing gate 99) + (P1 input wire)]",
+        ];
+        for (id, description) in expected.iter().enumerate() {
+            assert_eq!(circuit.describe_gate(id as u32), *description);
+        }
+        assert_eq!(circuit.describe_gate(17), "[17 | missing gate]");
+    }
+
+    #[test]
+    fn stray_delimiters_and_blank_fields_are_accepted() {
+        let outputs = |c: &Circuit| (c.get_outputs(Party::P1).clone(), c.get_outputs(Party::P2).clone());
+
+        // empty or blank output lists give a circuit without outputs, and thus without gates to evaluate
+        for encoding in ["0, P1, mul, P2 &&", "0, P1, mul, P2 & & ", "0, P1, mul, P2 &  \t & \n"] {
+            let circuit: Circuit = encoding.parse().unwrap();
+            assert_eq!(outputs(&circuit), (vec![], vec![]), "{encoding:?}");
+            assert!(circuit.get_topology().is_empty());
+        }
+        let circuit: Circuit = "0, P1, mul, P2 & & ".parse().unwrap();
+        let inputs = Inputs { p1: (vec![2], vec![]), p2: (vec![], vec![3]), public: vec![] };
+        let result = crate::protocol::run_beaver_protocol(
+            "0, P1, mul, P2 & & ",
+            101,
+            inputs.clone(),
+            &crate::protocol::ProtocolOptions { seed: Some(1), ..Default::default() },
+        )
+        .unwrap();
+        assert!(result.outputs_p1.is_empty() && result.outputs_p2.is_empty());
+        assert!(circuit.evaluate(&inputs, 101).unwrap().is_empty());
+
+        // one party without outputs, and stray separators around the gates and in the output lists
+        let expected = "0, P1, mul, P2 | 1, 0, add, P1 & 1 &";
+        for encoding in [
+            expected,
+            "0, P1, mul, P2 | 1, 0, add, P1 & 1,&",
+            "| 0, P1, mul, P2 || 1, 0, add, P1 | & ,1 , & ",
+            " |0, P1, mul, P2 |  | 1, 0, add, P1| &\t1\t&\t",
+        ] {
+            let circuit: Circuit = encoding.parse().unwrap();
+            assert_eq!(outputs(&circuit), (vec![1], vec![]), "{encoding:?}");
+            assert_eq!(circuit.get_topology(), [0, 1]);
+        }
+        let circuit: Circuit = "0, P1, mul, P2 | 1, 0, add, P1 && 0, 1,".parse().unwrap();
+        assert_eq!(outputs(&circuit), (vec![], vec![0, 1]));
+
+        // a missing or extra & is reported with the number of fields, and a bad id with the list it is in
+        let e = "0, P1, mul, P2 & 0".parse::<Circuit>().err().unwrap();
+        assert!(e.contains("found 2 fields separated by &"), "{e}");
+        let e = "0, P1, mul, P2 & 0 & 0 &".parse::<Circuit>().err().unwrap();
+        assert!(e.contains("found 4 fields separated by &"), "{e}");
+        let e = "0, P1, mul, P2 & 0 & 0, x".parse::<Circuit>().err().unwrap();
+        assert!(e.starts_with("Invalid list of outputs for P2"), "{e}");
+    }
+
+    #[test]
+    fn input_values_equal_to_other_gate_ids_are_warned_about() {
+        let circuit: Circuit =
+            "10, P1, mul, P2 | 20, P1, add, 10 | 30, P1, mul, 20 | 40, P2, addc, 1 & 30 & 40".parse().unwrap();
+
+        // the ids of gates 20 and 10 pasted in place of the first two inputs of P1
+        let inputs = Inputs { p1: (vec![20, 10, 5], vec![]), p2: (vec![40], vec![30]), public: vec![] };
+        let warnings = circuit.input_id_warnings(&inputs);
+        assert_eq!(warnings.len(), 2, "{warnings:?}");
+        assert_eq!(
+            warnings[0],
+            "Warning: value 20 at position 1 of the first inputs of P1 equals the id of gate 20, which takes the value \
+             at position 2 instead. Is it a gate id? The first inputs of P1 feed gates 10, 20, 30 in this order, so \
+             this value goes to gate 10"
+        );
+        assert!(warnings[1].starts_with("Warning: value 10 at position 2 of the first inputs of P1"));
+
+        // a value equal to the id of the gate it feeds, or of a gate fed by the other slot or party, is fine
+        let inputs = Inputs { p1: (vec![10, 40, 30], vec![]), p2: (vec![20], vec![10]), public: vec![] };
+        assert!(circuit.input_id_warnings(&inputs).is_empty());
+    }
+
+    #[test]
+    fn signed_and_unsigned_outputs_are_formatted_apart() {
+        // 7 is marked as signed in the list of P1 only, but is displayed as such for both parties
+        let circuit: Circuit = "3, P1, add, P2 | 7, 3, mulc, -1 & 3, -7 & 7, 3".parse().unwrap();
+        assert_eq!(circuit.get_outputs(Party::P1), &[3, 7]);
+        assert_eq!(circuit.get_outputs(Party::P2), &[7, 3]);
+        assert!(!circuit.is_signed_output(3) && circuit.is_signed_output(7));
+
+        let inputs = Inputs { p1: (vec![2], vec![]), p2: (vec![], vec![5]), public: vec![] };
+        let values = circuit.evaluate(&inputs, 101).unwrap();
+        assert_eq!((values[&3], values[&7]), (7, 94));
+        assert_eq!(circuit.format_output(3, values[&3], 101), "7");
+        assert_eq!(circuit.format_output(7, values[&7], 101), "-7");
+
+        // the same residues the other way round: unsigned outputs stay residues, and signed ones are centred in
+        // (-q / 2, q / 2]
+        for (v, unsigned, signed) in [(0, "0", "0"), (50, "50", "50"), (51, "51", "-50"), (100, "100", "-1")] {
+            assert_eq!(circuit.format_output(3, v, 101), unsigned);
+            assert_eq!(circuit.format_output(7, v, 101), signed);
+        }
+        assert!(circuit.to_string().contains("Outputs for P1: 3, -7\n"));
+
+        // a range of signed ids, and one mixing signed and unsigned ones
+        let circuit: Circuit = "1, P1, add, P2 | 2, 1, add, 1 | 3, 2, add, 1 & -3..=-1 &".parse().unwrap();
+        assert!((1..=3).all(|id| circuit.is_signed_output(id)));
+        let e = "1, P1, add, P2 | 2, 1, add, 1 & -1..=2 &".parse::<Circuit>().err().unwrap();
+        assert!(e.contains("mixes signed and unsigned output ids"), "{e}");
+    }
+
+    // q - 1 written in hexadecimal, which subtracts 1 as -1 and -0b1 do
+    #[test]
+    fn hexadecimal_constant_equal_to_q_minus_1() {
+        let q = 2147483647;
+        let circuit: Circuit =
+            "0, P1, addc, 0x7FFFFFFE | 1, P1, addc, -1 | 2, P1, addc, -0b1 | 3, P2, mulc, 0x7ffffffe & 0, 1, 2 & 3"
+                .parse()
+                .unwrap();
+        assert_eq!(circuit.describe_gate(0), "[0 | (P1 input wire) + C(2147483646)]");
+
+        let inputs = Inputs { p1: (vec![10, 10, 10], vec![]), p2: (vec![7], vec![]), public: vec![] };
+        let values = circuit.evaluate(&inputs, q).unwrap();
+        assert_eq!([values[&0], values[&1], values[&2]], [9, 9, 9]);
+        assert_eq!(values[&3], q - 7);
+
+        // the constants are i32, so q - 1 is out of range for larger moduli
+        let e = "0, P1, addc, 0xfffffffe & 0 &".parse::<Circuit>().err().unwrap();
+        assert!(e.contains("out of range"), "{e}");
+        let e = "0, P1, addc, 0x7g & 0 &".parse::<Circuit>().err().unwrap();
+        assert!(e.contains("invalid digit for base 16"), "{e}");
     }
-    v1
 }
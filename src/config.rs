@@ -0,0 +1,349 @@
+// Run configuration files: a single TOML or JSON file describing a run, resolved into the parameters of
+// run_beaver_protocol. A TOML configuration looks like:
+//
+//     circuit_path = "example_inputs/example_1.txt"
+//     q = 7
+//
+//     [inputs]
+//     p1_first = [33]
+//     p2_first = [-5, 4]
+//
+//     [options]
+//     authenticated = true
+//     master_seed = "<64 hexadecimal characters>"
+//     corruption = { party = "p1", degree = 0.2 }
+//     output_path = "logs/run"
+//
+// The circuit is given either as a circuit file (circuit_path) or inline (circuit). Each party's inputs are given
+// either as its input vectors or as a file (p1_file, p2_file) with its first and second inputs on its first two
+// lines. Missing input vectors are empty and missing options take their default values. JSON configurations, told
+// apart by their .json extension, have the same structure.
+
+use std::{fs, time::Duration};
+
+use serde::Deserialize;
+
+use crate::circuit::{self, Inputs, Party};
+use crate::error::BeaverError;
+use crate::protocol::{CheckpointConfig, CorruptionConfig, LogLevel, ProtocolOptions, CORRUPTION_DEGREE};
+use crate::utilities;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RunConfig {
+    pub circuit: Option<String>,
+    pub circuit_path: Option<String>,
+    pub q: u32,
+    #[serde(default)]
+    pub inputs: InputsConfig,
+    #[serde(default)]
+    pub options: OptionsConfig,
+}
+
+// values as in the input vector lines, i.e. integers reduced modulo q
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct InputsConfig {
+    pub p1_first: Vec<i32>,
+    pub p1_second: Vec<i32>,
+    pub p2_first: Vec<i32>,
+    pub p2_second: Vec<i32>,
+    pub p1_file: Option<String>,
+    pub p2_file: Option<String>,
+    pub public: Vec<i32>,
+}
+
+// the options of ProtocolOptions which make sense in a file, under the same names. those only meant for testing
+// the protocol (e.g. crash_at) or set up by code (e.g. trace) are left out
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct OptionsConfig {
+    pub authenticated: bool,
+    pub corruption: Option<CorruptionFileConfig>,
+    pub seed: Option<u64>,
+    pub master_seed: Option<String>,
+    pub output_path: Option<String>,
+    pub party_names: Option<(String, String)>,
+    // off, normal or verbose
+    pub log_level: Option<String>,
+    // as log_level, for the dealers only; if unset, log_level applies to them too
+    pub dealer_log_level: Option<String>,
+    pub checkpoint: Option<CheckpointFileConfig>,
+    pub coalesce: bool,
+    pub channel_capacity: Option<usize>,
+    pub blind_outputs: bool,
+    pub latency_ms: Option<u64>,
+    pub dealers: usize,
+    pub triple_audit: Option<f32>,
+    pub no_dealer_triples: bool,
+    pub fold_constants: bool,
+    pub reduce_inputs: bool,
+    pub record_views: bool,
+    pub insecure_audit: bool,
+    pub log_flush_lines: Option<usize>,
+    pub explain_aborts: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CorruptionFileConfig {
+    // p1 or p2; if unset, the corrupt party is chosen at random
+    pub party: Option<String>,
+    pub degree: Option<f32>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CheckpointFileConfig {
+    pub path: String,
+    pub interval: usize,
+}
+
+// the arguments of run_beaver_protocol
+pub struct RunParameters {
+    pub circuit_encoding: String,
+    pub q: u32,
+    pub inputs: Inputs,
+    pub options: ProtocolOptions,
+}
+
+impl RunConfig {
+    // TOML, or JSON if the extension of the path is .json. TOML errors quote the offending line, while JSON ones only
+    // give its position, hence the path of the field
+    pub fn load(path: &str) -> Result<RunConfig, BeaverError> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| BeaverError::Io(format!("Error opening run configuration {path}: {e}")))?;
+
+        if path.to_lowercase().ends_with(".json") {
+            serde_path_to_error::deserialize(&mut serde_json::Deserializer::from_str(&contents)).map_err(|e| {
+                BeaverError::Parse(format!("Error in run configuration {path}: {}: {}", e.path(), e.inner()))
+            })
+        } else {
+            toml::from_str(&contents).map_err(|e| BeaverError::Parse(format!("Error in run configuration {path}: {e}")))
+        }
+    }
+
+    // reads the circuit and input files and checks the values serde cannot, naming the offending field
+    pub fn resolve(self) -> Result<RunParameters, BeaverError> {
+        let invalid = |field: &str, e: &str| BeaverError::Parse(format!("Error in run configuration: {field}: {e}"));
+
+        let circuit_encoding = match (self.circuit, self.circuit_path) {
+            (Some(c), None) => c,
+            (None, Some(path)) => {
+                let contents = fs::read_to_string(&path)
+                    .map_err(|e| invalid("circuit_path", &format!("cannot open {path}: {e}")))?;
+                // as circuit files, up to the first empty line after the encoding
+                let lines = &mut contents.lines().map(|l| Ok::<String, ()>(l.to_string()));
+                circuit::read_encoding(lines).unwrap().0
+            }
+            _ => return Err(invalid("circuit", "exactly one of circuit and circuit_path should be given")),
+        };
+
+        let q = self.q;
+        if q < 2 {
+            return Err(invalid("q", &format!("the modulus should be at least 2, not {q}")));
+        }
+
+        let reduce = |v: &[i32]| -> Vec<u32> { v.iter().map(|v| utilities::modulo(*v, q)).collect() };
+
+        let party_inputs = |party: &str, file: &Option<String>, first: &[i32], second: &[i32]| {
+            let field = format!("inputs.{party}_file");
+            match file {
+                None => Ok((reduce(first), reduce(second))),
+                Some(_) if !first.is_empty() || !second.is_empty() => {
+                    Err(invalid(&field, &format!("cannot be combined with {party}_first and {party}_second")))
+                }
+                Some(path) => {
+                    let contents =
+                        fs::read_to_string(path).map_err(|e| invalid(&field, &format!("cannot open {path}: {e}")))?;
+                    let mut lines = contents.lines();
+                    let mut line = || {
+                        utilities::str_i32_to_vec_u32(lines.next().unwrap_or(""), q)
+                            .map_err(|e| invalid(&field, &format!("{path}: {e}")))
+                    };
+                    Ok((line()?, line()?))
+                }
+            }
+        };
+
+        let i = &self.inputs;
+        let inputs = Inputs {
+            p1: party_inputs("p1", &i.p1_file, &i.p1_first, &i.p1_second)?,
+            p2: party_inputs("p2", &i.p2_file, &i.p2_first, &i.p2_second)?,
+            public: reduce(&i.public),
+        };
+
+        let o = self.options;
+
+        let corruption = match o.corruption {
+            None => None,
+            Some(c) => Some(CorruptionConfig {
+                party: match c.party.as_deref().map(str::to_lowercase).as_deref() {
+                    None => None,
+                    Some("p1") => Some(Party::P1),
+                    Some("p2") => Some(Party::P2),
+                    Some(p) => return Err(invalid("options.corruption.party", &format!("should be p1 or p2, not {p}"))),
+                },
+                degree: match c.degree {
+                    None => CORRUPTION_DEGREE,
+                    Some(d) if (0.0..=1.0).contains(&d) => d,
+                    Some(d) => return Err(invalid("options.corruption.degree", &format!("should be in [0, 1], not {d}"))),
+                },
+            }),
+        };
+
+        let master_seed = match o.master_seed {
+            None => None,
+            Some(s) => Some(utilities::seed_from_hex(&s).map_err(|e| invalid("options.master_seed", &e))?),
+        };
+
+        let log_level = match o.log_level {
+            None => LogLevel::Normal,
+            Some(l) => l.parse().map_err(|e: String| invalid("options.log_level", &e))?,
+        };
+
+        let dealer_log_level = match o.dealer_log_level {
+            None => None,
+            Some(l) => Some(l.parse().map_err(|e: String| invalid("options.dealer_log_level", &e))?),
+        };
+
+        if o.checkpoint.as_ref().is_some_and(|c| c.interval == 0) {
+            return Err(invalid("options.checkpoint.interval", "should be at least 1"));
+        }
+
+        if let Some(a) = o.triple_audit.filter(|a| *a <= 0.0 || *a >= 1.0) {
+            return Err(invalid("options.triple_audit", &format!("should be in (0, 1), not {a}")));
+        }
+
+        Ok(RunParameters {
+            circuit_encoding,
+            q,
+            inputs,
+            options: ProtocolOptions {
+                authenticated: o.authenticated,
+                corruption,
+                seed: o.seed,
+                master_seed,
+                output_path: o.output_path,
+                party_names: o.party_names,
+                log_level,
+                dealer_log_level,
+                checkpoint: o.checkpoint.map(|c| CheckpointConfig { path: c.path, interval: c.interval }),
+                coalesce: o.coalesce,
+                channel_capacity: o.channel_capacity,
+                blind_outputs: o.blind_outputs,
+                latency: o.latency_ms.map(Duration::from_millis),
+                dealers: o.dealers,
+                triple_audit: o.triple_audit,
+                no_dealer_triples: o.no_dealer_triples,
+                fold_constants: o.fold_constants,
+                reduce_inputs: o.reduce_inputs,
+                record_views: o.record_views,
+                insecure_audit: o.insecure_audit,
+                log_flush_lines: o.log_flush_lines,
+                explain_aborts: o.explain_aborts,
+                ..Default::default()
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::Circuit;
+    use crate::protocol::run_beaver_protocol;
+
+    const CONFIGS: &str = "test_vectors/configs";
+
+    fn resolve(name: &str) -> Result<RunParameters, BeaverError> {
+        RunConfig::load(&format!("{CONFIGS}/{name}"))?.resolve()
+    }
+
+    fn resolve_str(toml: &str) -> Result<RunParameters, BeaverError> {
+        toml::from_str::<RunConfig>(toml).unwrap().resolve()
+    }
+
+    fn parse_error(r: Result<RunParameters, BeaverError>) -> String {
+        match r {
+            Err(BeaverError::Parse(e)) => e,
+            Err(e) => panic!("expected a parse error, got {e}"),
+            Ok(_) => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn fixtures_resolve_to_their_input_files() {
+        let params = resolve("example_1.toml").unwrap();
+        let contents = fs::read_to_string("example_inputs/example_1.txt").unwrap();
+        let lines = &mut contents.lines().map(|l| Ok::<String, ()>(l.to_string()));
+        assert_eq!(params.circuit_encoding, circuit::read_encoding(lines).unwrap().0);
+        assert_eq!(params.q, 7);
+        assert_eq!((params.inputs.p1, params.inputs.p2), ((vec![5], vec![]), (vec![2, 4], vec![])));
+        assert!(!params.options.authenticated && params.options.corruption.is_none());
+        assert_eq!(params.options.master_seed, Some([0xab; 32]));
+        assert_eq!(params.options.output_path.as_deref(), Some("/tmp/beaver_config_example_1"));
+        assert!(matches!(params.options.log_level, LogLevel::Normal));
+
+        // JSON, with a corrupt party
+        let params = resolve("example_4_auth_corrupt.json").unwrap();
+        assert_eq!(params.q, 31);
+        assert_eq!(params.inputs.p1, (vec![3, 30], vec![2, 4]));
+        assert!(params.options.authenticated);
+        let corruption = params.options.corruption.unwrap();
+        assert_eq!((corruption.party, corruption.degree), (Some(Party::P1), 0.2));
+
+        // inputs from files, which run as the combined input file
+        let params = resolve("example_4_input_files.toml").unwrap();
+        assert_eq!(params.inputs.p1, (vec![3, 30], vec![2, 4]));
+        assert_eq!(params.inputs.p2, (vec![1, 5], vec![2, 3]));
+        assert_eq!(params.options.seed, Some(3));
+        assert!(matches!(params.options.log_level, LogLevel::Off));
+        let circuit: Circuit = params.circuit_encoding.parse().unwrap();
+        let values = circuit.evaluate(&params.inputs, params.q).unwrap();
+        let result = run_beaver_protocol(&params.circuit_encoding, params.q, params.inputs, &params.options).unwrap();
+        assert_eq!(result.outputs_p1, [(1, values[&1]), (3, values[&3])].into());
+        assert_eq!(result.outputs_p2, [(5, values[&5]), (3, values[&3])].into());
+
+        // an inline circuit with public inputs
+        let params = resolve("public_scale.toml").unwrap();
+        assert_eq!(params.inputs.public, [12, 98]);
+        let result = run_beaver_protocol(&params.circuit_encoding, params.q, params.inputs, &params.options).unwrap();
+        assert_eq!(result.outputs_p1[&1], 81);
+    }
+
+    #[test]
+    fn invalid_configurations_name_the_offending_field() {
+        let e = parse_error(resolve("invalid_corrupt_party.toml"));
+        assert_eq!(e, "Error in run configuration: options.corruption.party: should be p1 or p2, not p3");
+        // JSON errors give the path of the field, TOML ones quote the line
+        let e = parse_error(resolve("invalid_unknown_option.json"));
+        let expected = "Error in run configuration test_vectors/configs/invalid_unknown_option.json: \
+                        options.blind_output: unknown field `blind_output`";
+        assert!(e.starts_with(expected), "{e}");
+        let e = RunConfig::load(&format!("{CONFIGS}/missing.toml")).err().unwrap();
+        assert!(matches!(e, BeaverError::Io(_)), "{e}");
+        assert!(toml::from_str::<RunConfig>("circuit = \"0, P1, add, P2 & 0 & 0\"").is_err());
+
+        let circuit = "circuit = \"0, P1, add, P2 & 0 & 0\"";
+        let options = format!("{circuit}\nq = 101\n[options]");
+        let cases = [
+            (String::from("q = 101"), "circuit: exactly one of circuit and circuit_path should be given"),
+            (format!("{circuit}\ncircuit_path = \"c.txt\"\nq = 101"), "circuit: exactly one"),
+            (String::from("circuit_path = \"missing.txt\"\nq = 101"), "circuit_path: cannot open missing.txt"),
+            (format!("{circuit}\nq = 1"), "q: the modulus should be at least 2, not 1"),
+            (format!("{circuit}\nq = 101\n[inputs]\np1_file = \"f\"\np1_first = [1]"), "inputs.p1_file: cannot be"),
+            (format!("{circuit}\nq = 101\n[inputs]\np2_file = \"missing.txt\""), "inputs.p2_file: cannot open"),
+            (format!("{options}\ncorruption = {{ degree = 1.5 }}"), "options.corruption.degree"),
+            (format!("{options}\nmaster_seed = \"ab\""), "options.master_seed"),
+            (format!("{options}\nlog_level = \"loud\""), "options.log_level"),
+            (format!("{options}\ndealer_log_level = \"loud\""), "options.dealer_log_level"),
+            (format!("{options}\ncheckpoint = {{ path = \"c\", interval = 0 }}"), "options.checkpoint.interval"),
+            (format!("{options}\ntriple_audit = 1.0"), "options.triple_audit"),
+        ];
+        for (toml, expected) in cases {
+            let e = parse_error(resolve_str(&toml));
+            assert!(e.starts_with(&format!("Error in run configuration: {expected}")), "{toml}: {e}");
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use std::{error::Error, fmt, io};
+
+use crate::circuit::Party;
+use crate::protocol::Phase;
+
+#[derive(Debug, Clone)]
+pub enum BeaverError {
+    // invalid command-line arguments
+    Usage(String),
+    // malformed circuit encoding or input data
+    Parse(String),
+    // well-formed input data which does not match the circuit
+    Input(String),
+    // an input value which is not below q, given as the party providing it, whether it is one of its first or
+    // second inputs and its position in that list
+    InputRange {
+        party: Party,
+        first: bool,
+        position: usize,
+        value: u32,
+        q: u32,
+    },
+    // failure to write an execution log
+    Io(String),
+    // an unexpected message (or none at all) was received from the other party or the dealer
+    Protocol(String),
+    // a received opening did not pass the MAC check of the receiving party, which was processing the given gate
+    // (if any) in the given phase. the explanation is only set by runs which explain aborts
+    Authentication {
+        party: Party,
+        sharing: String,
+        phase: Phase,
+        gate: Option<u32>,
+        explanation: Option<String>,
+    },
+    // a party stopped executing the protocol because of the wrapped error
+    Aborted(Box<BeaverError>),
+    // errors reported by the dealer and/or parties of a run, each labelled with its source
+    Run(Vec<(String, BeaverError)>),
+}
+
+impl BeaverError {
+    // whether the error (or any of the errors it wraps) is due to a failed MAC check
+    pub fn is_authentication_failure(&self) -> bool {
+        match self {
+            BeaverError::Authentication { .. } => true,
+            BeaverError::Aborted(e) => e.is_authentication_failure(),
+            BeaverError::Run(errors) => errors.iter().any(|(_, e)| e.is_authentication_failure()),
+            _ => false,
+        }
+    }
+    // party, phase and gate of the first failed MAC check found in the error, if any
+    pub fn authentication_context(&self) -> Option<(Party, Phase, Option<u32>)> {
+        match self {
+            BeaverError::Authentication { party, phase, gate, .. } => Some((*party, *phase, *gate)),
+            BeaverError::Aborted(e) => e.authentication_context(),
+            BeaverError::Run(errors) => errors.iter().find_map(|(_, e)| e.authentication_context()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for BeaverError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BeaverError::Usage(s)
+            | BeaverError::Parse(s)
+            | BeaverError::Input(s)
+            | BeaverError::Io(s)
+            | BeaverError::Protocol(s) => write!(f, "{s}"),
+            BeaverError::InputRange { party, first, position, value, q } => write!(
+                f,
+                "Error: input value {value} at position {position} of the {} inputs of {party} is not below q = {q}",
+                if *first { "first" } else { "second" }
+            ),
+            BeaverError::Authentication { sharing, phase, gate, explanation, .. } => {
+                write!(f, "Authentication failed for sharing {sharing} during {phase}")?;
+                if let Some(id) = gate {
+                    write!(f, " of gate {id}")?;
+                }
+                match explanation {
+                    Some(e) => write!(f, " (post-mortem: {e})"),
+                    None => Ok(()),
+                }
+            }
+            BeaverError::Aborted(e) => write!(f, "{e}. Aborting."),
+            BeaverError::Run(errors) => write!(
+                f,
+                "{}",
+                errors
+                    .iter()
+                    .map(|(source, e)| format!("{source}: {e}"))
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            ),
+        }
+    }
+}
+
+impl Error for BeaverError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BeaverError::Aborted(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for BeaverError {
+    fn from(e: io::Error) -> Self {
+        BeaverError::Io(format!("Error reading input file: {e}"))
+    }
+}
+
+// a malformed entry of a list of numbers, such as a line of inputs, given with the whole line, the (1-based)
+// position where the entry starts and the entry itself
+#[derive(Debug, PartialEq)]
+pub struct ParseVecError {
+    pub line: String,
+    pub position: usize,
+    pub token: String,
+    pub kind: ParseVecErrorKind,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ParseVecErrorKind {
+    // not a number in the given base (10 for reals)
+    NotANumber(u32),
+    // a number outside the interval described by the string
+    OutOfRange(String),
+    // a value, repetition count or range bound without any digits, e.g. 0x or the count in 1*
+    EmptyToken,
+    // a range whose last value precedes its first
+    EmptyRange,
+    // a repetition or range which would take the list past the given number of values
+    TooLong(usize),
+}
+
+impl fmt::Display for ParseVecErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseVecErrorKind::NotANumber(10) => write!(f, "not a number"),
+            ParseVecErrorKind::NotANumber(radix) => write!(f, "invalid digit for base {radix}"),
+            ParseVecErrorKind::OutOfRange(range) => write!(f, "out of range {range}"),
+            ParseVecErrorKind::EmptyToken => write!(f, "missing digits"),
+            ParseVecErrorKind::EmptyRange => write!(f, "empty range"),
+            ParseVecErrorKind::TooLong(max) => write!(f, "expands the list past the limit of {max} values"),
+        }
+    }
+}
+
+// at most this many characters of the line are shown on either side of the offending entry
+const CONTEXT_CHARS: usize = 30;
+
+// the reason, followed by (a window of) the line with a caret under the entry, e.g.
+//     entry "x" at position 4: not a number
+//     1, x, 2
+//        ^
+impl fmt::Display for ParseVecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "entry \"{}\" at position {}: {}", self.token, self.position, self.kind)?;
+
+        let chars: Vec<char> = self.line.chars().collect();
+        let start = self.position.saturating_sub(1 + CONTEXT_CHARS);
+        let end = chars.len().min(self.position - 1 + self.token.chars().count() + CONTEXT_CHARS);
+
+        let prefix = if start > 0 { "..." } else { "" };
+        let suffix = if end < chars.len() { "..." } else { "" };
+        let window: String = chars[start..end].iter().collect();
+
+        writeln!(f, "{prefix}{window}{suffix}")?;
+        write!(f, "{}^", " ".repeat(prefix.len() + self.position - 1 - start))
+    }
+}
+
+impl Error for ParseVecError {}
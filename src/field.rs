@@ -0,0 +1,368 @@
+// Modular arithmetic behind a context holding the modulus, so that supporting a new element width only takes one
+// more implementation of FieldOps. F32 backs all current computations, with u64 intermediates; F64 uses u128
+// ones and is meant for moduli which do not fit in a u32. Reducer computes the same as F32 without divisions,
+// for hot loops over a single modulus. ModField is F32 under the name the sharings take it by, so that the modulus
+// travels with its arithmetic instead of as a bare u32 next to the values. Despite the name, the modulus need not
+// be prime (see inv).
+
+use rand_chacha::ChaCha20Rng;
+
+use crate::utilities;
+
+pub trait FieldOps {
+    type Elem: Copy + Eq;
+
+    fn modulus(&self) -> Self::Elem;
+    // the residue of v
+    fn reduce_u64(&self, v: u64) -> Self::Elem;
+    fn add(&self, a: Self::Elem, b: Self::Elem) -> Self::Elem;
+    fn sub(&self, a: Self::Elem, b: Self::Elem) -> Self::Elem;
+    fn mul(&self, a: Self::Elem, b: Self::Elem) -> Self::Elem;
+    // None if a is not coprime with the modulus
+    fn inv(&self, a: Self::Elem) -> Option<Self::Elem>;
+    // uniformly random
+    fn random(&self, rng: &mut ChaCha20Rng) -> Self::Elem;
+
+    fn neg(&self, a: Self::Elem) -> Self::Elem {
+        self.sub(self.reduce_u64(0), a)
+    }
+    fn pow(&self, a: Self::Elem, mut exp: u64) -> Self::Elem {
+        let (mut base, mut result) = (a, self.reduce_u64(1));
+        while exp > 0 {
+            if exp & 1 == 1 {
+                result = self.mul(result, base);
+            }
+            base = self.mul(base, base);
+            exp >>= 1;
+        }
+        result
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct F32 {
+    pub q: u32,
+}
+
+pub type ModField = F32;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct F64 {
+    pub q: u64,
+}
+
+impl F32 {
+    pub fn new(q: u32) -> Self {
+        F32 { q }
+    }
+}
+
+impl F64 {
+    pub fn new(q: u64) -> Self {
+        F64 { q }
+    }
+}
+
+// the operands may be unreduced, as long as they fit in the element type
+impl FieldOps for F32 {
+    type Elem = u32;
+
+    fn modulus(&self) -> u32 {
+        self.q
+    }
+    fn reduce_u64(&self, v: u64) -> u32 {
+        (v % self.q as u64) as u32
+    }
+    fn add(&self, a: u32, b: u32) -> u32 {
+        ((a as u64 + b as u64) % self.q as u64) as u32
+    }
+    // unsigned arithmetic, which is cheaper than a signed remainder
+    fn sub(&self, a: u32, b: u32) -> u32 {
+        ((a as u64 + (self.q - b % self.q) as u64) % self.q as u64) as u32
+    }
+    fn mul(&self, a: u32, b: u32) -> u32 {
+        ((a as u64 * b as u64) % self.q as u64) as u32
+    }
+    fn inv(&self, a: u32) -> Option<u32> {
+        match utilities::ext_gcd(a as u64 % self.q as u64, self.q as u64) {
+            (1, x, _) => Some(x.rem_euclid(self.q as i128) as u32),
+            _ => None,
+        }
+    }
+    fn random(&self, rng: &mut ChaCha20Rng) -> u32 {
+        utilities::gen_below(rng, self.q as u64) as u32
+    }
+}
+
+impl FieldOps for F64 {
+    type Elem = u64;
+
+    fn modulus(&self) -> u64 {
+        self.q
+    }
+    fn reduce_u64(&self, v: u64) -> u64 {
+        v % self.q
+    }
+    fn add(&self, a: u64, b: u64) -> u64 {
+        ((a as u128 + b as u128) % self.q as u128) as u64
+    }
+    fn sub(&self, a: u64, b: u64) -> u64 {
+        ((a as u128 + (self.q - b % self.q) as u128) % self.q as u128) as u64
+    }
+    fn mul(&self, a: u64, b: u64) -> u64 {
+        ((a as u128 * b as u128) % self.q as u128) as u64
+    }
+    fn inv(&self, a: u64) -> Option<u64> {
+        match utilities::ext_gcd(a % self.q, self.q) {
+            (1, x, _) => Some(x.rem_euclid(self.q as i128) as u64),
+            _ => None,
+        }
+    }
+    fn random(&self, rng: &mut ChaCha20Rng) -> u64 {
+        utilities::gen_below(rng, self.q)
+    }
+}
+
+// Barrett reduction: with m = floor(2^64 / q) precomputed (2^64 - 1 for q = 1), the quotient of any u64 x by q
+// is estimated as floor(x * m / 2^64), which falls short by at most 1. a multiplication and one conditional
+// subtraction thus replace the division, at the cost of a 128-bit division when the context is created
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Reducer {
+    q: u32,
+    m: u64,
+}
+
+impl Reducer {
+    pub fn new(q: u32) -> Self {
+        Reducer {
+            q,
+            m: ((1u128 << 64) / q as u128).min(u64::MAX as u128) as u64,
+        }
+    }
+    // x mod q for any x
+    pub fn reduce(&self, x: u64) -> u32 {
+        let quotient = ((x as u128 * self.m as u128) >> 64) as u64;
+        let r = x - quotient * self.q as u64;
+        if r >= self.q as u64 {
+            (r - self.q as u64) as u32
+        } else {
+            r as u32
+        }
+    }
+}
+
+// matches F32 bit for bit, including on unreduced operands
+impl FieldOps for Reducer {
+    type Elem = u32;
+
+    fn modulus(&self) -> u32 {
+        self.q
+    }
+    fn reduce_u64(&self, v: u64) -> u32 {
+        self.reduce(v)
+    }
+    fn add(&self, a: u32, b: u32) -> u32 {
+        self.reduce(a as u64 + b as u64)
+    }
+    fn sub(&self, a: u32, b: u32) -> u32 {
+        self.reduce(a as u64 + (self.q - self.reduce(b as u64)) as u64)
+    }
+    fn mul(&self, a: u32, b: u32) -> u32 {
+        self.reduce(a as u64 * b as u64)
+    }
+    fn inv(&self, a: u32) -> Option<u32> {
+        F32::new(self.q).inv(a)
+    }
+    fn random(&self, rng: &mut ChaCha20Rng) -> u32 {
+        utilities::gen_below(rng, self.q as u64) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+    use rand_chacha::rand_core::SeedableRng;
+
+    use super::*;
+    use crate::utilities::*;
+
+    // F32 against the same operations computed in u128, on edge and random operands (reduced or not), and the
+    // deprecated free functions against F32 until they are removed
+    #[test]
+    #[allow(deprecated)]
+    fn f32_matches_wide_arithmetic() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(1);
+        let random_q = (0..8).map(|_| rng.gen_range(2..=u32::MAX)).collect::<Vec<u32>>();
+
+        for q in [2, 3, 7, 101, 2147483647, u32::MAX - 4, u32::MAX].into_iter().chain(random_q) {
+            let f = ModField::new(q);
+            let wide = q as u128;
+            let edges = [0, 1, q / 2, q - 1, q, u32::MAX];
+            let pairs = edges
+                .iter()
+                .flat_map(|a| edges.iter().map(move |b| (*a, *b)))
+                .chain((0..100).map(|_| (rng.gen(), rng.gen())));
+
+            for (a, b) in pairs {
+                let (wa, wb) = (a as u128, b as u128);
+                assert_eq!(f.add(a, b) as u128, (wa + wb) % wide, "{a} + {b} mod {q}");
+                assert_eq!(f.sub(a, b) as u128, (wa % wide + wide - wb % wide) % wide, "{a} - {b} mod {q}");
+                assert_eq!(f.mul(a, b) as u128, wa * wb % wide, "{a} * {b} mod {q}");
+                assert_eq!(f.add(f.neg(a), a), 0, "-{a} + {a} mod {q}");
+
+                assert_eq!(f.add(a, b), add_without_overflow(a, b, q));
+                assert_eq!(f.sub(a, b), subtract_without_overflow(a, b, q));
+                assert_eq!(f.mul(a, b), mul_without_overflow(a, b, q));
+            }
+
+            // the same draws from the same RNG state
+            let seed = rng.gen();
+            let (r1, r2) = (&mut ChaCha20Rng::seed_from_u64(seed), &mut ChaCha20Rng::seed_from_u64(seed));
+            for _ in 0..100 {
+                let r = f.random(r1);
+                assert_eq!(r, safe_gen_mod(r2, q));
+                assert!(r < q);
+            }
+        }
+    }
+
+    // as above, for moduli up to 2^64 - 1, whose products only fit in the u128 F64 computes in
+    #[test]
+    fn f64_matches_wide_arithmetic() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(2);
+        let random_q = (0..8).map(|_| rng.gen_range(2..=u64::MAX)).collect::<Vec<u64>>();
+
+        // 2^61 - 1 and 2^64 - 59 are prime
+        for q in [2, 3, 101, 1 << 32, (1 << 61) - 1, u64::MAX - 58, u64::MAX].into_iter().chain(random_q) {
+            let f = F64::new(q);
+            let wide = q as u128;
+            let edges = [0, 1, q / 2, q - 1, q, u64::MAX];
+            let pairs = edges
+                .iter()
+                .flat_map(|a| edges.iter().map(move |b| (*a, *b)))
+                .chain((0..100).map(|_| (rng.gen(), rng.gen())));
+
+            for (a, b) in pairs {
+                let (wa, wb) = (a as u128, b as u128);
+                assert_eq!(f.reduce_u64(a) as u128, wa % wide);
+                assert_eq!(f.add(a, b) as u128, (wa + wb) % wide, "{a} + {b} mod {q}");
+                assert_eq!(f.sub(a, b) as u128, (wa % wide + wide - wb % wide) % wide, "{a} - {b} mod {q}");
+                assert_eq!(f.mul(a, b) as u128, wa * wb % wide, "{a} * {b} mod {q}");
+                assert_eq!(f.add(f.neg(a), a), 0, "-{a} + {a} mod {q}");
+            }
+
+            for _ in 0..100 {
+                assert!(f.random(rng) < q);
+            }
+        }
+    }
+
+    // a * inv(a) = 1 whenever a is coprime with the modulus, and None otherwise, for both widths
+    #[test]
+    fn inverses_exist_for_units_only() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(3);
+
+        for q in [2u32, 7, 101, 2147483647, 4294967291] {
+            let f = F32::new(q);
+            assert_eq!(f.inv(0), None);
+            assert_eq!(f.inv(q), None);
+            for a in [1, 2, q - 1, q + 1, u32::MAX].into_iter().chain((0..100).map(|_| rng.gen_range(1..q))) {
+                match a % q {
+                    0 => assert_eq!(f.inv(a), None),
+                    _ => assert_eq!(f.mul(a, f.inv(a).unwrap()), 1, "{a} mod {q}"),
+                }
+            }
+        }
+
+        for q in [(1u64 << 61) - 1, u64::MAX - 58] {
+            let f = F64::new(q);
+            assert_eq!(f.inv(0), None);
+            for a in (0..100).map(|_| rng.gen_range(1..q)) {
+                assert_eq!(f.mul(a, f.inv(a).unwrap()), 1, "{a} mod {q}");
+            }
+        }
+
+        // in Z_12 exactly the residues coprime with 12 are invertible, 7 being its own inverse
+        let f = F32::new(12);
+        let units: Vec<u32> = (0..12).filter(|a| f.inv(*a).is_some()).collect();
+        assert_eq!(units, [1, 5, 7, 11]);
+        assert_eq!(f.inv(7), Some(7));
+        assert_eq!(F64::new(12).inv(8), None);
+    }
+
+    // square and multiply against repeated multiplication, and Fermat's little theorem for large exponents
+    #[test]
+    fn pow_matches_repeated_multiplication() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(4);
+
+        for q in [2u32, 12, 101, 4294967291, u32::MAX] {
+            let f = F32::new(q);
+            for a in [0, 1, q - 1, u32::MAX].into_iter().chain((0..20).map(|_| rng.gen())) {
+                let mut expected = 1 % q;
+                for exp in 0..40 {
+                    assert_eq!(f.pow(a, exp), expected, "{a}^{exp} mod {q}");
+                    expected = f.mul(expected, a);
+                }
+            }
+        }
+
+        // a^(p - 1) = 1 and a^p = a for a prime p and a not divisible by it
+        let p = 4294967291;
+        let f = F32::new(p);
+        for a in (0..100).map(|_| rng.gen_range(1..p)) {
+            assert_eq!(f.pow(a, p as u64 - 1), 1);
+            assert_eq!(f.pow(a, p as u64), a);
+        }
+
+        let p = u64::MAX - 58;
+        let f = F64::new(p);
+        for a in (0..100).map(|_| rng.gen_range(1..p)) {
+            assert_eq!(f.pow(a, p - 1), 1);
+            assert_eq!(f.mul(f.pow(a, p - 2), a), 1);
+        }
+        assert_eq!(F64::new(1).pow(5, 0), 0);
+    }
+
+    // Reducer against F32 bit for bit: every operation on the boundary moduli and operands, whose quotient
+    // estimates are the furthest off, then on random moduli and operands
+    #[test]
+    fn reducer_matches_f32() {
+        let check = |q: u32, pairs: &mut dyn Iterator<Item = (u64, u64)>| {
+            let (r, f) = (Reducer::new(q), F32::new(q));
+            assert_eq!(r.modulus(), q);
+            for (x, y) in pairs {
+                assert_eq!(r.reduce_u64(x), f.reduce_u64(x), "{x} mod {q}");
+                assert_eq!(r.reduce_u64(y), f.reduce_u64(y), "{y} mod {q}");
+
+                let (a, b) = (x as u32, y as u32);
+                assert_eq!(r.add(a, b), f.add(a, b), "{a} + {b} mod {q}");
+                assert_eq!(r.sub(a, b), f.sub(a, b), "{a} - {b} mod {q}");
+                assert_eq!(r.mul(a, b), f.mul(a, b), "{a} * {b} mod {q}");
+                assert_eq!(r.neg(a), f.neg(a), "-{a} mod {q}");
+                assert_eq!(r.inv(a), f.inv(a), "1 / {a} mod {q}");
+                assert_eq!(r.pow(a, y), f.pow(a, y), "{a}^{y} mod {q}");
+            }
+        };
+
+        for q in [1, 2, 1 << 31, u32::MAX] {
+            // the operations on elements take them truncated to u32, so u64::MAX also stands for u32::MAX there
+            let edges = [0, q as u64 - 1, q as u64, u64::MAX, u32::MAX as u64];
+            check(q, &mut edges.iter().flat_map(|x| edges.iter().map(move |y| (*x, *y))));
+        }
+
+        let rng = &mut ChaCha20Rng::seed_from_u64(5);
+        for _ in 0..200 {
+            let q = rng.gen_range(1..=u32::MAX);
+            let random = (0..500).map(|_| (rng.gen(), rng.gen_range(0..u32::MAX as u64 * 2))).collect::<Vec<_>>();
+            check(q, &mut random.into_iter());
+        }
+
+        // the same draws from the same RNG state
+        let (r1, r2) = (&mut ChaCha20Rng::seed_from_u64(6), &mut ChaCha20Rng::seed_from_u64(6));
+        for q in [1, 2, 1 << 31, u32::MAX] {
+            for _ in 0..100 {
+                assert_eq!(Reducer::new(q).random(r1), F32::new(q).random(r2));
+            }
+        }
+    }
+}
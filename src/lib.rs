@@ -0,0 +1,15 @@
+pub mod benchcircuits;
+pub mod checkpoint;
+pub mod circuit;
+pub mod config;
+pub mod error;
+pub mod field;
+pub mod protocol;
+pub mod session;
+pub mod shares;
+pub mod sharing;
+pub mod simulation;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod testgen;
+pub mod utilities;
+pub mod vectors;
@@ -1,116 +1,1265 @@
-
-use std::{process::exit, fs::File};
+use std::{collections::{BTreeMap, HashMap}, fmt, process::exit, fs::{self, File}};
 use std::io::{self, BufRead, BufReader};
 
-mod circuit;
-mod protocol;
-mod sharing;
-mod utilities;
+use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+use serde::Serialize;
+use serde_json::json;
+
+use beaver::benchcircuits::{self, CircuitShape};
+use beaver::circuit::{self, Circuit, Inputs, Party};
+use beaver::config::{RunConfig, RunParameters};
+use beaver::error::BeaverError;
+use beaver::protocol::{self, CommStats, CorruptionConfig, LogLevel, PreprocessingEstimate, ProtocolOptions, ProtocolResult, RoundReport};
+use beaver::utilities;
 
 fn main() {
 
-    let mut args = std::env::args();
+    if let Err(e) = run(std::env::args()) {
+        eprintln!("{e}");
+        exit(exit_code(&e));
+    }
+
+}
+
+fn run(mut args: impl Iterator<Item = String>) -> Result<(), BeaverError> {
 
     args.next();
 
-    let input_path = args.next();
-    if input_path == None {
-        eprintln!("Error: the first argument should be the input path");
-        exit(1);
-    }
-    let output_path = args.next();
-    if output_path == None {
-        eprintln!("Error: the second argument should be the output path (including filename without extension)");
-        exit(1);
+    let command = args.next().ok_or(BeaverError::Usage(String::from(
+        "Error: the first argument should be a command (run, check, eval or gen) or the input path",
+    )))?;
+
+    match command.as_str() {
+        "check" => check_circuit(args).map(|out| print!("{out}")),
+        "eval" => eval_circuit(args).map(|out| print!("{out}")),
+        "gen" => gen_circuit(args).map(|out| print!("{out}")),
+        // a first argument other than a command is the input path of run, as before commands existed
+        _ => {
+            let format = if command == "run" {
+                run_protocol(args)?
+            } else {
+                run_protocol(std::iter::once(command).chain(args))?
+            };
+            if format == OutputFormat::Text {
+                println!("Finished successfully");
+            }
+            Ok(())
+        }
     }
 
-    let input_path = input_path.unwrap();
-    let output_path = output_path.unwrap();
+}
 
-    let input_file = File::open(input_path);
-    if input_file.is_err() {
-        eprintln!("Error opening input file")
+// the exit code of the programme when failing with the given error (see the README)
+fn exit_code(e: &BeaverError) -> i32 {
+    match e {
+        BeaverError::Usage(_) => 2,
+        BeaverError::Parse(_) | BeaverError::Input(_) | BeaverError::InputRange { .. } => 3,
+        BeaverError::Io(_) => 4,
+        BeaverError::Protocol(_) | BeaverError::Authentication { .. } | BeaverError::Aborted(_) => 5,
+        // the highest code among those of the dealer and the parties, so that an abort of any of them prevails over
+        // e.g. a log which could not be written
+        BeaverError::Run(errors) => errors.iter().map(|(_, e)| exit_code(e)).max().unwrap_or(1),
     }
-    
-    let mut lines = BufReader::new(input_file.unwrap()).lines();
+}
+
+const USAGE: &str = "Usage:
+    beaver [run] <input_path> <output_path> [--inputs <inputs_path>] [flags]
+    beaver [run] --circuit <circuit_path> --q <q> --out <output_path> [--p1-inputs <inputs|@file>] [--p2-inputs <inputs|@file>]
+                 [--authenticated] [--corrupt <none|random|p1|p2>] [flags]
+    beaver [run] --circuit <circuit_path> --inputs <inputs_path> --out <output_path> [flags]
+    beaver [run] --config <config_path> [flags]
+    beaver check <circuit_path>
+    beaver eval <circuit_path> <inputs_path> [--fixed-point <f>]
+    beaver gen [--gates <n>] [--outputs <n>] [--mul-ratio <r>] [--constant-ratio <r>] [--input-ratio <r>] [--q <q>] [--seed <seed>]";
 
-    let mut circuit_encoding = String::new();
+fn usage_error(msg: &str) -> BeaverError {
+    BeaverError::Usage(format!("Error: {msg}\n{USAGE}"))
+}
 
-    loop {
-        let l = lines.next();
-        match l {
-            None => {eprintln!("Input file error: the circuit should be followed by more data"); exit(1)},
-            Some(Ok(s)) => {
-                if s.is_empty() {
-                    break;
-                } else {
-                    circuit_encoding.push_str(&s);
+// how the results of a run are printed to stdout: as free text, or as a single JSON object and nothing else (see
+// JsonReport)
+#[derive(Clone, Copy, PartialEq)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+fn run_protocol(mut args: impl Iterator<Item = String>) -> Result<OutputFormat, BeaverError> {
+
+    // the input and output paths of the legacy form
+    let mut positional = Vec::new();
+
+    // the named flags replacing the input file
+    let mut circuit_path = None;
+    let mut q_flag = None;
+    let mut inputs_flags: (Option<String>, Option<String>) = (None, None);
+    let mut authenticated_flag = false;
+    let mut corrupt_flag = None;
+    let mut out = None;
+    // the rest of an input file, after a circuit file or an input file holding only the circuit
+    let mut inputs_path = None;
+
+    // a run configuration replacing both
+    let mut config_path = None;
+
+    // optional flags
+    let mut round_report = false;
+    let mut count_only = false;
+    let mut estimate_cost = false;
+    let mut no_dealer_triples = false;
+    let mut fold_constants = false;
+    let mut cross_check = false;
+    let mut corrupt_party = None;
+    let mut insecure_audit = false;
+    let mut explain_aborts = false;
+    let mut fixed_point = None;
+    let mut master_seed = None;
+    let mut check_inputs = false;
+    let mut cleartext = false;
+    let mut public_inputs = None;
+    let mut dealer_log_level: Option<LogLevel> = None;
+    let mut format = OutputFormat::Text;
+
+    while let Some(flag) = args.next() {
+        let mut value = || args.next().ok_or_else(|| usage_error(&format!("{flag} should be followed by a value")));
+
+        match flag.as_str() {
+            "--circuit" => circuit_path = Some(value()?),
+            "--q" => q_flag = Some(value()?),
+            "--p1-inputs" => inputs_flags.0 = Some(value()?),
+            "--p2-inputs" => inputs_flags.1 = Some(value()?),
+            "--authenticated" => authenticated_flag = true,
+            "--corrupt" => {
+                corrupt_flag = match value()?.to_lowercase().as_str() {
+                    "none" => Some(None),
+                    "random" => Some(Some(None)),
+                    "p1" => Some(Some(Some(Party::P1))),
+                    "p2" => Some(Some(Some(Party::P2))),
+                    _ => return Err(usage_error("--corrupt should be followed by none, random, p1 or p2")),
                 }
             }
-            _ => {eprintln!("Error reading input file"); exit(1)},
+            "--out" => out = Some(value()?),
+            "--inputs" => inputs_path = Some(value()?),
+            "--config" => config_path = Some(value()?),
+            "--round-report" => round_report = true,
+            "--count-only" => count_only = true,
+            "--estimate-cost" => estimate_cost = true,
+            "--no-dealer-triples" => no_dealer_triples = true,
+            "--fold-constants" => fold_constants = true,
+            "--cross-check" => cross_check = true,
+            "--insecure-audit" => insecure_audit = true,
+            "--explain-abort" => explain_aborts = true,
+            "--check-inputs" => check_inputs = true,
+            "--cleartext" => cleartext = true,
+            "--public-inputs" => public_inputs = Some(value()?),
+            "--fixed-point" => {
+                fixed_point = match value()?.parse::<u32>() {
+                    Ok(f) if f < 31 => Some(f),
+                    _ => return Err(BeaverError::Usage(String::from(
+                        "Error: --fixed-point should be followed by a number of fractional bits below 31",
+                    ))),
+                }
+            }
+            "--seed" => {
+                master_seed = Some(utilities::seed_from_hex(&value()?).map_err(|e| BeaverError::Usage(format!("Error: {e}")))?);
+            }
+            "--corrupt-party" => {
+                corrupt_party = match value()?.to_lowercase().as_str() {
+                    "p1" => Some(Party::P1),
+                    "p2" => Some(Party::P2),
+                    _ => return Err(BeaverError::Usage(String::from("Error: --corrupt-party should be followed by p1 or p2"))),
+                }
+            }
+            "--format" => {
+                format = match value()?.as_str() {
+                    "text" => OutputFormat::Text,
+                    "json" => OutputFormat::Json,
+                    f => return Err(usage_error(&format!("--format should be followed by text or json, not {f}"))),
+                }
+            }
+            "--dealer-log-level" => {
+                dealer_log_level = Some(value()?.parse().map_err(|e| BeaverError::Usage(format!("Error: --dealer-log-level {e}")))?);
+            }
+            f if f.starts_with("--") => return Err(usage_error(&format!("unknown argument {flag}"))),
+            _ => positional.push(flag),
+        }
+    }
+
+    // the flags replacing the rest of the input file, which an inputs file replaces too
+    let data_flags = q_flag.is_some()
+        || inputs_flags.0.is_some()
+        || inputs_flags.1.is_some()
+        || authenticated_flag
+        || corrupt_flag.is_some();
+    let named = circuit_path.is_some() || out.is_some() || data_flags;
+
+    let params = match (config_path, &positional[..], named) {
+        (Some(path), [], false) if inputs_path.is_none() => {
+            if fixed_point.is_some() {
+                return Err(usage_error("--fixed-point does not apply to the integer inputs of a run configuration"));
+            }
+            RunConfig::load(&path)?.resolve()?
+        }
+        (Some(_), _, _) => return Err(usage_error("a run configuration cannot be combined with an input file or flags replacing it")),
+        (None, [input_path, output_path], false) => {
+            read_input_file(input_path, inputs_path.as_deref(), output_path, fixed_point)?
+        }
+        (None, [], true) if inputs_path.is_some() => {
+            if data_flags {
+                return Err(usage_error("--inputs cannot be combined with --q, --p1-inputs, --p2-inputs, --authenticated or --corrupt"));
+            }
+            let circuit_path = circuit_path.ok_or_else(|| usage_error("--circuit is required with --inputs"))?;
+            let output_path = out.ok_or_else(|| usage_error("--out is required without an input file"))?;
+
+            read_input_file(&circuit_path, inputs_path.as_deref(), &output_path, fixed_point)?
+        }
+        (None, [], true) => {
+            let circuit_path = circuit_path.ok_or_else(|| usage_error("--circuit is required without an input file"))?;
+            let q = q_flag.ok_or_else(|| usage_error("--q is required without an input file"))?;
+            let output_path = out.ok_or_else(|| usage_error("--out is required without an input file"))?;
+
+            let q = utilities::parse_modulus(&q).map_err(|e| BeaverError::Usage(format!("Error: {e}")))?;
+
+            RunParameters {
+                circuit_encoding: read_circuit_encoding(&circuit_path)?,
+                q,
+                inputs: Inputs {
+                    p1: read_party_inputs(inputs_flags.0, q, fixed_point)?,
+                    p2: read_party_inputs(inputs_flags.1, q, fixed_point)?,
+                    public: Vec::new(),
+                },
+                options: ProtocolOptions {
+                    authenticated: authenticated_flag,
+                    corruption: match corrupt_flag {
+                        Some(Some(party)) => Some(CorruptionConfig { party, ..Default::default() }),
+                        _ => None,
+                    },
+                    output_path: Some(output_path),
+                    ..Default::default()
+                },
+            }
+        }
+        (None, [], false) => return Err(usage_error("expected an input file and an output path, the named flags or a run configuration")),
+        (None, _, true) => return Err(usage_error("the input file cannot be combined with the flags replacing it")),
+        _ => return Err(usage_error("expected an input path and an output path")),
+    };
+
+    let RunParameters { circuit_encoding, q, mut inputs, mut options } = params;
+
+    if format == OutputFormat::Json && (count_only || estimate_cost || cross_check || cleartext) {
+        return Err(usage_error("--format json only applies to runs of the protocol, not to --count-only, --estimate-cost, --cross-check or --cleartext"));
+    }
+
+    if let Some(l) = public_inputs {
+        inputs.public = read_input_vector(Some(Ok(l)), q, fixed_point)?;
+    }
+
+    // --corrupt-party overrides the party set by --corrupt or the run configuration
+    if corrupt_party.is_some() {
+        match options.corruption.as_mut() {
+            Some(c) => c.party = corrupt_party,
+            None => {
+                return Err(BeaverError::Usage(String::from(
+                    "Error: --corrupt-party requires party corruption to be enabled in the input file, with --corrupt or in the run configuration",
+                )))
+            }
+        }
+    }
+
+    if count_only {
+        let circuit: Circuit = circuit_encoding.parse().map_err(BeaverError::Parse)?;
+        println!("{}", PreprocessingEstimate::new(&circuit, options.authenticated));
+        return Ok(format);
+    }
+
+    if estimate_cost {
+        let circuit: Circuit = circuit_encoding.parse().map_err(BeaverError::Parse)?;
+        println!("{}", protocol::estimate_cost(&circuit, options.authenticated));
+        return Ok(format);
+    }
+
+    if check_inputs {
+        let circuit: Circuit = circuit_encoding.parse().map_err(BeaverError::Parse)?;
+        for warning in circuit.input_id_warnings(&inputs) {
+            eprintln!("{warning}");
+        }
+    }
+
+    // the authentication line of the input file is ignored, as both kinds of run are performed
+    if cross_check {
+        if options.corruption.is_some() {
+            return Err(BeaverError::Input(String::from("Error: cross-checking requires honest parties")));
+        }
+        protocol::cross_check(&circuit_encoding, q, inputs, None)?;
+        println!("Cross-check passed: the unauthenticated and authenticated runs agree on every output");
+        return Ok(format);
+    }
+
+    // the outputs are all decoded at the scale of the inputs, which products do not preserve
+    if fixed_point.is_some() {
+        let circuit: Circuit = circuit_encoding.parse().map_err(BeaverError::Parse)?;
+        if !circuit.mul_gate_ids().is_empty() || !circuit.dot_gate_lengths().is_empty() {
+            eprintln!("Warning: products of fixed-point values have twice as many fractional bits unless truncated with truncf");
         }
     }
 
-    let q: u32 = match lines.next() {
-        Some(Ok(s)) => {
-            match s.parse() {
-                Ok(n) => n,
-                Err(_) => {eprintln!("Error reading input file: could not parse q"); exit(1)}
+    // no dealer, parties or logs: the circuit is evaluated on the inputs in the clear, and the authentication and
+    // corruption lines of the input file are ignored
+    if cleartext {
+        print!("{}", cleartext_outputs(&circuit_encoding, q, &inputs, fixed_point)?);
+        return Ok(format);
+    }
+
+    // the flags add to the run configuration, and --seed and --dealer-log-level take precedence over its settings
+    options.master_seed = master_seed.or(options.master_seed);
+    options.no_dealer_triples |= no_dealer_triples;
+    options.fold_constants |= fold_constants;
+    options.insecure_audit |= insecure_audit;
+    options.explain_aborts |= explain_aborts;
+    options.dealer_log_level = dealer_log_level.or(options.dealer_log_level);
+
+    if options.insecure_audit {
+        eprintln!("Warning: insecure audit enabled, the value of every wire (and thus every input) will be printed");
+    }
+    if options.explain_aborts {
+        eprintln!("Warning: abort explanations enabled, the parties share every opening in the clear");
+    }
+
+    let result = protocol::run_beaver_protocol(&circuit_encoding, q, inputs, &options);
+
+    // the report is printed whether the run succeeded or not, and the error is then reported as usual
+    if format == OutputFormat::Json {
+        println!("{}", JsonReport::new(&circuit_encoding, q, &options, fixed_point, &result));
+        return result.map(|_| format);
+    }
+
+    let result = result?;
+
+    if round_report {
+        println!("{}", result.rounds);
+    }
+
+    if fixed_point.is_some() {
+        let circuit: Circuit = circuit_encoding.parse().map_err(BeaverError::Parse)?;
+        print!("{}", format_outputs(&circuit, Party::P1, &result.outputs_p1, q, fixed_point));
+        print!("{}", format_outputs(&circuit, Party::P2, &result.outputs_p2, q, fixed_point));
+    }
+
+    if let Some(values) = result.wire_values {
+        let mut ids: Vec<&u32> = values.keys().collect();
+        ids.sort();
+        for id in ids {
+            println!("Wire {id}: {}", values[id]);
+        }
+    }
+
+    Ok(format)
+
+}
+
+// the results of a run printed by --format json. outputs are keyed by gate id and given as displayed: the residue,
+// its centred representative for signed outputs or the decoded real in fixed point
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    success: bool,
+    q: u32,
+    authenticated: bool,
+    // p1, p2 or random, if a party is corrupt
+    corrupt: Option<&'static str>,
+    fixed_point: Option<u32>,
+    outputs_p1: Option<BTreeMap<u32, serde_json::Value>>,
+    outputs_p2: Option<BTreeMap<u32, serde_json::Value>>,
+    elapsed_ms: Option<f64>,
+    triples: Option<usize>,
+    messages: Option<&'a CommStats>,
+    rounds: Option<RoundReport>,
+    wire_values: Option<BTreeMap<u32, u32>>,
+    // the reason the run failed, and the code the programme exits with
+    error: Option<serde_json::Value>,
+}
+
+impl<'a> JsonReport<'a> {
+    fn new(
+        circuit_encoding: &str,
+        q: u32,
+        options: &ProtocolOptions,
+        fixed_point: Option<u32>,
+        result: &'a Result<ProtocolResult, BeaverError>,
+    ) -> Self {
+        let mut report = JsonReport {
+            success: result.is_ok(),
+            q,
+            authenticated: options.authenticated,
+            corrupt: options.corruption.as_ref().map(|c| match c.party {
+                Some(Party::P1) => "p1",
+                Some(Party::P2) => "p2",
+                None => "random",
+            }),
+            fixed_point,
+            outputs_p1: None,
+            outputs_p2: None,
+            elapsed_ms: None,
+            triples: None,
+            messages: None,
+            rounds: None,
+            wire_values: None,
+            error: None,
+        };
+
+        match result {
+            Ok(r) => {
+                // a successful run has parsed the circuit already
+                let circuit: Circuit = circuit_encoding.parse().unwrap();
+                let shown = |outputs: &HashMap<u32, u32>| {
+                    outputs
+                        .iter()
+                        .map(|(id, v)| {
+                            let v = match fixed_point {
+                                Some(f) => json!(utilities::decode_fixed(*v, f, q)),
+                                None if circuit.is_signed_output(*id) => json!(utilities::centered(*v, q)),
+                                None => json!(v),
+                            };
+                            (*id, v)
+                        })
+                        .collect()
+                };
+                report.outputs_p1 = Some(shown(&r.outputs_p1));
+                report.outputs_p2 = Some(shown(&r.outputs_p2));
+                report.elapsed_ms = Some(r.elapsed.as_secs_f64() * 1000.0);
+                report.triples = Some(r.triples);
+                report.messages = Some(&r.stats);
+                report.rounds = Some(r.rounds);
+                report.wire_values = r.wire_values.as_ref().map(|w| w.iter().map(|(id, v)| (*id, *v)).collect());
+            }
+            Err(e) => {
+                report.error = Some(json!({
+                    "message": e.to_string(),
+                    "exit_code": exit_code(e),
+                    "authentication_failure": e.is_authentication_failure(),
+                }));
             }
+        }
+
+        report
+    }
+}
+
+impl fmt::Display for JsonReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", serde_json::to_string(self).map_err(|_| fmt::Error)?)
+    }
+}
+
+// the outputs of both parties as printed by --cleartext, evaluating the circuit on the inputs in the clear
+fn cleartext_outputs(circuit_encoding: &str, q: u32, inputs: &Inputs, fixed_point: Option<u32>) -> Result<String, BeaverError> {
+    let circuit: Circuit = circuit_encoding.parse().map_err(BeaverError::Parse)?;
+    let values = circuit.evaluate(inputs, q).map_err(BeaverError::Input)?;
+    let mut out = String::new();
+    for party in [Party::P1, Party::P2] {
+        let outputs = circuit.get_outputs(party).iter().map(|id| (*id, values[id])).collect();
+        out.push_str(&format_outputs(&circuit, party, &outputs, q, fixed_point));
+    }
+    Ok(out)
+}
+
+// beaver check <circuit_path>: parses the circuit and returns its statistics, to be printed, without running anything
+fn check_circuit(mut args: impl Iterator<Item = String>) -> Result<String, BeaverError> {
+    let path = args.next().ok_or(BeaverError::Usage(String::from("Error: usage: beaver check <circuit_path>")))?;
+    if let Some(a) = args.next() {
+        return Err(BeaverError::Usage(format!("Error: unknown argument {a}")));
+    }
+
+    let circuit = read_circuit_file(&path)?;
+    let arity = circuit.input_arity();
+    let outputs = |party| circuit.get_outputs(party).iter().map(|id| id.to_string()).collect::<Vec<String>>().join(", ");
+
+    Ok([
+        String::from("Circuit is valid"),
+        format!("Gates evaluated: {}", circuit.get_topology().len()),
+        format!("Inputs: {} + {} from P1, {} + {} from P2", arity.p1_first, arity.p1_second, arity.p2_first, arity.p2_second),
+        format!("Public inputs: {}", circuit.num_public_inputs()),
+        format!("Outputs to P1: {}", outputs(Party::P1)),
+        format!("Outputs to P2: {}", outputs(Party::P2)),
+        format!("Multiplicative depth: {}", circuit.multiplicative_depth()),
+        format!("Interactive depth: {}", circuit.interactive_depth()),
+        format!("Layers: {}", circuit.layers().len()),
+        format!("{}\n", PreprocessingEstimate::new(&circuit, false)),
+    ]
+    .join("\n"))
+}
+
+// beaver eval <circuit_path> <inputs_path> [--fixed-point <f>]: evaluates the circuit in the clear, as --cleartext,
+// and returns the outputs to be printed. the inputs file holds q followed by the four input vector lines, as in an
+// input file, and optionally the public inputs
+fn eval_circuit(mut args: impl Iterator<Item = String>) -> Result<String, BeaverError> {
+    let usage = || BeaverError::Usage(String::from("Error: usage: beaver eval <circuit_path> <inputs_path> [--fixed-point <f>]"));
+    let circuit_path = args.next().ok_or_else(usage)?;
+    let inputs_path = args.next().ok_or_else(usage)?;
+
+    let fixed_point = match (args.next().as_deref(), args.next().map(|f| f.parse::<u32>())) {
+        (None, _) => None,
+        (Some("--fixed-point"), Some(Ok(f))) if f < 31 => Some(f),
+        _ => return Err(usage()),
+    };
+
+    let circuit = read_circuit_file(&circuit_path)?;
+
+    let mut lines = open_reader(&inputs_path, "inputs file")?.lines();
+
+    let q = read_modulus(lines.next())?;
+    let inputs = Inputs {
+        p1: (read_input_vector(lines.next(), q, fixed_point)?, read_input_vector(lines.next(), q, fixed_point)?),
+        p2: (read_input_vector(lines.next(), q, fixed_point)?, read_input_vector(lines.next(), q, fixed_point)?),
+        // an optional sixth line with the public inputs
+        public: match lines.next() {
+            None => Vec::new(),
+            l => read_input_vector(l, q, fixed_point)?,
         },
-        _ => {eprintln!("Error reading input file at line corresponding to q"); exit(1)},
     };
 
-    let inputs_p1_first = read_input_vector(lines.next(), q);
-    let inputs_p1_second = read_input_vector(lines.next(), q);
-    let inputs_p2_first = read_input_vector(lines.next(), q);
-    let inputs_p2_second = read_input_vector(lines.next(), q);
-    
-    let authenticated = read_boolean(lines.next(), "for circuit authentication");
-    let corrupt = read_boolean(lines.next(), "for party corruption");
+    let values = circuit.evaluate(&inputs, q).map_err(BeaverError::Input)?;
+    let mut out = String::new();
+    for party in [Party::P1, Party::P2] {
+        let outputs = circuit.get_outputs(party).iter().map(|id| (*id, values[id])).collect();
+        out.push_str(&format_outputs(&circuit, party, &outputs, q, fixed_point));
+    }
+
+    Ok(out)
+}
+
+// beaver gen [--gates <n>] [--outputs <n>] [--mul-ratio <r>] [--constant-ratio <r>] [--input-ratio <r>]
+// [--q <q>] [--seed <seed>]: returns the encoding of a random circuit (see benchcircuits::random), to be printed. the
+// seed is given as 64 hexadecimal characters, as for run
+fn gen_circuit(mut args: impl Iterator<Item = String>) -> Result<String, BeaverError> {
+    let mut shape = CircuitShape::default();
+    let mut q = 2147483647;
+    let mut seed = None;
+
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or(BeaverError::Usage(format!("Error: {flag} should be followed by a value")))?;
+        let invalid = || BeaverError::Usage(format!("Error: invalid value {value} for {flag}"));
+        let ratio = || match value.parse::<f64>() {
+            Ok(r) if (0.0..=1.0).contains(&r) => Ok(r),
+            _ => Err(BeaverError::Usage(format!("Error: {flag} should be followed by a probability in [0, 1], not {value}"))),
+        };
+
+        match flag.as_str() {
+            "--gates" => shape.gates = value.parse().map_err(|_| invalid())?,
+            "--outputs" => shape.outputs = value.parse().map_err(|_| invalid())?,
+            "--mul-ratio" => shape.mul_ratio = ratio()?,
+            "--constant-ratio" => shape.constant_ratio = ratio()?,
+            "--input-ratio" => shape.input_ratio = ratio()?,
+            "--q" => q = utilities::parse_modulus(&value).map_err(|e| BeaverError::Usage(format!("Error: {e}")))?,
+            "--seed" => {
+                seed = Some(utilities::seed_from_hex(&value).map_err(|e| BeaverError::Usage(format!("Error: {e}")))?)
+            }
+            _ => return Err(BeaverError::Usage(format!("Error: unknown argument {flag}"))),
+        }
+    }
+
+    let mut rng = match seed {
+        Some(seed) => ChaCha20Rng::from_seed(seed),
+        None => ChaCha20Rng::from_entropy(),
+    };
+
+    Ok(format!("{}\n", benchcircuits::random(&mut rng, &shape, q).encoding))
+}
+
+// the circuit at the start of the file, i.e. up to the first empty line after it or the end of the file, so that
+// input files can be given as circuit files too
+fn read_circuit_encoding(path: &str) -> Result<String, BeaverError> {
+    Ok(circuit::read_encoding(&mut open_reader(path, "circuit file")?.lines())?.0)
+}
+
+// stdin if the path is -, so that circuits and inputs can be piped in
+fn open_reader(path: &str, what: &str) -> Result<Box<dyn BufRead>, BeaverError> {
+    if path == "-" {
+        return Ok(Box::new(io::stdin().lock()));
+    }
+    let file = File::open(path).map_err(|e| BeaverError::Io(format!("Error opening {what} {path}: {e}")))?;
+    Ok(Box::new(BufReader::new(file)))
+}
+
+fn read_circuit_file(path: &str) -> Result<Circuit, BeaverError> {
+    read_circuit_encoding(path)?.parse().map_err(BeaverError::Parse)
+}
+
+// the first and second inputs of a party, given either as @ followed by the path of a file with one line for each, or
+// directly as the two lists separated by |, e.g. "3, 5 | 7". the second list may be left out, and both if the flag is
+// absent. a file is only ever read when marked so, whatever files the lists may happen to name
+fn read_party_inputs(arg: Option<String>, q: u32, fixed_point: Option<u32>) -> Result<(Vec<u32>, Vec<u32>), BeaverError> {
+    let arg = arg.unwrap_or_default();
+
+    let lists: Vec<String> = match arg.strip_prefix('@') {
+        Some(path) => fs::read_to_string(path)
+            .map_err(|e| BeaverError::Io(format!("Error opening inputs file {path}: {e}")))?
+            .lines()
+            .map(String::from)
+            .collect(),
+        None => arg.split('|').map(String::from).collect(),
+    };
+
+    match &lists[..] {
+        [] => Ok((Vec::new(), Vec::new())),
+        [first] => Ok((read_input_vector(Some(Ok(first.clone())), q, fixed_point)?, Vec::new())),
+        [first, second] => Ok((
+            read_input_vector(Some(Ok(first.clone())), q, fixed_point)?,
+            read_input_vector(Some(Ok(second.clone())), q, fixed_point)?,
+        )),
+        _ => Err(BeaverError::Parse(format!("Error reading inputs {arg}: expected at most two lists, the first and second inputs"))),
+    }
+}
+
+// the input file, or a circuit file followed by a separate inputs file holding the rest of an input file (q, the
+// input vectors, the mode lines and the optional party names). either path may be - for stdin
+fn read_input_file(
+    input_path: &str,
+    inputs_path: Option<&str>,
+    output_path: &str,
+    fixed_point: Option<u32>,
+) -> Result<RunParameters, BeaverError> {
+    let inputs = match inputs_path {
+        Some(path) => Some(open_reader(path, "inputs file")?),
+        None => None,
+    };
+    read_input(open_reader(input_path, "input file")?, inputs, output_path, fixed_point)
+}
+
+// as read_input_file, from any readers. the inputs are only read from the separate one if the first holds nothing
+// but the circuit, possibly followed by blank lines (as piped input often is)
+fn read_input(
+    input: impl BufRead,
+    inputs: Option<impl BufRead>,
+    output_path: &str,
+    fixed_point: Option<u32>,
+) -> Result<RunParameters, BeaverError> {
+    let mut lines = input.lines();
+
+    let (circuit_encoding, _) = circuit::read_encoding(&mut lines)?;
+
+    // read at once, so that input ending early fails the same whether or not blank lines follow the circuit
+    let rest = lines.collect::<io::Result<Vec<String>>>()?;
+    let more = rest.iter().any(|l| !l.trim().is_empty());
+
+    match (more, inputs) {
+        (true, None) => read_run_data(circuit_encoding, &mut rest.into_iter().map(Ok), output_path, fixed_point),
+        (false, Some(inputs)) => read_run_data(circuit_encoding, &mut inputs.lines(), output_path, fixed_point),
+        (true, Some(_)) => Err(BeaverError::Input(String::from(
+            "Error: the input file holds inputs after the circuit, which cannot be combined with --inputs",
+        ))),
+        (false, None) => Err(BeaverError::Parse(String::from(
+            "Input file error: the circuit should be followed by more data, or the inputs given with --inputs",
+        ))),
+    }
+}
+
+fn read_run_data(
+    circuit_encoding: String,
+    lines: &mut impl Iterator<Item = io::Result<String>>,
+    output_path: &str,
+    fixed_point: Option<u32>,
+) -> Result<RunParameters, BeaverError> {
+    let q = read_modulus(lines.next())?;
+
+    let inputs_p1_first = read_input_vector(lines.next(), q, fixed_point)?;
+    let inputs_p1_second = read_input_vector(lines.next(), q, fixed_point)?;
+    let inputs_p2_first = read_input_vector(lines.next(), q, fixed_point)?;
+    let inputs_p2_second = read_input_vector(lines.next(), q, fixed_point)?;
+
+    let authenticated = read_boolean(lines.next(), "for circuit authentication")?;
+    let corrupt = read_boolean(lines.next(), "for party corruption")?;
+
+    // optional display names for the parties
+    let party_names = match lines.next() {
+        None => None,
+        Some(l) => match l?.split(',').map(|n| n.trim()).collect::<Vec<&str>>()[..] {
+            [n1, n2] if !n1.is_empty() && !n2.is_empty() => Some((n1.to_string(), n2.to_string())),
+            _ => return Err(BeaverError::Parse(String::from("Input file error: expected two comma-separated party names"))),
+        },
+    };
 
     if lines.next().is_some() {
-        eprintln!("Error reading input file: unexpected lines after authentication parameter");
-        exit(1);
+        return Err(BeaverError::Parse(String::from("Error reading input file: unexpected lines after party names")));
     }
 
-    if let Err(e) = protocol::run_beaver_protocol(
-        &circuit_encoding,
+    Ok(RunParameters {
+        circuit_encoding,
         q,
-        inputs_p1_first,
-        inputs_p1_second,
-        inputs_p2_first,
-        inputs_p2_second,
-        authenticated,
-        corrupt,
-        &output_path,
-    ) {
-        eprintln!("{e}");
-        exit(1);
-    } else {
-        println!("Finished successfully")
+        inputs: Inputs {
+            p1: (inputs_p1_first, inputs_p1_second),
+            p2: (inputs_p2_first, inputs_p2_second),
+            public: Vec::new(),
+        },
+        options: ProtocolOptions {
+            authenticated,
+            corruption: corrupt.then(CorruptionConfig::default),
+            output_path: Some(output_path.to_string()),
+            party_names,
+            ..Default::default()
+        },
+    })
+}
+
+fn read_modulus(line: Option<io::Result<String>>) -> Result<u32, BeaverError> {
+    match line {
+        Some(l) => {
+            let l = l?;
+            utilities::parse_modulus(&l)
+                .map_err(|e| BeaverError::Parse(format!("Error reading input file: {e}{}", misplaced_q_hint(&l))))
+        }
+        None => Err(BeaverError::Parse(String::from("Error reading input file at line corresponding to q"))),
     }
+}
 
+// one line per output, in ascending order of id, decoded if the inputs were read in fixed point and otherwise as the
+// circuit marks them
+fn format_outputs(
+    circuit: &Circuit,
+    party: Party,
+    outputs: &HashMap<u32, u32>,
+    q: u32,
+    fixed_point: Option<u32>,
+) -> String {
+    let mut ids: Vec<&u32> = outputs.keys().collect();
+    ids.sort();
+    ids.into_iter()
+        .map(|id| match fixed_point {
+            Some(f) => format!("{party} output {id}: {}\n", utilities::decode_fixed(outputs[id], f, q)),
+            None => format!("{party} output {id}: {}\n", circuit.format_output(*id, outputs[id], q)),
+        })
+        .collect()
 }
 
-fn read_input_vector(line: Option<io::Result<String>>, q: u32) -> Vec<u32> {
+// with a number of fractional bits, the values are read as reals in fixed point
+fn read_input_vector(line: Option<io::Result<String>>, q: u32, fixed_point: Option<u32>) -> Result<Vec<u32>, BeaverError> {
     match line {
-        None => {eprintln!("Error reading input file: expected vector of input indices"); exit(1);},
-        Some(Ok(s)) => match utilities::str_i32_to_vec_u32(&s, q) {
-            Ok(v) => v,
-            Err(_) => {eprintln!("Error reading input file: incorrect format for vector of input indices"); exit(1)},
+        None => Err(BeaverError::Parse(String::from("Error reading input file: expected vector of input indices"))),
+        Some(l) => {
+            let l = l?;
+            match fixed_point {
+                Some(f) => utilities::str_f64_to_vec_u32(&l, f, q),
+                None => utilities::str_i32_to_vec_u32(&l, q),
+            }
+            .map_err(|e| {
+                BeaverError::Parse(format!(
+                    "Error reading input file: incorrect format for vector of input indices{}, {e}",
+                    misplaced_vector_hint(&l)
+                ))
+            })
         }
-        _ => {eprintln!("Error reading input file"); exit(1)},
     }
 }
 
-fn read_boolean(line: Option<io::Result<String>>, msg: &str) -> bool {
+// the lines of the input file are positional, so a line in the wrong place usually fails to parse as what should
+// be there. these guess what the line actually is and suggest the likely mistake, or return an empty string
+fn misplaced_q_hint(line: &str) -> String {
+    if line.trim().is_empty() {
+        String::from(" (the line after the circuit should contain q: is there more than one blank line after the circuit?)")
+    } else if utilities::str_i32_to_vec_u32(line, u32::MAX).is_ok() {
+        String::from(" (this line looks like an input vector: did you put q in the wrong place? It should directly follow the blank line after the circuit)")
+    } else {
+        String::new()
+    }
+}
+
+fn misplaced_vector_hint(line: &str) -> String {
+    match line.trim() {
+        "true" | "false" => String::from(" (this line looks like a boolean: are some of the four input vector lines missing? Empty lines stand for empty vectors)"),
+        l if utilities::parse_modulus(l).is_ok() => String::from(" (this line looks like a modulus: did you put q in the wrong place?)"),
+        _ => String::new(),
+    }
+}
+
+fn read_boolean(line: Option<io::Result<String>>, msg: &str) -> Result<bool, BeaverError> {
     match line {
-        Some(Ok(s)) => match s.as_str() {
-            "true" => true,
-            "false" => false,
-            _ => {eprintln!("Input file error: expected \"true\" or \"false\" {}", msg); exit(1)},
+        Some(l) => match l?.as_str() {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            _ => Err(BeaverError::Parse(format!("Input file error: expected \"true\" or \"false\" {}", msg))),
         },
-        _ => {eprintln!("Input file error: expected \"true\" or \"false\" for {}", msg); exit(1)},
+        None => Err(BeaverError::Parse(format!("Input file error: expected \"true\" or \"false\" {}", msg))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    const SEED: &str = "0000000000000000000000000000000000000000000000000000000000000003";
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        ["beaver"].iter().chain(args).map(|a| a.to_string()).collect::<Vec<String>>().into_iter()
+    }
+
+    fn logs(prefix: &Path) -> Vec<String> {
+        ["dealer", "p1", "p2"]
+            .iter()
+            .map(|who| fs::read_to_string(format!("{}_{who}.txt", prefix.display())).unwrap())
+            .collect()
+    }
+
+    // the input file and the named flags replacing it, with the inputs given directly or in files, run the same
+    // protocol: with the same seed, the dealer and both parties write the same logs
+    #[test]
+    fn input_file_and_named_flags_run_identically() {
+        let dir = std::env::temp_dir().join(format!("beaver_named_flags_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        fs::write(path("p1_inputs.txt"), "3, -1\n2, 4\n").unwrap();
+        fs::write(path("p2_inputs.txt"), "1, 5\n2, 3\n").unwrap();
+
+        let named = |p1: &str, p2: &str, out: &str| {
+            run(args(&[
+                "run", "--circuit", "example_inputs/example_4_circuit.txt", "--q", "31", "--p1-inputs", p1, "--p2-inputs",
+                p2, "--authenticated", "--out", out, "--seed", SEED,
+            ]))
+        };
+        run(args(&["run", "example_inputs/example_4_auth_honest.txt", &path("file"), "--seed", SEED])).unwrap();
+        named("3, -1 | 2, 4", "1, 5 | 2, 3", &path("lists")).unwrap();
+        named(&format!("@{}", path("p1_inputs.txt")), &format!("@{}", path("p2_inputs.txt")), &path("files")).unwrap();
+
+        let expected = logs(&dir.join("file"));
+        let (lists, files) = (logs(&dir.join("lists")), logs(&dir.join("files")));
+        // a value naming a file without the marker is read as the input lists, which it is not
+        let unmarked = named(&path("p1_inputs.txt"), "1, 5 | 2, 3", &path("unmarked"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(expected.iter().all(|log| log.contains("Sent to")));
+        assert_eq!(lists, expected);
+        assert_eq!(files, expected);
+        assert!(matches!(unmarked, Err(BeaverError::Parse(_))), "{unmarked:?}");
+    }
+
+    // the arguments of a command, after its name
+    fn command_args(args: &[&str]) -> impl Iterator<Item = String> {
+        args.iter().map(|a| a.to_string()).collect::<Vec<String>>().into_iter()
+    }
+
+    const EXAMPLE_4_CIRCUIT: &str = "example_inputs/example_4_circuit.txt";
+
+    #[test]
+    fn check_prints_statistics_and_reports_errors() {
+        let out = check_circuit(command_args(&[EXAMPLE_4_CIRCUIT])).unwrap();
+        let expected = "Circuit is valid\nGates evaluated: 7\nInputs: 2 + 2 from P1, 2 + 2 from P2\nPublic inputs: 0\n\
+                        Outputs to P1: 1, 3\nOutputs to P2: 5, 3\nMultiplicative depth: 2\nInteractive depth: 2\n\
+                        Layers: 4\n";
+        assert!(out.starts_with(expected), "{out}");
+        assert!(out.ends_with("3 triples, 8 input singletons, producing ~152 bytes of transcript.\n"), "{out}");
+
+        let dir = std::env::temp_dir().join(format!("beaver_check_command_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let malformed = dir.join("malformed.txt");
+        fs::write(&malformed, "0, P1, pow, P2 & 0 &").unwrap();
+        let malformed = check_circuit(command_args(&[malformed.to_str().unwrap()]));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(exit_code(&malformed.unwrap_err()), 3);
+        assert_eq!(exit_code(&check_circuit(command_args(&["missing_circuit.txt"])).unwrap_err()), 4);
+        assert_eq!(exit_code(&check_circuit(command_args(&[])).unwrap_err()), 2);
+        assert_eq!(exit_code(&check_circuit(command_args(&[EXAMPLE_4_CIRCUIT, "extra"])).unwrap_err()), 2);
+    }
+
+    // the plaintext evaluation prints the outputs an honest run of the protocol computes
+    #[test]
+    fn eval_matches_a_protocol_run() {
+        let dir = std::env::temp_dir().join(format!("beaver_eval_command_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        fs::write(path("inputs.txt"), "31\n3, -1\n2, 4\n1, 5\n2, 3\n").unwrap();
+        fs::write(path("short.txt"), "31\n3\n2, 4\n1, 5\n2, 3\n").unwrap();
+
+        let out = eval_circuit(command_args(&[EXAMPLE_4_CIRCUIT, &path("inputs.txt")])).unwrap();
+        let short = eval_circuit(command_args(&[EXAMPLE_4_CIRCUIT, &path("short.txt")]));
+        fs::remove_dir_all(&dir).unwrap();
+
+        let circuit = read_circuit_file(EXAMPLE_4_CIRCUIT).unwrap();
+        let inputs = Inputs { p1: (vec![3, 30], vec![2, 4]), p2: (vec![1, 5], vec![2, 3]), public: vec![] };
+        let options = ProtocolOptions { authenticated: true, seed: Some(3), ..Default::default() };
+        let encoding = read_circuit_encoding(EXAMPLE_4_CIRCUIT).unwrap();
+        let result = protocol::run_beaver_protocol(&encoding, 31, inputs, &options).unwrap();
+        let expected = format_outputs(&circuit, Party::P1, &result.outputs_p1, 31, None)
+            + &format_outputs(&circuit, Party::P2, &result.outputs_p2, 31, None);
+
+        assert_eq!(out, expected);
+        assert_eq!(out.lines().count(), 4);
+        assert_eq!(exit_code(&short.unwrap_err()), 3);
+        assert_eq!(exit_code(&eval_circuit(command_args(&[EXAMPLE_4_CIRCUIT])).unwrap_err()), 2);
+    }
+
+    // gen takes the same seeds as run, and its circuits pass check and eval
+    #[test]
+    fn gen_is_reproducible_and_its_circuits_check_and_evaluate() {
+        let gen = |seed: &str| {
+            gen_circuit(command_args(&["--gates", "40", "--outputs", "3", "--q", "101", "--seed", seed]))
+        };
+        let other_seed = SEED.replace('3', "4");
+
+        let encoding = gen(SEED).unwrap();
+        assert_eq!(gen(SEED).unwrap(), encoding);
+        assert_ne!(gen(&other_seed).unwrap(), encoding);
+        for seed in ["42", &SEED[1..]] {
+            let e = gen(seed).unwrap_err();
+            assert_eq!(exit_code(&e), 2);
+            assert!(e.to_string().contains("expected 64 hexadecimal characters"), "{e}");
+        }
+
+        let circuit: Circuit = encoding.trim().parse().unwrap();
+        let arity = circuit.input_arity();
+        let ones = |n: usize| vec!["1"; n].join(", ");
+
+        let dir = std::env::temp_dir().join(format!("beaver_gen_command_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        fs::write(path("circuit.txt"), &encoding).unwrap();
+        let vectors = [arity.p1_first, arity.p1_second, arity.p2_first, arity.p2_second].map(ones);
+        fs::write(path("inputs.txt"), format!("101\n{}\n", vectors.join("\n"))).unwrap();
+
+        let check = check_circuit(command_args(&[&path("circuit.txt")])).unwrap();
+        let out = eval_circuit(command_args(&[&path("circuit.txt"), &path("inputs.txt")])).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(check.starts_with(&format!("Circuit is valid\nGates evaluated: {}\n", circuit.get_topology().len())));
+        let inputs = Inputs {
+            p1: (vec![1; arity.p1_first], vec![1; arity.p1_second]),
+            p2: (vec![1; arity.p2_first], vec![1; arity.p2_second]),
+            public: vec![],
+        };
+        let values = circuit.evaluate(&inputs, 101).unwrap();
+        let expected: String = [Party::P1, Party::P2]
+            .into_iter()
+            .map(|party| {
+                let outputs = circuit.get_outputs(party).iter().map(|id| (*id, values[id])).collect();
+                format_outputs(&circuit, party, &outputs, 101, None)
+            })
+            .collect();
+        assert_eq!(out, expected);
+    }
+
+    // the report of a run, once parsed, holds the outputs of a plaintext evaluation of the circuit, shown as the text
+    // output shows them
+    #[test]
+    fn json_report_matches_a_plaintext_evaluation() {
+        let report = |encoding: &str, q, inputs: &Inputs, options: &ProtocolOptions, fixed_point| {
+            let result = protocol::run_beaver_protocol(encoding, q, inputs.clone(), options);
+            let json = JsonReport::new(encoding, q, options, fixed_point, &result).to_string();
+            serde_json::from_str::<serde_json::Value>(&json).unwrap()
+        };
+        let options = ProtocolOptions { authenticated: true, seed: Some(3), ..Default::default() };
+
+        // gate 1 is a signed output, shown as its centred representative
+        let encoding = "0, P1, add, P2 | 1, 0, mul, P2 | 2, 1, addc, 40 & 0, -1 & 2";
+        let circuit: Circuit = encoding.parse().unwrap();
+        let inputs = Inputs { p1: (vec![2], vec![]), p2: (vec![], vec![3, 29]), public: vec![] };
+        let values = circuit.evaluate(&inputs, 31).unwrap();
+        let json = report(encoding, 31, &inputs, &options, None);
+
+        assert_eq!(json["success"], json!(true));
+        assert_eq!(json["q"], json!(31));
+        assert_eq!(json["authenticated"], json!(true));
+        assert_eq!(json["error"], serde_json::Value::Null);
+        assert_eq!(json["outputs_p1"], json!({ "0": values[&0], "1": utilities::centered(values[&1], 31) }));
+        assert_eq!(json["outputs_p2"], json!({ "2": values[&2] }));
+        assert_eq!(json["outputs_p1"], json!({ "0": 5, "1": -10 }));
+        assert_eq!(json["outputs_p2"], json!({ "2": 30 }));
+        assert_eq!(json["triples"], json!(1));
+
+        // in fixed point, the outputs are decoded to reals
+        let (q, f) = (2147483647, 4);
+        let encoding = "0, P1, add, P2 & 0 & 0";
+        let inputs = Inputs {
+            p1: (utilities::str_f64_to_vec_u32("1.5", f, q).unwrap(), vec![]),
+            p2: (vec![], utilities::str_f64_to_vec_u32("-0.25", f, q).unwrap()),
+            public: vec![],
+        };
+        let values = encoding.parse::<Circuit>().unwrap().evaluate(&inputs, q).unwrap();
+        let json = report(encoding, q, &inputs, &options, Some(f));
+        assert_eq!(json["fixed_point"], json!(f));
+        assert_eq!(json["outputs_p1"]["0"], json!(utilities::decode_fixed(values[&0], f, q)));
+        assert_eq!(json["outputs_p2"]["0"], json!(1.25));
+
+        // a corrupt party is caught, and the report holds the error instead of the outputs
+        let corruption = Some(CorruptionConfig { degree: 1.0, party: Some(Party::P1) });
+        let options = ProtocolOptions { corruption, ..options };
+        let encoding = read_circuit_encoding(EXAMPLE_4_CIRCUIT).unwrap();
+        let inputs = Inputs { p1: (vec![3, 30], vec![2, 4]), p2: (vec![1, 5], vec![2, 3]), public: vec![] };
+        let json = report(&encoding, 31, &inputs, &options, None);
+
+        assert_eq!(json["success"], json!(false));
+        assert_eq!(json["corrupt"], json!("p1"));
+        assert_eq!(json["outputs_p1"], serde_json::Value::Null);
+        assert_eq!(json["error"]["exit_code"], json!(5));
+        assert_eq!(json["error"]["authentication_failure"], json!(true));
+        assert!(json["error"]["message"].as_str().unwrap().contains("P1"), "{}", json["error"]);
+    }
+
+    // each kind of failure exits with its own code (see the README)
+    #[test]
+    fn crafted_failures_exit_with_their_codes() {
+        let dir = std::env::temp_dir().join(format!("beaver_exit_codes_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        let circuit = fs::read_to_string(EXAMPLE_4_CIRCUIT).unwrap();
+        fs::write(path("malformed.txt"), "0, P1, pow, P2 & 0 &\n\n31\n1\n\n\n\nfalse\nfalse\n").unwrap();
+        fs::write(path("short.txt"), format!("{circuit}\n\n31\n3\n2, 4\n1, 5\n2, 3\nfalse\nfalse\n")).unwrap();
+        fs::write(path("no_modes.txt"), format!("{circuit}\n\n31\n3, 1\n2, 4\n1, 5\n2, 3\n")).unwrap();
+
+        let code = |a: &[&str]| exit_code(&run(args(a)).unwrap_err());
+        let out = path("out");
+        let corrupt = "example_inputs/example_4_auth_corrupt.txt";
+        let honest = "example_inputs/example_4_auth_honest.txt";
+        let codes = [
+            // arguments
+            code(&["run", "--bogus"]),
+            code(&["run", honest]),
+            code(&["run", honest, &out, "--format", "xml"]),
+            code(&["run", honest, &out, "--seed", "42"]),
+            code(&["run", honest, &out, "--count-only", "--format", "json"]),
+            code(&["run", "--circuit", EXAMPLE_4_CIRCUIT, "--out", &out]),
+            // malformed or mismatched data
+            code(&["run", &path("malformed.txt"), &out]),
+            code(&["run", &path("short.txt"), &out]),
+            code(&["run", &path("no_modes.txt"), &out]),
+            code(&["run", corrupt, &out, "--cross-check"]),
+            // files
+            code(&["run", &path("missing.txt"), &out]),
+            code(&["run", honest, &path("missing_dir/out")]),
+            // aborts
+            code(&["run", corrupt, &out, "--seed", SEED]),
+        ];
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(codes, [2, 2, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 5]);
+
+        // the errors of a run exit with the highest of their codes
+        let io = || BeaverError::Io(String::from("log"));
+        let abort = BeaverError::Aborted(Box::new(BeaverError::Protocol(String::from("missing message"))));
+        assert_eq!(exit_code(&BeaverError::Run(vec![(String::from("dealer"), io())])), 4);
+        assert_eq!(exit_code(&BeaverError::Run(vec![(String::from("dealer"), io()), (String::from("P1"), abort)])), 5);
+        assert_eq!(exit_code(&BeaverError::Run(vec![])), 1);
+    }
+
+    // the fields of the parameters read from an input file which the file determines, with the circuit encoding
+    // stripped of whitespace (which the separate circuit file of the examples lays out differently)
+    fn read_fields(params: &RunParameters) -> (String, u32, [Vec<u32>; 4], bool, bool, Option<String>) {
+        let RunParameters { circuit_encoding, q, inputs, options } = params;
+        let encoding = circuit_encoding.split_whitespace().collect();
+        let vectors = [&inputs.p1.0, &inputs.p1.1, &inputs.p2.0, &inputs.p2.1].map(|v| v.to_vec());
+        (encoding, *q, vectors, options.authenticated, options.corruption.is_some(), options.output_path.clone())
+    }
+
+    // input piped in (or held in memory) is read as the same input in files, whether combined or split in two
+    #[test]
+    fn input_is_read_alike_from_buffers_and_files() {
+        let circuit = fs::read_to_string(EXAMPLE_4_CIRCUIT).unwrap();
+        let inputs = fs::read_to_string("example_inputs/example_4_auth_honest_inputs.txt").unwrap();
+        let combined = fs::read_to_string("example_inputs/example_4_auth_honest.txt").unwrap();
+        let none: Option<&[u8]> = None;
+
+        let expected = read_input_file("example_inputs/example_4_auth_honest.txt", None, "out", None).unwrap();
+        let expected = read_fields(&expected);
+        assert_eq!(expected.1, 31);
+        assert_eq!(expected.2, [vec![3, 30], vec![2, 4], vec![1, 5], vec![2, 3]]);
+
+        let from_buffer = read_input(combined.as_bytes(), none, "out", None).unwrap();
+        assert_eq!(read_fields(&from_buffer), expected);
+        // Windows line endings, and the blank lines piped circuits often end with
+        let crlf = combined.replace('\n', "\r\n");
+        assert_eq!(read_fields(&read_input(crlf.as_bytes(), none, "out", None).unwrap()), expected);
+        let padded = format!("{circuit}\n\n\n");
+        let split = read_input(padded.as_bytes(), Some(inputs.as_bytes()), "out", None).unwrap();
+        assert_eq!(read_fields(&split), expected);
+
+        // stdin is a reader like any other: a circuit file and inputs from a buffer read as both from files
+        let circuit_file = BufReader::new(File::open(EXAMPLE_4_CIRCUIT).unwrap());
+        let file_and_buffer = read_input(circuit_file, Some(inputs.as_bytes()), "out", None).unwrap();
+        assert_eq!(read_fields(&file_and_buffer), expected);
+
+        let both = read_input(combined.as_bytes(), Some(inputs.as_bytes()), "out", None).map(|_| ());
+        assert!(matches!(both, Err(BeaverError::Input(_))), "{both:?}");
+        let neither = read_input(circuit.as_bytes(), none, "out", None).map(|_| ());
+        assert!(matches!(neither, Err(BeaverError::Parse(_))), "{neither:?}");
+        let empty = read_input(&b""[..], Some(&b""[..]), "out", None).map(|_| ());
+        assert_eq!(exit_code(&empty.unwrap_err()), 3);
+    }
+
+    // the circuit and the inputs in separate files run the same protocol as in a single input file, including for a
+    // circuit file with comments and blank lines and for an inputs file given after the legacy positional arguments
+    #[test]
+    fn separate_files_run_as_the_combined_file() {
+        let dir = std::env::temp_dir().join(format!("beaver_separate_files_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        let commented = "# the circuit of example 4\n\n0, P1, add, P2 | 6, P2, mul, P1 |  # two input gates\n\n\
+                         1, 0, mul, P2 | 2, P1, add, 1 | 5, 6, add, P1 |\n4, P2, mul, 5 | 3, 2, add, 4\n\
+                         # outputs\n& 1, 3 & 5, 3\n\n";
+        fs::write(path("commented.txt"), commented).unwrap();
+
+        let inputs = "example_inputs/example_4_auth_honest_inputs.txt";
+        let combined = "example_inputs/example_4_auth_honest.txt";
+        run(args(&["run", combined, &path("combined"), "--seed", SEED])).unwrap();
+        run(args(&["run", "--circuit", EXAMPLE_4_CIRCUIT, "--inputs", inputs, "--out", &path("named"), "--seed", SEED]))
+            .unwrap();
+        run(args(&["run", EXAMPLE_4_CIRCUIT, &path("positional"), "--inputs", inputs, "--seed", SEED])).unwrap();
+        let commented = path("commented.txt");
+        let out = path("commented");
+        run(args(&["run", "--circuit", &commented, "--inputs", inputs, "--out", &out, "--seed", SEED])).unwrap();
+        // a combined file holds its inputs already
+        let both = run(args(&["run", combined, &path("both"), "--inputs", inputs]));
+
+        let expected = logs(&dir.join("combined"));
+        let separate = ["named", "positional", "commented"].map(|name| logs(&dir.join(name)));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(expected[1].contains("Sent to"));
+        for logs in separate {
+            assert_eq!(logs, expected);
+        }
+        assert!(matches!(both, Err(BeaverError::Input(_))), "{both:?}");
+
+        // the outputs, read back the same way, are those of the plaintext evaluation
+        let circuit = read_circuit_file(EXAMPLE_4_CIRCUIT).unwrap();
+        let params = read_input_file(EXAMPLE_4_CIRCUIT, Some(inputs), "out", None).unwrap();
+        let values = circuit.evaluate(&params.inputs, params.q).unwrap();
+        for (id, log) in [(1, &expected[1]), (3, &expected[1]), (5, &expected[2]), (3, &expected[2])] {
+            assert!(log.contains(&format!("Output of gate {id}: {}\n", values[&id])), "{log}");
+        }
     }
+
+    // the cleartext mode prints the outputs of an honest authenticated run, without running the dealer or the parties
+    #[test]
+    fn cleartext_matches_an_honest_authenticated_run() {
+        let dir = std::env::temp_dir().join(format!("beaver_cleartext_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let out = dir.join("run").to_str().unwrap().to_string();
+        // the authentication and corruption lines of the input file are ignored
+        run(args(&["run", "example_inputs/example_4_auth_corrupt.txt", &out, "--cleartext"])).unwrap();
+        let written = fs::read_dir(&dir).unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(written, 0);
+
+        let options = ProtocolOptions { authenticated: true, seed: Some(3), ..Default::default() };
+        let mut checked = 0;
+        // signed outputs, public inputs and constants, and the reals of example 4 in fixed point
+        let cases = [
+            ("0, P1, mul, P2 | 1, 0, addc, -40 | 2, pub0, mul, 1 & -1 & 2, 0", vec![5], vec![7], vec![3], None),
+            ("0, P1, add, P2 | 1, 0, mulc, 3 & 0 & 1", vec![1], vec![2], vec![], None),
+            (
+                &read_circuit_encoding(EXAMPLE_4_CIRCUIT).unwrap(),
+                vec![3, -1, 2, 4],
+                vec![1, 5, 2, 3],
+                vec![],
+                Some(2),
+            ),
+        ];
+        for (encoding, p1, p2, public, fixed_point) in cases {
+            let q = 2147483647;
+            let encode = |v: &[i32]| v.iter().map(|x| utilities::modulo(*x << fixed_point.unwrap_or(0), q)).collect();
+            let split = |v: Vec<u32>, n: usize| (v[..n].to_vec(), v[n..].to_vec());
+            let circuit: Circuit = encoding.parse().unwrap();
+            let arity = circuit.input_arity();
+            let inputs = Inputs {
+                p1: split(encode(&p1), arity.p1_first),
+                p2: split(encode(&p2), arity.p2_first),
+                public: encode(&public),
+            };
+
+            let cleartext = cleartext_outputs(encoding, q, &inputs, fixed_point).unwrap();
+            let result = protocol::run_beaver_protocol(encoding, q, inputs, &options).unwrap();
+            let expected = format_outputs(&circuit, Party::P1, &result.outputs_p1, q, fixed_point)
+                + &format_outputs(&circuit, Party::P2, &result.outputs_p2, q, fixed_point);
+            assert_eq!(cleartext, expected);
+            checked += cleartext.lines().count();
+        }
+        assert_eq!(checked, 3 + 2 + 4);
+    }
+
+    // a line out of place in the input file fails with a hint at the likely mistake
+    #[test]
+    fn misordered_input_files_get_hints() {
+        let circuit = fs::read_to_string(EXAMPLE_4_CIRCUIT).unwrap();
+        let error = |rest: &str| {
+            let file = format!("{}\n\n{rest}", circuit.trim_end());
+            match read_input(file.as_bytes(), None::<&[u8]>, "out", None) {
+                Err(BeaverError::Parse(e)) => e,
+                Err(e) => panic!("expected a parse error, got {e}"),
+                Ok(_) => panic!("expected an error for {rest:?}"),
+            }
+        };
+
+        // q after the input vectors, and an extra blank line before q
+        let e = error("3, -1\n2, 4\n1, 5\n2, 3\n31\ntrue\nfalse\n");
+        assert!(e.starts_with("Error reading input file: Invalid modulus 3, -1"), "{e}");
+        assert!(e.ends_with("(this line looks like an input vector: did you put q in the wrong place? \
+                             It should directly follow the blank line after the circuit)"), "{e}");
+        let e = error("\n31\n3, -1\n2, 4\n1, 5\n2, 3\ntrue\nfalse\n");
+        assert!(e.ends_with("(the line after the circuit should contain q: is there more than one blank line after \
+                             the circuit?)"), "{e}");
+
+        // input vector lines left out rather than empty, and a modulus among them
+        let e = error("31\n3, -1\n2, 4\ntrue\nfalse\n");
+        assert!(e.contains("(this line looks like a boolean: are some of the four input vector lines missing?"), "{e}");
+        let e = error("31\n3, -1\n2, 4\n1, 5\nmersenne31\ntrue\nfalse\n");
+        assert!(e.contains("(this line looks like a modulus: did you put q in the wrong place?)"), "{e}");
+
+        // lines which look like nothing in particular get no hint
+        let e = error("thirty-one\n3, -1\n2, 4\n1, 5\n2, 3\ntrue\nfalse\n");
+        assert!(e.starts_with("Error reading input file: Invalid modulus thirty-one: expected a u32"), "{e}");
+        assert!(!e.contains(" (this line") && !e.contains(" (the line"), "{e}");
+        let e = error("31\n3, -1\n2, x\n1, 5\n2, 3\ntrue\nfalse\n");
+        let expected = "Error reading input file: incorrect format for vector of input indices, entry \"x\"";
+        assert!(e.starts_with(expected), "{e}");
+
+        // the hints apply to the positional lines only: the same file in the right order is read
+        let file = format!("{}\n\n31\n3, -1\n2, 4\n1, 5\n2, 3\ntrue\nfalse\n", circuit.trim_end());
+        assert!(read_input(file.as_bytes(), None::<&[u8]>, "out", None).is_ok());
+    }
+
+    // a malformed input file makes run return the error describing it, rather than exiting
+    #[test]
+    fn malformed_input_files_are_reported_by_run() {
+        let dir = std::env::temp_dir().join(format!("beaver_malformed_input_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        let circuit = fs::read_to_string(EXAMPLE_4_CIRCUIT).unwrap();
+        let circuit = circuit.trim_end();
+
+        let files = [
+            ("bad_gate.txt", String::from("0, P1, pow, P2 & 0 &\n\n31\n1\n\n\n\nfalse\nfalse\n")),
+            ("bad_vector.txt", format!("{circuit}\n\n31\n3, -1\n2, 4\n1; 5,, 0x\n2, 3\nfalse\nfalse\n")),
+            ("bad_boolean.txt", format!("{circuit}\n\n31\n3, -1\n2, 4\n1, 5\n2, 3\nyes\nfalse\n")),
+            ("bad_names.txt", format!("{circuit}\n\n31\n3, -1\n2, 4\n1, 5\n2, 3\nfalse\nfalse\nAlice\n")),
+            ("extra_line.txt", format!("{circuit}\n\n31\n3, -1\n2, 4\n1, 5\n2, 3\nfalse\nfalse\nA, B\nC\n")),
+            ("circuit_only.txt", format!("{circuit}\n")),
+        ];
+        for (name, contents) in &files {
+            fs::write(path(name), contents).unwrap();
+        }
+        fs::write(path("not_utf8.txt"), [circuit.as_bytes(), b"\n\n31\n\xff\xfe\n"].concat()).unwrap();
+
+        let error = |name: &str| run(args(&["run", &path(name), &path("out")])).unwrap_err();
+        let errors: Vec<BeaverError> =
+            files.iter().map(|(name, _)| *name).chain(["not_utf8.txt"]).map(error).collect();
+        let written = fs::read_dir(&dir).unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let message = |i: usize| match &errors[i] {
+            BeaverError::Parse(e) => e.clone(),
+            e => panic!("expected a parse error, got {e:?}"),
+        };
+        assert!(message(0).contains("pow"), "{}", message(0));
+        let expected = "Error reading input file: incorrect format for vector of input indices, \
+                        entry \"0x\" at position 8: missing digits\n1; 5,, 0x\n       ^";
+        assert_eq!(message(1), expected);
+        assert_eq!(message(2), "Input file error: expected \"true\" or \"false\" for circuit authentication");
+        assert_eq!(message(3), "Input file error: expected two comma-separated party names");
+        assert_eq!(message(4), "Error reading input file: unexpected lines after party names");
+        assert!(message(5).starts_with("Input file error: the circuit should be followed by more data"));
+        let unreadable = &errors[6];
+        let is_read_error = matches!(unreadable, BeaverError::Io(e) if e.starts_with("Error reading input file: "));
+        assert!(is_read_error, "{unreadable:?}");
+        // nothing was run, so no log was written
+        assert_eq!(written, files.len() + 1);
+    }
+
 }
@@ -2,96 +2,1190 @@
 #![allow(unused_must_use)]
 
 use std::{
-    collections::HashMap,
-    fmt::Display,
-    fs,
-    sync::mpsc::{self, Receiver, Sender},
-    thread,
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::{self, Display},
+    fs::{self, File},
+    future::{self, Future},
+    io::{self, BufWriter, Write},
+    pin::Pin,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicIsize, Ordering},
+        mpsc::{self, Receiver, RecvError, SendError, Sender, SyncSender},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Waker},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
-use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+#[cfg(debug_assertions)]
+use std::sync::OnceLock;
 
+use rand::{seq::index, Rng};
+use rayon::prelude::*;
+use rand_chacha::{rand_core::{RngCore, SeedableRng}, ChaCha20Rng};
+use serde::Serialize;
+
+use crate::checkpoint;
+use crate::error::BeaverError;
+use crate::field::{FieldOps, ModField, Reducer, F32};
 use crate::sharing::*;
-use crate::utilities;
-use crate::{circuit::*, utilities::subtract_without_overflow};
+use crate::utilities::{self, Fnv1a};
+use crate::circuit::*;
 
-enum Msg<T: Sharing> {
+// a message between the dealers and the parties, as handed to and from the state machines (see PartyMachine)
+pub enum Msg<T: Sharing> {
     Value(u32),
     Singleton(T),
+    // sharing of the mask of the given input wire
+    Mask(InputWire, T),
+    // sharing of the blind of the given single-party output, together with the blind itself if the receiver is the
+    // party the output is revealed to
+    Blind(u32, T, Option<u32>),
     Triple(BeaverSharing<T>),
+    InnerProduct(InnerProductSharing<T>),
+    Truncation(TruncationSharing<T>),
+    Comparison(ComparisonSharing<T>),
+    Equality(EqualitySharing<T>),
+    // openings of all output wires of the receiving party, sorted by gate id
+    Openings(Vec<T>),
+    // values reconstructed by the sender for the outputs revealed to both parties, sorted by gate id
+    Values(Vec<u32>),
+    // consecutive messages coalesced by the sender, unpacked in order by the receiver
+    Batch(Vec<Msg<T>>),
+    // hash of all messages sent through the channel so far
+    Digest(u64),
+    // contribution to a jointly drawn random seed
+    Nonce(u64),
     Abort,
 }
 
+impl<T: Sharing> Msg<T> {
+    // appends the canonical byte encoding of the message, as it would be sent over a network: a tag byte for the
+    // variant followed by its fields, with integers in little endian, sharings and lists as their length (u32)
+    // followed by their components, and options as a 0 or 1 byte followed by the value if present
+    fn encode(&self, out: &mut Vec<u8>) {
+        fn u32s(out: &mut Vec<u8>, v: &[u32]) {
+            out.extend((v.len() as u32).to_le_bytes());
+            for x in v {
+                out.extend(x.to_le_bytes());
+            }
+        }
+
+        match self {
+            Msg::Value(v) => {
+                out.push(0);
+                out.extend(v.to_le_bytes());
+            }
+            Msg::Singleton(s) => {
+                out.push(1);
+                u32s(out, &s.components());
+            }
+            Msg::Mask(wire, s) => {
+                out.push(2);
+                out.push(wire.party as u8);
+                out.push(wire.first as u8);
+                out.extend(wire.id.to_le_bytes());
+                u32s(out, &s.components());
+            }
+            Msg::Blind(id, s, r) => {
+                out.push(3);
+                out.extend(id.to_le_bytes());
+                u32s(out, &s.components());
+                match r {
+                    Some(r) => {
+                        out.push(1);
+                        out.extend(r.to_le_bytes());
+                    }
+                    None => out.push(0),
+                }
+            }
+            Msg::Triple(t) => {
+                out.push(4);
+                u32s(out, &t.components());
+            }
+            Msg::InnerProduct(t) => {
+                out.push(5);
+                out.extend((t.len() as u32).to_le_bytes());
+                u32s(out, &t.components());
+            }
+            Msg::Truncation(t) => {
+                out.push(6);
+                u32s(out, &t.components());
+            }
+            Msg::Comparison(t) => {
+                out.push(7);
+                out.extend((t.len() as u32).to_le_bytes());
+                u32s(out, &t.components());
+            }
+            Msg::Equality(t) => {
+                out.push(8);
+                out.extend((t.len() as u32).to_le_bytes());
+                u32s(out, &t.components());
+            }
+            Msg::Openings(v) => {
+                out.push(9);
+                out.extend((v.len() as u32).to_le_bytes());
+                for s in v {
+                    u32s(out, &s.components());
+                }
+            }
+            Msg::Values(v) => {
+                out.push(10);
+                u32s(out, v);
+            }
+            Msg::Batch(msgs) => {
+                out.push(11);
+                out.extend((msgs.len() as u32).to_le_bytes());
+                for m in msgs {
+                    m.encode(out);
+                }
+            }
+            Msg::Digest(d) => {
+                out.push(12);
+                out.extend(d.to_le_bytes());
+            }
+            Msg::Abort => out.push(13),
+            Msg::Nonce(n) => {
+                out.push(14);
+                out.extend(n.to_le_bytes());
+            }
+        }
+    }
+    // feeds the encoding of the message to the hasher
+    fn digest(&self, h: &mut Fnv1a) {
+        let mut bytes = Vec::new();
+        self.encode(&mut bytes);
+        h.write(&bytes);
+    }
+    // not part of the protocol! the message with the first value it carries changed to another one, which is below
+    // any q it was below. messages which only come from the dealer are returned unchanged
+    fn tampered(self) -> Self {
+        let nudge = |v: u32| if v == 0 { 1 } else { v - 1 };
+        let nudge_sharing = |s: T| {
+            let mut c = s.components();
+            c[0] = nudge(c[0]);
+            T::from_components(&c).unwrap_or(s)
+        };
+
+        match self {
+            Msg::Value(v) => Msg::Value(nudge(v)),
+            Msg::Singleton(s) => Msg::Singleton(nudge_sharing(s)),
+            Msg::Openings(mut v) if !v.is_empty() => {
+                let first = nudge_sharing(v.remove(0));
+                v.insert(0, first);
+                Msg::Openings(v)
+            }
+            Msg::Values(mut v) if !v.is_empty() => {
+                v[0] = nudge(v[0]);
+                Msg::Values(v)
+            }
+            Msg::Batch(mut msgs) if !msgs.is_empty() => {
+                let first = msgs.remove(0).tampered();
+                msgs.insert(0, first);
+                Msg::Batch(msgs)
+            }
+            Msg::Digest(d) => Msg::Digest(d ^ 1),
+            Msg::Nonce(n) => Msg::Nonce(n ^ 1),
+            m => m,
+        }
+    }
+    // the message as recorded in a party's view, if it is one the parties exchange
+    fn entry(&self) -> Option<TranscriptEntry> {
+        match self {
+            Msg::Value(v) => Some(TranscriptEntry::Value(*v)),
+            Msg::Singleton(s) => Some(TranscriptEntry::Opening(s.components())),
+            Msg::Openings(v) => Some(TranscriptEntry::Openings(v.iter().map(|s| s.components()).collect())),
+            Msg::Values(v) => Some(TranscriptEntry::Values(v.clone())),
+            Msg::Digest(d) => Some(TranscriptEntry::Digest(*d)),
+            Msg::Nonce(n) => Some(TranscriptEntry::Nonce(*n)),
+            Msg::Abort => Some(TranscriptEntry::Abort),
+            _ => None,
+        }
+    }
+}
+
+// sending end of a channel which keeps count of the messages sent through it. if coalescing, messages are
+// buffered until the next flush and then sent as a single batch
+struct Link<T: Sharing> {
+    tx: Tx<T>,
+    sent: Cell<usize>,
+    coalesce: bool,
+    buffer: RefCell<Vec<Msg<T>>>,
+    // simulated network delay of each delivered message
+    latency: Option<Duration>,
+    // running hash of the messages sent, before any coalescing
+    digest: RefCell<Fnv1a>,
+}
+
+impl<T: Sharing> Link<T> {
+    fn new(tx: Tx<T>, coalesce: bool, latency: Option<Duration>) -> Self {
+        Link {
+            tx,
+            sent: Cell::new(0),
+            coalesce,
+            buffer: RefCell::new(Vec::new()),
+            latency,
+            digest: RefCell::new(Fnv1a::new()),
+        }
+    }
+    fn send(&self, msg: Msg<T>) -> Result<(), SendError<Msg<T>>> {
+        msg.digest(&mut self.digest.borrow_mut());
+        if self.coalesce {
+            self.buffer.borrow_mut().push(msg);
+            return Ok(());
+        }
+        self.deliver(msg)
+    }
+    fn flush(&self) -> Result<(), SendError<Msg<T>>> {
+        let mut buffered: Vec<Msg<T>> = self.buffer.borrow_mut().drain(..).collect();
+        let msg = match buffered.len() {
+            0 => return Ok(()),
+            1 => buffered.pop().unwrap(),
+            _ => Msg::Batch(buffered),
+        };
+        self.deliver(msg)
+    }
+    fn digest(&self) -> u64 {
+        self.digest.borrow().finish()
+    }
+    fn deliver(&self, msg: Msg<T>) -> Result<(), SendError<Msg<T>>> {
+        if let Some(l) = self.latency {
+            thread::sleep(l);
+        }
+        self.sent.set(self.sent.get() + 1);
+        self.tx.send(msg)
+    }
+}
+
+// a bounded channel makes the sender wait for the receiver once it holds as many messages as its capacity
+enum Tx<T: Sharing> {
+    Unbounded(Sender<Msg<T>>),
+    Bounded(SyncSender<Msg<T>>, Arc<QueueLength>),
+    // not part of the protocol! an unbounded channel which alters the message with the given index (counted from 0)
+    // in transit, after the sender hashed it. the second value counts the messages sent so far
+    Tampering(Sender<Msg<T>>, usize, Cell<usize>),
+    // the outbox of a state machine, which its driver empties, with the endpoint the messages are addressed to
+    Mailbox(Arc<Mutex<Mailbox<T>>>, Endpoint),
+}
+
+impl<T: Sharing> Tx<T> {
+    fn send(&self, msg: Msg<T>) -> Result<(), SendError<Msg<T>>> {
+        match self {
+            Tx::Unbounded(tx) => tx.send(msg),
+            Tx::Bounded(tx, queue) => tx.send(msg).inspect(|_| queue.pushed()),
+            Tx::Tampering(tx, index, sent) => {
+                let i = sent.replace(sent.get() + 1);
+                tx.send(if i == *index { msg.tampered() } else { msg })
+            }
+            Tx::Mailbox(mailbox, to) => {
+                mailbox.lock().unwrap().outgoing.push(Outgoing { to: *to, msg });
+                Ok(())
+            }
+        }
+    }
+}
+
+// number of messages a bounded channel holds, counted up by the sender once each is in and down by the receiver
+// once it is out. it thus exceeds the true length by one at most, while a message taken out is not counted yet
+#[derive(Default)]
+struct QueueLength {
+    len: AtomicIsize,
+    peak: AtomicIsize,
+}
+
+impl QueueLength {
+    fn pushed(&self) {
+        let len = self.len.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak.fetch_max(len, Ordering::SeqCst);
+    }
+    fn popped(&self) {
+        self.len.fetch_sub(1, Ordering::SeqCst);
+    }
+    fn peak(&self) -> usize {
+        self.peak.load(Ordering::SeqCst) as usize
+    }
+}
+
+// receiving end of a channel, which keeps the length of a bounded one up to date
+struct Rx<T: Sharing> {
+    rx: Receiver<Msg<T>>,
+    queue: Option<Arc<QueueLength>>,
+}
+
+impl<T: Sharing> Rx<T> {
+    fn recv(&self) -> Result<Msg<T>, RecvError> {
+        let msg = self.rx.recv()?;
+        self.queue.iter().for_each(|q| q.popped());
+        Ok(msg)
+    }
+    fn try_iter(&self) -> impl Iterator<Item = Msg<T>> + '_ {
+        std::iter::from_fn(|| {
+            let msg = self.rx.try_recv().ok()?;
+            self.queue.iter().for_each(|q| q.popped());
+            Some(msg)
+        })
+    }
+}
+
+fn channel<T: Sharing>(capacity: Option<usize>) -> (Tx<T>, Rx<T>) {
+    match capacity {
+        Some(c) => {
+            let (tx, rx) = mpsc::sync_channel(c);
+            let queue = Arc::new(QueueLength::default());
+            (Tx::Bounded(tx, queue.clone()), Rx { rx, queue: Some(queue) })
+        }
+        None => {
+            let (tx, rx) = mpsc::channel();
+            (Tx::Unbounded(tx), Rx { rx, queue: None })
+        }
+    }
+}
+
+fn tampering_channel<T: Sharing>(index: usize) -> (Tx<T>, Rx<T>) {
+    let (tx, rx) = mpsc::channel();
+    (Tx::Tampering(tx, index, Cell::new(0)), Rx { rx, queue: None })
+}
+
+// where a message comes from or goes to
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Endpoint {
+    // 0 being the main dealer
+    Dealer(usize),
+    Party(Party),
+}
+
+// a message a state machine hands to its driver for delivery
+pub struct Outgoing<T: Sharing> {
+    pub to: Endpoint,
+    pub msg: Msg<T>,
+}
+
+// what a state machine shares with its driver: the messages it sent which were not handed out yet and, while it
+// waits, the endpoint it waits for and the message (or disconnection) handed in for it
+pub(crate) struct Mailbox<T: Sharing> {
+    awaiting: Option<Endpoint>,
+    delivered: Option<Result<Msg<T>, RecvError>>,
+    outgoing: Vec<Outgoing<T>>,
+}
+
+impl<T: Sharing> Mailbox<T> {
+    fn shared() -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Mailbox { awaiting: None, delivered: None, outgoing: Vec::new() }))
+    }
+}
+
+// receiving end of the messages from one endpoint, handed in through the mailbox, which transparently unpacks
+// batches
+struct Inbox<T: Sharing> {
+    from: Endpoint,
+    mailbox: Arc<Mutex<Mailbox<T>>>,
+    pending: RefCell<VecDeque<Msg<T>>>,
+    // running hash of the messages received, after unpacking, which matches that of the sender's Link
+    digest: RefCell<Fnv1a>,
+}
+
+impl<T: Sharing> Inbox<T> {
+    fn new(from: Endpoint, mailbox: Arc<Mutex<Mailbox<T>>>) -> Self {
+        Inbox { from, mailbox, pending: RefCell::new(VecDeque::new()), digest: RefCell::new(Fnv1a::new()) }
+    }
+    fn digest(&self) -> u64 {
+        self.digest.borrow().finish()
+    }
+    async fn recv(&self) -> Result<Msg<T>, RecvError> {
+        loop {
+            let pending = self.pending.borrow_mut().pop_front();
+            let msg = match pending {
+                Some(m) => m,
+                None => self.next().await?,
+            };
+            match msg {
+                Msg::Batch(msgs) => {
+                    let mut pending = self.pending.borrow_mut();
+                    for m in msgs.into_iter().rev() {
+                        pending.push_front(m);
+                    }
+                }
+                m => {
+                    m.digest(&mut self.digest.borrow_mut());
+                    return Ok(m);
+                }
+            }
+        }
+    }
+    // the next message from the endpoint, which suspends the party until its driver hands one in
+    fn next(&self) -> impl Future<Output = Result<Msg<T>, RecvError>> + '_ {
+        future::poll_fn(move |_| {
+            let mut mailbox = self.mailbox.lock().unwrap();
+            match mailbox.delivered.take() {
+                Some(r) => {
+                    mailbox.awaiting = None;
+                    Poll::Ready(r)
+                }
+                None => {
+                    mailbox.awaiting = Some(self.from);
+                    Poll::Pending
+                }
+            }
+        })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum LogLevel {
+    // nothing is recorded and no log files are written, e.g. for benchmarking
+    Off,
+    #[default]
+    Normal,
+    // as Normal, but the components of authenticated sharings are labelled
+    Verbose,
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "off" => Ok(LogLevel::Off),
+            "normal" => Ok(LogLevel::Normal),
+            "verbose" => Ok(LogLevel::Verbose),
+            l => Err(format!("should be off, normal or verbose, not {l}")),
+        }
+    }
+}
+
+// the execution log of the dealer or one of the parties
+struct Transcript {
+    level: LogLevel,
+    text: String,
+    // if streamed, the log file the text is moved to whenever it holds the given number of lines, along with the
+    // number it holds so far
+    sink: Option<(BufWriter<File>, usize, usize)>,
+    // the first error opening or writing to the log file, reported by write_log
+    error: Option<io::Error>,
+}
+
+impl Transcript {
+    fn new(level: LogLevel) -> Self {
+        Transcript {
+            level,
+            text: String::new(),
+            sink: None,
+            error: None,
+        }
+    }
+    // a transcript streamed to the log file at path (if any) every flush_lines lines (if set), or otherwise kept in
+    // memory until write_log
+    fn open(level: LogLevel, path: &Option<String>, flush_lines: Option<usize>) -> Self {
+        let mut o = Transcript::new(level);
+        if let (Some(path), Some(n), true) = (path, flush_lines, o.enabled()) {
+            match File::create(path) {
+                Ok(f) => o.sink = Some((BufWriter::new(f), n.max(1), 0)),
+                Err(e) => o.error = Some(e),
+            }
+        }
+        o
+    }
+    fn enabled(&self) -> bool {
+        self.level != LogLevel::Off
+    }
+    fn push_str(&mut self, s: &str) {
+        if self.enabled() {
+            self.text.push_str(s);
+            self.count_lines(s.matches('\n').count());
+        }
+    }
+    fn push(&mut self, c: char) {
+        if self.enabled() {
+            self.text.push(c);
+            self.count_lines((c == '\n') as usize);
+        }
+    }
+    fn count_lines(&mut self, n: usize) {
+        if let Some((_, flush_lines, lines)) = &mut self.sink {
+            *lines += n;
+            if *lines >= *flush_lines {
+                *lines = 0;
+                self.drain();
+            }
+        }
+    }
+    // moves the text to the sink, if any
+    fn drain(&mut self) {
+        if let Some((w, ..)) = &mut self.sink {
+            if let Err(e) = w.write_all(self.text.as_bytes()) {
+                self.error.get_or_insert(e);
+            }
+            self.text.clear();
+        }
+    }
+}
+
 struct Dealer<T: Sharing> {
     circuit: Circuit,
-    q: u32,
-    tx_d_p1: Sender<Msg<T>>,
-    tx_d_p2: Sender<Msg<T>>,
-    log_path: String,
+    field: ModField,
+    tx_d_p1: Link<T>,
+    tx_d_p2: Link<T>,
+    log_path: Option<String>,
+    log_level: LogLevel,
+    log_flush_lines: Option<usize>,
+    rng: ChaCha20Rng,
+    // display names of P1 and P2 in the log
+    names: (String, String),
+    blind_outputs: bool,
+    // number of triples the dealer generates, which with several dealers is only its share of them, not counting
+    // those over-provisioned for an audit
+    num_triples: usize,
+    triple_audit: Option<f32>,
+    // if set, the dealer skips the generation of triples altogether, which the circuit must not need
+    no_triples: bool,
+    // input wires shared externally, which get no singleton
+    external_inputs: HashSet<InputWire>,
+    // if set, the masks are sent to each party in an order shuffled with this seed, a different one for each party
+    #[cfg(test)]
+    shuffle_masks: Option<u64>,
+    // the other dealers, which MAC their triples with the keys sent through these
+    tx_keys: Vec<Sender<(u32, u32)>>,
+    // not part of the protocol! if set, the product of each triple is off by one with this probability
+    corrupt: Option<f32>,
+    // if set, the keys to MAC with instead of fresh ones
+    keys: Option<(u32, u32)>,
+    // the keys the dealer MACs with, exposed to the parties in debug builds only (see ProtocolParty::debug_keys)
+    #[cfg(debug_assertions)]
+    debug_keys: Arc<OnceLock<(u32, u32)>>,
+}
+
+// an additional dealer, which only generates Beaver triples with the keys of the main one
+struct TripleDealer<T: Sharing> {
+    // 0 being the main dealer
+    index: usize,
+    field: ModField,
+    num_triples: usize,
+    rx_keys: Receiver<(u32, u32)>,
+    tx_d_p1: Link<T>,
+    tx_d_p2: Link<T>,
+    log_path: Option<String>,
+    log_level: LogLevel,
+    log_flush_lines: Option<usize>,
+    rng: ChaCha20Rng,
+    names: (String, String),
+    corrupt: Option<f32>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Phase {
+    Preprocessing,
+    KeyOpening,
+    TripleCheck,
+    InputProcessing,
+    GateProcessing,
+    OutputProcessing,
+}
+
+impl Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Phase::Preprocessing => write!(f, "preprocessing"),
+            Phase::KeyOpening => write!(f, "key opening"),
+            Phase::TripleCheck => write!(f, "triple check"),
+            Phase::InputProcessing => write!(f, "input processing"),
+            Phase::GateProcessing => write!(f, "gate processing"),
+            Phase::OutputProcessing => write!(f, "output processing"),
+        }
+    }
 }
 
+// number of triples generated from each independent random stream by the dealer
+const TRIPLE_CHUNK_SIZE: u64 = 1024;
+
 // a value in [0, 1] indicating how likely a corrupt party is to tamper with each opening it sends
-const CORRUPTION_DEGREE: f32 = 0.2;
+pub const CORRUPTION_DEGREE: f32 = 0.2;
+
+pub struct CorruptionConfig {
+    // a value in [0, 1] indicating how likely the corrupt party is to tamper with each opening it sends
+    pub degree: f32,
+    // the corrupt party; if unset, it is chosen at random
+    pub party: Option<Party>,
+}
+
+impl Default for CorruptionConfig {
+    fn default() -> Self {
+        CorruptionConfig {
+            degree: CORRUPTION_DEGREE,
+            party: None,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ProtocolOptions {
+    pub authenticated: bool,
+    // if set, one of the parties (chosen at random unless specified) is corrupt
+    pub corruption: Option<CorruptionConfig>,
+    // if set, all randomness of the run (dealer, corrupt party) is derived from it
+    pub seed: Option<u64>,
+    // a full 256-bit master seed, e.g. from utilities::seed_from_hex, which takes precedence over seed
+    pub master_seed: Option<[u8; 32]>,
+    // partial path for the execution logs; if unset, no logs are written
+    pub output_path: Option<String>,
+    // display names for P1 and P2 in logs, errors and log file names; if unset, "P1" and "P2" are used
+    pub party_names: Option<(String, String)>,
+    pub log_level: LogLevel,
+    // level of the logs of the dealers, which hold every key sharing, singleton and triple and dominate disk usage
+    // for large circuits. if unset, log_level is used
+    pub dealer_log_level: Option<LogLevel>,
+    // if set, the parties periodically save their state so that an interrupted run can be resumed
+    pub checkpoint: Option<CheckpointConfig>,
+    // not part of the protocol! makes the given party stop abruptly right before processing the given gate
+    pub crash_at: Option<(Party, u32)>,
+    // makes the given party panic right before processing the given gate, as a bug in it would
+    #[cfg(test)]
+    pub panic_at: Option<(Party, u32)>,
+    // makes the dealer send the input masks in an order shuffled with the given seed
+    #[cfg(test)]
+    pub shuffle_masks: Option<u64>,
+    // if set, consecutive messages on each channel are sent as a single batch
+    pub coalesce: bool,
+    // if set, at most this many messages from the dealer are queued for each party at any time
+    pub channel_capacity: Option<usize>,
+    // if set, each output revealed to a single party is blinded with a value only that party knows before being
+    // opened, so that the opening itself carries no information about the output
+    pub blind_outputs: bool,
+    // if set, every message between the parties is delayed by this long before being delivered, which makes the
+    // cost of each communication round observable. messages from the dealer are not delayed
+    pub latency: Option<Duration>,
+    // number of dealers generating the Beaver triples. with more than one, each triple comes from a randomly chosen
+    // dealer and is checked by sacrificing another one from a different dealer, so that a single corrupt dealer is
+    // caught. the rest of the preprocessing material still comes from the main dealer
+    pub dealers: usize,
+    // not part of the protocol! makes the dealer with the given index (0 being the main one) generate bad triples
+    // with the given probability in [0, 1]
+    pub corrupt_dealer: Option<(usize, f32)>,
+    // if set, the dealer over-provisions triples and the parties open this fraction (in (0, 1)) of them at random,
+    // aborting if any is bad. a lighter alternative to several dealers, which catches a dealer corrupting many
+    // triples with high probability rather than always
+    pub triple_audit: Option<f32>,
+    // if set, the dealer generates no triples, which only suits circuits without multiplications or other gates
+    // consuming them. meant to isolate the additive parts of the protocol
+    pub no_dealer_triples: bool,
+    // if set, the multiplications of two public inputs are folded before the run (see Circuit::fold_constants), so
+    // that the dealer generates no triples for them
+    pub fold_constants: bool,
+    // if set, its hook is called by each party after each gate, e.g. to build debuggers on top of the protocol.
+    // it does not alter the run in any way
+    pub trace: Option<TraceConfig>,
+    // if set, input values not below q are reduced modulo q instead of being rejected
+    pub reduce_inputs: bool,
+    // if set, each party records the messages it exchanges with the other one as typed values, returned in the
+    // views of the result
+    pub record_views: bool,
+    // not part of the protocol! if set, the value of every wire is reconstructed from the shares of both parties
+    // once the run is over, which reveals all the inputs and is only meant to debug circuits
+    pub insecure_audit: bool,
+    // if set, the execution logs are written to their files every this many lines instead of all at once at the
+    // end, so that the memory they take stays bounded for very long runs
+    pub log_flush_lines: Option<usize>,
+    // input wires shared by an upstream protocol, e.g. as the output of another computation. they skip input
+    // processing and need no singleton from the dealer. their values in the inputs are not used, but must still
+    // be given (e.g. as 0) so that the input counts match the circuit. unauthenticated runs only
+    pub external_inputs: Vec<ExternalInput>,
+    // not part of the protocol! if set, each party also hands the other the openings it sends before any
+    // tampering, so that a failed MAC check can be traced back to a tampered opening or to a sharing which was
+    // already inconsistent (e.g. from a bad triple). this reveals everything opened, and is only meant to debug
+    pub explain_aborts: bool,
+    // not part of the protocol! if set, the message of the given party to the other one with the given index
+    // (counted from 0, after any coalescing) is altered in transit, which the transcript digests should catch
+    pub tamper_in_transit: Option<(Party, usize)>,
+    // if set, the dealer MACs with the keys of this epoch instead of drawing fresh ones, and the parties record the
+    // epoch in their logs and checkpoints. set by sessions (see session.rs), which reuse keys until refreshed
+    pub key_epoch: Option<KeyEpoch>,
+}
+
+// the MAC keys k1 and k2 of a session, along with the number of times they have been refreshed plus one
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct KeyEpoch {
+    pub epoch: u32,
+    pub keys: (u32, u32),
+}
+
+// the shares of an input wire held by P1 and P2, each given by its components (see Sharing::components). the
+// value of the wire is whatever they add up to modulo q
+#[derive(Clone, PartialEq, Debug)]
+pub struct ExternalInput {
+    pub wire: InputWire,
+    pub shares: (Vec<u32>, Vec<u32>),
+}
+
+// the messages a party sent to and received from the other party, in order and labelled with the phase the party
+// was in. messages from the dealer are not included, and a resumed run only records those after the checkpoint
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct PartyView {
+    pub sent: Vec<(Phase, TranscriptEntry)>,
+    pub received: Vec<(Phase, TranscriptEntry)>,
+}
+
+// a message exchanged between the parties, with sharings given by their components
+#[derive(Clone, PartialEq, Debug)]
+pub enum TranscriptEntry {
+    Value(u32),
+    Opening(Vec<u32>),
+    Openings(Vec<Vec<u32>>),
+    Values(Vec<u32>),
+    Digest(u64),
+    Nonce(u64),
+    Abort,
+}
+
+// reported by each party right after processing each gate, in topology order
+#[derive(Clone, Debug)]
+pub struct GateTrace {
+    pub gate: u32,
+    // as in the circuit encoding, e.g. "mulc"
+    pub op: &'static str,
+    pub party: Party,
+    // components of the party's share of the gate's output, unless redacted
+    pub share: Option<Vec<u32>>,
+    // by this and the preceding gates
+    pub triples_consumed: usize,
+}
+
+#[derive(Clone)]
+pub struct TraceConfig {
+    // called from the threads of both parties
+    pub hook: Arc<dyn Fn(GateTrace) + Send + Sync>,
+    // if set, the events carry no shares
+    pub redact: bool,
+}
+
+#[derive(Clone)]
+pub struct CheckpointConfig {
+    // partial path for the checkpoint files, completed as for the log files but with extension .ckpt
+    pub path: String,
+    // number of gates of the topology between consecutive checkpoints
+    pub interval: usize,
+}
+
+// number of messages sent through each channel during a run
+#[derive(Default, Serialize)]
+pub struct CommStats {
+    pub dealer_to_p1: usize,
+    pub dealer_to_p2: usize,
+    pub p1_to_p2: usize,
+    pub p2_to_p1: usize,
+    // with bounded channels, the most messages from any dealer queued for either party at any time (give or take
+    // one, see QueueLength)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peak_dealer_queue: Option<usize>,
+}
+
+// logical communication rounds between the parties, i.e. those of a schedule which exchanges all independent
+// openings at once. the run itself processes inputs and gates one at a time, so it takes more
+#[derive(Clone, Copy, PartialEq, Debug, Serialize)]
+pub struct RoundReport {
+    pub key_opening: usize,
+    // with several dealers, multiplication with the sacrificed triples, commitment to nonces, their opening, then
+    // opening of a random combination of the differences. with an audit, exchange of nonces, then opening of the
+    // audited triples
+    pub triple_check: usize,
+    // opening of the masks to the input owners, then sending of the deltas
+    pub input_processing: usize,
+    // the interactive depth of the circuit
+    pub gate_processing: usize,
+    // opening of the outputs, then cross-check of those revealed to both parties, then exchange of transcript
+    // digests
+    pub output_processing: usize,
+}
+
+impl RoundReport {
+    pub fn new(circuit: &Circuit) -> Self {
+        let shared_outputs = circuit
+            .get_outputs(Party::P1)
+            .iter()
+            .any(|id| circuit.get_outputs(Party::P2).contains(id));
+        let any_outputs = !circuit.get_outputs(Party::P1).is_empty() || !circuit.get_outputs(Party::P2).is_empty();
+
+        RoundReport {
+            key_opening: 1,
+            triple_check: 0,
+            input_processing: if circuit.total_input_wires() > 0 { 2 } else { 0 },
+            gate_processing: circuit.interactive_depth(),
+            output_processing: any_outputs as usize + shared_outputs as usize + 1,
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.key_opening + self.triple_check + self.input_processing + self.gate_processing + self.output_processing
+    }
+}
+
+impl Display for RoundReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // the triple check is only listed if it took place
+        let triple_check = match self.triple_check {
+            0 => String::new(),
+            n => format!(", triple check {n}"),
+        };
+
+        write!(
+            f,
+            "Communication rounds: {} (key opening {}{}, input processing {}, gate processing {}, output processing {})",
+            self.total(),
+            self.key_opening,
+            triple_check,
+            self.input_processing,
+            self.gate_processing,
+            self.output_processing
+        )
+    }
+}
+
+// the correlated randomness the dealer generates for a run, computed from the circuit alone without generating any
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PreprocessingEstimate {
+    // of k1 and k2
+    pub key_sharings: usize,
+    pub input_singletons: usize,
+    pub triples: usize,
+    // for dot, truncation, comparison and equality gates
+    pub other_sharings: usize,
+    // size of the values sent by the dealer to both parties, at 4 bytes per u32 and ignoring message framing
+    pub transcript_bytes: usize,
+}
+
+impl PreprocessingEstimate {
+    pub fn new(circuit: &Circuit, authenticated: bool) -> Self {
+        let key_sharings = 2;
+        let input_singletons = circuit.total_input_wires();
+        let triples = circuit.num_beaver_triples();
+        let other_sharings = circuit.dot_gate_lengths().iter().map(|n| 2 * n + 1).sum::<usize>()
+            + 2 * circuit.truncation_shifts().len()
+            + circuit.comparison_bit_lengths().iter().map(|k| *k as usize).sum::<usize>()
+            + circuit.num_equality_tests() * utilities::EQUALITY_BITS as usize;
+
+        // each sharing is sent as one share to each party. key sharings are always unauthenticated, whereas the
+        // others carry two MACs besides the value in authenticated runs
+        let components = if authenticated { 3 } else { 1 };
+        let values = 2 * (key_sharings + components * (input_singletons + 3 * triples + other_sharings));
+
+        PreprocessingEstimate {
+            key_sharings,
+            input_singletons,
+            triples,
+            other_sharings,
+            transcript_bytes: 4 * values,
+        }
+    }
+}
+
+impl Display for PreprocessingEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "This run will generate {} triples, {} input singletons, producing ~{} bytes of transcript.",
+            self.triples, self.input_singletons, self.transcript_bytes
+        )
+    }
+}
+
+// the cost of a run, computed from the circuit alone without running the protocol. it assumes the default options:
+// a single dealer, no triple audit, blinding or checkpoints, and no coalescing of messages
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CostReport {
+    pub preprocessing: PreprocessingEstimate,
+    pub rounds: RoundReport,
+    // messages sent by the dealer to each party
+    pub dealer_messages: usize,
+    // messages sent by each party to the other, the same for both
+    pub messages: MessageCounts,
+    // size of the values exchanged by the parties in both directions, at 4 bytes per u32 and ignoring message
+    // framing
+    pub online_bytes: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MessageCounts {
+    pub key_opening: usize,
+    pub input_processing: usize,
+    pub gate_processing: usize,
+    pub output_processing: usize,
+}
+
+impl MessageCounts {
+    pub fn total(&self) -> usize {
+        self.key_opening + self.input_processing + self.gate_processing + self.output_processing
+    }
+}
+
+pub fn estimate_cost(circuit: &Circuit, authenticated: bool) -> CostReport {
+    let preprocessing = PreprocessingEstimate::new(circuit, authenticated);
+    let components = if authenticated { 3 } else { 1 };
+
+    let gates: Vec<&Gate> = circuit.get_topology().iter().filter_map(|id| circuit.get_gate(id)).collect();
+    let inputs = circuit.total_input_wires();
+    let outputs = circuit.get_outputs(Party::P1).len() + circuit.get_outputs(Party::P2).len();
+    let shared_outputs = circuit
+        .get_outputs(Party::P1)
+        .iter()
+        .filter(|id| circuit.get_outputs(Party::P2).contains(id))
+        .count();
+
+    // keys, masks and one message per triple or other preprocessing sharing
+    let dealer_messages = 2
+        + inputs
+        + preprocessing.triples
+        + circuit.dot_gate_lengths().len()
+        + circuit.truncation_shifts().len()
+        + circuit.comparison_bit_lengths().len()
+        + circuit.num_equality_tests();
+
+    // per input wire, one party opens the mask and the owner sends the delta. at the end, each party opens the
+    // other's outputs at once, the shared ones are cross-checked and the transcript digests exchanged
+    let messages = MessageCounts {
+        key_opening: 1,
+        input_processing: inputs,
+        gate_processing: gates.iter().map(|g| g.num_messages()).sum(),
+        output_processing: 2 + (shared_outputs > 0) as usize,
+    };
+
+    let openings = inputs + 2 * gates.iter().map(|g| g.num_openings()).sum::<usize>() + outputs;
+    let values = 2 + inputs + 2 * shared_outputs;
+
+    CostReport {
+        preprocessing,
+        rounds: RoundReport::new(circuit),
+        dealer_messages,
+        messages,
+        online_bytes: 4 * (components * openings + values) + 2 * 8,
+    }
+}
+
+impl Display for CostReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.preprocessing)?;
+        writeln!(f, "{}", self.rounds)?;
+        writeln!(
+            f,
+            "Messages per party: {} (key opening {}, input processing {}, gate processing {}, output processing {}), plus {} from the dealer",
+            self.messages.total(),
+            self.messages.key_opening,
+            self.messages.input_processing,
+            self.messages.gate_processing,
+            self.messages.output_processing,
+            self.dealer_messages
+        )?;
+        write!(f, "Data exchanged between the parties: ~{} bytes", self.online_bytes)
+    }
+}
+
+pub struct ProtocolResult {
+    pub outputs_p1: HashMap<u32, u32>,
+    pub outputs_p2: HashMap<u32, u32>,
+    // wall-clock time from the parsing of the circuit until all threads have finished
+    pub elapsed: Duration,
+    pub triples: usize,
+    pub stats: CommStats,
+    pub rounds: RoundReport,
+    // views of P1 and P2, if recorded
+    pub views: Option<(PartyView, PartyView)>,
+    // the value of every wire, reconstructed from the shares of both parties in an insecure audit
+    pub wire_values: Option<HashMap<u32, u32>>,
+}
 
 impl<T: Sharing> Dealer<T> {
-    fn run(&self) -> Result<(), String> {
-        let rng = &mut ChaCha20Rng::from_entropy();
+    fn run(&mut self) -> Result<(), BeaverError> {
+        let rng = &mut self.rng;
 
-        let o = &mut String::new();
+        let o = &mut Transcript::open(self.log_level, &self.log_path, self.log_flush_lines);
 
         o.push_str("**** Distribution of key sharings\n");
 
         // distributing key sharings (not of type T: the former are always unauthenticated)
-        let k1 = utilities::safe_gen_mod(rng, self.q);
-        let k2 = utilities::safe_gen_mod(rng, self.q);
-        let (sk11, sk12) = UnauthSharing::share(k1, 0, 0, rng, self.q);
-        let (sk21, sk22) = UnauthSharing::share(k2, 0, 0, rng, self.q);
+        let (k1, k2) = self.keys.unwrap_or_else(|| (self.field.random(rng), self.field.random(rng)));
+        #[cfg(debug_assertions)]
+        self.debug_keys.set((k1, k2));
+        for tx in &self.tx_keys {
+            tx.send((k1, k2));
+        }
+        let (sk11, sk12) = UnauthSharing::share(k1, 0, 0, rng, &self.field);
+        let (sk21, sk22) = UnauthSharing::share(k2, 0, 0, rng, &self.field);
 
         self.tx_d_p1.send(Msg::Value(sk11.value()));
-        log(o, false, "P1", "sharing of k1", &sk11);
+        log(o, false, &self.names.0, "sharing of k1", &sk11);
         self.tx_d_p1.send(Msg::Value(sk21.value()));
-        log(o, false, "P2", "sharing of k1", &sk12);
+        log(o, false, &self.names.0, "sharing of k2", &sk21);
         self.tx_d_p2.send(Msg::Value(sk12.value()));
-        log(o, false, "P1", "sharing of k2", &sk21);
+        log(o, false, &self.names.1, "sharing of k1", &sk12);
         self.tx_d_p2.send(Msg::Value(sk22.value()));
-        log(o, false, "P2", "sharing of k2", &sk22);
+        log(o, false, &self.names.1, "sharing of k2", &sk22);
+        self.tx_d_p1.flush();
+        self.tx_d_p2.flush();
 
         o.push_str("\n**** Distribution of singleton sharings for inputs\n");
         // distributing sharings for input wires
-        for _ in 0..self.circuit.total_input_wires() {
-            let (s1, s2) = T::share(utilities::safe_gen_mod(rng, self.q), k1, k2, rng, self.q);
+        #[allow(unused_mut)]
+        let (mut masks_p1, mut masks_p2): (Vec<_>, Vec<_>) = self
+            .circuit
+            .input_wires()
+            .into_iter()
+            .filter(|w| !self.external_inputs.contains(w))
+            .map(|wire| {
+                let (s1, s2) = T::share(self.field.random(rng), k1, k2, rng, &self.field);
+                ((wire, s1), (wire, s2))
+            })
+            .unzip();
+        #[cfg(test)]
+        if let Some(seed) = self.shuffle_masks {
+            use rand::seq::SliceRandom;
+            masks_p1.shuffle(&mut ChaCha20Rng::seed_from_u64(seed));
+            masks_p2.shuffle(&mut ChaCha20Rng::seed_from_u64(seed + 1));
+        }
+        for ((wire1, s1), (wire2, s2)) in masks_p1.into_iter().zip(masks_p2) {
+            let names = (self.names.0.as_str(), self.names.1.as_str());
+            let desc = format!("singleton sharing for {}", wire1.named(names));
+            self.tx_d_p1.send(Msg::Mask(wire1, log(o, false, &self.names.0, &desc, s1)));
+            let desc = format!("singleton sharing for {}", wire2.named(names));
+            self.tx_d_p2.send(Msg::Mask(wire2, log(o, false, &self.names.1, &desc, s2)));
+        }
+        self.tx_d_p1.flush();
+        self.tx_d_p2.flush();
+
+        if self.no_triples {
+            o.push_str("\n**** Triple generation disabled\n");
+        } else {
+            o.push_str("\n**** Distribution of triple sharings for multiplication gates\n");
+            deal_triples(
+                o,
+                rng,
+                (k1, k2),
+                &self.field,
+                self.num_triples + audited_triples(self.num_triples, self.triple_audit),
+                self.corrupt.map(|p| ("dealer", p)),
+                (&self.tx_d_p1, &self.tx_d_p2),
+                &self.names,
+            );
+        }
+
+        let dot_lengths = self.circuit.dot_gate_lengths();
+
+        if !dot_lengths.is_empty() {
+            o.push_str("\n**** Distribution of inner-product sharings for dot gates\n");
+        }
+
+        // a single tuple (a, b, <a, b>) per dot gate, rather than one triple per product
+        let f = Reducer::new(self.field.q);
+        for n in dot_lengths {
+            let a: Vec<u32> = (0..n).map(|_| self.field.random(rng)).collect();
+            let b: Vec<u32> = (0..n).map(|_| self.field.random(rng)).collect();
+            let c = a.iter().zip(&b).fold(0, |acc, (a, b)| f.add(acc, f.mul(*a, *b)));
+
+            let (a1, a2): (Vec<T>, Vec<T>) = a.iter().map(|v| T::share(*v, k1, k2, rng, &self.field)).unzip();
+            let (b1, b2): (Vec<T>, Vec<T>) = b.iter().map(|v| T::share(*v, k1, k2, rng, &self.field)).unzip();
+            let (c1, c2) = T::share(c, k1, k2, rng, &self.field);
+
+            let t1 = InnerProductSharing(a1, b1, c1);
+            let t2 = InnerProductSharing(a2, b2, c2);
             self.tx_d_p1
-                .send(Msg::Singleton(log(o, false, "P1", "singleton sharing", s1)));
+                .send(Msg::InnerProduct(log(o, false, &self.names.0, "inner-product sharing", t1)));
             self.tx_d_p2
-                .send(Msg::Singleton(log(o, false, "P2", "singleton sharing", s2)));
+                .send(Msg::InnerProduct(log(o, false, &self.names.1, "inner-product sharing", t2)));
         }
+        self.tx_d_p1.flush();
+        self.tx_d_p2.flush();
 
-        o.push_str("\n**** Distribution of triple sharings for multiplication gates\n");
-        // need to run over the topology (rather than the hashmap of gates) to ensure order
-        for id in self.circuit.get_topology() {
-            // always gets a valid gate by construction
-            match self.circuit.get_gate(id).unwrap() {
-                Gate::GateWithoutC {
-                    op: GateOp::Mul, ..
-                } => {
-                    let (t1, t2) = T::beaver_share(k1, k2, self.q, rng);
-                    self.tx_d_p1
-                        .send(Msg::Triple(log(o, false, "P1", "triple sharing", t1)));
-                    self.tx_d_p2
-                        .send(Msg::Triple(log(o, false, "P2", "triple sharing", t2)));
-                }
-                _ => (),
+        let shifts = self.circuit.truncation_shifts();
+
+        if !shifts.is_empty() {
+            o.push_str("\n**** Distribution of truncation sharings for truncation gates\n");
+        }
+
+        // r has TRUNCATION_SECURITY more bits than the (shifted) inputs it masks, so that the opening of the sum
+        // reveals almost nothing about the input while never wrapping around q
+        let r_bits = utilities::truncation_bits(self.field.q) + utilities::TRUNCATION_SECURITY;
+        for f in shifts {
+            let r = F32::new(1 << r_bits).random(rng);
+            let (r1, r2) = T::share(r, k1, k2, rng, &self.field);
+            let (h1, h2) = T::share(r >> f, k1, k2, rng, &self.field);
+
+            let desc = format!("truncation sharing for shift {f}");
+            self.tx_d_p1
+                .send(Msg::Truncation(log(o, false, &self.names.0, &desc, TruncationSharing(r1, h1))));
+            self.tx_d_p2
+                .send(Msg::Truncation(log(o, false, &self.names.1, &desc, TruncationSharing(r2, h2))));
+        }
+        self.tx_d_p1.flush();
+        self.tx_d_p2.flush();
+
+        let bit_lengths = self.circuit.comparison_bit_lengths();
+
+        if !bit_lengths.is_empty() {
+            o.push_str("\n**** Distribution of random bit sharings for comparison gates\n");
+        }
+
+        // as for truncations, the random r = h * 2^(k - 1) + sum(r_i * 2^i) has TRUNCATION_SECURITY more bits
+        // than the (shifted) inputs it masks
+        for k in bit_lengths {
+            let (bits1, bits2): (Vec<T>, Vec<T>) = (0..k - 1)
+                .map(|_| T::share(F32::new(2).random(rng), k1, k2, rng, &self.field))
+                .unzip();
+            let h = F32::new(1 << (utilities::TRUNCATION_SECURITY + 1)).random(rng);
+            let (h1, h2) = T::share(h, k1, k2, rng, &self.field);
+
+            let desc = format!("comparison sharing for bit length {k}");
+            self.tx_d_p1
+                .send(Msg::Comparison(log(o, false, &self.names.0, &desc, ComparisonSharing(bits1, h1))));
+            self.tx_d_p2
+                .send(Msg::Comparison(log(o, false, &self.names.1, &desc, ComparisonSharing(bits2, h2))));
+        }
+        self.tx_d_p1.flush();
+        self.tx_d_p2.flush();
+
+        let n_equalities = self.circuit.num_equality_tests();
+
+        if n_equalities > 0 {
+            o.push_str("\n**** Distribution of random bit sharings for equality tests\n");
+        }
+
+        // the bits of a uniformly random value modulo q, which masks the opened difference perfectly
+        for _ in 0..n_equalities {
+            let r = self.field.random(rng);
+            let (bits1, bits2): (Vec<T>, Vec<T>) = (0..utilities::EQUALITY_BITS)
+                .map(|i| T::share((r >> i) & 1, k1, k2, rng, &self.field))
+                .unzip();
+
+            self.tx_d_p1
+                .send(Msg::Equality(log(o, false, &self.names.0, "equality sharing", EqualitySharing(bits1))));
+            self.tx_d_p2
+                .send(Msg::Equality(log(o, false, &self.names.1, "equality sharing", EqualitySharing(bits2))));
+        }
+        self.tx_d_p1.flush();
+        self.tx_d_p2.flush();
+
+        if self.blind_outputs {
+            o.push_str("\n**** Distribution of output blinds\n");
+            // each output revealed to a single party is blinded by a random value only that party learns
+            for id in self.circuit.single_party_outputs() {
+                let r = self.field.random(rng);
+                let (s1, s2) = T::share(r, k1, k2, rng, &self.field);
+                let to_p1 = self.circuit.get_outputs(Party::P1).contains(&id);
+                let desc = format!("blind sharing for output gate {id}");
+
+                let s1 = log(o, false, &self.names.0, &desc, s1);
+                let s2 = log(o, false, &self.names.1, &desc, s2);
+                let owner = if to_p1 { &self.names.0 } else { &self.names.1 };
+                log(o, false, owner, &format!("blind for output gate {id}"), r);
+
+                self.tx_d_p1.send(Msg::Blind(id, s1, Some(r).filter(|_| to_p1)));
+                self.tx_d_p2.send(Msg::Blind(id, s2, Some(r).filter(|_| !to_p1)));
             }
+            self.tx_d_p1.flush();
+            self.tx_d_p2.flush();
         }
 
         o.push_str("\nEnded successfully");
 
-        if let Err(e) = fs::write(&self.log_path, o) {
-            return Err(format!(
-                "Dealer failed to write execution log: {}",
-                e.to_string()
-            ));
-        }
+        write_log(&self.log_path, o, "Dealer")?;
 
         // TODO keeping the dealer honest
 
@@ -99,29 +1193,314 @@ impl<T: Sharing> Dealer<T> {
     }
 }
 
-struct ProtocolParty<T: Sharing> {
+impl<T: Sharing> TripleDealer<T> {
+    fn run(&mut self) -> Result<(), BeaverError> {
+        let name = format!("Dealer {}", self.index);
+        let o = &mut Transcript::open(self.log_level, &self.log_path, self.log_flush_lines);
+
+        // the main dealer does not send the keys if it fails before generating them
+        let keys = self.rx_keys.recv().map_err(|_| {
+            BeaverError::Protocol(format!("{name}: keys not received from the main dealer"))
+        })?;
+
+        o.push_str("**** Distribution of triple sharings for multiplication gates\n");
+        let lowercase_name = name.to_lowercase();
+        deal_triples(
+            o,
+            &mut self.rng,
+            keys,
+            &self.field,
+            self.num_triples,
+            self.corrupt.map(|p| (lowercase_name.as_str(), p)),
+            (&self.tx_d_p1, &self.tx_d_p2),
+            &self.names,
+        );
+
+        o.push_str("\nEnded successfully");
+
+        write_log(&self.log_path, o, &name)
+    }
+}
+
+// the triples to add to n for a fraction of them all to be audited, leaving at least n
+fn audited_triples(n: usize, fraction: Option<f32>) -> usize {
+    match fraction {
+        Some(f) if n > 0 => (n as f64 * f as f64 / (1.0 - f as f64)).ceil() as usize,
+        _ => 0,
+    }
+}
+
+// triples are generated in parallel by chunks, each from its own stream of a common seed so that the result does not
+// depend on scheduling. they are then sent in order, matching the parties' receive loop. a corrupt dealer (named in
+// console messages) adds 1 to the product of each triple with the given probability, with MACs to match
+#[allow(clippy::too_many_arguments)]
+fn deal_triples<T: Sharing>(
+    o: &mut Transcript,
+    rng: &mut ChaCha20Rng,
+    (k1, k2): (u32, u32),
+    f: &ModField,
+    n_triples: usize,
+    corrupt: Option<(&str, f32)>,
+    (tx_d_p1, tx_d_p2): (&Link<T>, &Link<T>),
+    names: &(String, String),
+) {
+    let mut triple_seed = [0u8; 32];
+    rng.fill_bytes(&mut triple_seed);
+    let n_triples = n_triples as u64;
+
+    let triples: Vec<(BeaverSharing<T>, BeaverSharing<T>)> = (0..n_triples.div_ceil(TRIPLE_CHUNK_SIZE))
+        .into_par_iter()
+        .flat_map_iter(|chunk| {
+            let mut chunk_rng = ChaCha20Rng::from_seed(triple_seed);
+            chunk_rng.set_stream(chunk);
+            (chunk * TRIPLE_CHUNK_SIZE..n_triples.min((chunk + 1) * TRIPLE_CHUNK_SIZE))
+                .map(move |_| T::beaver_share(k1, k2, f, &mut chunk_rng))
+        })
+        .collect();
+
+    for (mut t1, t2) in triples {
+        let tamper = match corrupt {
+            Some((_, p)) => rng.gen::<f32>() < p,
+            None => false,
+        };
+        if tamper {
+            // not part of the protocol! only here to simulate a corrupt dealer
+            eprintln!("(Corrupt {} tampering with triple)", corrupt.unwrap().0);
+            t1.2 = T::addc(&t1.2, 1, k1, k2, f, Party::P1);
+        }
+        let desc = if tamper { "*tampered-with* triple sharing" } else { "triple sharing" };
+        tx_d_p1.send(Msg::Triple(log(o, false, &names.0, desc, t1)));
+        tx_d_p2.send(Msg::Triple(log(o, false, &names.1, desc, t2)));
+    }
+    tx_d_p1.flush();
+    tx_d_p2.flush();
+}
+
+struct ProtocolParty<T: Sharing> {
     identity: Party,
     circuit: Circuit,
-    q: u32,
+    field: ModField,
     inputs_first: Vec<u32>,
     inputs_second: Vec<u32>,
-    corrupt: bool,
-    rx_d_me: Receiver<Msg<T>>,
-    rx_other_me: Receiver<Msg<T>>,
-    tx_me_other: Sender<Msg<T>>,
-    log_path: String,
+    public_inputs: Vec<u32>,
+    // the party's shares of the input wires shared externally, which replace the masked inputs
+    external_inputs: HashMap<InputWire, T>,
+    // probability of tampering with each opening sent, if the party is corrupt
+    corruption_degree: Option<f32>,
+    // shared with the PartyMachine driving the party, through which all its messages pass
+    mailbox: Arc<Mutex<Mailbox<T>>>,
+    rx_d_me: Inbox<T>,
+    // from the dealers other than the main one, which only send triples
+    rx_dealers: Vec<Inbox<T>>,
+    // for each triple, the dealer it comes from and the one its sacrifice comes from (empty if there is a single
+    // dealer). in a real deployment, the parties would draw these jointly
+    triple_sources: Vec<(usize, usize)>,
+    triple_audit: Option<f32>,
+    // if set, no triples are expected from the dealer
+    no_triples: bool,
+    rx_other_me: Inbox<T>,
+    tx_me_other: Link<T>,
+    log_path: Option<String>,
+    log_level: LogLevel,
+    log_flush_lines: Option<usize>,
+    // display name of the party and how it refers to the other party in its log
+    name: String,
+    peer: String,
+    // display names of P1 and P2, which name the parties of input wires in the log
+    names: (String, String),
+    // used to draw nonces and to simulate corruption, which happens during otherwise immutable operations
+    rng: RefCell<ChaCha20Rng>,
     key: u32,
     s_k1: u32,
     s_k2: u32,
+    // epoch of the keys, if in a session
+    key_epoch: Option<u32>,
+    checkpoint: Option<CheckpointConfig>,
+    // not part of the protocol! used to simulate a failure right before processing the given gate
+    crash_at: Option<u32>,
+    #[cfg(test)]
+    panic_at: Option<u32>,
+    blind_outputs: bool,
+    // debug builds check that the key opened by the party is the one the dealer MACs with, which would otherwise
+    // only show up as failed authentications if the two got out of sync
+    #[cfg(debug_assertions)]
+    debug_keys: Arc<OnceLock<(u32, u32)>>,
+    // phase and gate being processed, reported if an opening fails authentication
+    context: Cell<(Phase, Option<u32>)>,
+    trace: Option<TraceConfig>,
+    view: Option<RefCell<PartyView>>,
+    // not part of the protocol! if set, the party hands over its share of every wire at the end of the run
+    wire_shares: Option<HashMap<u32, u32>>,
+    // not part of the protocol! if set, the record of the untampered openings, used to explain failed MAC checks
+    openings_record: Option<OpeningsRecord>,
+}
+
+// the components of the openings sent by each party as they were before any tampering, in the order they were
+// sent. openings are received in the order they are sent, so the n-th opening a party checks is the n-th one the
+// other party recorded
+struct OpeningsRecord {
+    sent: Arc<Mutex<Vec<Vec<u32>>>>,
+    received: Arc<Mutex<Vec<Vec<u32>>>>,
+    checked: Cell<usize>,
+}
+
+// everything a party needs to carry on with the evaluation of the gates: the sharings of all input and already
+// computed wires and the triples not consumed yet
+pub(crate) struct EvalState<T: Sharing> {
+    // number of gates of the topology already processed
+    pub(crate) position: usize,
+    pub(crate) inputs_p1: (HashMap<u32, T>, HashMap<u32, T>),
+    pub(crate) inputs_p2: (HashMap<u32, T>, HashMap<u32, T>),
+    // by index, as in the pub<i> gate inputs
+    pub(crate) public: HashMap<u32, T>,
+    pub(crate) inner_wires: HashMap<u32, T>,
+    pub(crate) triples: Vec<BeaverSharing<T>>,
+    // in reverse topology order of the dot gates consuming them
+    pub(crate) inner_products: Vec<InnerProductSharing<T>>,
+    // in reverse topology order of the truncation gates consuming them
+    pub(crate) truncations: Vec<TruncationSharing<T>>,
+    // in reverse topology order of the comparison gates consuming them
+    pub(crate) comparisons: Vec<ComparisonSharing<T>>,
+    pub(crate) equalities: Vec<EqualitySharing<T>>,
+    // public values of the reveal gates processed so far
+    pub(crate) revealed: HashMap<u32, u32>,
+    // sharings of the blinds of single-party outputs, along with the blinds of the party's own outputs
+    pub(crate) output_blinds: HashMap<u32, (T, Option<u32>)>,
 }
 
 impl<T: Sharing> ProtocolParty<T> {
-    fn run(&mut self) -> Result<(), String> {
-        let o = &mut String::new();
+    // the remaining settings are taken from the options. the MAC key and its shares start out as 0 and are only set
+    // once opened during preprocessing
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        identity: Party,
+        circuit: Circuit,
+        field: ModField,
+        (inputs_first, inputs_second): (Vec<u32>, Vec<u32>),
+        public_inputs: Vec<u32>,
+        corruption_degree: Option<f32>,
+        n_dealers: usize,
+        triple_sources: Vec<(usize, usize)>,
+        (name, peer): (String, String),
+        rng: ChaCha20Rng,
+        options: &ProtocolOptions,
+        openings_record: Option<OpeningsRecord>,
+        #[cfg(debug_assertions)] debug_keys: Arc<OnceLock<(u32, u32)>>,
+    ) -> Self {
+        let mailbox = Mailbox::shared();
+        let other = Endpoint::Party(match identity {
+            Party::P1 => Party::P2,
+            Party::P2 => Party::P1,
+        });
+
+        ProtocolParty {
+            identity,
+            circuit,
+            field,
+            inputs_first,
+            inputs_second,
+            public_inputs,
+            // the components were checked before the run
+            external_inputs: options
+                .external_inputs
+                .iter()
+                .map(|e| {
+                    let c = if identity == Party::P1 { &e.shares.0 } else { &e.shares.1 };
+                    (e.wire, T::from_components(c).unwrap())
+                })
+                .collect(),
+            corruption_degree,
+            rx_d_me: Inbox::new(Endpoint::Dealer(0), mailbox.clone()),
+            rx_dealers: (1..n_dealers).map(|i| Inbox::new(Endpoint::Dealer(i), mailbox.clone())).collect(),
+            triple_sources,
+            triple_audit: options.triple_audit,
+            no_triples: options.no_dealer_triples,
+            rx_other_me: Inbox::new(other, mailbox.clone()),
+            // any latency is simulated by the driver delivering the messages
+            tx_me_other: Link::new(Tx::Mailbox(mailbox.clone(), other), options.coalesce, None),
+            mailbox,
+            log_path: options.output_path.as_ref().map(|p| format!("{}_{}.txt", p, log_file_name(&name))),
+            log_level: options.log_level,
+            log_flush_lines: options.log_flush_lines,
+            name,
+            peer,
+            names: options.party_names.clone().unwrap_or((String::from("P1"), String::from("P2"))),
+            rng: RefCell::new(rng),
+            key: 0,
+            s_k1: 0,
+            s_k2: 0,
+            key_epoch: options.key_epoch.map(|e| e.epoch),
+            checkpoint: options.checkpoint.clone(),
+            crash_at: options.crash_at.filter(|(p, _)| *p == identity).map(|(_, id)| id),
+            #[cfg(test)]
+            panic_at: options.panic_at.filter(|(p, _)| *p == identity).map(|(_, id)| id),
+            blind_outputs: options.blind_outputs,
+            #[cfg(debug_assertions)]
+            debug_keys,
+            context: Cell::new((Phase::Preprocessing, None)),
+            trace: options.trace.clone(),
+            view: options.record_views.then(RefCell::default),
+            wire_shares: options.insecure_audit.then(HashMap::new),
+            openings_record,
+        }
+    }
+    async fn run(&mut self) -> Result<HashMap<u32, u32>, BeaverError> {
+        let o = &mut Transcript::open(self.log_level, &self.log_path, self.log_flush_lines);
+
+        let mut state = self.prepare(o).await?;
+
+        let outputs = self.evaluate(o, &mut state).await?;
+        self.record_wire_shares(&state);
+        Ok(outputs)
+    }
+    // resumes the evaluation from the last checkpoint, once both parties agree on its position
+    async fn resume(&mut self) -> Result<HashMap<u32, u32>, BeaverError> {
+        let o = &mut Transcript::open(self.log_level, &self.log_path, self.log_flush_lines);
+
+        o.push_str("**** Resuming from checkpoint\n");
+
+        let path = self.checkpoint_path();
+        let (mut state, (s_k1, s_k2, key), sent, epoch) = match checkpoint::load(&path) {
+            Ok(c) => c,
+            Err(e) => return Err(self.abort_with(o, e)),
+        };
+        // the shares in the checkpoint are MACed with the keys of the epoch it was saved in
+        if epoch != self.key_epoch {
+            let epoch_name = |e: Option<u32>| e.map_or(String::from("no key epoch"), |e| format!("key epoch {e}"));
+            let msg = format!(
+                "Error during resumption: the checkpoint {path} was saved in {}, but the run is in {}",
+                epoch_name(epoch),
+                epoch_name(self.key_epoch)
+            );
+            return Err(self.abort(o, &msg));
+        }
+        self.s_k1 = s_k1;
+        self.s_k2 = s_k2;
+        self.key = key;
+        self.tx_me_other.sent.set(sent);
+
+        self.synchronise_checkpoint(o, state.position).await?;
+
+        let outputs = self.evaluate(o, &mut state).await?;
+        self.record_wire_shares(&state);
+        Ok(outputs)
+    }
+    fn record_wire_shares(&mut self, state: &EvalState<T>) {
+        if let Some(shares) = &mut self.wire_shares {
+            shares.extend(state.inner_wires.iter().map(|(id, s)| (*id, s.value())));
+        }
+    }
+    // phases prior to the evaluation of the gates: distribution of preprocessing material, opening of the keys
+    // and input processing
+    async fn prepare(&mut self, o: &mut Transcript) -> Result<EvalState<T>, BeaverError> {
+        self.context.set((Phase::Preprocessing, None));
 
         // distributing key sharings
         o.push_str("**** Distribution of key sharings\n");
-        self.s_k1 = match self.rx_d_me.recv() {
+        if let Some(epoch) = self.key_epoch {
+            o.push_str(&format!("Key epoch: {epoch}\n"));
+        }
+        self.s_k1 = match self.rx_d_me.recv().await {
             Ok(Msg::Value(v)) => log(o, true, "dealer", "sharing of k1", v),
             _ => {
                 return Err(self.abort(
@@ -131,7 +1510,7 @@ impl<T: Sharing> ProtocolParty<T> {
             }
         };
 
-        self.s_k2 = match self.rx_d_me.recv() {
+        self.s_k2 = match self.rx_d_me.recv().await {
             Ok(Msg::Value(v)) => log(o, true, "dealer", "sharing of k2", v),
             _ => {
                 return Err(self.abort(
@@ -141,485 +1520,3951 @@ impl<T: Sharing> ProtocolParty<T> {
             }
         };
 
-        let mut singletons = Vec::new();
+        // masks are indexed by the input wire they are tagged with, so that the order in which they are received
+        // does not matter
+        let mut masks = HashMap::new();
+
+        // distributing sharings for input wires
+        o.push_str("\n**** Distribution of singleton sharings for inputs\n");
+
+        for _ in 0..self.circuit.total_input_wires() - self.external_inputs.len() {
+            match self.rx_d_me.recv().await {
+                Ok(Msg::Mask(wire, s)) => {
+                    let s = log(o, true, "dealer", &format!("singleton sharing for {}", self.wire_name(&wire)), s);
+                    if masks.insert(wire, s).is_some() {
+                        return Err(self.abort(o, &format!("Error during distribution of input sharings: received two singleton sharings for {}", self.wire_name(&wire))));
+                    }
+                }
+                _ => return Err(self.abort(o, "Error during distribution of input and key-opening sharings: Expected singleton sharing")),
+            };
+        }
+
+        // as many masks as input wires were received, none of them twice
+        if let Some(wire) =
+            self.circuit.input_wires().iter().find(|w| !masks.contains_key(w) && !self.external_inputs.contains_key(w))
+        {
+            return Err(self.abort(o, &format!("Error during distribution of input sharings: no singleton sharing received for {}", self.wire_name(wire))));
+        }
+
+        let mut triples = Vec::new();
+        let mut sacrificed = Vec::new();
+
+        // triples are sent in the topology order of the gates consuming them, which both dealer and parties derive
+        if self.no_triples {
+            o.push_str("\n**** Triple generation disabled\n");
+        } else if self.triple_sources.is_empty() {
+            o.push_str("\n**** Distribution of triple sharings for multiplication gates\n");
+            let n = self.circuit.num_beaver_triples();
+            for _ in 0..n + audited_triples(n, self.triple_audit) {
+                triples.push(self.recv_triple(o, 0).await?);
+            }
+        } else {
+            o.push_str("\n**** Distribution of triple sharings for multiplication gates\n");
+            for (source, sacrifice) in &self.triple_sources {
+                triples.push(self.recv_triple(o, *source).await?);
+                sacrificed.push(self.recv_triple(o, *sacrifice).await?);
+            }
+        }
+
+        let mut inner_products = Vec::new();
+
+        let dot_lengths = self.circuit.dot_gate_lengths();
+
+        if !dot_lengths.is_empty() {
+            o.push_str("\n**** Distribution of inner-product sharings for dot gates\n");
+        }
+
+        for n in dot_lengths {
+            match self.rx_d_me.recv().await {
+                Ok(Msg::InnerProduct(t)) if t.len() == n => {
+                    inner_products.push(log(o, true, "dealer", "inner-product sharing", t))
+                }
+                _ => return Err(self.abort(
+                    o,
+                    &format!("Error during distribution of inner-product sharings: Expected inner-product sharing of length {n}"),
+                )),
+            };
+        }
+
+        // unlike triples, tuples are not interchangeable (their lengths differ), so they are consumed with pop in
+        // topology order
+        inner_products.reverse();
+
+        let mut truncations = Vec::new();
+
+        let shifts = self.circuit.truncation_shifts();
+
+        if !shifts.is_empty() {
+            o.push_str("\n**** Distribution of truncation sharings for truncation gates\n");
+        }
+
+        for f in shifts {
+            match self.rx_d_me.recv().await {
+                Ok(Msg::Truncation(t)) => {
+                    truncations.push(log(o, true, "dealer", &format!("truncation sharing for shift {f}"), t))
+                }
+                _ => return Err(self.abort(
+                    o,
+                    "Error during distribution of truncation sharings: Expected truncation sharing",
+                )),
+            };
+        }
+
+        // each pair only suits the shift it was generated for
+        truncations.reverse();
+
+        let mut comparisons = Vec::new();
+
+        let bit_lengths = self.circuit.comparison_bit_lengths();
+
+        if !bit_lengths.is_empty() {
+            o.push_str("\n**** Distribution of random bit sharings for comparison gates\n");
+        }
+
+        for k in bit_lengths {
+            match self.rx_d_me.recv().await {
+                Ok(Msg::Comparison(t)) if t.len() == k as usize - 1 => comparisons
+                    .push(log(o, true, "dealer", &format!("comparison sharing for bit length {k}"), t)),
+                _ => return Err(self.abort(
+                    o,
+                    &format!("Error during distribution of comparison sharings: Expected {} random bit sharings", k - 1),
+                )),
+            };
+        }
+
+        comparisons.reverse();
+
+        let mut equalities = Vec::new();
+
+        let n_equalities = self.circuit.num_equality_tests();
+
+        if n_equalities > 0 {
+            o.push_str("\n**** Distribution of random bit sharings for equality tests\n");
+        }
+
+        for _ in 0..n_equalities {
+            match self.rx_d_me.recv().await {
+                Ok(Msg::Equality(t)) if t.len() == utilities::EQUALITY_BITS as usize => {
+                    equalities.push(log(o, true, "dealer", "equality sharing", t))
+                }
+                _ => return Err(self.abort(
+                    o,
+                    &format!(
+                        "Error during distribution of equality sharings: Expected {} random bit sharings",
+                        utilities::EQUALITY_BITS
+                    ),
+                )),
+            };
+        }
+
+        let mut output_blinds = HashMap::new();
+
+        if self.blind_outputs {
+            o.push_str("\n**** Distribution of output blinds\n");
+
+            for id in self.circuit.single_party_outputs() {
+                let own = self.circuit.get_outputs(self.identity).contains(&id);
+                match self.rx_d_me.recv().await {
+                    Ok(Msg::Blind(b_id, s, r)) if b_id == id && r.is_some() == own => {
+                        let s = log(o, true, "dealer", &format!("blind sharing for output gate {id}"), s);
+                        if let Some(r) = r {
+                            log(o, true, "dealer", &format!("blind for output gate {id}"), r);
+                        }
+                        output_blinds.insert(id, (s, r));
+                    }
+                    _ => return Err(self.abort(o, &format!("Error during distribution of output blinds: Expected blind for output gate {id}"))),
+                }
+            }
+        }
+
+        // opening key sharings
+        o.push_str("\n**** Opening of key sharings\n");
+        self.context.set((Phase::KeyOpening, None));
+        if self.identity == Party::P1 {
+            let k12 = match self.recv_other().await {
+                Ok(Msg::Value(v)) => log(o, true, &self.peer, "opening of k1", v),
+                _ => return Err(self.abort(o, "Error during key opening: Expected opening of k1")),
+            };
+            self.key = self.field.add(self.s_k1, k12);
+            self.send_other(Msg::Value(log(
+                o,
+                false,
+                &self.peer,
+                "opening of k2",
+                self.s_k2,
+            )));
+        } else {
+            self.send_other(Msg::Value(log(
+                o,
+                false,
+                &self.peer,
+                "opening of k1",
+                self.s_k1,
+            )));
+            let k21 = match self.recv_other().await {
+                Ok(Msg::Value(v)) => log(o, true, &self.peer, "opening of k2", v),
+                _ => return Err(self.abort(o, "Error during key opening: Expected opening of k2")),
+            };
+            self.key = self.field.add(self.s_k2, k21);
+        }
+
+        // set by the dealer before sending the key sharings. an opening tampered with in transit is caught here in
+        // debug builds, and by the MAC checks otherwise, so it aborts the run rather than panicking
+        #[cfg(debug_assertions)]
+        if let Some((k1, k2)) = self.debug_keys.get() {
+            let expected = if self.identity == Party::P1 { k1 } else { k2 };
+            if self.key % self.field.q != *expected {
+                let msg = format!("Error during key opening: the key opened by {} is not the dealer's", self.name);
+                return Err(self.abort(o, &msg));
+            }
+        }
+
+        if !sacrificed.is_empty() {
+            o.push_str("\n**** Checking triples against sacrificed ones\n");
+            self.context.set((Phase::TripleCheck, None));
+            self.check_triples(o, &triples, sacrificed).await?;
+        }
+
+        let n_audited = audited_triples(self.circuit.num_beaver_triples(), self.triple_audit);
+        if n_audited > 0 {
+            o.push_str("\n**** Auditing triples\n");
+            self.context.set((Phase::TripleCheck, None));
+            triples = self.audit_triples(o, triples, n_audited).await?;
+        }
+
+        // input processing
+        o.push_str("\n**** Processing input wires\n");
+        self.context.set((Phase::InputProcessing, None));
+
+        let (i_p1_first, i_p1_second) = self.circuit.get_inputs_p1();
+        let (i_p2_first, i_p2_second) = self.circuit.get_inputs_p2();
+
+        let i_sharings_p1_first =
+            self.process_inputs(o, i_p1_first, &mut masks, Party::P1, true).await?;
+        let i_sharings_p1_second =
+            self.process_inputs(o, i_p1_second, &mut masks, Party::P1, false).await?;
+        let i_sharings_p2_first =
+            self.process_inputs(o, i_p2_first, &mut masks, Party::P2, true).await?;
+        let i_sharings_p2_second =
+            self.process_inputs(o, i_p2_second, &mut masks, Party::P2, false).await?;
+
+        // public inputs need no interaction: each party adds the value to its share of 0
+        let public = (0..)
+            .zip(&self.public_inputs)
+            .map(|(i, v)| (i, T::public(*v, self.s_k1, self.s_k2, &self.field, self.identity)))
+            .collect();
+
+        o.push_str("\n**** Processing gates\n");
+
+        Ok(EvalState {
+            position: 0,
+            inputs_p1: (i_sharings_p1_first, i_sharings_p1_second),
+            inputs_p2: (i_sharings_p2_first, i_sharings_p2_second),
+            public,
+            inner_wires: HashMap::new(),
+            triples,
+            inner_products,
+            truncations,
+            comparisons,
+            equalities,
+            revealed: HashMap::new(),
+            output_blinds,
+        })
+    }
+
+    // evaluation of the gates from the current position of the state onwards, followed by output processing
+    async fn evaluate(&self, o: &mut Transcript, state: &mut EvalState<T>) -> Result<HashMap<u32, u32>, BeaverError> {
+        let topology = self.circuit.get_topology();
+
+        for (i, id) in topology.iter().enumerate().skip(state.position) {
+            self.context.set((Phase::GateProcessing, Some(*id)));
+
+            if self.crash_at == Some(*id) {
+                // not part of the protocol! simulates an abrupt failure, which the other party notices when the
+                // channel closes
+                let e = BeaverError::Protocol(format!("Simulated crash before processing gate {id}"));
+                o.push_str(&format!("{e}\n"));
+                write_log(&self.log_path, o, &self.name)?;
+                return Err(e);
+            }
+
+            #[cfg(test)]
+            if self.panic_at == Some(*id) {
+                panic!("injected panic before processing gate {id}");
+            }
+
+            let g = self.circuit.get_gate(id).unwrap();
+            let EvalState { inputs_p1, inputs_p2, public, inner_wires, triples, inner_products, truncations, comparisons, equalities, revealed, .. } =
+                &mut *state;
+
+            match g {
+                Gate::GateWithoutC { op, i1, i2, .. } => {
+                    let v1 = match i1 {
+                        // these unwraps cannot fail by the order of the topology and input processing
+                        GateInput::Id(s_id) => inner_wires.get(s_id).unwrap(),
+                        GateInput::InputParty(Party::P1) => inputs_p1.0.get(id).unwrap(),
+                        GateInput::InputParty(Party::P2) => inputs_p2.0.get(id).unwrap(),
+                        GateInput::Public(i) => public.get(i).unwrap(),
+                    };
+                    let v2 = match i2 {
+                        GateInput::Id(s_id) => inner_wires.get(s_id).unwrap(),
+                        GateInput::InputParty(Party::P1) => inputs_p1.1.get(id).unwrap(),
+                        GateInput::InputParty(Party::P2) => inputs_p2.1.get(id).unwrap(),
+                        GateInput::Public(i) => public.get(i).unwrap(),
+                    };
+
+                    inner_wires.insert(
+                        *id,
+                        match op {
+                            GateOp::Add => self.process_gate_add(v1, v2),
+                            GateOp::Mul => self.process_gate_mul(o, v1, v2, triples.pop().unwrap()).await?,
+                            GateOp::Eq => {
+                                let d = T::subtract(v1, v2, &self.field);
+                                self.process_gate_eqz(o, &d, equalities.pop().unwrap(), triples).await?
+                            }
+                        },
+                    );
+                }
+                Gate::GateWithC { op, i1, c, .. } => {
+                    let v1 = match i1 {
+                        GateInput::Id(s_id) => inner_wires.get(s_id).unwrap(),
+                        GateInput::InputParty(Party::P1) => inputs_p1.0.get(id).unwrap(),
+                        GateInput::InputParty(Party::P2) => inputs_p2.0.get(id).unwrap(),
+                        GateInput::Public(i) => public.get(i).unwrap(),
+                    };
+
+                    let c = c.resolve(revealed, &self.public_inputs, self.field.q);
+
+                    inner_wires.insert(
+                        *id,
+                        match op {
+                            GateOp::Add => self.process_gate_addc(v1, c),
+                            GateOp::Mul => self.process_gate_mulc(v1, c),
+                            GateOp::Eq => {
+                                let d = self.process_gate_addc(v1, self.field.neg(c));
+                                self.process_gate_eqz(o, &d, equalities.pop().unwrap(), triples).await?
+                            }
+                        },
+                    );
+                }
+                Gate::AssertZero { i1, .. } => {
+                    let v1 = match i1 {
+                        GateInput::Id(s_id) => inner_wires.get(s_id).unwrap(),
+                        GateInput::InputParty(Party::P1) => inputs_p1.0.get(id).unwrap(),
+                        GateInput::InputParty(Party::P2) => inputs_p2.0.get(id).unwrap(),
+                        GateInput::Public(i) => public.get(i).unwrap(),
+                    };
+
+                    if !self.check_zero(o, v1, triples.pop().unwrap()).await? {
+                        return Err(self.abort(o, &format!("Error during gate processing: the input of gate {id} is not zero")));
+                    }
+
+                    // multiplying by 1 to obtain a copy of the sharing
+                    inner_wires.insert(*id, self.process_gate_mulc(v1, 1));
+                }
+                Gate::Reveal { i1, .. } => {
+                    let v1 = match i1 {
+                        GateInput::Id(s_id) => inner_wires.get(s_id).unwrap(),
+                        GateInput::InputParty(Party::P1) => inputs_p1.0.get(id).unwrap(),
+                        GateInput::InputParty(Party::P2) => inputs_p2.0.get(id).unwrap(),
+                        GateInput::Public(i) => public.get(i).unwrap(),
+                    };
+
+                    // opened to both parties, each of which checks the MAC under its own key
+                    self.send_opening(o, v1);
+                    let v = self.receive_opening(o, v1).await?;
+                    o.push_str(&format!("Revealed value of gate {id}: {v}\n"));
+                    revealed.insert(*id, v);
+
+                    // multiplying by 1 to obtain a copy of the sharing
+                    inner_wires.insert(*id, self.process_gate_mulc(v1, 1));
+                }
+                Gate::Truncate { i1, f, .. } => {
+                    let v1 = match i1 {
+                        GateInput::Id(s_id) => inner_wires.get(s_id).unwrap(),
+                        GateInput::InputParty(Party::P1) => inputs_p1.0.get(id).unwrap(),
+                        GateInput::InputParty(Party::P2) => inputs_p2.0.get(id).unwrap(),
+                        GateInput::Public(i) => public.get(i).unwrap(),
+                    };
+
+                    let z = self.process_gate_truncate(o, v1, *f, truncations.pop().unwrap()).await?;
+                    inner_wires.insert(*id, z);
+                }
+                Gate::Ltz { i1, k, .. } => {
+                    let v1 = match i1 {
+                        GateInput::Id(s_id) => inner_wires.get(s_id).unwrap(),
+                        GateInput::InputParty(Party::P1) => inputs_p1.0.get(id).unwrap(),
+                        GateInput::InputParty(Party::P2) => inputs_p2.0.get(id).unwrap(),
+                        GateInput::Public(i) => public.get(i).unwrap(),
+                    };
+
+                    let z = self.process_gate_ltz(o, v1, *k, comparisons.pop().unwrap(), triples).await?;
+                    inner_wires.insert(*id, z);
+                }
+                Gate::Linear { inputs, coeffs, .. } => {
+                    let vs: Vec<&T> = inputs.iter().map(|s_id| inner_wires.get(s_id).unwrap()).collect();
+                    let cs: Vec<u32> = coeffs.iter().map(|c| utilities::modulo(*c, self.field.q)).collect();
+
+                    inner_wires.insert(*id, self.process_gate_linear(&vs, &cs));
+                }
+                Gate::Dot { left, right, .. } => {
+                    let xs: Vec<&T> = left.iter().map(|s_id| inner_wires.get(s_id).unwrap()).collect();
+                    let ys: Vec<&T> = right.iter().map(|s_id| inner_wires.get(s_id).unwrap()).collect();
+
+                    // the lengths were checked upon reception
+                    let z = self.process_gate_dot(o, &xs, &ys, inner_products.pop().unwrap()).await?;
+                    inner_wires.insert(*id, z);
+                }
+            }
+
+            state.position = i + 1;
+
+            if let Some(t) = &self.trace {
+                (t.hook)(GateTrace {
+                    gate: *id,
+                    op: g.op_name(),
+                    party: self.identity,
+                    // every processed gate has an output sharing
+                    share: (!t.redact).then(|| state.inner_wires[id].components()),
+                    triples_consumed: self.circuit.num_beaver_triples() - state.triples.len(),
+                });
+            }
+
+            if let Some(c) = &self.checkpoint {
+                if state.position.is_multiple_of(c.interval) && state.position < topology.len() {
+                    self.save_checkpoint(o, state).await?;
+                }
+            }
+        }
+
+        let inner_wires = &state.inner_wires;
+        let blinds = &state.output_blinds;
+
+        // processing outputs
+        o.push_str("\n**** Processing outputs\n");
+
+        // both parties derive the same order of the openings from the sorted output ids
+        let mut own_outputs = self.circuit.get_outputs(self.identity).clone();
+        let mut other_outputs = self.circuit.get_outputs(self.other_party()).clone();
+        own_outputs.sort();
+        other_outputs.sort();
+
+        // also when the other party has no outputs, so that the message is labelled with this phase
+        self.context.set((Phase::OutputProcessing, None));
+        let mut openings = Vec::with_capacity(other_outputs.len());
+
+        for id in &other_outputs {
+            self.context.set((Phase::OutputProcessing, Some(*id)));
+            let s = self.output_sharing(o, inner_wires, *id)?;
+            // the other party only learns the blinded output, which it unblinds itself
+            let blinded;
+            let s = match blinds.get(id) {
+                Some((b, _)) => {
+                    blinded = T::add(s, b, &self.field);
+                    &blinded
+                }
+                None => s,
+            };
+            openings.push(self.opening(o, s));
+        }
+
+        self.send_other(Msg::Openings(openings));
+
+        let received = match self.recv_other().await {
+            Ok(Msg::Openings(v)) if v.len() == own_outputs.len() => v,
+            Ok(Msg::Openings(v)) => {
+                return Err(self.abort(
+                    o,
+                    &format!(
+                        "Error during output processing: expected {} openings, received {}",
+                        own_outputs.len(),
+                        v.len()
+                    ),
+                ))
+            }
+            _ => return Err(self.abort(o, "Error during output processing: Expected output openings")),
+        };
+
+        let mut output_wires = HashMap::new();
+
+        for (id, s) in own_outputs.iter().zip(received) {
+            self.context.set((Phase::OutputProcessing, Some(*id)));
+            let own_s = self.output_sharing(o, inner_wires, *id)?;
+            match blinds.get(id) {
+                Some((b, r)) => {
+                    let v = self.check_opening(o, &T::add(own_s, b, &self.field), s)?;
+                    // always set for the party's own single-party outputs
+                    let r = r.unwrap();
+                    o.push_str(&format!("Removed blind {r} from output of gate {id}\n"));
+                    output_wires.insert(*id, self.field.sub(v, r));
+                }
+                None => {
+                    output_wires.insert(*id, self.check_opening(o, own_s, s)?);
+                }
+            }
+        }
+
+        // outputs revealed to both parties are cross-checked: the parties must agree on their values, which catches
+        // tampered-with openings the MAC checks do not (e.g. in unauthenticated runs) as a single clear error
+        let shared: Vec<u32> = own_outputs.iter().filter(|id| other_outputs.contains(id)).copied().collect();
+
+        if !shared.is_empty() {
+            self.context.set((Phase::OutputProcessing, None));
+
+            let values: Vec<u32> = shared.iter().map(|id| output_wires[id]).collect();
+
+            for (id, v) in shared.iter().zip(&values) {
+                log(o, false, &self.peer, &format!("value of output gate {id}"), v);
+            }
+
+            self.send_other(Msg::Values(values.clone()));
+
+            let other_values = match self.recv_other().await {
+                Ok(Msg::Values(v)) if v.len() == shared.len() => v,
+                _ => return Err(self.abort(o, "Error during output processing: Expected values of shared outputs")),
+            };
+
+            for ((id, v), other_v) in shared.iter().zip(&values).zip(other_values) {
+                let other_v = log(o, true, &self.peer, &format!("value of output gate {id}"), other_v);
+                if *v != other_v {
+                    return Err(self.abort(
+                        o,
+                        &format!("Error during output processing: the parties disagree on the value of output gate {id} ({v} against {other_v})"),
+                    ));
+                }
+            }
+        }
+
+        // the hashes of the messages each party sent and the other received should match. this catches messages
+        // which were dropped, reordered or altered in transit, even without authentication
+        let received_digest = self.rx_other_me.digest();
+        let sent_digest = self.tx_me_other.digest();
+        self.send_other(Msg::Digest(log(o, false, &self.peer, "transcript digest", sent_digest)));
+
+        match self.recv_other().await {
+            Ok(Msg::Digest(d)) if log(o, true, &self.peer, "transcript digest", d) == received_digest => (),
+            Ok(Msg::Digest(_)) => return Err(self.abort(
+                o,
+                &format!("Error during output processing: transcript divergence, the messages received from {} differ from those it sent", self.peer),
+            )),
+            _ => return Err(self.abort(o, "Error during output processing: Expected transcript digest")),
+        }
+
+        o.push('\n');
+
+        for id in &own_outputs {
+            o.push_str(&format!("Output of gate {}: {}\n", id, self.circuit.format_output(*id, output_wires[id], self.field.q)));
+        }
+
+        o.push_str(&format!("\n{}\n", RoundReport::new(&self.circuit)));
+
+        o.push_str("\nEnded successfully");
+
+        write_log(&self.log_path, o, &self.name)?;
+
+        Ok(output_wires)
+    }
+    fn checkpoint_path(&self) -> String {
+        // only called when checkpointing is enabled
+        let c = self.checkpoint.as_ref().unwrap();
+        format!("{}_{}.ckpt", c.path, log_file_name(&self.name))
+    }
+    // both parties only save a checkpoint once the other one has reached the same position, so that their latest
+    // checkpoints always match
+    async fn save_checkpoint(&self, o: &mut Transcript, state: &EvalState<T>) -> Result<(), BeaverError> {
+        self.synchronise_checkpoint(o, state.position).await?;
+
+        let keys = (self.s_k1, self.s_k2, self.key);
+        let sent = self.tx_me_other.sent.get();
+        if let Err(e) = checkpoint::save(&self.checkpoint_path(), state, keys, self.key_epoch, sent) {
+            return Err(self.abort_with(o, e));
+        }
+
+        o.push_str(&format!("Saved checkpoint at position {}\n", state.position));
+
+        Ok(())
+    }
+    async fn synchronise_checkpoint(&self, o: &mut Transcript, position: usize) -> Result<(), BeaverError> {
+        self.send_other(Msg::Value(log(o, false, &self.peer, "checkpoint position", position as u32)));
+
+        match self.recv_other().await {
+            Ok(Msg::Value(v)) if v as usize == position => {
+                log(o, true, &self.peer, "checkpoint position", v);
+                Ok(())
+            }
+            Ok(Msg::Value(v)) => Err(self.abort(
+                o,
+                &format!("Error during checkpoint synchronisation: own position is {position}, other party's is {v}"),
+            )),
+            _ => Err(self.abort(o, "Error during checkpoint synchronisation: Expected checkpoint position")),
+        }
+    }
+    // any coalesced messages are sent before blocking, as the other party may be waiting for them
+    fn send_other(&self, msg: Msg<T>) -> Result<(), SendError<Msg<T>>> {
+        if let (Some(view), Some(entry)) = (&self.view, msg.entry()) {
+            view.borrow_mut().sent.push((self.context.get().0, entry));
+        }
+        self.tx_me_other.send(msg)
+    }
+    async fn recv_other(&self) -> Result<Msg<T>, RecvError> {
+        self.tx_me_other.flush();
+        let msg = self.rx_other_me.recv().await?;
+        if let (Some(view), Some(entry)) = (&self.view, msg.entry()) {
+            view.borrow_mut().received.push((self.context.get().0, entry));
+        }
+        Ok(msg)
+    }
+    fn abort(&self, output: &mut Transcript, msg: &str) -> BeaverError {
+        self.abort_with(output, BeaverError::Protocol(msg.to_string()))
+    }
+    fn abort_with(&self, output: &mut Transcript, e: BeaverError) -> BeaverError {
+        self.send_other(Msg::Abort);
+        self.tx_me_other.flush();
+
+        let e = BeaverError::Aborted(Box::new(e));
+        output.push_str(&e.to_string());
+
+        if let Err(io_e) = write_log(&self.log_path, output, &self.name) {
+            return io_e;
+        }
+
+        e
+    }
+    // every output is in the topology by construction, but a missing wire must not bring the party down
+    fn output_sharing<'a>(
+        &self,
+        output: &mut Transcript,
+        inner_wires: &'a HashMap<u32, T>,
+        id: u32,
+    ) -> Result<&'a T, BeaverError> {
+        match inner_wires.get(&id) {
+            Some(s) => Ok(s),
+            None => Err(self.abort(
+                output,
+                &format!("Error during output processing: no sharing computed for output gate {id}"),
+            )),
+        }
+    }
+    // the sharing to be sent to the other party to open s, tampered with if the party is corrupt
+    fn opening(&self, output: &mut Transcript, s: &T) -> T {
+        if let Some(record) = &self.openings_record {
+            record.sent.lock().unwrap().push(s.opened(self.other_party()).components());
+        }
+
+        let tamper = match self.corruption_degree {
+            Some(degree) => self.rng.borrow_mut().gen::<f32>() <= degree,
+            None => false,
+        };
+
+        if tamper {
+            // not part of the protocol! only here to simulate a  corrupt party
+            eprintln!("(Corrupt {} tampering with sharing)", self.name);
+            let s_t = s.tweaked();
+            log(
+                output,
+                false,
+                &self.peer,
+                "*tampered-with* opened sharing",
+                s_t.opened(self.other_party()),
+            )
+        } else {
+            log(
+                output,
+                false,
+                &self.peer,
+                "opened sharing",
+                s.opened(self.other_party()),
+            )
+        }
+    }
+    fn send_opening(&self, output: &mut Transcript, s: &T) {
+        let opening = self.opening(output, s);
+        self.send_other(Msg::Singleton(opening));
+    }
+    async fn receive_opening(&self, output: &mut Transcript, own_s: &T) -> Result<u32, BeaverError> {
+        match self.recv_other().await {
+            Ok(Msg::Singleton(s)) => self.check_opening(output, own_s, s),
+            _ => Err(self.abort(
+                output,
+                "Error during opening of sharing: Expected opened sharing",
+            )),
+        }
+    }
+    // combines the own sharing with the other party's opening of it and checks the MAC of the result
+    fn check_opening(&self, output: &mut Transcript, own_s: &T, s: T) -> Result<u32, BeaverError> {
+        let s = log(output, true, &self.peer, "opened sharing", s);
+        let new_s = T::add(own_s, &s, &self.field);
+
+        // the record is kept in step even when the check passes
+        let untampered = self.openings_record.as_ref().map(|record| {
+            let n = record.checked.get();
+            record.checked.set(n + 1);
+            record.received.lock().unwrap().get(n).cloned()
+        });
+
+        if T::authenticate(&new_s, self.key, &self.field, self.identity) {
+            Ok(new_s.value())
+        } else {
+            Err(self.abort_with(
+                output,
+                BeaverError::Authentication {
+                    party: self.identity,
+                    sharing: new_s.to_string(),
+                    phase: self.context.get().0,
+                    gate: self.context.get().1,
+                    explanation: untampered.flatten().and_then(|u| self.explain_failure(own_s, &s, &new_s, &u)),
+                },
+            ))
+        }
+    }
+    // not part of the protocol! compares the failed MAC with the expected one and the opening received with the
+    // untampered one the other party recorded. tampering with the value by d moves it away from its MAC by
+    // -d * key, whereas an opening received untampered means the sharing was inconsistent to begin with
+    fn explain_failure(&self, own_s: &T, s: &T, new_s: &T, untampered: &[u32]) -> Option<String> {
+        let f = &self.field;
+        let got = new_s.mac(self.identity)?;
+        let expected = f.mul(new_s.value(), self.key);
+        let mut e = format!("expected MAC {expected}, got {got}, difference {}", f.sub(got, expected));
+
+        let untampered = T::from_components(untampered)?;
+        let d = f.sub(s.value(), untampered.value());
+
+        if d != 0 {
+            let v = T::add(own_s, &untampered, &self.field).value();
+            e.push_str(&format!("; the opened value was off by {d}, consistent with tampering of magnitude {d}; the true value is {v}"));
+        } else if s.components() != untampered.components() {
+            e.push_str("; the opened MACs were tampered with, but not the value");
+        } else {
+            e.push_str("; the opening was not tampered with, so the sharing was already inconsistent (e.g. from a bad triple or an arithmetic bug)");
+        }
+
+        Some(e)
+    }
+    async fn recv_triple(&self, output: &mut Transcript, dealer: usize) -> Result<BeaverSharing<T>, BeaverError> {
+        let (inbox, name) = match dealer {
+            0 => (&self.rx_d_me, String::from("dealer")),
+            i => (&self.rx_dealers[i - 1], format!("dealer {i}")),
+        };
+
+        match inbox.recv().await {
+            Ok(Msg::Triple(t)) => Ok(log(output, true, &name, "triple sharing", t)),
+            _ => Err(self.abort(
+                output,
+                "Error during distribution of Beaver sharings: Expected triple sharing",
+            )),
+        }
+    }
+    // commit-and-open coin tossing: each party sends the digest of a random nonce and only opens the nonce once it
+    // holds the other party's digest, so that neither can choose its nonce after seeing the other's. the seed is
+    // derived from both nonces, P1's first. the digests are FNV-1a as for the transcripts, salted with the
+    // identity of the sender, so they only keep honest parties from influencing each other and would have to be
+    // replaced by a cryptographic commitment against a party searching for collisions
+    async fn joint_randomness(&self, output: &mut Transcript) -> Result<[u8; 32], String> {
+        let commitment = |party: Party, nonce: u64| {
+            let mut h = Fnv1a::new();
+            h.write(&[(party == Party::P2) as u8]);
+            h.write(&nonce.to_le_bytes());
+            h.finish()
+        };
+
+        let nonce = self.rng.borrow_mut().next_u64();
+        self.send_other(Msg::Digest(log(output, false, &self.peer, "nonce commitment", commitment(self.identity, nonce))));
+
+        let other_commitment = match self.recv_other().await {
+            Ok(Msg::Digest(d)) => log(output, true, &self.peer, "nonce commitment", d),
+            _ => return Err(String::from("Expected nonce commitment")),
+        };
+
+        self.send_other(Msg::Nonce(log(output, false, &self.peer, "nonce", nonce)));
+
+        let other_nonce = match self.recv_other().await {
+            Ok(Msg::Nonce(n)) => log(output, true, &self.peer, "nonce", n),
+            _ => return Err(String::from("Expected nonce")),
+        };
+        if commitment(self.other_party(), other_nonce) != other_commitment {
+            return Err(String::from("the nonce opened does not match its commitment"));
+        }
+
+        let (n1, n2) = if self.identity == Party::P1 { (nonce, other_nonce) } else { (other_nonce, nonce) };
+        let seed = joint_seed(n1, n2);
+        output.push_str(&format!("Joint seed: {}\n", seed.iter().map(|b| format!("{b:02x}")).collect::<String>()));
+
+        Ok(seed)
+    }
+    // [a * b] is computed for each triple (a, b, c) using the corresponding sacrificed one, and a random linear
+    // combination of the differences [c - a * b], with coefficients drawn from a seed neither party chose alone,
+    // is opened. since the two triples come from different dealers, each difference is only zero for both good or,
+    // with one corrupt dealer, with negligible probability, and a combination of differences which are not all
+    // zero is zero with probability 1 / q. only then are the differences opened one by one, to tell which triple is
+    // bad
+    async fn check_triples(
+        &self,
+        output: &mut Transcript,
+        triples: &[BeaverSharing<T>],
+        mut sacrificed: Vec<BeaverSharing<T>>,
+    ) -> Result<(), BeaverError> {
+        // process_muls pops the triples it uses
+        sacrificed.reverse();
+
+        let pairs: Vec<(&T, &T)> = triples.iter().map(|BeaverSharing(a, b, _)| (a, b)).collect();
+        let products = self.process_muls(output, &pairs, &mut sacrificed).await?;
+
+        let differences: Vec<T> = triples
+            .iter()
+            .zip(&products)
+            .map(|(BeaverSharing(_, _, c), p)| T::subtract(c, p, &self.field))
+            .collect();
+
+        let seed = match self.joint_randomness(output).await {
+            Ok(seed) => seed,
+            Err(e) => return Err(self.abort(output, &format!("Error during triple check: {e}"))),
+        };
+        let rng = &mut ChaCha20Rng::from_seed(seed);
+        // there is at least one triple to check
+        let combination = differences
+            .iter()
+            .map(|d| T::mulc(d, self.field.random(rng), &self.field))
+            .reduce(|acc, d| T::add(&acc, &d, &self.field))
+            .unwrap();
+
+        self.send_opening(output, &combination);
+        if self.receive_opening(output, &combination).await? == 0 {
+            return Ok(());
+        }
+
+        let openings: Vec<T> = differences.iter().map(|s| self.opening(output, s)).collect();
+        self.send_other(Msg::Openings(openings));
+
+        let received = match self.recv_other().await {
+            Ok(Msg::Openings(r)) if r.len() == differences.len() => r,
+            _ => return Err(self.abort(output, "Error during triple check: Expected openings of differences")),
+        };
+
+        for (i, (s, r)) in differences.iter().zip(received).enumerate() {
+            if self.check_opening(output, s, r)? != 0 {
+                let (source, sacrifice) = self.triple_sources[i];
+                return Err(self.abort(
+                    output,
+                    &format!("Error during triple check: triple {i} does not match its sacrifice, so dealer {source} or {sacrifice} is corrupt"),
+                ));
+            }
+        }
+
+        Err(self.abort(output, "Error during triple check: the combination of the differences is not zero, but each of them is"))
+    }
+    // the parties draw which triples to audit from a seed they both contribute to, so that the dealer cannot
+    // predict it, then open those completely and check their MACs and products. only the rest are kept, in order
+    async fn audit_triples(
+        &self,
+        output: &mut Transcript,
+        triples: Vec<BeaverSharing<T>>,
+        n_audited: usize,
+    ) -> Result<Vec<BeaverSharing<T>>, BeaverError> {
+        let nonce = self.rng.borrow_mut().next_u64();
+        self.send_other(Msg::Nonce(log(output, false, &self.peer, "nonce", nonce)));
+
+        let other_nonce = match self.recv_other().await {
+            Ok(Msg::Nonce(n)) => log(output, true, &self.peer, "nonce", n),
+            _ => return Err(self.abort(output, "Error during triple audit: Expected nonce")),
+        };
+
+        let mut selected: Vec<usize> =
+            index::sample(&mut ChaCha20Rng::seed_from_u64(nonce ^ other_nonce), triples.len(), n_audited).into_vec();
+        selected.sort();
+
+        let audited: Vec<&T> = selected
+            .iter()
+            .flat_map(|i| {
+                let BeaverSharing(a, b, c) = &triples[*i];
+                [a, b, c]
+            })
+            .collect();
+
+        let openings: Vec<T> = audited.iter().map(|s| self.opening(output, s)).collect();
+        self.send_other(Msg::Openings(openings));
+
+        let received = match self.recv_other().await {
+            Ok(Msg::Openings(r)) if r.len() == audited.len() => r,
+            _ => return Err(self.abort(output, "Error during triple audit: Expected openings of audited triples")),
+        };
+
+        let mut opened = Vec::with_capacity(received.len());
+        for (s, r) in audited.iter().zip(received) {
+            opened.push(self.check_opening(output, s, r)?);
+        }
+
+        for (i, abc) in selected.iter().zip(opened.chunks_exact(3)) {
+            if self.field.mul(abc[0], abc[1]) != abc[2] {
+                return Err(self.abort(
+                    output,
+                    &format!("Error during triple audit: triple {i} is not a valid Beaver triple, so the dealer is corrupt"),
+                ));
+            }
+        }
+
+        Ok(triples
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| selected.binary_search(i).is_err())
+            .map(|(_, t)| t)
+            .collect())
+    }
+    async fn process_inputs(
+        &self,
+        output: &mut Transcript,
+        ids: &Vec<u32>,
+        masks: &mut HashMap<InputWire, T>,
+        source: Party,
+        first: bool,
+    ) -> Result<HashMap<u32, T>, BeaverError> {
+        let mut sharing_hash = HashMap::new();
+
+        if source == self.identity {
+            let inputs = if first {&self.inputs_first} else {&self.inputs_second};
+
+            for (id, v) in ids.iter().zip(inputs.iter()) {
+                self.context.set((Phase::InputProcessing, Some(*id)));
+
+                if let Some(s) = self.import_input(output, InputWire { party: source, first, id: *id }) {
+                    sharing_hash.insert(*id, s);
+                    continue;
+                }
+
+                // cannot fail, as the masks were checked against the circuit's input wires
+                let a = masks.remove(&InputWire { party: source, first, id: *id }).unwrap();
+                let a_open = self.receive_opening(output, &a).await?;
+                let d = self.field.sub(*v, a_open);
+
+                self.send_other(Msg::Value(log(
+                    output,
+                    false,
+                    &self.peer,
+                    "delta for input processing",
+                    d,
+                )));
+
+                sharing_hash.insert(
+                    *id,
+                    T::addc(&a, d, self.s_k1, self.s_k2, &self.field, self.identity),
+                );
+            }
+        } else {
+            for id in ids {
+                self.context.set((Phase::InputProcessing, Some(*id)));
+
+                if let Some(s) = self.import_input(output, InputWire { party: source, first, id: *id }) {
+                    sharing_hash.insert(*id, s);
+                    continue;
+                }
+
+                let a = masks.remove(&InputWire { party: source, first, id: *id }).unwrap();
+                self.send_opening(output, &a);
+
+                let d = match self.recv_other().await {
+                    Ok(Msg::Value(v)) => log(output, true, &self.peer, "delta for input processing", v),
+                    _ => return Err(self.abort(output, "Error during distribution of key sharings: expected delta for input processing")),
+                };
+
+                sharing_hash.insert(
+                    *id,
+                    T::addc(&a, d, self.s_k1, self.s_k2, &self.field, self.identity),
+                );
+            }
+        }
+
+        Ok(sharing_hash)
+    }
+    // a copy of the party's share of the given wire, if it was shared externally
+    fn import_input(&self, output: &mut Transcript, wire: InputWire) -> Option<T> {
+        let s = T::mulc(self.external_inputs.get(&wire)?, 1, &self.field);
+        output.push_str(&format!("Imported external sharing for {}: {s}\n", self.wire_name(&wire)));
+        Some(s)
+    }
+    fn process_gate_add(&self, s1: &T, s2: &T) -> T {
+        T::add(s1, s2, &self.field)
+    }
+    async fn process_gate_mul(
+        &self,
+        output: &mut Transcript,
+        s1: &T,
+        s2: &T,
+        BeaverSharing(a, b, c): BeaverSharing<T>,
+    ) -> Result<T, BeaverError> {
+        let u = T::subtract(s1, &a, &self.field);
+        let v = T::subtract(s2, &b, &self.field);
+
+        self.send_opening(output, &u);
+        self.send_opening(output, &v);
+
+        let u_open = self.receive_opening(output, &u).await?;
+        let v_open = self.receive_opening(output, &v).await?;
+
+        Ok(self.beaver_product(&BeaverSharing(a, b, c), u_open, v_open))
+    }
+    // [x * y] = u * v + u * [b] + v * [a] + [c] from the openings u = x - a and v = y - b
+    fn beaver_product(&self, BeaverSharing(a, b, c): &BeaverSharing<T>, u_open: u32, v_open: u32) -> T {
+        T::addc(
+            &T::add(
+                &T::mulc(b, u_open, &self.field),
+                &T::add(&T::mulc(a, v_open, &self.field), c, &self.field),
+                &self.field,
+            ),
+            self.field.mul(u_open, v_open),
+            self.s_k1,
+            self.s_k2,
+            &self.field,
+            self.identity,
+        )
+    }
+    // multiplies the given pairs with one triple each, opening all the masked values in a single exchange
+    async fn process_muls(
+        &self,
+        output: &mut Transcript,
+        pairs: &[(&T, &T)],
+        triples: &mut Vec<BeaverSharing<T>>,
+    ) -> Result<Vec<T>, BeaverError> {
+        // the number of triples was checked upon reception
+        let used: Vec<BeaverSharing<T>> = pairs.iter().map(|_| triples.pop().unwrap()).collect();
+
+        let masked: Vec<T> = pairs
+            .iter()
+            .zip(&used)
+            .flat_map(|((x, y), BeaverSharing(a, b, _))| [T::subtract(x, a, &self.field), T::subtract(y, b, &self.field)])
+            .collect();
+
+        let openings: Vec<T> = masked.iter().map(|s| self.opening(output, s)).collect();
+        self.send_other(Msg::Openings(openings));
+
+        let received = match self.recv_other().await {
+            Ok(Msg::Openings(r)) if r.len() == masked.len() => r,
+            _ => return Err(self.abort(output, "Error during multiplication: Expected openings of masked values")),
+        };
+
+        let mut opened = Vec::with_capacity(received.len());
+        for (s, r) in masked.iter().zip(received) {
+            opened.push(self.check_opening(output, s, r)?);
+        }
+
+        Ok(used.iter().zip(opened.chunks_exact(2)).map(|(t, uv)| self.beaver_product(t, uv[0], uv[1])).collect())
+    }
+    // whether the shared d is zero: with r = sum(r_i * 2^i) for the shared bits r_i of a uniformly random value
+    // modulo q, c = d + r is opened and d is zero iff c = r, i.e. iff every bit r_i equals c_i. the product of the
+    // flags [r_i = c_i] (r_i if c_i = 1, 1 - r_i otherwise) is computed with one multiplication round per level of a
+    // binary tree
+    async fn process_gate_eqz(
+        &self,
+        output: &mut Transcript,
+        d: &T,
+        EqualitySharing(bits): EqualitySharing<T>,
+        triples: &mut Vec<BeaverSharing<T>>,
+    ) -> Result<T, BeaverError> {
+        let f = &self.field;
+
+        let r = bits.iter().enumerate().fold(T::mulc(d, 0, f), |acc, (i, r_i)| {
+            T::add(&acc, &T::mulc(r_i, ((1u64 << i) % f.q as u64) as u32, f), f)
+        });
+        let masked = T::add(d, &r, f);
+
+        self.send_opening(output, &masked);
+        let c = self.receive_opening(output, &masked).await?;
+
+        let mut flags: Vec<T> = bits
+            .iter()
+            .enumerate()
+            .map(|(i, r_i)| {
+                if (c >> i) & 1 == 1 {
+                    T::mulc(r_i, 1, f)
+                } else {
+                    self.process_gate_addc(&r_i.complement(f), 1)
+                }
+            })
+            .collect();
+
+        while flags.len() > 1 {
+            let pairs: Vec<(&T, &T)> = flags.chunks_exact(2).map(|f| (&f[0], &f[1])).collect();
+            let mut next = self.process_muls(output, &pairs, triples).await?;
+            if flags.len() % 2 == 1 {
+                next.push(flags.pop().unwrap());
+            }
+            flags = next;
+        }
+
+        // the number of bits was checked upon reception
+        Ok(flags.pop().unwrap())
+    }
+    // comparison with zero of x in [-2^(k - 1), 2^(k - 1)). with m = k - 1, x is negative iff bit m of
+    // x' = x + 2^(k - 1) is 0. c = x' + r is opened (without wrapping around q) for r = h * 2^m + r_low, so that
+    // x' mod 2^m = (c mod 2^m) - r_low + 2^m * [c mod 2^m < r_low], where the last term is computed from the
+    // public bits of c and the shared bits of r_low with one multiplication round per level of a binary tree.
+    // bit m of x' is then (x' - x' mod 2^m) / 2^m
+    async fn process_gate_ltz(
+        &self,
+        output: &mut Transcript,
+        x: &T,
+        k: u32,
+        ComparisonSharing(bits, high): ComparisonSharing<T>,
+        triples: &mut Vec<BeaverSharing<T>>,
+    ) -> Result<T, BeaverError> {
+        let f = &self.field;
+        let m = k - 1;
+
+        let r_low = bits
+            .iter()
+            .enumerate()
+            .fold(T::mulc(&high, 0, f), |acc, (i, r)| T::add(&acc, &T::mulc(r, 1 << i, f), f));
+
+        let shifted = self.process_gate_addc(x, 1 << m);
+        let masked = T::add(&T::add(&shifted, &T::mulc(&high, 1 << m, f), f), &r_low, f);
+
+        self.send_opening(output, &masked);
+        let c = self.receive_opening(output, &masked).await?;
+        let c_low = c & ((1 << m) - 1);
+
+        // [c_low < r_low] is the carry of a prefix computation over (generate, propagate) pairs from the lowest
+        // bit up: bit i generates a borrow if c_i = 0 and r_i = 1 and propagates the one below if c_i = r_i
+        let mut nodes: Vec<(T, T)> = bits
+            .iter()
+            .enumerate()
+            .map(|(i, r)| {
+                if (c_low >> i) & 1 == 0 {
+                    (T::mulc(r, 1, f), self.process_gate_addc(&r.complement(f), 1))
+                } else {
+                    (T::mulc(r, 0, f), T::mulc(r, 1, f))
+                }
+            })
+            .collect();
+
+        // each level combines adjacent pairs (lo, hi) into (g_hi + p_hi * g_lo, p_hi * p_lo)
+        while nodes.len() > 1 {
+            let pairs: Vec<(&T, &T)> =
+                nodes.chunks_exact(2).flat_map(|n| [(&n[1].1, &n[0].0), (&n[1].1, &n[0].1)]).collect();
+            let mut products = self.process_muls(output, &pairs, triples).await?.into_iter();
+
+            let mut next: Vec<(T, T)> = nodes
+                .chunks_exact(2)
+                .map(|n| (T::add(&n[1].0, &products.next().unwrap(), f), products.next().unwrap()))
+                .collect();
+            if nodes.len() % 2 == 1 {
+                next.push(nodes.pop().unwrap());
+            }
+            nodes = next;
+        }
+
+        let borrow = match nodes.pop() {
+            Some((g, _)) => g,
+            None => T::mulc(x, 0, f),
+        };
+
+        let low = self.process_gate_addc(&T::subtract(&T::mulc(&borrow, 1 << m, f), &r_low, f), c_low);
+        // q is odd (see Circuit::check_value_bounds), so 2^m is invertible even if q is not prime
+        let top = T::mulc(&T::subtract(&shifted, &low, f), f.inv(1 << m).unwrap(), f);
+
+        Ok(self.process_gate_addc(&top.complement(f), 1))
+    }
+    // whether the shared value x is zero, revealing nothing else about it: x is multiplied by the random b of a
+    // triple (a, b, c) and the product opened. computing [x * b] = (x - a) * [b] + [c] only requires opening
+    // x - a, which a masks. if x is not zero and q is prime, x * b is uniformly random, so a false positive has
+    // probability 1/q
+    async fn check_zero(
+        &self,
+        output: &mut Transcript,
+        s: &T,
+        BeaverSharing(a, b, c): BeaverSharing<T>,
+    ) -> Result<bool, BeaverError> {
+        let u = T::subtract(s, &a, &self.field);
+
+        self.send_opening(output, &u);
+        let u_open = self.receive_opening(output, &u).await?;
+
+        let product = T::add(&T::mulc(&b, u_open, &self.field), &c, &self.field);
+
+        self.send_opening(output, &product);
+        let product_open = self.receive_opening(output, &product).await?;
+
+        Ok(product_open == 0)
+    }
+    fn process_gate_addc(&self, s: &T, c: u32) -> T {
+        T::addc(s, c, self.s_k1, self.s_k2, &self.field, self.identity)
+    }
+    fn process_gate_mulc(&self, s: &T, c: u32) -> T {
+        T::mulc(s, c, &self.field)
+    }
+    // probabilistic truncation: with x shifted to x' = x + 2^(k - 1) in [0, 2^k), c = x' + r is opened (without
+    // wrapping around q) and c / 2^f - r' - 2^(k - 1 - f) is computed locally. the result is x / 2^f rounded down,
+    // plus 1 if the carry from the low bits of x' and r reaches bit f
+    async fn process_gate_truncate(
+        &self,
+        output: &mut Transcript,
+        x: &T,
+        f: u32,
+        TruncationSharing(r, r_high): TruncationSharing<T>,
+    ) -> Result<T, BeaverError> {
+        let k = utilities::truncation_bits(self.field.q);
+
+        let masked = T::add(&self.process_gate_addc(x, 1 << (k - 1)), &r, &self.field);
+
+        self.send_opening(output, &masked);
+        let c = self.receive_opening(output, &masked).await?;
+
+        let constant = self.field.sub(c >> f, 1 << (k - 1 - f));
+
+        Ok(self.process_gate_addc(&r_high.complement(&self.field), constant))
+    }
+    // with a tuple (a, b, c = <a, b>), opens u = x - a and v = y - b in a single exchange, after which
+    // <x, y> = <u, v> + <u, b> + <v, a> + c is local
+    async fn process_gate_dot(
+        &self,
+        output: &mut Transcript,
+        xs: &[&T],
+        ys: &[&T],
+        InnerProductSharing(a, b, c): InnerProductSharing<T>,
+    ) -> Result<T, BeaverError> {
+        let us: Vec<T> = xs.iter().zip(&a).map(|(x, a)| T::subtract(x, a, &self.field)).collect();
+        let vs: Vec<T> = ys.iter().zip(&b).map(|(y, b)| T::subtract(y, b, &self.field)).collect();
+
+        let openings: Vec<T> = us.iter().chain(&vs).map(|s| self.opening(output, s)).collect();
+        self.send_other(Msg::Openings(openings));
+
+        let received = match self.recv_other().await {
+            Ok(Msg::Openings(r)) if r.len() == us.len() + vs.len() => r,
+            _ => return Err(self.abort(output, "Error during dot gate processing: Expected openings of masked vectors")),
+        };
+
+        let mut opened = Vec::with_capacity(received.len());
+        for (s, r) in us.iter().chain(&vs).zip(received) {
+            opened.push(self.check_opening(output, s, r)?);
+        }
+        let (u_open, v_open) = opened.split_at(us.len());
+
+        let mut z = c;
+        let mut constant = 0;
+
+        for i in 0..us.len() {
+            z = T::add(&z, &T::mulc(&b[i], u_open[i], &self.field), &self.field);
+            z = T::add(&z, &T::mulc(&a[i], v_open[i], &self.field), &self.field);
+            constant = self.field.add(constant, self.field.mul(u_open[i], v_open[i]));
+        }
+
+        Ok(T::addc(&z, constant, self.s_k1, self.s_k2, &self.field, self.identity))
+    }
+    // local, like addition and multiplication by constants. gates have at least one input by parsing
+    fn process_gate_linear(&self, inputs: &[&T], coeffs: &[u32]) -> T {
+        let mut terms = inputs.iter().zip(coeffs).map(|(s, c)| T::mulc(s, *c, &self.field));
+        let first = terms.next().unwrap();
+        terms.fold(first, |acc, t| T::add(&acc, &t, &self.field))
+    }
+    fn wire_name(&self, wire: &InputWire) -> String {
+        wire.named((&self.names.0, &self.names.1))
+    }
+    fn other_party(&self) -> Party {
+        match self.identity {
+            Party::P1 => Party::P2,
+            Party::P2 => Party::P1,
+        }
+    }
+}
+
+// what a state machine reports when polled
+pub enum PartyStatus<T: Sharing> {
+    // it waits for the next message from this endpoint, to be handed in with handle (or disconnect)
+    NeedsInput(Endpoint),
+    // the messages it sent since last polled, which the driver delivers in order
+    WantsToSend(Vec<Outgoing<T>>),
+    // it is over and sent all of its messages
+    Finished(HashMap<u32, u32>),
+    Aborted(BeaverError),
+}
+
+// a party along with the result of its run
+type PartyRun<T> = (ProtocolParty<T>, Result<HashMap<u32, u32>, BeaverError>);
+type PartyTask<T> = Pin<Box<dyn Future<Output = PartyRun<T>>>>;
+
+// the main dealer, P1 and P2
+pub type ProtocolMachines<T> = (DealerMachine<T>, PartyMachine<T>, PartyMachine<T>);
+
+// a party as a sans-io state machine, which neither blocks nor spawns threads: it runs until it needs a message
+// which was not handed in yet, and hands out the ones it sends. its driver delivers those and hands in the
+// messages of the dealer and the other party, over threads and channels as in run_beaver_protocol or otherwise
+pub struct PartyMachine<T: Sharing> {
+    mailbox: Arc<Mutex<Mailbox<T>>>,
+    task: Option<PartyTask<T>>,
+    // the party and its result, once the run is over
+    done: Option<PartyRun<T>>,
+}
+
+impl<T: Sharing + 'static> PartyMachine<T> {
+    // a resumed party starts from its latest checkpoint
+    fn new(mut party: ProtocolParty<T>, resume: bool) -> Self {
+        let mailbox = party.mailbox.clone();
+        let task: PartyTask<T> = Box::pin(async move {
+            let r = if resume { party.resume().await } else { party.run().await };
+            (party, r)
+        });
+        PartyMachine { mailbox, task: Some(task), done: None }
+    }
+    // hands in the next message from the endpoint the party needs input from, and returns the messages it sent
+    // in response
+    pub fn handle(&mut self, incoming: Msg<T>) -> Vec<Outgoing<T>> {
+        self.deliver(Ok(incoming))
+    }
+    // tells the party that the endpoint it needs input from hung up, as a dropped channel would
+    pub fn disconnect(&mut self) -> Vec<Outgoing<T>> {
+        self.deliver(Err(RecvError))
+    }
+    // the messages to deliver come first, so that a party which is over has sent everything
+    pub fn poll(&mut self) -> PartyStatus<T> {
+        self.step();
+        let mut mailbox = self.mailbox.lock().unwrap();
+        if !mailbox.outgoing.is_empty() {
+            return PartyStatus::WantsToSend(mailbox.outgoing.drain(..).collect());
+        }
+        match (&self.done, mailbox.awaiting) {
+            (Some((_, Ok(outputs))), _) => PartyStatus::Finished(outputs.clone()),
+            (Some((_, Err(e))), _) => PartyStatus::Aborted(e.clone()),
+            (None, Some(from)) => PartyStatus::NeedsInput(from),
+            // a party only suspends while waiting for a message
+            (None, None) => unreachable!("the party is suspended without waiting for a message"),
+        }
+    }
+    fn deliver(&mut self, r: Result<Msg<T>, RecvError>) -> Vec<Outgoing<T>> {
+        self.mailbox.lock().unwrap().delivered = Some(r);
+        self.step();
+        self.mailbox.lock().unwrap().outgoing.drain(..).collect()
+    }
+    // runs the party until it waits for a message which was not handed in, or is over
+    fn step(&mut self) {
+        if let Some(task) = &mut self.task {
+            if let Poll::Ready(done) = task.as_mut().poll(&mut Context::from_waker(Waker::noop())) {
+                self.task = None;
+                self.done = Some(done);
+            }
+        }
+    }
+    // the result and the party, once polling reported the run over
+    fn finish(self) -> (Result<HashMap<u32, u32>, BeaverError>, ProtocolParty<T>) {
+        let (party, r) = self.done.expect("the party is not over");
+        (r, party)
+    }
+}
+
+// the main dealer as a state machine, which needs no input: it sends all of its messages when first polled
+pub struct DealerMachine<T: Sharing> {
+    dealer: Dealer<T>,
+    rx_p1: Rx<T>,
+    rx_p2: Rx<T>,
+    result: Option<Result<(), BeaverError>>,
+}
+
+impl<T: Sharing> DealerMachine<T> {
+    pub fn poll(&mut self) -> PartyStatus<T> {
+        let result = self.result.get_or_insert_with(|| self.dealer.run());
+        let outgoing: Vec<Outgoing<T>> = [(Party::P1, &self.rx_p1), (Party::P2, &self.rx_p2)]
+            .into_iter()
+            .flat_map(|(p, rx)| rx.try_iter().map(move |msg| Outgoing { to: Endpoint::Party(p), msg }))
+            .collect();
+        match result {
+            _ if !outgoing.is_empty() => PartyStatus::WantsToSend(outgoing),
+            Ok(()) => PartyStatus::Finished(HashMap::new()),
+            Err(e) => PartyStatus::Aborted(e.clone()),
+        }
+    }
+}
+
+// sets up a run as state machines for the caller to drive: the main dealer, P1 and P2. T must be the sharing the
+// options ask for, as in run_beaver_protocol. only runs with a single dealer over unbounded channels can be set up
+// so, and any latency or tampering in transit is up to the driver
+pub fn beaver_protocol_machines<T: Sharing + 'static>(
+    circuit_encoding: &str,
+    q: u32,
+    inputs: Inputs,
+    options: &ProtocolOptions,
+) -> Result<ProtocolMachines<T>, BeaverError> {
+    if options.dealers > 1 || options.channel_capacity.is_some() {
+        return Err(BeaverError::Input(String::from(
+            "Error: state machines are only set up for a single dealer over unbounded channels",
+        )));
+    }
+    if options.authenticated != (T::SCHEME == AuthSharing::SCHEME) {
+        return Err(BeaverError::Input(format!(
+            "Error: {} sharings do not match the {}authenticated options",
+            T::SCHEME,
+            if options.authenticated { "" } else { "un" }
+        )));
+    }
+
+    let Setup { dealer, mut rx_dealers_p1, mut rx_dealers_p2, party1, party2, .. } =
+        setup::<T>(circuit_encoding, q, inputs, options)?;
+    let dealer = DealerMachine { dealer, rx_p1: rx_dealers_p1.remove(0), rx_p2: rx_dealers_p2.remove(0), result: None };
+
+    Ok((dealer, PartyMachine::new(party1, false), PartyMachine::new(party2, false)))
+}
+
+// drives a party over channels: it delivers what the party sends to the other one, after the simulated latency,
+// and blocks on the channel of the endpoint the party needs input from. returns the result along with the party
+fn drive_party<T: Sharing + 'static>(
+    mut machine: PartyMachine<T>,
+    rx_dealers: Vec<Rx<T>>,
+    (rx_other, tx_other): (Rx<T>, Tx<T>),
+    latency: Option<Duration>,
+) -> (Result<HashMap<u32, u32>, BeaverError>, ProtocolParty<T>) {
+    let send = |outgoing: Vec<Outgoing<T>>| {
+        for Outgoing { msg, .. } in outgoing {
+            if let Some(l) = latency {
+                thread::sleep(l);
+            }
+            // if the other party hung up, this one fails once it waits for it
+            let _ = tx_other.send(msg);
+        }
+    };
+
+    loop {
+        match machine.poll() {
+            PartyStatus::WantsToSend(outgoing) => send(outgoing),
+            PartyStatus::NeedsInput(from) => {
+                let rx = match from {
+                    Endpoint::Dealer(i) => &rx_dealers[i],
+                    Endpoint::Party(_) => &rx_other,
+                };
+                send(match rx.recv() {
+                    Ok(msg) => machine.handle(msg),
+                    Err(_) => machine.disconnect(),
+                });
+            }
+            PartyStatus::Finished(_) | PartyStatus::Aborted(_) => return machine.finish(),
+        }
+    }
+}
+
+pub fn run_beaver_protocol(
+    circuit_encoding: &str,
+    q: u32,
+    inputs: Inputs,
+    options: &ProtocolOptions,
+) -> Result<ProtocolResult, BeaverError> {
+    if options.authenticated {
+        run_beaver_protocol_internal::<AuthSharing>(circuit_encoding, q, inputs, options, false)
+    } else {
+        run_beaver_protocol_internal::<UnauthSharing>(circuit_encoding, q, inputs, options, false)
+    }
+}
+
+// runs the protocol twice with honest parties and the same seed, first with unauthenticated and then with
+// authenticated sharings, and checks that both runs reveal the same outputs. any difference points to a bug in
+// the arithmetic of one of the two sharing types. returns the result of the authenticated run
+pub fn cross_check(circuit_encoding: &str, q: u32, inputs: Inputs, seed: Option<u64>) -> Result<ProtocolResult, BeaverError> {
+    let options = |authenticated| ProtocolOptions { authenticated, seed, log_level: LogLevel::Off, ..Default::default() };
+
+    let unauth = run_beaver_protocol(circuit_encoding, q, inputs.clone(), &options(false))?;
+    let auth = run_beaver_protocol(circuit_encoding, q, inputs, &options(true))?;
+
+    for (party, outputs_unauth, outputs_auth) in [
+        (Party::P1, &unauth.outputs_p1, &auth.outputs_p1),
+        (Party::P2, &unauth.outputs_p2, &auth.outputs_p2),
+    ] {
+        let mut ids: Vec<&u32> = outputs_unauth.keys().collect();
+        ids.sort();
+
+        for id in ids {
+            if outputs_auth.get(id) != Some(&outputs_unauth[id]) {
+                return Err(BeaverError::Protocol(format!(
+                    "Error: cross-check failed for output {id} of {party}: {} in the unauthenticated run, {} in the authenticated one",
+                    outputs_unauth[id],
+                    outputs_auth.get(id).map_or(String::from("none"), |v| v.to_string())
+                )));
+            }
+        }
+    }
+
+    Ok(auth)
+}
+
+// resumes an interrupted run from the latest checkpoints saved by the parties, which must have been produced
+// with the same circuit, q, mode and checkpoint options. no input values or dealer are needed
+pub fn resume_beaver_protocol(
+    circuit_encoding: &str,
+    q: u32,
+    options: &ProtocolOptions,
+) -> Result<ProtocolResult, BeaverError> {
+    if options.checkpoint.is_none() {
+        return Err(BeaverError::Input(String::from(
+            "Error: resuming a run requires the checkpoint options it was run with",
+        )));
+    }
+
+    let circuit: Circuit = circuit_encoding.parse().map_err(BeaverError::Parse)?;
+    let arity = circuit.input_arity();
+    // the actual inputs are already shared in the checkpoints, these only pass the input count validation
+    let inputs = Inputs {
+        p1: (vec![0; arity.p1_first], vec![0; arity.p1_second]),
+        p2: (vec![0; arity.p2_first], vec![0; arity.p2_second]),
+        public: vec![0; circuit.num_public_inputs()],
+    };
+
+    if options.authenticated {
+        run_beaver_protocol_internal::<AuthSharing>(circuit_encoding, q, inputs, options, true)
+    } else {
+        run_beaver_protocol_internal::<UnauthSharing>(circuit_encoding, q, inputs, options, true)
+    }
+}
+
+// runs the protocol with sharings which carry no data: all outputs are 0, but every gate is processed and
+// every message is sent as in a real run. only meant for profiling
+#[cfg(feature = "benchmarking")]
+pub fn run_beaver_protocol_null(
+    circuit_encoding: &str,
+    q: u32,
+    inputs: Inputs,
+    options: &ProtocolOptions,
+) -> Result<ProtocolResult, BeaverError> {
+    run_beaver_protocol_internal::<NullSharing>(circuit_encoding, q, inputs, options, false)
+}
+
+// every input value must be below q, as the sharings of larger ones would not be reduced
+fn check_input_range(inputs: &Inputs, q: u32) -> Result<(), BeaverError> {
+    let lists = [
+        (Party::P1, true, &inputs.p1.0),
+        (Party::P1, false, &inputs.p1.1),
+        (Party::P2, true, &inputs.p2.0),
+        (Party::P2, false, &inputs.p2.1),
+    ];
+
+    for (party, first, values) in lists {
+        if let Some((position, value)) = values.iter().enumerate().find(|(_, v)| **v >= q) {
+            return Err(BeaverError::InputRange { party, first, position, value: *value, q });
+        }
+    }
+
+    if let Some((i, value)) = inputs.public.iter().enumerate().find(|(_, v)| **v >= q) {
+        return Err(BeaverError::Input(format!("Error: public input {i} is {value}, which is not below q = {q}")));
+    }
+
+    Ok(())
+}
+
+// each externally shared wire must be an input wire of the circuit, given once, with valid shares of both parties.
+// their MACs would have to be under the keys of the dealer, which the upstream protocol cannot know
+fn check_external_inputs<T: Sharing>(circuit: &Circuit, q: u32, options: &ProtocolOptions) -> Result<(), BeaverError> {
+    if options.external_inputs.is_empty() {
+        return Ok(());
+    }
+    if options.authenticated {
+        return Err(BeaverError::Input(String::from(
+            "Error: externally shared inputs are only supported in unauthenticated runs",
+        )));
+    }
+
+    let wires = circuit.input_wires();
+    let mut seen = HashSet::new();
+
+    for ExternalInput { wire, shares: (c1, c2) } in &options.external_inputs {
+        if !wires.contains(wire) {
+            return Err(BeaverError::Input(format!("Error: the {wire} shared externally is not an input wire of the circuit")));
+        }
+        if !seen.insert(*wire) {
+            return Err(BeaverError::Input(format!("Error: the {wire} is shared externally more than once")));
+        }
+        for (party, c) in [(Party::P1, c1), (Party::P2, c2)] {
+            if c.iter().any(|v| *v >= q) || T::from_components(c).is_none() {
+                return Err(BeaverError::Input(format!(
+                    "Error: the share of {party} of the externally shared {wire} is not a valid {} share modulo {q}",
+                    T::SCHEME
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn reduce_inputs(inputs: &mut Inputs, q: u32) {
+    for values in [&mut inputs.p1.0, &mut inputs.p1.1, &mut inputs.p2.0, &mut inputs.p2.1, &mut inputs.public] {
+        values.iter_mut().for_each(|v| *v %= q);
+    }
+}
+
+// everything a run needs once its circuit, inputs and options are checked: the dealers, the receiving ends of the
+// channels from each of them (the main one first) to each party, and the parties
+struct Setup<T: Sharing> {
+    dealer: Dealer<T>,
+    triple_dealers: Vec<TripleDealer<T>>,
+    rx_dealers_p1: Vec<Rx<T>>,
+    rx_dealers_p2: Vec<Rx<T>>,
+    party1: ProtocolParty<T>,
+    party2: ProtocolParty<T>,
+    names: (String, String),
+    field: ModField,
+    triples: usize,
+    rounds: RoundReport,
+    start: Instant,
+}
+
+fn setup<T: Sharing>(
+    circuit_encoding: &str,
+    q: u32,
+    mut inputs: Inputs,
+    options: &ProtocolOptions,
+) -> Result<Setup<T>, BeaverError> {
+    // every value is sampled and reduced modulo q, which is only meaningful (and, for 0, possible) from 2 onwards
+    if q < 2 {
+        return Err(BeaverError::Input(format!("Error: the modulus q should be at least 2, not {q}")));
+    }
+
+    let field = ModField::new(q);
+    let start = Instant::now();
+
+    let mut c1: Circuit = circuit_encoding.parse().map_err(BeaverError::Parse)?;
+    // the next two calls cannot fail if this line is reached
+    // the circuit is computed separately by each party to mimic execution in independent machines
+    let mut c2: Circuit = circuit_encoding.parse().unwrap();
+    let mut c3: Circuit = circuit_encoding.parse().unwrap();
+
+    if options.fold_constants {
+        for c in [&mut c1, &mut c2, &mut c3] {
+            c.fold_constants();
+        }
+    }
+
+    c1.check_inputs(&inputs).map_err(BeaverError::Input)?;
+    if options.reduce_inputs {
+        reduce_inputs(&mut inputs, q);
+    } else {
+        check_input_range(&inputs, q)?;
+    }
+    c1.check_value_bounds(q).map_err(BeaverError::Input)?;
+
+    // over Z_(2^n), a MAC key * v is also valid for v + 2^(n - 1) whenever the key is even, so the MAC checks
+    // would miss such tampering half of the time. q = 2 is prime, and fine
+    if options.authenticated && q > 2 && q.is_power_of_two() {
+        return Err(BeaverError::Input(format!(
+            "Error: MACs require a prime field; use SPDZ2k-style authentication, not yet implemented (q = {q} is a power of two)"
+        )));
+    }
+    // unauthenticated runs also accept other composite moduli, with the same caveats as powers of two
+    if options.authenticated && !utilities::is_prime(q as u64) {
+        return Err(BeaverError::Input(format!("Error: MACs require a prime field, but q = {q} is not prime")));
+    }
+
+    check_external_inputs::<T>(&c1, q, options)?;
+
+    // a single master seed from which independent RNGs for the dealers, each party and the adversary are derived
+    let master = match (options.master_seed, options.seed) {
+        (Some(master), _) => master,
+        (None, Some(seed)) => ChaCha20Rng::seed_from_u64(seed).get_seed(),
+        (None, None) => ChaCha20Rng::from_entropy().get_seed(),
+    };
+    let sub_rng = |label: &str| ChaCha20Rng::from_seed(utilities::derive_subseed(&master, label));
+
+    if options.no_dealer_triples && c1.num_beaver_triples() > 0 {
+        return Err(BeaverError::Input(String::from(
+            "Error: circuit requires Beaver triples but dealer triples are disabled",
+        )));
+    }
+
+    let n_dealers = options.dealers.max(1);
+    if options.corrupt_dealer.is_some_and(|(i, _)| i >= n_dealers) {
+        return Err(BeaverError::Input(format!(
+            "Error: the corrupt dealer must be one of the {n_dealers} dealers, numbered from 0"
+        )));
+    }
+    if let Some(f) = options.triple_audit {
+        if !(f > 0.0 && f < 1.0) {
+            return Err(BeaverError::Input(format!("Error: the fraction of triples audited should be in (0, 1), not {f}")));
+        }
+        if n_dealers > 1 {
+            return Err(BeaverError::Input(String::from(
+                "Error: triples are either audited or checked against those of other dealers, not both",
+            )));
+        }
+    }
+
+    // with several dealers, each triple and its sacrifice come from two different random ones
+    let triple_sources: Vec<(usize, usize)> = if n_dealers > 1 {
+        let rng = &mut sub_rng("triple-sources");
+        (0..c1.num_beaver_triples())
+            .map(|_| {
+                let source = rng.gen_range(0..n_dealers);
+                (source, (source + rng.gen_range(1..n_dealers)) % n_dealers)
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let num_triples = |dealer: usize| match n_dealers {
+        1 => c1.num_beaver_triples(),
+        _ => triple_sources.iter().map(|(s, t)| (*s == dealer) as usize + (*t == dealer) as usize).sum(),
+    };
+
+    // only the dealer's channels can be bounded: it sends all of its messages upfront, whereas the parties
+    // alternate sending and receiving. the dealer interleaves its sends to P1 and P2, each of which receives
+    // everything from the dealer before waiting for the other party, so a full channel never blocks it for good
+    let (tx_d_p1, rx_d_p1) = channel::<T>(options.channel_capacity);
+    let (tx_d_p2, rx_d_p2) = channel::<T>(options.channel_capacity);
+
+    // each additional dealer only sends triples, which the parties receive before waiting for the other party too
+    let (mut rx_dealers_p1, mut rx_dealers_p2, mut tx_keys) = (vec![rx_d_p1], vec![rx_d_p2], Vec::new());
+
+    let (name1, name2) = options
+        .party_names
+        .clone()
+        .unwrap_or((String::from("P1"), String::from("P2")));
+
+    // without custom names, each party refers to the other one generically
+    let (peer1, peer2) = match options.party_names {
+        Some(_) => (name2.clone(), name1.clone()),
+        None => (String::from("other party"), String::from("other party")),
+    };
+
+    #[cfg(debug_assertions)]
+    let debug_keys = Arc::new(OnceLock::new());
+
+    let dealer_log_level = options.dealer_log_level.unwrap_or(options.log_level);
+
+    let mut triple_dealers = Vec::new();
+
+    for index in 1..n_dealers {
+        let (tx_d_p1, rx_d_p1) = channel::<T>(options.channel_capacity);
+        let (tx_d_p2, rx_d_p2) = channel::<T>(options.channel_capacity);
+        let (tx, rx_keys) = mpsc::channel();
+        rx_dealers_p1.push(rx_d_p1);
+        rx_dealers_p2.push(rx_d_p2);
+        tx_keys.push(tx);
+
+        triple_dealers.push(TripleDealer {
+            index,
+            field,
+            num_triples: num_triples(index),
+            rx_keys,
+            tx_d_p1: Link::new(tx_d_p1, options.coalesce, None),
+            tx_d_p2: Link::new(tx_d_p2, options.coalesce, None),
+            log_path: options.output_path.as_ref().map(|p| format!("{}_dealer{}.txt", p, index)),
+            log_level: dealer_log_level,
+            log_flush_lines: options.log_flush_lines,
+            rng: sub_rng(&format!("dealer{index}")),
+            names: (name1.clone(), name2.clone()),
+            corrupt: options.corrupt_dealer.filter(|(i, _)| *i == index).map(|(_, p)| p),
+        });
+    }
+
+    let dealer = Dealer {
+        num_triples: num_triples(0),
+        tx_keys,
+        triple_audit: options.triple_audit,
+        no_triples: options.no_dealer_triples,
+        external_inputs: options.external_inputs.iter().map(|e| e.wire).collect(),
+        #[cfg(test)]
+        shuffle_masks: options.shuffle_masks,
+        corrupt: options.corrupt_dealer.filter(|(i, _)| *i == 0).map(|(_, p)| p),
+        keys: options.key_epoch.map(|e| e.keys),
+        circuit: c1,
+        field,
+        tx_d_p1: Link::new(tx_d_p1, options.coalesce, None),
+        tx_d_p2: Link::new(tx_d_p2, options.coalesce, None),
+        log_path: options.output_path.as_ref().map(|p| format!("{}_dealer.txt", p)),
+        log_level: dealer_log_level,
+        log_flush_lines: options.log_flush_lines,
+        rng: sub_rng("dealer"),
+        names: (name1.clone(), name2.clone()),
+        blind_outputs: options.blind_outputs,
+        #[cfg(debug_assertions)]
+        debug_keys: debug_keys.clone(),
+    };
+
+    // one record of untampered openings per direction, written by the sender and read by the receiver
+    let (record_p1, record_p2) = if options.explain_aborts {
+        let (p1_to_p2, p2_to_p1) = (Arc::new(Mutex::new(Vec::new())), Arc::new(Mutex::new(Vec::new())));
+        (
+            Some(OpeningsRecord { sent: p1_to_p2.clone(), received: p2_to_p1.clone(), checked: Cell::new(0) }),
+            Some(OpeningsRecord { sent: p2_to_p1, received: p1_to_p2, checked: Cell::new(0) }),
+        )
+    } else {
+        (None, None)
+    };
+
+    // if corrupt, randomly choose a party to be so unless one is given; otherwise, no party is so. the coin comes
+    // from its own stream, so that the rest of the randomness does not depend on the choice
+    let degree = options.corruption.as_ref().map(|c| c.degree);
+    let p1_corrupt = match options.corruption.as_ref().and_then(|c| c.party) {
+        Some(p) => p == Party::P1,
+        None => degree.is_some() && sub_rng("adversary").gen::<bool>(),
+    };
+
+    let Inputs { p1: (inputs_p1_first, inputs_p1_second), p2: (inputs_p2_first, inputs_p2_second), public } = inputs;
+
+    let party1 = ProtocolParty::new(
+        Party::P1,
+        c2,
+        field,
+        (inputs_p1_first, inputs_p1_second),
+        public.clone(),
+        degree.filter(|_| p1_corrupt),
+        n_dealers,
+        triple_sources.clone(),
+        (name1.clone(), peer1),
+        sub_rng("p1"),
+        options,
+        record_p1,
+        #[cfg(debug_assertions)]
+        debug_keys.clone(),
+    );
+
+    let party2 = ProtocolParty::new(
+        Party::P2,
+        c3,
+        field,
+        (inputs_p2_first, inputs_p2_second),
+        public,
+        degree.filter(|_| !p1_corrupt),
+        n_dealers,
+        triple_sources,
+        (name2.clone(), peer2),
+        sub_rng("p2"),
+        options,
+        record_p2,
+        #[cfg(debug_assertions)]
+        debug_keys.clone(),
+    );
+
+    let triples = dealer.circuit.num_beaver_triples();
+    let mut rounds = RoundReport::new(&dealer.circuit);
+    if triples > 0 {
+        rounds.triple_check = if n_dealers > 1 { 4 } else if options.triple_audit.is_some() { 2 } else { 0 };
+    }
+    // externally shared inputs are not processed
+    if options.external_inputs.len() == dealer.circuit.total_input_wires() {
+        rounds.input_processing = 0;
+    }
+
+    Ok(Setup {
+        dealer,
+        triple_dealers,
+        rx_dealers_p1,
+        rx_dealers_p2,
+        party1,
+        party2,
+        names: (name1, name2),
+        field,
+        triples,
+        rounds,
+        start,
+    })
+}
+
+fn run_beaver_protocol_internal<T: Sharing + 'static>(
+    circuit_encoding: &str,
+    q: u32,
+    inputs: Inputs,
+    options: &ProtocolOptions,
+    resume: bool,
+) -> Result<ProtocolResult, BeaverError> {
+    let Setup {
+        mut dealer,
+        triple_dealers,
+        rx_dealers_p1,
+        rx_dealers_p2,
+        party1,
+        party2,
+        names: (name1, name2),
+        field,
+        triples,
+        rounds,
+        start,
+    } = setup::<T>(circuit_encoding, q, inputs, options)?;
+
+    let party_channel = |p: Party| match options.tamper_in_transit {
+        Some((party, index)) if party == p => tampering_channel::<T>(index),
+        _ => channel::<T>(None),
+    };
+    let (tx_p1_p2, rx_p1_p2) = party_channel(Party::P1);
+    let (tx_p2_p1, rx_p2_p1) = party_channel(Party::P2);
+    let latency = options.latency;
+    let queues: Vec<Arc<QueueLength>> =
+        rx_dealers_p1.iter().chain(&rx_dealers_p2).filter_map(|rx| rx.queue.clone()).collect();
+
+    // each thread also reports how many messages it sent. a resumed run needs no dealer
+    let thread_dealer = thread::spawn(move || {
+        let r = if resume { Ok(()) } else { dealer.run() };
+        (r, dealer.tx_d_p1.sent.get(), dealer.tx_d_p2.sent.get())
+    });
+
+    let threads_triple_dealers: Vec<_> = triple_dealers
+        .into_iter()
+        .map(|mut d| {
+            thread::spawn(move || {
+                let r = if resume { Ok(()) } else { d.run() };
+                (r, d.tx_d_p1.sent.get(), d.tx_d_p2.sent.get())
+            })
+        })
+        .collect();
+
+    // each party thread is a thin loop over its state machine, which is created in the thread as it is not Send
+    let thread_p1 = thread::spawn(move || {
+        let (r, party1) = drive_party(PartyMachine::new(party1, resume), rx_dealers_p1, (rx_p2_p1, tx_p1_p2), latency);
+        (r, party1.tx_me_other.sent.get(), party1.view.map(RefCell::into_inner), party1.wire_shares)
+    });
+
+    let thread_p2 = thread::spawn(move || {
+        let (r, party2) = drive_party(PartyMachine::new(party2, resume), rx_dealers_p2, (rx_p1_p2, tx_p2_p1), latency);
+        (r, party2.tx_me_other.sent.get(), party2.view.map(RefCell::into_inner), party2.wire_shares)
+    });
+
+    let mut errors = Vec::new();
+
+    // a thread which panicked is reported like one which failed, having sent no messages
+    let (r_dealer, mut dealer_to_p1, mut dealer_to_p2) =
+        join_worker(thread_dealer).unwrap_or_else(|e| (Err(e), 0, 0));
+    if let Err(e) = r_dealer {
+        errors.push((String::from("Dealer"), e));
+    }
+    for (i, thread) in threads_triple_dealers.into_iter().enumerate() {
+        let (r, to_p1, to_p2) = join_worker(thread).unwrap_or_else(|e| (Err(e), 0, 0));
+        if let Err(e) = r {
+            errors.push((format!("Dealer {}", i + 1), e));
+        }
+        dealer_to_p1 += to_p1;
+        dealer_to_p2 += to_p2;
+    }
+    let (r_p1, p1_to_p2, view_p1, wires_p1) = join_worker(thread_p1).unwrap_or_else(|e| (Err(e), 0, None, None));
+    let outputs_p1 = r_p1.unwrap_or_else(|e| {
+        errors.push((name1, e));
+        HashMap::new()
+    });
+    let (r_p2, p2_to_p1, view_p2, wires_p2) = join_worker(thread_p2).unwrap_or_else(|e| (Err(e), 0, None, None));
+    let outputs_p2 = r_p2.unwrap_or_else(|e| {
+        errors.push((name2, e));
+        HashMap::new()
+    });
+
+    if errors.is_empty() {
+        Ok(ProtocolResult {
+            outputs_p1,
+            outputs_p2,
+            elapsed: start.elapsed(),
+            triples,
+            stats: CommStats {
+                dealer_to_p1,
+                dealer_to_p2,
+                p1_to_p2,
+                p2_to_p1,
+                peak_dealer_queue: queues.iter().map(|q| q.peak()).max(),
+            },
+            rounds,
+            views: view_p1.zip(view_p2),
+            // both parties evaluated every gate of the topology
+            wire_values: wires_p1.zip(wires_p2).map(|(w1, w2)| {
+                w1.iter().map(|(id, v)| (*id, field.add(*v, w2[id]))).collect()
+            }),
+        })
+    } else {
+        Err(BeaverError::Run(errors))
+    }
+}
+
+// the error is labelled with the thread's name in the run error, e.g. "P1: thread panicked: <payload>"
+fn join_worker<R>(handle: JoinHandle<R>) -> Result<R, BeaverError> {
+    handle.join().map_err(|payload| {
+        // panic payloads are almost always a &str or a String
+        let msg = match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+            (Some(s), _) => s.to_string(),
+            (_, Some(s)) => s.clone(),
+            _ => String::from("unknown cause"),
+        };
+        BeaverError::Protocol(format!("thread panicked: {msg}"))
+    })
+}
+
+// "P1" becomes "p1", "Big Hospital" becomes "big_hospital"
+fn log_file_name(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<&str>>()
+        .join("_")
+        .to_lowercase()
+}
+
+// writes the whole log or, if streamed, what remains of it
+fn write_log(path: &Option<String>, output: &mut Transcript, writer: &str) -> Result<(), BeaverError> {
+    output.drain();
+    let result = match (&mut output.sink, output.error.take()) {
+        (_, Some(e)) => Err(e),
+        (Some((w, ..)), None) => w.flush(),
+        (None, None) => match path {
+            Some(path) if output.enabled() => fs::write(path, &output.text),
+            _ => Ok(()),
+        },
+    };
+    result.map_err(|e| BeaverError::Io(format!("{writer} failed to write execution log: {e}")))
+}
+
+// the seed drawn by the parties from their nonces (see ProtocolParty::joint_randomness)
+fn joint_seed(nonce_p1: u64, nonce_p2: u64) -> [u8; 32] {
+    let mut master = [0; 32];
+    master[..8].copy_from_slice(&nonce_p1.to_le_bytes());
+    master[8..16].copy_from_slice(&nonce_p2.to_le_bytes());
+    utilities::derive_subseed(&master, "joint-randomness")
+}
+
+fn log<T: Display>(output: &mut Transcript, receive: bool, other: &str, desc: &str, value: T) -> T {
+    if output.enabled() {
+        let shown = if output.level == LogLevel::Verbose { format!("{value:#}") } else { value.to_string() };
+        output.push_str(&format!(
+            "{} {other} {desc}: {shown}\n",
+            if receive { "Received from" } else { "Sent to" }
+        ));
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // each party reveals an output to itself alone, so that there are no shared outputs whose cross-check would catch
+    // a tampered opening before the transcript digests
+    const CIRCUIT: &str = "0, P1, mul, P2 | 1, 0, add, P1 & 0 & 1";
+    const Q: u32 = 101;
+
+    fn inputs() -> Inputs {
+        Inputs { p1: (vec![3], vec![4]), p2: (vec![], vec![5]), public: vec![] }
+    }
+
+    fn options(tamper_in_transit: Option<(Party, usize)>) -> ProtocolOptions {
+        ProtocolOptions {
+            seed: Some(1),
+            log_level: LogLevel::Off,
+            tamper_in_transit,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn encoding_is_canonical() {
+        let mut bytes = Vec::new();
+        Msg::<UnauthSharing>::Batch(vec![Msg::Value(1), Msg::Values(vec![2, 3]), Msg::Abort]).encode(&mut bytes);
+        assert_eq!(bytes, [11, 3, 0, 0, 0, 0, 1, 0, 0, 0, 10, 2, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 13]);
+
+        // the digest of a message only depends on its encoding
+        let digest = |m: &Msg<UnauthSharing>| {
+            let mut h = Fnv1a::new();
+            m.digest(&mut h);
+            h.finish()
+        };
+        assert_eq!(digest(&Msg::Digest(7)), digest(&Msg::Digest(7)));
+        assert_ne!(digest(&Msg::Value(7)), digest(&Msg::Nonce(7)));
+        assert_ne!(digest(&Msg::Values(vec![1, 2])), digest(&Msg::Values(vec![2, 1])));
+    }
+
+    // a message of either party tampered with in transit never goes unnoticed: an unauthenticated run reports the
+    // divergence of the transcript digests at the latest, and an authenticated one aborts on a failed check first
+    #[test]
+    fn tampered_opening_is_reported_as_divergence() {
+        for authenticated in [false, true] {
+            let recorded = ProtocolOptions { authenticated, record_views: true, ..options(None) };
+            let honest = run_beaver_protocol(CIRCUIT, Q, inputs(), &recorded).unwrap();
+            assert_eq!(honest.outputs_p1, HashMap::from([(0, 15)]));
+            assert_eq!(honest.outputs_p2, HashMap::from([(1, 19)]));
+
+            let (view_p1, view_p2) = honest.views.unwrap();
+            for (party, view) in [(Party::P1, view_p1), (Party::P2, view_p2)] {
+                // without coalescing, the position of a message in the view is its index in the channel
+                assert!(view.sent.len() >= 3);
+
+                for index in 0..view.sent.len() {
+                    let tampered = ProtocolOptions { authenticated, ..options(Some((party, index))) };
+                    let errors = match run_beaver_protocol(CIRCUIT, Q, inputs(), &tampered) {
+                        Err(BeaverError::Run(errors)) => errors,
+                        r => panic!("message {index} of {party} tampered with, expected a run error, got {:?}", r.ok().map(|r| r.outputs_p1)),
+                    };
+                    assert!(errors.iter().all(|(_, e)| !e.to_string().contains("panicked")), "{errors:?}");
+
+                    // the key opening is checked against the dealer's keys in debug builds
+                    let detected = |e: &BeaverError| match e {
+                        BeaverError::Aborted(e) if matches!(**e, BeaverError::Authentication { .. }) => authenticated,
+                        e => {
+                            let e = e.to_string();
+                            e.contains("transcript divergence") || e.contains("is not the dealer's")
+                        }
+                    };
+                    assert!(
+                        errors.iter().any(|(_, e)| detected(e)),
+                        "message {index} of {party} ({:?}) tampered with: {errors:?}",
+                        view.sent[index].0
+                    );
+                }
+            }
+        }
+    }
+
+    // the folded multiplication of two public inputs takes no triple from the dealer, and the outputs are those of
+    // the unfolded circuit
+    #[test]
+    fn folded_constants_save_a_triple() {
+        let circuit = "0, pub0, mul, pub1 | 1, 0, mul, P1 | 2, 1, add, pub1 & 2 & 2";
+        let inputs = || Inputs { p1: (vec![], vec![5]), p2: (vec![], vec![]), public: vec![6, 7] };
+        let expected = HashMap::from([(2, (6 * 7 * 5 + 7) % Q)]);
+
+        for authenticated in [false, true] {
+            let unfolded = ProtocolOptions { authenticated, ..options(None) };
+            let r = run_beaver_protocol(circuit, Q, inputs(), &unfolded).unwrap();
+            assert_eq!((r.triples, &r.outputs_p1, &r.outputs_p2), (2, &expected, &expected));
+
+            let folded = ProtocolOptions { authenticated, fold_constants: true, ..options(None) };
+            let r = run_beaver_protocol(circuit, Q, inputs(), &folded).unwrap();
+            assert_eq!((r.triples, &r.outputs_p1, &r.outputs_p2), (1, &expected, &expected));
+        }
+    }
+
+    // with several dealers, the coefficients of the triple check come from a seed both parties draw together: they
+    // log the same one, which changes with their nonces, i.e. with the seed of the run
+    #[test]
+    fn parties_derive_the_same_joint_seed() {
+        let dir = std::env::temp_dir().join(format!("beaver_joint_seed_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let joint_seeds = |seed: u64| -> (String, String) {
+            let path = dir.join(format!("run{seed}")).to_str().unwrap().to_string();
+            let options = ProtocolOptions {
+                authenticated: true,
+                dealers: 3,
+                seed: Some(seed),
+                log_level: LogLevel::Normal,
+                output_path: Some(path.clone()),
+                ..options(None)
+            };
+            let result = run_beaver_protocol(CIRCUIT, Q, inputs(), &options).unwrap();
+            assert_eq!(result.outputs_p1, HashMap::from([(0, 15)]));
+            assert_eq!(result.rounds.triple_check, 4);
+
+            let joint_seed = |party: &str| {
+                let log = std::fs::read_to_string(format!("{path}_{party}.txt")).unwrap();
+                let lines: Vec<&str> = log.lines().filter(|l| l.starts_with("Joint seed: ")).collect();
+                assert_eq!(lines.len(), 1, "{log}");
+                lines[0].to_string()
+            };
+            (joint_seed("p1"), joint_seed("p2"))
+        };
+
+        let (p1, p2) = joint_seeds(1);
+        assert_eq!(p1, p2);
+        let (other_p1, other_p2) = joint_seeds(2);
+        assert_eq!(other_p1, other_p2);
+        assert_ne!(p1, other_p1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // either nonce changes the seed, and so does their order
+        assert_eq!(joint_seed(1, 2), joint_seed(1, 2));
+        assert_ne!(joint_seed(1, 2), joint_seed(1, 3));
+        assert_ne!(joint_seed(1, 2), joint_seed(3, 2));
+        assert_ne!(joint_seed(1, 2), joint_seed(2, 1));
+    }
+
+    // a nonce altered in transit after its commitment is caught by the party receiving it
+    #[test]
+    fn tampered_nonce_breaks_its_commitment() {
+        let recorded = ProtocolOptions { dealers: 3, record_views: true, ..options(None) };
+        let (_, view_p2) = run_beaver_protocol(CIRCUIT, Q, inputs(), &recorded).unwrap().views.unwrap();
+        let index = view_p2.sent.iter().position(|(_, e)| matches!(e, TranscriptEntry::Nonce(_))).unwrap();
+        assert!(matches!(view_p2.sent[index - 1], (Phase::TripleCheck, TranscriptEntry::Digest(_))));
+
+        let tampered = ProtocolOptions { dealers: 3, ..options(Some((Party::P2, index))) };
+        let errors = match run_beaver_protocol(CIRCUIT, Q, inputs(), &tampered) {
+            Err(BeaverError::Run(errors)) => errors,
+            r => panic!("expected a run error, got {:?}", r.ok().map(|r| r.outputs_p1)),
+        };
+        assert!(
+            errors.iter().any(|(name, e)| name == "P1" && e.to_string().contains("does not match its commitment")),
+            "{errors:?}"
+        );
+    }
+
+    // the first message P2 sends in an authenticated run is its opening of k1, so tampering with it makes P1 open the
+    // wrong key. the run goes through the key check and aborts instead of panicking
+    #[test]
+    fn tampered_key_opening_aborts_the_run() {
+        let honest = ProtocolOptions { authenticated: true, ..options(None) };
+        let r = run_beaver_protocol(CIRCUIT, Q, inputs(), &honest).unwrap();
+        assert_eq!(r.outputs_p1, HashMap::from([(0, 15)]));
+        assert_eq!(r.outputs_p2, HashMap::from([(1, 19)]));
+
+        for party in [Party::P1, Party::P2] {
+            let tampered = ProtocolOptions { authenticated: true, ..options(Some((party, 0))) };
+            let errors = match run_beaver_protocol(CIRCUIT, Q, inputs(), &tampered) {
+                Err(BeaverError::Run(errors)) => errors,
+                r => panic!("key opening of {party} tampered with, expected a run error, got {:?}", r.ok().map(|r| r.outputs_p1)),
+            };
+            assert!(errors.iter().all(|(_, e)| !e.to_string().contains("panicked")), "{errors:?}");
+            assert!(errors.iter().any(|(_, e)| matches!(e, BeaverError::Aborted(_))), "{errors:?}");
+        }
+    }
+
+    type Outputs = Result<HashMap<u32, u32>, BeaverError>;
+    type Queues<T> = HashMap<(Endpoint, Endpoint), VecDeque<Msg<T>>>;
+
+    fn queue<T: Sharing>(queues: &mut Queues<T>, from: Endpoint, outgoing: Vec<Outgoing<T>>) {
+        for Outgoing { to, msg } in outgoing {
+            queues.entry((from, to)).or_default().push_back(msg);
+        }
+    }
+
+    // drives the dealer and both parties by hand, in this thread alone: the messages sent are queued by sender and
+    // recipient, and handed in once the recipient needs input from the sender. a recipient waiting for a sender
+    // which is over and sent nothing more is told it hung up, as the dropped channel of a threaded run would
+    fn drive_by_hand<T: Sharing + 'static>(options: &ProtocolOptions) -> (Outputs, Outputs) {
+        drive_machines(beaver_protocol_machines::<T>(CIRCUIT, Q, inputs(), options).unwrap())
+    }
+
+    fn drive_machines<T: Sharing + 'static>((mut dealer, p1, p2): ProtocolMachines<T>) -> (Outputs, Outputs) {
+        let mut queues = HashMap::new();
+
+        loop {
+            match dealer.poll() {
+                PartyStatus::WantsToSend(outgoing) => queue(&mut queues, Endpoint::Dealer(0), outgoing),
+                PartyStatus::Finished(_) => break,
+                PartyStatus::NeedsInput(_) | PartyStatus::Aborted(_) => panic!("the dealer should run to completion"),
+            }
+        }
+
+        let mut parties = [(Party::P1, p1, None), (Party::P2, p2, None)];
+        while parties.iter().any(|(_, _, r)| r.is_none()) {
+            let over: Vec<Endpoint> =
+                parties.iter().filter(|(_, _, r)| r.is_some()).map(|(p, ..)| Endpoint::Party(*p)).collect();
+            let mut progress = false;
+
+            for (party, machine, result) in parties.iter_mut().filter(|(_, _, r)| r.is_none()) {
+                let me = Endpoint::Party(*party);
+                match machine.poll() {
+                    PartyStatus::WantsToSend(outgoing) => queue(&mut queues, me, outgoing),
+                    PartyStatus::NeedsInput(from) => match queues.get_mut(&(from, me)).and_then(VecDeque::pop_front) {
+                        Some(msg) => queue(&mut queues, me, machine.handle(msg)),
+                        None if from == Endpoint::Dealer(0) || over.contains(&from) => {
+                            queue(&mut queues, me, machine.disconnect())
+                        }
+                        None => continue,
+                    },
+                    PartyStatus::Finished(outputs) => *result = Some(Ok(outputs)),
+                    PartyStatus::Aborted(e) => *result = Some(Err(e)),
+                }
+                progress = true;
+            }
+            assert!(progress, "both parties wait for each other");
+        }
+
+        let [(_, _, r1), (_, _, r2)] = parties;
+        (r1.unwrap(), r2.unwrap())
+    }
+
+    // the threaded runs are loops over the same state machines, so driving these by hand gives the same outputs
+    #[test]
+    fn machines_driven_by_hand_match_threaded_runs() {
+        for (authenticated, coalesce) in [(false, false), (false, true), (true, false), (true, true)] {
+            let options = ProtocolOptions { authenticated, coalesce, ..options(None) };
+            let threaded = run_beaver_protocol(CIRCUIT, Q, inputs(), &options).unwrap();
+            let (r1, r2) = if authenticated {
+                drive_by_hand::<AuthSharing>(&options)
+            } else {
+                drive_by_hand::<UnauthSharing>(&options)
+            };
+
+            assert_eq!(r1.unwrap(), threaded.outputs_p1);
+            assert_eq!(r2.unwrap(), threaded.outputs_p2);
+            assert_eq!(threaded.outputs_p1, HashMap::from([(0, 15)]));
+            assert_eq!(threaded.outputs_p2, HashMap::from([(1, 19)]));
+        }
+    }
+
+    // a failed MAC check aborts both parties, with the errors of the threaded run
+    #[test]
+    fn machines_driven_by_hand_abort_as_threaded_runs() {
+        let corruption = Some(CorruptionConfig { degree: 1.0, party: Some(Party::P2) });
+        let options = ProtocolOptions { authenticated: true, corruption, ..options(None) };
+        let errors = match run_beaver_protocol(CIRCUIT, Q, inputs(), &options) {
+            Err(BeaverError::Run(errors)) => errors,
+            r => panic!("expected a run error, got {:?}", r.ok().map(|r| r.outputs_p1)),
+        };
+
+        let (r1, r2) = drive_by_hand::<AuthSharing>(&options);
+        let by_hand: Vec<(String, String)> = [("P1", r1), ("P2", r2)]
+            .into_iter()
+            .filter_map(|(name, r)| r.err().map(|e| (String::from(name), e.to_string())))
+            .collect();
+        let threaded: Vec<(String, String)> = errors.into_iter().map(|(name, e)| (name, e.to_string())).collect();
+        assert!(by_hand.iter().any(|(name, e)| name == "P1" && e.contains("Authentication failed")), "{by_hand:?}");
+        assert_eq!(by_hand, threaded);
+    }
+
+    #[test]
+    fn machines_reject_options_they_cannot_run() {
+        for options in [
+            ProtocolOptions { dealers: 2, ..options(None) },
+            ProtocolOptions { channel_capacity: Some(1), ..options(None) },
+            ProtocolOptions { authenticated: true, ..options(None) },
+        ] {
+            assert!(matches!(
+                beaver_protocol_machines::<UnauthSharing>(CIRCUIT, Q, inputs(), &options),
+                Err(BeaverError::Input(_))
+            ));
+        }
+    }
+
+    // the circuit of the example_4 input files, with 7 gates over 3 layers
+    const EXAMPLE_4: &str = "0, P1, add, P2 | 6, P2, mul, P1 | 1, 0, mul, P2 | 2, P1, add, 1 | 5, 6, add, P1 | \
+                             4, P2, mul, 5 | 3, 2, add, 4 & 1, 3 & 5, 3";
+
+    fn example_4_inputs() -> Inputs {
+        Inputs { p1: (vec![3, 30], vec![2, 4]), p2: (vec![1, 5], vec![2, 3]), public: vec![] }
+    }
+
+    // a run in which either party crashes before any gate and both parties then resume from their latest
+    // checkpoints ends as one which was never interrupted
+    #[test]
+    fn resumed_run_matches_an_uninterrupted_one() {
+        let dir = std::env::temp_dir().join(format!("beaver_resume_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let circuit: Circuit = EXAMPLE_4.parse().unwrap();
+        let topology = circuit.get_topology();
+
+        for authenticated in [false, true] {
+            let options = |checkpoint, crash_at| ProtocolOptions {
+                authenticated,
+                seed: Some(5),
+                log_level: LogLevel::Off,
+                checkpoint,
+                crash_at,
+                ..Default::default()
+            };
+            let uninterrupted = run_beaver_protocol(EXAMPLE_4, 31, example_4_inputs(), &options(None, None)).unwrap();
+
+            // the first checkpoint is saved once interval gates are processed
+            for (interval, party) in [(1, Party::P1), (1, Party::P2), (2, Party::P1), (2, Party::P2)] {
+                for id in &topology[interval..] {
+                    let path = dir.join(format!("{authenticated}_{interval}_{party}_{id}")).to_str().unwrap().to_string();
+                    let checkpoint = CheckpointConfig { path, interval };
+                    let crash = options(Some(checkpoint.clone()), Some((party, *id)));
+
+                    assert!(run_beaver_protocol(EXAMPLE_4, 31, example_4_inputs(), &crash).is_err());
+
+                    let resumed = resume_beaver_protocol(EXAMPLE_4, 31, &options(Some(checkpoint), None)).unwrap();
+                    assert_eq!(resumed.outputs_p1, uninterrupted.outputs_p1, "crash of {party} before gate {id}");
+                    assert_eq!(resumed.outputs_p2, uninterrupted.outputs_p2, "crash of {party} before gate {id}");
+                }
+            }
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    const MERSENNE31: u32 = 2147483647;
+
+    // a circuit applying the given unary gate (e.g. "truncf, 8") to each of n inputs of P1, all revealed to P1
+    fn unary_circuit(gate: &str, n: usize) -> String {
+        let gates: Vec<String> = (0..n).map(|id| format!("{id}, P1, {gate}")).collect();
+        let outputs: Vec<String> = (0..n).map(|id| id.to_string()).collect();
+        format!("{} & {} &", gates.join(" | "), outputs.join(", "))
+    }
+
+    // the residue read as a signed integer, negative above q / 2
+    fn signed(v: u32, q: u32) -> i64 {
+        if v > q / 2 { v as i64 - q as i64 } else { v as i64 }
+    }
+
+    fn encode(x: i64, q: u32) -> u32 {
+        x.rem_euclid(q as i64) as u32
+    }
+
+    // truncf rounds down, or up by one with a probability growing with the discarded bits, and MACs of its output
+    // check out in authenticated runs
+    #[test]
+    fn truncation_is_within_one_of_the_floor() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(11);
+        // inputs of truncation gates are below 2^20 in absolute value for mersenne31
+        let bound = 1i64 << 20;
+
+        for authenticated in [false, true] {
+            for f in [1, 8, 16, 20] {
+                let xs: Vec<i64> = (0..100).map(|_| rng.gen_range(-bound + 1..bound)).collect();
+                let values = xs.iter().map(|x| encode(*x, MERSENNE31)).collect();
+                let inputs = Inputs { p1: (values, vec![]), p2: (vec![], vec![]), public: vec![] };
+                let options = ProtocolOptions { authenticated, seed: Some(f as u64), ..Default::default() };
+                let circuit = unary_circuit(&format!("truncf, {f}"), 100);
+                let result = run_beaver_protocol(&circuit, MERSENNE31, inputs, &options).unwrap();
+
+                for (id, x) in xs.iter().enumerate() {
+                    let floor = x.div_euclid(1 << f);
+                    let got = signed(result.outputs_p1[&(id as u32)], MERSENNE31);
+                    assert!(got == floor || got == floor + 1, "truncf, {f} of {x} gave {got}, not within 1 of {floor}");
+                }
+            }
+        }
+    }
+
+    // ltz agrees with the plaintext evaluation and with x < 0 on every value of small bit lengths, and on random
+    // values of 16 bits
+    #[test]
+    fn comparison_matches_plaintext() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(12);
+        let mut cases: Vec<(u32, Vec<i64>)> =
+            (1..=6).map(|k| (k, (-(1i64 << (k - 1))..1 << (k - 1)).collect())).collect();
+        cases.push((16, (0..100).map(|_| rng.gen_range(-(1i64 << 15)..1 << 15)).collect()));
+
+        for authenticated in [false, true] {
+            for (k, xs) in &cases {
+                let encoding = unary_circuit(&format!("ltz, {k}"), xs.len());
+                let inputs = Inputs {
+                    p1: (xs.iter().map(|x| encode(*x, MERSENNE31)).collect(), vec![]),
+                    p2: (vec![], vec![]),
+                    public: vec![],
+                };
+                let expected = encoding.parse::<Circuit>().unwrap().evaluate(&inputs, MERSENNE31).unwrap();
+                let options = ProtocolOptions { authenticated, seed: Some(*k as u64), ..Default::default() };
+                let result = run_beaver_protocol(&encoding, MERSENNE31, inputs, &options).unwrap();
+
+                for (id, x) in xs.iter().enumerate() {
+                    let id = id as u32;
+                    assert_eq!(result.outputs_p1[&id], (*x < 0) as u32, "ltz, {k} of {x}");
+                    assert_eq!(result.outputs_p1[&id], expected[&id], "ltz, {k} of {x}");
+                }
+            }
+        }
+    }
+
+    // the Fermat gadget for the equality of the first inputs of P1 and P2: 1 - (a - b)^(q - 1), computed by
+    // square-and-multiply with mul gates
+    fn fermat_equality_circuit(q: u32) -> String {
+        let mut gates = vec![String::from("0, P2, mulc, -1"), String::from("1, P1, add, 0")];
+        let e = q - 1;
+        // the power of the difference computed so far, which starts as the difference itself (the top bit of e)
+        let mut power = 1;
+        let mut next = 2;
+        for bit in (0..e.ilog2()).rev() {
+            gates.push(format!("{next}, {power}, mul, {power}"));
+            power = next;
+            next += 1;
+            if (e >> bit) & 1 == 1 {
+                gates.push(format!("{next}, {power}, mul, 1"));
+                power = next;
+                next += 1;
+            }
+        }
+        gates.push(format!("{next}, {power}, mulc, -1"));
+        gates.push(format!("{}, {next}, addc, 1", next + 1));
+        format!("{} & {} &", gates.join(" | "), next + 1)
+    }
+
+    // eq and eqc output 1 exactly on equal values whatever the modulus, with a number of triples which does not
+    // depend on it, unlike the Fermat gadget
+    #[test]
+    fn equality_tests_match_plaintext_at_several_moduli() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(13);
+
+        for q in [3, 101, 65537, MERSENNE31, 4294967291] {
+            let f = F32::new(q);
+            // half of the pairs are equal, and constants of eqc fit in an i32
+            let a: Vec<u32> = (0..20).map(|_| f.random(rng) % 1000).collect();
+            let b: Vec<u32> = a
+                .iter()
+                .enumerate()
+                .map(|(i, a)| if i % 2 == 0 { *a } else { f.add(a + 1, f.random(rng) % (q - 1)) })
+                .collect();
+
+            let eq: Vec<String> = (0..20).map(|id| format!("{id}, P1, eq, P2")).collect();
+            let eqc: Vec<String> =
+                b.iter().enumerate().map(|(i, b)| format!("{}, P1, eqc, {}", 20 + i, signed(*b, q))).collect();
+            let ids: Vec<String> = (0..40).map(|id| id.to_string()).collect();
+            let encoding = format!("{} | {} & {} &", eq.join(" | "), eqc.join(" | "), ids.join(", "));
+            let inputs = Inputs { p1: ([a.clone(), a.clone()].concat(), vec![]), p2: (vec![], b.clone()), public: vec![] };
+
+            for authenticated in [false, true] {
+                let options = ProtocolOptions { authenticated, seed: Some(q as u64), ..Default::default() };
+                let result = run_beaver_protocol(&encoding, q, inputs.clone(), &options).unwrap();
+                for i in 0..20 {
+                    let expected = (a[i] == b[i]) as u32;
+                    assert_eq!(result.outputs_p1[&(i as u32)], expected, "eq of {} and {} modulo {q}", a[i], b[i]);
+                    assert_eq!(result.outputs_p1[&(20 + i as u32)], expected, "eqc of {} and {} modulo {q}", a[i], b[i]);
+                }
+            }
+
+            // the Fermat gadget agrees on equal and unequal pairs, at the cost of more triples for larger moduli
+            let fermat = fermat_equality_circuit(q);
+            let eq_triples = "0, P1, eq, P2 & 0 &".parse::<Circuit>().unwrap().num_beaver_triples();
+            let fermat_triples = fermat.parse::<Circuit>().unwrap().num_beaver_triples();
+            println!("q = {q}: eq consumes {eq_triples} triples, the Fermat gadget {fermat_triples}");
+            assert_eq!(eq_triples, utilities::EQUALITY_BITS as usize - 1);
+            assert_eq!(fermat_triples, (q - 1).ilog2() as usize + (q - 1).count_ones() as usize - 1);
+            for i in [0, 1] {
+                let inputs = Inputs { p1: (vec![a[i]], vec![]), p2: (vec![b[i]], vec![]), public: vec![] };
+                let result = run_beaver_protocol(&fermat, q, inputs, &ProtocolOptions::default()).unwrap();
+                assert_eq!(result.outputs_p1.values().copied().collect::<Vec<u32>>(), [(a[i] == b[i]) as u32]);
+            }
+        }
+    }
+
+    // dot gates compute the inner product of P1's and P2's inputs, for lengths up to a few thousand
+    #[test]
+    fn inner_products_match_plaintext() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(14);
+        let f = F32::new(MERSENNE31);
+
+        for n in [1, 7, 100, 2000] {
+            let left: Vec<u32> = (0..n).map(|_| f.random(rng)).collect();
+            let right: Vec<u32> = (0..n).map(|_| f.random(rng)).collect();
+            let expected = left.iter().zip(&right).fold(0, |acc, (l, r)| f.add(acc, f.mul(*l, *r)));
+
+            // gates 0..n hold P1's inputs, n..2n P2's ones and 2n their inner product
+            let ids = |from: usize| (from..from + n).map(|id| id.to_string()).collect::<Vec<String>>().join(" ");
+            let inputs_gates: Vec<String> = (0..2 * n)
+                .map(|id| format!("{id}, {}, addc, 0", if id < n { "P1" } else { "P2" }))
+                .collect();
+            let encoding = format!("{} | dot, {}, {}, {} & {} &", inputs_gates.join(" | "), 2 * n, ids(0), ids(n), 2 * n);
+            let inputs = Inputs { p1: (left.clone(), vec![]), p2: (right.clone(), vec![]), public: vec![] };
+            let plaintext = encoding.parse::<Circuit>().unwrap().evaluate(&inputs, MERSENNE31).unwrap();
+            assert_eq!(plaintext[&(2 * n as u32)], expected);
+
+            for authenticated in [false, true] {
+                let options = ProtocolOptions { authenticated, seed: Some(n as u64), ..Default::default() };
+                let result = run_beaver_protocol(&encoding, MERSENNE31, inputs.clone(), &options).unwrap();
+                assert_eq!(result.outputs_p1[&(2 * n as u32)], expected, "inner product of length {n}");
+                assert_eq!(result.triples, 0);
+            }
+        }
+    }
+
+    // linmap gates give the product of their matrix and the vector of their input gates, with negative and
+    // hexadecimal coefficients, and no triples
+    #[test]
+    fn linear_maps_match_matrix_vector_products() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(15);
+        let f = F32::new(MERSENNE31);
+
+        for (rows, cols) in [(1, 1), (2, 3), (5, 4), (16, 16)] {
+            let matrix: Vec<Vec<i32>> =
+                (0..rows).map(|_| (0..cols).map(|_| rng.gen_range(-1000..1000)).collect()).collect();
+            let v: Vec<u32> = (0..cols).map(|_| f.random(rng)).collect();
+            let product = |row: &Vec<i32>| {
+                row.iter().zip(&v).fold(0, |acc, (c, x)| f.add(acc, f.mul(encode(*c as i64, MERSENNE31), *x)))
+            };
+            let expected: Vec<u32> = matrix.iter().map(product).collect();
+
+            // gates 0..cols hold P1's inputs and 100.. the rows of the product, the first of them written in hex
+            let join = |ids: Vec<String>, sep: &str| ids.join(sep);
+            let input_gates = join((0..cols).map(|id| format!("{id}, P1, addc, 0")).collect(), " | ");
+            let outputs = join((0..rows).map(|r| (100 + r).to_string()).collect(), " ");
+            let hex = |c: &i32| format!("{}0x{:x}", if *c < 0 { "-" } else { "" }, c.unsigned_abs());
+            let mut rows_text: Vec<String> =
+                matrix.iter().map(|row| join(row.iter().map(|c| c.to_string()).collect(), " ")).collect();
+            rows_text[0] = join(matrix[0].iter().map(hex).collect(), " ");
+            let encoding = format!(
+                "{input_gates} | linmap, {outputs}, {}, {} & {} &",
+                join((0..cols).map(|id| id.to_string()).collect(), " "),
+                rows_text.join("; "),
+                outputs.replace(' ', ", ")
+            );
+
+            let inputs = Inputs { p1: (v.clone(), vec![]), p2: (vec![], vec![]), public: vec![] };
+            for authenticated in [false, true] {
+                let options = ProtocolOptions { authenticated, seed: Some(rows as u64), ..Default::default() };
+                let result = run_beaver_protocol(&encoding, MERSENNE31, inputs.clone(), &options).unwrap();
+                for (r, expected) in expected.iter().enumerate() {
+                    assert_eq!(result.outputs_p1[&(100 + r as u32)], *expected, "row {r} of a {rows}x{cols} matrix");
+                }
+                assert_eq!(result.triples, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn linear_map_dimensions_are_validated() {
+        let parse = |linmap: &str| format!("0, P1, addc, 0 | 1, P1, addc, 0 | {linmap} & 10 &").parse::<Circuit>();
+
+        assert!(parse("linmap, 10 11, 0 1, 1 2; 3 4").is_ok());
+        for (linmap, error) in [
+            ("linmap, 10 11, 0 1, 1 2", "the matrix has 1 rows for 2 output gates"),
+            ("linmap, 10, 0 1, 1 2; 3 4", "the matrix has 2 rows for 1 output gates"),
+            ("linmap, 10 11, 0 1, 1 2; 3", "a row of the matrix has 1 entries for 2 input gates"),
+            ("linmap, 10, 0 1, 1 2 3", "a row of the matrix has 3 entries for 2 input gates"),
+            ("linmap, 10, , 1", "without input gates"),
+            ("linmap, 10, 0 1, 1 x", "Invalid constant format in linmap"),
+            ("linmap, 10, 0 1", "Invalid number of linmap parameters"),
+        ] {
+            let e = parse(linmap).err().unwrap();
+            assert!(e.contains(error), "{linmap}: {e}");
+        }
+    }
+
+    // P1 and P2 add their inputs and assert that the sum is zero
+    const ASSERT_ZERO: &str = "0, P1, add, P2 | 1, 0, assert_zero & 1 &";
+
+    #[test]
+    fn zero_check_passes_on_zero_only() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(16);
+        let split = |rng: &mut ChaCha20Rng, v: u32, q: u32| {
+            let x = F32::new(q).random(rng);
+            Inputs { p1: (vec![x], vec![]), p2: (vec![], vec![F32::new(q).add(v, q - x)]), public: vec![] }
+        };
+
+        for authenticated in [false, true] {
+            for seed in 0..20 {
+                let options = ProtocolOptions { authenticated, seed: Some(seed), ..Default::default() };
+                let result = run_beaver_protocol(ASSERT_ZERO, MERSENNE31, split(rng, 0, MERSENNE31), &options).unwrap();
+                assert_eq!(result.outputs_p1[&1], 0);
+            }
+
+            // a nonzero value only passes if the b of the triple is 0, which masks it: with probability 1/q
+            for (q, trials, max_passes) in [(101, 500, 15), (MERSENNE31, 100, 0)] {
+                let mut passes = 0;
+                for seed in 0..trials {
+                    let v = 1 + F32::new(q - 1).random(rng);
+                    let options = ProtocolOptions { authenticated, seed: Some(seed), ..Default::default() };
+                    match run_beaver_protocol(ASSERT_ZERO, q, split(rng, v, q), &options) {
+                        Ok(_) => passes += 1,
+                        Err(e) => assert!(e.to_string().contains("the input of gate 1 is not zero"), "{v}: {e}"),
+                    }
+                }
+                assert!(passes <= max_passes, "{passes} nonzero values out of {trials} passed modulo {q}");
+            }
+        }
+    }
+
+    // the parties only open the difference of the value with a and its product with b, which the random a and b
+    // mask: neither the value nor anything equal to it across runs shows up in their logs
+    #[test]
+    fn zero_check_reveals_nothing_but_nonzeroness() {
+        let dir = std::env::temp_dir().join(format!("beaver_zero_check_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (x, y) = (123456789, 987654321);
+        let v = x + y;
+
+        let mut opened = Vec::new();
+        for seed in [1, 2] {
+            let path = dir.join(format!("run{seed}")).to_str().unwrap().to_string();
+            let options = ProtocolOptions {
+                authenticated: true,
+                seed: Some(seed),
+                log_level: LogLevel::Normal,
+                output_path: Some(path.clone()),
+                ..Default::default()
+            };
+            let inputs = Inputs { p1: (vec![x], vec![]), p2: (vec![], vec![y]), public: vec![] };
+            assert!(run_beaver_protocol(ASSERT_ZERO, MERSENNE31, inputs, &options).is_err());
+
+            let mut logs = String::new();
+            for party in ["p1", "p2"] {
+                logs.push_str(&fs::read_to_string(format!("{path}_{party}.txt")).unwrap());
+            }
+            let numbers: Vec<u32> = logs.split(|c: char| !c.is_ascii_digit()).filter_map(|n| n.parse().ok()).collect();
+            assert!(!numbers.contains(&v), "{logs}");
+            opened.push(logs.lines().filter(|l| l.contains("opened sharing")).map(String::from).collect::<Vec<_>>());
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(!opened[0].is_empty());
+        assert!(opened[0].iter().all(|l| !opened[1].contains(l)));
+    }
+
+    // a tampered opening of an output revealed to both parties leaves them with different values for it, which the
+    // cross-check reports on both sides for the same wire. the first opening of each direction is that of gate 0
+    #[test]
+    fn tampered_mutual_opening_aborts_both_parties_on_the_same_wire() {
+        let circuit = "0, P1, mul, P2 | 1, 0, add, P1 & 0, 1 & 0, 1";
+        let recorded = ProtocolOptions { record_views: true, ..options(None) };
+        let honest = run_beaver_protocol(circuit, Q, inputs(), &recorded).unwrap();
+        let (view_p1, view_p2) = honest.views.unwrap();
+
+        for (party, view) in [(Party::P1, view_p1), (Party::P2, view_p2)] {
+            let index = view.sent.iter().position(|(_, m)| matches!(m, TranscriptEntry::Openings(_))).unwrap();
+            let errors = match run_beaver_protocol(circuit, Q, inputs(), &options(Some((party, index)))) {
+                Err(BeaverError::Run(errors)) => errors,
+                r => panic!("opening of {party} tampered with, expected a run error: {:?}", r.map(|r| r.outputs_p1)),
+            };
+
+            let named: Vec<&str> = errors
+                .iter()
+                .filter(|(who, _)| who.starts_with('P'))
+                .map(|(who, e)| {
+                    let e = e.to_string();
+                    assert!(e.contains("the parties disagree on the value of output gate 0 ("), "{who}: {e}");
+                    who.as_str()
+                })
+                .collect();
+            assert_eq!(named.len(), 2, "{errors:?}");
+        }
+    }
+
+    // outputs blinded before being opened still match the cleartext evaluation, whichever parties they go to. the
+    // blinds, and hence what is opened, change from run to run, whereas the outputs do not
+    #[test]
+    fn blinded_outputs_match_cleartext() {
+        let dir = std::env::temp_dir().join(format!("beaver_blinded_outputs_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("run").to_str().unwrap().to_string();
+
+        // gate 0 goes to P1 alone, gate 1 to P2 alone and gate 2 to both, which is not blinded
+        let circuit = "0, P1, mul, P2 | 1, 0, add, P1 | 2, 1, mul, 0 & 0, 2 & 1, 2";
+        let rng = &mut ChaCha20Rng::seed_from_u64(17);
+
+        for authenticated in [false, true] {
+            for _ in 0..10 {
+                let f = F32::new(Q);
+                let inputs = Inputs {
+                    p1: (vec![f.random(rng)], vec![f.random(rng)]),
+                    p2: (vec![], vec![f.random(rng)]),
+                    public: vec![],
+                };
+                let cleartext = circuit.parse::<Circuit>().unwrap().evaluate(&inputs, Q).unwrap();
+
+                let mut blinds = Vec::new();
+                for seed in [1, 2] {
+                    let options = ProtocolOptions {
+                        authenticated,
+                        blind_outputs: true,
+                        seed: Some(seed),
+                        log_level: LogLevel::Normal,
+                        output_path: Some(path.clone()),
+                        ..Default::default()
+                    };
+                    let result = run_beaver_protocol(circuit, Q, inputs.clone(), &options).unwrap();
+                    assert_eq!(result.outputs_p1, HashMap::from([(0, cleartext[&0]), (2, cleartext[&2])]));
+                    assert_eq!(result.outputs_p2, HashMap::from([(1, cleartext[&1]), (2, cleartext[&2])]));
+
+                    let mut removed = Vec::new();
+                    for (party, id) in [("p1", 0), ("p2", 1)] {
+                        let log = fs::read_to_string(format!("{path}_{party}.txt")).unwrap();
+                        let lines: Vec<&str> = log.lines().filter(|l| l.starts_with("Removed blind")).collect();
+                        assert_eq!(lines.len(), 1, "{log}");
+                        assert!(lines[0].ends_with(&format!("from output of gate {id}")), "{log}");
+                        removed.push(lines[0].to_string());
+                    }
+                    blinds.push(removed);
+                }
+                assert_ne!(blinds[0], blinds[1]);
+            }
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // the product of the inputs of P1 and P2, squared seven times, with its cleartext evaluation
+    fn squaring_chain() -> (String, Inputs, HashMap<u32, u32>) {
+        let circuit: String = std::iter::once(String::from("0, P1, mul, P2"))
+            .chain((1..8).map(|i| format!("{i}, {}, mul, {}", i - 1, i - 1)))
+            .collect::<Vec<_>>()
+            .join(" | ")
+            + " & 7 &";
+        let inputs = Inputs { p1: (vec![3], vec![]), p2: (vec![], vec![5]), public: vec![] };
+        let cleartext = circuit.parse::<Circuit>().unwrap().evaluate(&inputs, Q).unwrap();
+        (circuit, inputs, cleartext)
+    }
+
+    // with one corrupt dealer among three, every bad triple is paired with a good one from another dealer, and the
+    // check names the pair. honest dealers go through and give the cleartext outputs
+    #[test]
+    fn one_corrupt_dealer_among_three_is_caught() {
+        let (circuit, inputs, cleartext) = squaring_chain();
+
+        for authenticated in [false, true] {
+            for seed in 0..20 {
+                let options = |corrupt_dealer| ProtocolOptions {
+                    authenticated,
+                    dealers: 3,
+                    corrupt_dealer,
+                    seed: Some(seed),
+                    ..options(None)
+                };
+                let result = run_beaver_protocol(&circuit, Q, inputs.clone(), &options(None)).unwrap();
+                assert_eq!(result.outputs_p1[&7], cleartext[&7]);
+
+                for corrupt in 0..3 {
+                    let options = options(Some((corrupt, 1.0)));
+                    let e = run_beaver_protocol(&circuit, Q, inputs.clone(), &options).err().unwrap().to_string();
+                    let named = e.split("so dealer ").nth(1).unwrap_or_else(|| panic!("seed {seed}: {e}"));
+                    let named: Vec<&str> = named.split(" is corrupt").next().unwrap().split(" or ").collect();
+                    assert!(named.contains(&corrupt.to_string().as_str()), "dealer {corrupt} corrupt: {e}");
+                }
+            }
+        }
+    }
+
+    // an audit of half the triples lets honest dealers through with the outputs unchanged, and catches a dealer
+    // corrupting a fifth of its triples unless none of the bad ones is drawn, about one time in ten for 16 triples
+    #[test]
+    fn triple_audit_catches_a_dealer_corrupting_a_fifth_of_the_triples() {
+        let (circuit, inputs, cleartext) = squaring_chain();
+
+        for authenticated in [false, true] {
+            let options = |seed, corrupt_dealer| ProtocolOptions {
+                authenticated,
+                triple_audit: Some(0.5),
+                corrupt_dealer,
+                seed: Some(seed),
+                ..options(None)
+            };
+
+            let mut caught = 0;
+            for seed in 0..50 {
+                let result = run_beaver_protocol(&circuit, Q, inputs.clone(), &options(seed, None)).unwrap();
+                assert_eq!(result.outputs_p1[&7], cleartext[&7]);
+
+                match run_beaver_protocol(&circuit, Q, inputs.clone(), &options(seed, Some((0, 0.2)))) {
+                    Err(e) if e.to_string().contains("is not a valid Beaver triple, so the dealer is corrupt") => {
+                        caught += 1
+                    }
+                    Err(e) => panic!("seed {seed}: {e}"),
+                    Ok(_) => (),
+                }
+            }
+            assert!(caught >= 35, "caught {caught} times out of 50");
+        }
+    }
+
+    // the masked values u and v of each multiplication of the chain are opened one after the other, in topology
+    // order, so tampering with either of those of the k-th gate makes the receiver's MAC check fail at that gate
+    #[test]
+    fn failed_authentication_names_the_tampered_multiplication() {
+        let (circuit, inputs, _) = squaring_chain();
+        let topology = circuit.parse::<Circuit>().unwrap().get_topology().to_vec();
+
+        let recorded = ProtocolOptions { authenticated: true, record_views: true, ..options(None) };
+        let (view_p1, view_p2) = run_beaver_protocol(&circuit, Q, inputs.clone(), &recorded).unwrap().views.unwrap();
+
+        for (party, view) in [(Party::P1, view_p1), (Party::P2, view_p2)] {
+            let indices: Vec<usize> = (0..view.sent.len())
+                .filter(|i| matches!(view.sent[*i], (Phase::GateProcessing, TranscriptEntry::Opening(_))))
+                .collect();
+            assert_eq!(indices.len(), 2 * topology.len());
+
+            for (uv, gate) in indices.chunks_exact(2).zip(&topology) {
+                for index in uv {
+                    let options = ProtocolOptions { authenticated: true, ..options(Some((party, *index))) };
+                    let e = run_beaver_protocol(&circuit, Q, inputs.clone(), &options).err().unwrap();
+                    let receiver = if party == Party::P1 { Party::P2 } else { Party::P1 };
+                    assert_eq!(e.authentication_context(), Some((receiver, Phase::GateProcessing, Some(*gate))), "{e}");
+                    assert!(e.to_string().contains(&format!("during gate processing of gate {gate}")), "{e}");
+                }
+            }
+        }
+    }
+
+    // a party panicking mid-run is reported as an error of that party, and the other threads are still joined: the
+    // other party reports the closed channel instead of hanging or panicking in turn
+    #[test]
+    fn panicking_party_gives_a_clean_error() {
+        for (party, name) in [(Party::P1, "P1"), (Party::P2, "P2")] {
+            let options = ProtocolOptions { panic_at: Some((party, 1)), ..options(None) };
+            let errors = match run_beaver_protocol(CIRCUIT, Q, inputs(), &options) {
+                Err(BeaverError::Run(errors)) => errors,
+                r => panic!("expected a run error: {:?}", r.map(|r| r.outputs_p1)),
+            };
+
+            let panicked: Vec<&String> =
+                errors.iter().filter(|(_, e)| e.to_string().contains("thread panicked")).map(|(n, _)| n).collect();
+            assert_eq!(panicked, [name], "{errors:?}");
+            let (_, e) = errors.iter().find(|(n, _)| n == name).unwrap();
+            assert_eq!(e.to_string(), "thread panicked: injected panic before processing gate 1");
+        }
+    }
+
+    // a long circuit goes through channels of 16 messages from the dealers, which stay that short: each dealer
+    // waits for the parties as it gets ahead of them, without ever blocking for good
+    #[test]
+    fn bounded_dealer_channels_keep_short_queues() {
+        let n = 5000;
+        let circuit: String = std::iter::once(String::from("0, P1, mul, P2"))
+            .chain((1..n).map(|i| format!("{i}, {}, mul, P1", i - 1)))
+            .collect::<Vec<_>>()
+            .join(" | ")
+            + &format!(" & {} &", n - 1);
+        let inputs = Inputs { p1: (vec![3], vec![2; n - 1]), p2: (vec![], vec![5]), public: vec![] };
+        let cleartext = circuit.parse::<Circuit>().unwrap().evaluate(&inputs, Q).unwrap();
+
+        for (authenticated, dealers) in [(false, 1), (true, 1), (true, 3)] {
+            let options =
+                |channel_capacity| ProtocolOptions { authenticated, dealers, channel_capacity, ..options(None) };
+
+            let unbounded = run_beaver_protocol(&circuit, Q, inputs.clone(), &options(None)).unwrap();
+            assert_eq!(unbounded.stats.peak_dealer_queue, None);
+
+            let bounded = run_beaver_protocol(&circuit, Q, inputs.clone(), &options(Some(16))).unwrap();
+            assert_eq!(bounded.outputs_p1[&(n as u32 - 1)], cleartext[&(n as u32 - 1)]);
+            assert_eq!(bounded.stats.dealer_to_p1, unbounded.stats.dealer_to_p1);
+            assert!(bounded.stats.dealer_to_p1 > 10 * 16);
+            let peak = bounded.stats.peak_dealer_queue.unwrap();
+            assert!(peak <= 16 + 1, "{peak} messages queued");
+        }
+    }
+
+    // coalescing only changes how the messages travel: the parties log and see exactly the same exchanges, in the
+    // same order, over fewer messages
+    #[test]
+    fn coalescing_leaves_the_transcripts_unchanged() {
+        let dir = std::env::temp_dir().join(format!("beaver_coalescing_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        for authenticated in [false, true] {
+            let run = |coalesce: bool| {
+                let path = dir.join(format!("run_{coalesce}")).to_str().unwrap().to_string();
+                let options = ProtocolOptions {
+                    authenticated,
+                    coalesce,
+                    seed: Some(6),
+                    log_level: LogLevel::Normal,
+                    output_path: Some(path.clone()),
+                    record_views: true,
+                    ..Default::default()
+                };
+                let result = run_beaver_protocol(EXAMPLE_4, 31, example_4_inputs(), &options).unwrap();
+                let logs: Vec<String> = ["dealer", "p1", "p2"]
+                    .iter()
+                    .map(|name| fs::read_to_string(format!("{path}_{name}.txt")).unwrap())
+                    .collect();
+                (result, logs)
+            };
+
+            let (plain, plain_logs) = run(false);
+            let (coalesced, coalesced_logs) = run(true);
+
+            assert_eq!(coalesced.outputs_p1, plain.outputs_p1);
+            assert_eq!(coalesced.outputs_p2, plain.outputs_p2);
+            assert_eq!(coalesced.views, plain.views);
+            for (c, p) in coalesced_logs.iter().zip(&plain_logs) {
+                let diff: Vec<(&str, &str)> = c.lines().zip(p.lines()).filter(|(c, p)| c != p).collect();
+                assert!(diff.is_empty() && c.lines().count() == p.lines().count(), "{diff:?}");
+            }
+
+            let messages = |r: &ProtocolResult| [r.stats.dealer_to_p1, r.stats.dealer_to_p2, r.stats.p1_to_p2];
+            for (c, p) in messages(&coalesced).into_iter().zip(messages(&plain)) {
+                assert!(c < p, "{c} coalesced messages against {p}");
+            }
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // however many outputs there are, each party sends the other a single message with their openings, and one with
+    // the values of those revealed to both (if any), before the transcript digest which ends every run
+    #[test]
+    fn output_phase_takes_at_most_two_messages() {
+        for n in [1usize, 10, 200] {
+            let gates: Vec<String> = (0..n).map(|i| format!("{i}, P1, mul, P2")).collect();
+            let ids = |range: std::ops::Range<usize>| range.map(|i| i.to_string()).collect::<Vec<_>>().join(", ");
+            // P1 gets the first half of the outputs, P2 the second and both those in the middle
+            let circuit = format!("{} & {} & {}", gates.join(" | "), ids(0..(2 * n).div_ceil(3)), ids(n / 3..n));
+            let p1 = (0..n as u32).map(|i| i % Q).collect();
+            let inputs = Inputs { p1: (p1, vec![]), p2: (vec![], vec![7; n]), public: vec![] };
+
+            for authenticated in [false, true] {
+                let options = ProtocolOptions { authenticated, record_views: true, ..options(None) };
+                let result = run_beaver_protocol(&circuit, Q, inputs.clone(), &options).unwrap();
+                assert_eq!(result.outputs_p1.len() + result.outputs_p2.len(), n + (2 * n).div_ceil(3) - n / 3);
+
+                let (view_p1, view_p2) = result.views.unwrap();
+                for view in [view_p1, view_p2] {
+                    let sent: Vec<&TranscriptEntry> =
+                        view.sent.iter().filter(|(p, _)| *p == Phase::OutputProcessing).map(|(_, e)| e).collect();
+                    let (digest, outputs) = sent.split_last().unwrap();
+                    assert!(matches!(digest, TranscriptEntry::Digest(_)));
+                    assert!(outputs.len() <= 2, "{n} outputs: {outputs:?}");
+                    assert!(matches!(outputs[0], TranscriptEntry::Openings(_)));
+                }
+            }
+        }
+    }
+
+    // the parties find each mask by the input wire it is tagged with, whatever order the dealer sends them in, and
+    // even if each party gets them in a different one
+    #[test]
+    fn masks_sent_in_any_order_are_assigned_to_their_wires() {
+        let cleartext = EXAMPLE_4.parse::<Circuit>().unwrap().evaluate(&example_4_inputs(), 31).unwrap();
+
+        for authenticated in [false, true] {
+            let options =
+                |shuffle_masks| ProtocolOptions { authenticated, seed: Some(8), shuffle_masks, ..options(None) };
+            let ordered = run_beaver_protocol(EXAMPLE_4, 31, example_4_inputs(), &options(None)).unwrap();
+
+            for seed in 0..10 {
+                let shuffled = run_beaver_protocol(EXAMPLE_4, 31, example_4_inputs(), &options(Some(seed))).unwrap();
+                assert_eq!(shuffled.outputs_p1, ordered.outputs_p1);
+                assert_eq!(shuffled.outputs_p2, ordered.outputs_p2);
+                for (id, v) in shuffled.outputs_p1.iter().chain(&shuffled.outputs_p2) {
+                    assert_eq!(*v, cleartext[id]);
+                }
+            }
+        }
+    }
+
+    // a run without logs gives the same outputs and counts as a logged one on the same seed, and writes no files.
+    // the time both take is printed for comparison (see with --nocapture)
+    #[test]
+    fn unlogged_runs_match_logged_ones() {
+        let dir = std::env::temp_dir().join(format!("beaver_log_off_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("run").to_str().unwrap().to_string();
+
+        let n = 2000;
+        let circuit: String = std::iter::once(String::from("0, P1, mul, P2"))
+            .chain((1..n).map(|i| format!("{i}, {}, mul, {}", i - 1, i - 1)))
+            .collect::<Vec<_>>()
+            .join(" | ")
+            + &format!(" & {} & {}", n - 1, n - 1);
+        let inputs = Inputs { p1: (vec![3], vec![]), p2: (vec![], vec![5]), public: vec![] };
+
+        for authenticated in [false, true] {
+            let options = |log_level| ProtocolOptions {
+                authenticated,
+                seed: Some(9),
+                log_level,
+                output_path: Some(path.clone()),
+                ..Default::default()
+            };
+
+            let off = run_beaver_protocol(&circuit, Q, inputs.clone(), &options(LogLevel::Off)).unwrap();
+            assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+
+            let logged = run_beaver_protocol(&circuit, Q, inputs.clone(), &options(LogLevel::Normal)).unwrap();
+            assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
+
+            assert_eq!(off.outputs_p1, logged.outputs_p1);
+            assert_eq!(off.outputs_p2, logged.outputs_p2);
+            assert_eq!(off.triples, logged.triples);
+            assert_eq!(off.rounds, logged.rounds);
+            let messages =
+                |r: &ProtocolResult| [r.stats.dealer_to_p1, r.stats.dealer_to_p2, r.stats.p1_to_p2, r.stats.p2_to_p1];
+            assert_eq!(messages(&off), messages(&logged));
+
+            println!(
+                "{n} multiplications, {}authenticated: {:?} without logs, {:?} with",
+                if authenticated { "" } else { "un" },
+                off.elapsed,
+                logged.elapsed
+            );
+            for f in fs::read_dir(&dir).unwrap() {
+                fs::remove_file(f.unwrap().path()).unwrap();
+            }
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // with display names, the log files are named after the parties and the logs refer to them by name throughout,
+    // P1 and P2 remaining internal
+    #[test]
+    fn party_names_replace_p1_and_p2_in_the_logs() {
+        let dir = std::env::temp_dir().join(format!("beaver_party_names_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("run").to_str().unwrap().to_string();
+
+        let options = ProtocolOptions {
+            seed: Some(10),
+            party_names: Some((String::from("Hospital"), String::from("Big Insurer"))),
+            log_level: LogLevel::Normal,
+            output_path: Some(path.clone()),
+            ..Default::default()
+        };
+        run_beaver_protocol(CIRCUIT, Q, inputs(), &options).unwrap();
+
+        let mut files: Vec<String> =
+            fs::read_dir(&dir).unwrap().map(|f| f.unwrap().file_name().into_string().unwrap()).collect();
+        files.sort();
+        assert_eq!(files, ["run_big_insurer.txt", "run_dealer.txt", "run_hospital.txt"]);
+
+        let log = |name: &str| fs::read_to_string(format!("{path}_{name}.txt")).unwrap();
+        let (dealer, hospital, insurer) = (log("dealer"), log("hospital"), log("big_insurer"));
+
+        let section = |log: &str, title: &str| {
+            log.split("\n\n").find(|s| s.trim_start().starts_with(title)).unwrap().trim().to_string()
+        };
+        assert_eq!(
+            section(&dealer, "**** Distribution of singleton sharings"),
+            "**** Distribution of singleton sharings for inputs\n\
+             Sent to Hospital singleton sharing for first input of gate 0 (Hospital): (14)\n\
+             Sent to Big Insurer singleton sharing for first input of gate 0 (Hospital): (14)\n\
+             Sent to Hospital singleton sharing for second input of gate 1 (Hospital): (85)\n\
+             Sent to Big Insurer singleton sharing for second input of gate 1 (Hospital): (22)\n\
+             Sent to Hospital singleton sharing for second input of gate 0 (Big Insurer): (18)\n\
+             Sent to Big Insurer singleton sharing for second input of gate 0 (Big Insurer): (82)"
+        );
+        assert_eq!(
+            section(&hospital, "**** Opening of key sharings"),
+            "**** Opening of key sharings\n\
+             Received from Big Insurer opening of k1: 24\n\
+             Sent to Big Insurer opening of k2: 19"
+        );
+        assert_eq!(
+            section(&insurer, "**** Processing gates"),
+            "**** Processing gates\n\
+             Sent to Hospital opened sharing: (28)\n\
+             Sent to Hospital opened sharing: (15)\n\
+             Received from Hospital opened sharing: (3)\n\
+             Received from Hospital opened sharing: (84)"
+        );
+
+        // the only remaining mention of P1 or P2 would be as a word of their own
+        for log in [&dealer, &hospital, &insurer] {
+            let words: Vec<&str> = log.split(|c: char| !c.is_alphanumeric()).collect();
+            assert!(!words.contains(&"P1") && !words.contains(&"P2"), "{log}");
+            assert!(!log.contains("other party"), "{log}");
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // a run with sharings carrying no data goes through every gate of the topology in both parties, and sends as
+    // many messages as a real one. run with --features benchmarking
+    #[cfg(feature = "benchmarking")]
+    #[test]
+    fn null_sharing_runs_touch_every_gate() {
+        let circuit = "0, P1, add, P2 | 6, P2, mul, P1 | 1, 0, mul, P2 | 2, P1, add, 1 | 5, 6, addc, 4 | \
+                       4, P2, mul, 5 | 3, 2, add, 4 | 7, 3, mulc, 2 & 1, 3 & 5, 7";
+        let traced = Arc::new(Mutex::new(Vec::new()));
+        let events = traced.clone();
+        let options = ProtocolOptions {
+            trace: Some(TraceConfig {
+                hook: Arc::new(move |t: GateTrace| events.lock().unwrap().push((t.party, t.gate))),
+                redact: true,
+            }),
+            ..options(None)
+        };
+        let inputs = Inputs { p1: (vec![3, 30], vec![2]), p2: (vec![1, 5], vec![2, 3]), public: vec![] };
+
+        let null = run_beaver_protocol_null(circuit, 31, inputs.clone(), &options).unwrap();
+        assert!(null.outputs_p1.values().chain(null.outputs_p2.values()).all(|v| *v == 0));
+        assert_eq!(null.outputs_p1.len() + null.outputs_p2.len(), 4);
+
+        let topology = circuit.parse::<Circuit>().unwrap().get_topology().to_vec();
+        assert_eq!(topology.len(), 8);
+        let traced = traced.lock().unwrap();
+        for party in [Party::P1, Party::P2] {
+            let gates: Vec<u32> = traced.iter().filter(|(p, _)| *p == party).map(|(_, g)| *g).collect();
+            assert_eq!(gates, topology);
+        }
+
+        let real = run_beaver_protocol(circuit, 31, inputs, &ProtocolOptions { trace: None, ..options }).unwrap();
+        let messages =
+            |r: &ProtocolResult| [r.stats.dealer_to_p1, r.stats.dealer_to_p2, r.stats.p1_to_p2, r.stats.p2_to_p1];
+        assert_eq!(messages(&null), messages(&real));
+        assert_eq!(null.triples, real.triples);
+    }
+
+    // numbers worked out by hand. example 4 has 8 input wires, 3 multiplications (two of them in the first layer)
+    // and the outputs 1 and 5 of one party each plus 3 of both
+    #[test]
+    fn cost_estimates_match_hand_counts() {
+        let circuit: Circuit = EXAMPLE_4.parse().unwrap();
+        for (authenticated, components, online_bytes) in [(false, 1, 160), (true, 3, 352)] {
+            let cost = estimate_cost(&circuit, authenticated);
+            assert_eq!(cost.preprocessing.input_singletons, 8);
+            assert_eq!(cost.preprocessing.triples, 3);
+            // keys are unauthenticated
+            assert_eq!(cost.preprocessing.transcript_bytes, 2 * 4 * (2 + components * (8 + 3 * 3)));
+            assert_eq!(cost.dealer_messages, 2 + 8 + 3);
+            assert_eq!(
+                cost.messages,
+                MessageCounts { key_opening: 1, input_processing: 8, gate_processing: 6, output_processing: 3 }
+            );
+            assert_eq!(
+                cost.rounds,
+                RoundReport {
+                    key_opening: 1,
+                    triple_check: 0,
+                    input_processing: 2,
+                    gate_processing: 2,
+                    output_processing: 3
+                }
+            );
+            // 8 mask openings, the u and v of 3 multiplications each way and 4 outputs, plus 2 keys, 8 deltas, the
+            // value of output 3 each way and 2 digests
+            assert_eq!(cost.online_bytes, 4 * (components * (8 + 3 * 4 + 4) + 2 + 8 + 2) + 2 * 8);
+            assert_eq!(cost.online_bytes, online_bytes);
+        }
+
+        // a single multiplication revealed to P1
+        let cost = estimate_cost(&"0, P1, mul, P2 & 0 &".parse().unwrap(), false);
+        assert_eq!(cost.dealer_messages, 5);
+        assert_eq!(cost.messages.total(), 1 + 2 + 2 + 2);
+        assert_eq!(cost.rounds.total(), 1 + 2 + 1 + 2);
+        assert_eq!(cost.online_bytes, 4 * (2 + 4 + 1 + 2 + 2) + 2 * 8);
+    }
+
+    // the messages of actual runs, counted per phase in the views, are those estimated, for every kind of gate
+    #[test]
+    fn measured_messages_match_the_estimate() {
+        let circuits = [
+            String::from(EXAMPLE_4),
+            squaring_chain().0,
+            String::from("0, P1, add, P2 | 1, 0, mulc, 3 | 2, 1, addc, 5 & 2 & 2"),
+            String::from("0, P1, eq, P2 | 1, P1, eqc, 4 | 2, P2, ltz, 6 | 3, 0, add, 1 & 0, 3 & 2, 3"),
+            String::from("0, P1, mulc, 8 | 1, 0, truncf, 3 | 2, 1, reveal | 3, 2, add, P2 & 3 & 1"),
+            String::from("0, P1, add, P1 | 1, P2, add, P2 | dot, 2, 0 1, 1 0 | 3, 2, add, P1 | 4, 3, mul, 0 & 4 & 2"),
+        ];
+
+        for circuit_encoding in &circuits {
+            let circuit: Circuit = circuit_encoding.parse().unwrap();
+            let arity = circuit.input_arity();
+            let inputs = Inputs {
+                p1: (vec![1; arity.p1_first], vec![2; arity.p1_second]),
+                p2: (vec![3; arity.p2_first], vec![4; arity.p2_second]),
+                public: vec![],
+            };
+
+            for authenticated in [false, true] {
+                let cost = estimate_cost(&circuit, authenticated);
+                let options = ProtocolOptions { authenticated, record_views: true, ..options(None) };
+                let result = run_beaver_protocol(circuit_encoding, MERSENNE31, inputs.clone(), &options).unwrap();
+
+                assert_eq!(result.stats.dealer_to_p1, cost.dealer_messages, "{circuit_encoding}");
+                assert_eq!(result.stats.dealer_to_p2, cost.dealer_messages, "{circuit_encoding}");
+
+                let (view_p1, view_p2) = result.views.unwrap();
+                for view in [view_p1, view_p2] {
+                    let count = |phase| view.sent.iter().filter(|(p, _)| *p == phase).count();
+                    let measured = MessageCounts {
+                        key_opening: count(Phase::KeyOpening),
+                        input_processing: count(Phase::InputProcessing),
+                        gate_processing: count(Phase::GateProcessing),
+                        output_processing: count(Phase::OutputProcessing),
+                    };
+                    assert_eq!(measured, cost.messages, "{circuit_encoding}");
+                    assert_eq!(view.sent.len(), cost.messages.total());
+                }
+            }
+        }
+    }
+
+    // each party reports one event per entry of the topology, in its order, with the shares of the gate outputs
+    // unless redacted and the running count of triples
+    #[test]
+    fn trace_hook_reports_every_gate_in_topology_order() {
+        let circuit: Circuit = EXAMPLE_4.parse().unwrap();
+        let topology = circuit.get_topology().to_vec();
+
+        for (authenticated, redact) in [(false, false), (true, false), (true, true)] {
+            let traced = Arc::new(Mutex::new(Vec::new()));
+            let events = traced.clone();
+            let options = ProtocolOptions {
+                authenticated,
+                insecure_audit: true,
+                trace: Some(TraceConfig { hook: Arc::new(move |t| events.lock().unwrap().push(t)), redact }),
+                ..options(None)
+            };
+            let result = run_beaver_protocol(EXAMPLE_4, 31, example_4_inputs(), &options).unwrap();
+            let wire_values = result.wire_values.unwrap();
+            let traced = traced.lock().unwrap();
+
+            let events = |party| traced.iter().filter(move |t: &&GateTrace| t.party == party).collect::<Vec<_>>();
+            let (p1, p2) = (events(Party::P1), events(Party::P2));
+            assert_eq!(traced.len(), 2 * topology.len());
 
-        // distributing sharings for input wires
-        o.push_str("\n**** Distribution of singleton sharings for inputs\n");
+            let mut triples = 0;
+            for ((t1, t2), id) in p1.iter().zip(&p2).zip(&topology) {
+                assert_eq!((t1.gate, t2.gate), (*id, *id));
+                let op = circuit.get_gate(id).unwrap().op_name();
+                assert_eq!((t1.op, t2.op), (op, op));
 
-        for _ in 0..self.circuit.total_input_wires() {
-            match self.rx_d_me.recv() {
-                Ok(Msg::Singleton(s)) => singletons.push(log(o, true, "dealer", "singleton sharing", s)),
-                _ => return Err(self.abort(o, "Error during distribution of input and key-opening sharings: Expected singleton sharing")),
-            };
+                triples += (t1.op == "mul") as usize;
+                assert_eq!((t1.triples_consumed, t2.triples_consumed), (triples, triples));
+
+                match (&t1.share, &t2.share) {
+                    (Some(s1), Some(s2)) => assert_eq!((s1[0] + s2[0]) % 31, wire_values[id]),
+                    (None, None) => assert!(redact),
+                    _ => panic!("only one share of gate {id} redacted"),
+                }
+            }
         }
+    }
 
-        o.push_str("\n**** Distribution of triple sharings for multiplication gates\n");
+    // an input not below q is rejected by default, naming its party, list and position, and reduced modulo q if so
+    // requested, which then gives the outputs of the reduced inputs
+    #[test]
+    fn out_of_range_inputs_are_rejected_or_reduced() {
+        let reduced = run_beaver_protocol(EXAMPLE_4, 31, example_4_inputs(), &options(None)).unwrap();
 
-        let topology = self.circuit.get_topology();
-        let mut triples = Vec::new();
+        fn list(inputs: &mut Inputs, party: Party, first: bool) -> &mut Vec<u32> {
+            let (first_list, second_list) = if party == Party::P1 { &mut inputs.p1 } else { &mut inputs.p2 };
+            if first { first_list } else { second_list }
+        }
+        for (party, first) in [(Party::P1, true), (Party::P1, false), (Party::P2, true), (Party::P2, false)] {
+            for (position, lift) in [(0, 31), (1, 31 * 1000), (1, u32::MAX / 31 * 31 - 31)] {
+                let mut inputs = example_4_inputs();
+                let value = list(&mut inputs, party, first)[position] + lift;
+                list(&mut inputs, party, first)[position] = value;
 
-        // need to run over the topology (rather than the hashmap of gates) to guarantee the same order across dealer and parties
-        for id in topology {
-            // always gets a valid gate by construction
-            match self.circuit.get_gate(id).unwrap() {
-                Gate::GateWithoutC {
-                    op: GateOp::Mul, ..
-                } => {
-                    match self.rx_d_me.recv() {
-                        Ok(Msg::Triple(t)) => {
-                            triples.push(log(o, true, "dealer", "triple sharing", t))
+                for authenticated in [false, true] {
+                    let strict = ProtocolOptions { authenticated, ..options(None) };
+                    match run_beaver_protocol(EXAMPLE_4, 31, inputs.clone(), &strict).err().unwrap() {
+                        BeaverError::InputRange { party: p, first: f, position: i, value: v, q: 31 } => {
+                            assert_eq!((p, f, i, v), (party, first, position, value))
                         }
-                        _ => return Err(self.abort(
-                            o,
-                            "Error during distribution of Beaver sharings: Expected triple sharing",
-                        )),
-                    };
+                        e => panic!("expected an input range error, got {e}"),
+                    }
+
+                    let reducing = ProtocolOptions { authenticated, reduce_inputs: true, ..options(None) };
+                    let result = run_beaver_protocol(EXAMPLE_4, 31, inputs.clone(), &reducing).unwrap();
+                    assert_eq!(result.outputs_p1, reduced.outputs_p1);
+                    assert_eq!(result.outputs_p2, reduced.outputs_p2);
                 }
-                _ => (),
             }
         }
 
-        // opening key sharings
-        o.push_str("\n**** Opening of key sharings\n");
-        if self.identity == Party::P1 {
-            let k12 = match self.rx_other_me.recv() {
-                Ok(Msg::Value(v)) => log(o, true, "other party", "opening of k1", v),
-                _ => return Err(self.abort(o, "Error during key opening: Expected opening of k1")),
-            };
-            self.key = self.s_k1 + k12;
-            self.tx_me_other.send(Msg::Value(log(
-                o,
-                false,
-                "other party",
-                "opening of k2",
-                self.s_k2,
-            )));
-        } else {
-            self.tx_me_other.send(Msg::Value(log(
-                o,
-                false,
-                "other party",
-                "opening of k1",
-                self.s_k1,
-            )));
-            let k21 = match self.rx_other_me.recv() {
-                Ok(Msg::Value(v)) => log(o, true, "other party", "opening of k2", v),
-                _ => return Err(self.abort(o, "Error during key opening: Expected opening of k2")),
+        let inputs = Inputs { p2: (vec![1, 31], vec![2, 3]), ..example_4_inputs() };
+        let e = run_beaver_protocol(EXAMPLE_4, 31, inputs, &options(None));
+        assert_eq!(
+            e.err().unwrap().to_string(),
+            "Error: input value 31 at position 1 of the first inputs of P2 is not below q = 31"
+        );
+    }
+
+    // inputs given as external shares adding up to their values, whose entries in the inputs are then zeroed, give
+    // the outputs of the cleartext evaluation, whether some or all of them are external
+    #[test]
+    fn external_inputs_match_cleartext() {
+        let circuit: Circuit = EXAMPLE_4.parse().unwrap();
+        let mut expected = circuit.evaluate(&example_4_inputs(), 31).unwrap();
+        expected.retain(|id, _| [1, 3, 5].contains(id));
+        let wires = circuit.input_wires();
+        let rng = &mut ChaCha20Rng::seed_from_u64(3);
+
+        // the inputs in the order of the input wires
+        let values = |i: Inputs| [i.p1.0, i.p1.1, i.p2.0, i.p2.1].concat();
+        let values_4 = values(example_4_inputs());
+        assert_eq!(values_4.len(), wires.len());
+
+        let subsets = (0..wires.len()).map(|k| vec![k]).chain([vec![0, 3, 5], (0..wires.len()).collect()]);
+        for external in subsets {
+            let mut zeroed = values_4.clone();
+            let external_inputs = external
+                .iter()
+                .map(|&k| {
+                    let s1 = rng.gen_range(0..31);
+                    zeroed[k] = 0;
+                    ExternalInput { wire: wires[k], shares: (vec![s1], vec![(values_4[k] + 31 - s1) % 31]) }
+                })
+                .collect();
+            let inputs = Inputs {
+                p1: (zeroed[0..2].to_vec(), zeroed[2..4].to_vec()),
+                p2: (zeroed[4..6].to_vec(), zeroed[6..8].to_vec()),
+                public: vec![],
             };
-            self.key = self.s_k2 + k21;
+
+            let options = ProtocolOptions { external_inputs, ..options(None) };
+            let result = run_beaver_protocol(EXAMPLE_4, 31, inputs, &options).unwrap();
+            let outputs: HashMap<u32, u32> = result.outputs_p1.into_iter().chain(result.outputs_p2).collect();
+            assert_eq!(outputs, expected, "external inputs {external:?}");
+
+            let input_rounds = if external.len() == wires.len() { 0 } else { 2 };
+            assert_eq!(result.rounds.input_processing, input_rounds);
         }
+    }
 
-        // input processing
-        o.push_str("\n**** Processing input wires\n");
+    #[test]
+    fn invalid_external_inputs_are_rejected() {
+        let wire = InputWire { party: Party::P2, first: false, id: 0 };
+        let external = |wire, shares: (Vec<u32>, Vec<u32>)| ExternalInput { wire, shares };
+        let error = |external_inputs, authenticated| {
+            let options = ProtocolOptions { external_inputs, authenticated, ..options(None) };
+            run_beaver_protocol(CIRCUIT, Q, inputs(), &options).err().unwrap().to_string()
+        };
 
-        let (i_p1_first, i_p1_second) = self.circuit.get_inputs_p1();
-        let (i_p2_first, i_p2_second) = self.circuit.get_inputs_p2();
+        let e = error(vec![external(wire, (vec![1], vec![2]))], true);
+        assert!(e.contains("only supported in unauthenticated runs"), "{e}");
+        let missing = InputWire { party: Party::P1, first: false, id: 0 };
+        let e = error(vec![external(missing, (vec![1], vec![2]))], false);
+        assert!(e.contains("is not an input wire of the circuit"), "{e}");
+        let twice = vec![external(wire, (vec![1], vec![2])), external(wire, (vec![3], vec![4]))];
+        let e = error(twice, false);
+        assert!(e.contains("is shared externally more than once"), "{e}");
+        for shares in [(vec![Q], vec![0]), (vec![0], vec![1, 2]), (vec![], vec![0])] {
+            let e = error(vec![external(wire, shares)], false);
+            assert!(e.contains("is not a valid additive share modulo 101"), "{e}");
+        }
+    }
 
-        let i_sharings_p1_first =
-            self.process_inputs(o, i_p1_first, &mut singletons, Party::P1, true)?;
-        let i_sharings_p1_second =
-            self.process_inputs(o, i_p1_second, &mut singletons, Party::P1, false)?;
-        let i_sharings_p2_first =
-            self.process_inputs(o, i_p2_first, &mut singletons, Party::P2, true)?;
-        let i_sharings_p2_second =
-            self.process_inputs(o, i_p2_second, &mut singletons, Party::P2, false)?;
+    // the explanation of an abort caused by P2 tampering with its first opening gives the true value and how far
+    // off the opened one was: 1 for a corrupt party, which adds 1, and -1 for a message altered in transit, which
+    // subtracts 1. either way the MAC is off by -(the tampering) * k1, so the two differences add up to 0
+    #[test]
+    fn explained_aborts_trace_back_a_known_tamper() {
+        let explaining = |corruption, tamper_in_transit| ProtocolOptions {
+            authenticated: true,
+            explain_aborts: true,
+            record_views: true,
+            corruption,
+            ..options(tamper_in_transit)
+        };
 
-        // processing gates
-        o.push_str("\n**** Processing gates\n");
+        // an honest run is not affected by the record of openings
+        let honest = run_beaver_protocol(CIRCUIT, Q, inputs(), &explaining(None, None)).unwrap();
+        let plain = ProtocolOptions { authenticated: true, ..options(None) };
+        assert_eq!(honest.outputs_p1, run_beaver_protocol(CIRCUIT, Q, inputs(), &plain).unwrap().outputs_p1);
+        let (_, view_p2) = honest.views.unwrap();
+        let index = view_p2.sent.iter().position(|(_, m)| matches!(m, TranscriptEntry::Opening(_))).unwrap();
 
-        let mut inner_wires = HashMap::new();
+        // the figures reported by P1, which checks the tampered opening
+        let report = |options: &ProtocolOptions| -> (u32, u32, u32) {
+            let e = match run_beaver_protocol(CIRCUIT, Q, inputs(), options).err().unwrap() {
+                BeaverError::Run(errors) => errors.into_iter().find(|(who, _)| who == "P1").unwrap().1.to_string(),
+                e => panic!("expected a run error, got {e}"),
+            };
+            let e = e.split("post-mortem: ").nth(1).unwrap_or_else(|| panic!("no explanation in {e}"));
+            let numbers: Vec<u32> = e
+                .split(|c: char| !c.is_ascii_digit())
+                .filter(|t| !t.is_empty())
+                .map(|t| t.parse().unwrap())
+                .collect();
+            let [expected_mac, got, difference, off, magnitude, value] = numbers[..] else { panic!("{e}") };
+            assert!(e.contains("; the opened value was off by"), "{e}");
+            assert_eq!(off, magnitude, "{e}");
+            assert_eq!(difference, (got + Q - expected_mac) % Q, "{e}");
+            (difference, off, value)
+        };
 
-        for id in self.circuit.get_topology() {
-            let g = self.circuit.get_gate(id).unwrap();
+        let corrupt = || Some(CorruptionConfig { degree: 1.0, party: Some(Party::P2) });
+        let (difference_corrupt, off_corrupt, value_corrupt) = report(&explaining(corrupt(), None));
+        let (difference_transit, off_transit, value_transit) = report(&explaining(None, Some((Party::P2, index))));
 
-            match g {
-                Gate::GateWithoutC { op, i1, i2, .. } => {
-                    let v1 = match i1 {
-                        // these unwraps cannot fail by the order of the topology and input processing
-                        GateInput::Id(s_id) => inner_wires.get(s_id).unwrap(),
-                        GateInput::InputParty(Party::P1) => i_sharings_p1_first.get(id).unwrap(),
-                        GateInput::InputParty(Party::P2) => i_sharings_p2_first.get(id).unwrap(),
-                    };
-                    let v2 = match i2 {
-                        GateInput::Id(s_id) => inner_wires.get(s_id).unwrap(),
-                        GateInput::InputParty(Party::P1) => i_sharings_p1_second.get(id).unwrap(),
-                        GateInput::InputParty(Party::P2) => i_sharings_p2_second.get(id).unwrap(),
-                    };
+        assert_eq!((off_corrupt, off_transit), (1, Q - 1));
+        assert_eq!(value_corrupt, value_transit);
+        assert_ne!(difference_corrupt, 0);
+        assert_eq!((difference_corrupt + difference_transit) % Q, 0);
 
-                    inner_wires.insert(
-                        *id,
-                        match op {
-                            GateOp::Add => self.process_gate_add(v1, v2),
-                            GateOp::Mul => self.process_gate_mul(o, v1, v2, triples.pop().unwrap())?,
-                        },
-                    );
-                }
-                Gate::GateWithC { op, i1, c, .. } => {
-                    let v1 = match i1 {
-                        GateInput::Id(s_id) => inner_wires.get(s_id).unwrap(),
-                        GateInput::InputParty(Party::P1) => i_sharings_p1_first.get(id).unwrap(),
-                        GateInput::InputParty(Party::P2) => i_sharings_p2_first.get(id).unwrap(),
-                    };
+        // without the flag the same abort is not explained
+        let unexplained = ProtocolOptions { explain_aborts: false, ..explaining(corrupt(), None) };
+        let e = run_beaver_protocol(CIRCUIT, Q, inputs(), &unexplained);
+        assert!(!e.err().unwrap().to_string().contains("post-mortem"));
+    }
 
-                    let c = utilities::modulo(*c, self.q);
+    // a streamed transcript never holds as many lines as it is flushed every, and writes out the same text as one
+    // kept in memory
+    #[test]
+    fn streamed_transcripts_stay_short_and_match_buffered_ones() {
+        let dir = std::env::temp_dir().join(format!("beaver_streamed_transcript_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = Some(dir.join("streamed.txt").to_str().unwrap().to_string());
 
-                    inner_wires.insert(
-                        *id,
-                        match op {
-                            GateOp::Add => self.process_gate_addc(v1, c),
-                            GateOp::Mul => self.process_gate_mulc(v1, c),
-                        },
-                    );
-                }
+        let mut streamed = Transcript::open(LogLevel::Normal, &path, Some(4));
+        let mut buffered = Transcript::new(LogLevel::Normal);
+        for i in 0..1000 {
+            for o in [&mut streamed, &mut buffered] {
+                o.push_str(&format!("line {i}"));
+                o.push('\n');
             }
+            assert!(streamed.text.matches('\n').count() < 4);
+            assert!(streamed.text.len() <= 4 * "line 999\n".len());
         }
+        // a partial line at the end is written too
+        streamed.push_str("no newline");
+        buffered.push_str("no newline");
 
-        // processing outputs
-        o.push_str("\n**** Processing outputs\n");
+        write_log(&path, &mut streamed, "P1").unwrap();
+        assert_eq!(fs::read_to_string(path.as_ref().unwrap()).unwrap(), buffered.text);
 
-        let mut output_wires = HashMap::new();
+        fs::remove_dir_all(&dir).unwrap();
+    }
 
-        for id in self.circuit.get_outputs(Party::P1) {
-            match self.identity {
-                Party::P1 => {
-                    output_wires.insert(id, self.receive_opening(o, inner_wires.get(id).unwrap())?);
-                }
-                Party::P2 => {
-                    self.send_opening(o, inner_wires.get(id).unwrap());
-                }
-            };
-        }
+    // the logs of every party and dealer are the same whether they are streamed, however often, or written at the end
+    #[test]
+    fn streamed_logs_match_logs_written_at_the_end() {
+        let dir = std::env::temp_dir().join(format!("beaver_streamed_logs_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (circuit, inputs, _) = squaring_chain();
 
-        for id in self.circuit.get_outputs(Party::P2) {
-            match self.identity {
-                Party::P2 => {
-                    output_wires.insert(id, self.receive_opening(o, inner_wires.get(id).unwrap())?);
-                }
-                Party::P1 => {
-                    self.send_opening(o, inner_wires.get(id).unwrap());
-                }
+        for log_level in [LogLevel::Normal, LogLevel::Verbose] {
+            let logs = |log_flush_lines: Option<usize>| -> Vec<String> {
+                let path = dir.join(format!("{log_flush_lines:?}")).to_str().unwrap().to_string();
+                let options = ProtocolOptions {
+                    authenticated: true,
+                    dealers: 2,
+                    seed: Some(1),
+                    log_level,
+                    output_path: Some(path.clone()),
+                    log_flush_lines,
+                    ..Default::default()
+                };
+                run_beaver_protocol(&circuit, Q, inputs.clone(), &options).unwrap();
+                ["p1", "p2", "dealer", "dealer1"]
+                    .map(|name| fs::read_to_string(format!("{path}_{name}.txt")).unwrap())
+                    .to_vec()
             };
+
+            let at_the_end = logs(None);
+            assert!(at_the_end.iter().all(|log| log.lines().count() > 10));
+            for n in [1, 3, 1000] {
+                assert_eq!(logs(Some(n)), at_the_end, "flushed every {n} lines");
+            }
         }
 
-        o.push('\n');
+        fs::remove_dir_all(&dir).unwrap();
+    }
 
-        for (id, v) in output_wires {
-            o.push_str(&format!("Output of gate {}: {}\n", id, v));
-        }
+    // the wire values reconstructed by an insecure audit are those of the cleartext evaluation, for every gate and in
+    // both kinds of runs, and nothing is reconstructed without the flag
+    #[test]
+    fn insecure_audit_matches_cleartext_evaluation() {
+        let (chain, chain_inputs, _) = squaring_chain();
+        let circuits = [
+            (String::from(CIRCUIT), inputs(), Q),
+            (String::from(EXAMPLE_4), example_4_inputs(), 31),
+            (chain, chain_inputs, Q),
+            (String::from("0, P1, addc, 7 | 1, 0, mulc, 3 | 2, 1, mul, P2 | 3, 2, add, P1 & 3 & 2, 3"), inputs(), Q),
+        ];
 
-        o.push_str("\nEnded successfully");
+        for (circuit, inputs, q) in circuits {
+            let expected = circuit.parse::<Circuit>().unwrap().evaluate(&inputs, q).unwrap();
 
-        if let Err(e) = fs::write(&self.log_path, o) {
-            return Err(format!(
-                "{} failed to write execution log: {}",
-                self.identity,
-                e.to_string()
-            ));
-        }
+            for authenticated in [false, true] {
+                let audited = ProtocolOptions { authenticated, insecure_audit: true, ..options(None) };
+                let result = run_beaver_protocol(&circuit, q, inputs.clone(), &audited).unwrap();
+                assert_eq!(result.wire_values.unwrap(), expected, "{circuit}");
 
-        Ok(())
+                let plain = ProtocolOptions { authenticated, ..options(None) };
+                assert!(run_beaver_protocol(&circuit, q, inputs.clone(), &plain).unwrap().wire_values.is_none());
+            }
+        }
     }
-    fn abort(&self, output: &mut String, msg: &str) -> String {
-        self.tx_me_other.send(Msg::Abort);
 
-        let abort_msg = format!("{}. Aborting.", msg);
-        output.push_str(&abort_msg);
+    // the corrupt party given in the corruption config is the one tampering, whatever the seed: with a degree of 1,
+    // every opening it sends is marked as tampered with in its log, and none in the log of the other party. the
+    // outputs of the circuit go to one party each, so that the tampering goes unnoticed in these unauthenticated runs
+    #[test]
+    fn the_given_corrupt_party_is_the_one_tampering() {
+        let dir = std::env::temp_dir().join(format!("beaver_corrupt_party_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("run").to_str().unwrap().to_string();
 
-        if let Err(e) = fs::write(&self.log_path, output) {
-            return format!(
-                "{} failed to write execution log: {}",
-                self.identity,
-                e.to_string()
-            );
+        for (corrupt, honest) in [(Party::P2, Party::P1), (Party::P1, Party::P2)] {
+            for seed in 0..8 {
+                let options = ProtocolOptions {
+                    corruption: Some(CorruptionConfig { degree: 1.0, party: Some(corrupt) }),
+                    seed: Some(seed),
+                    record_views: true,
+                    output_path: Some(path.clone()),
+                    ..Default::default()
+                };
+                let (view_p1, view_p2) = run_beaver_protocol(CIRCUIT, Q, inputs(), &options).unwrap().views.unwrap();
+                let view = if corrupt == Party::P1 { view_p1 } else { view_p2 };
+                let sent: usize = view
+                    .sent
+                    .iter()
+                    .map(|(_, m)| match m {
+                        TranscriptEntry::Opening(_) => 1,
+                        TranscriptEntry::Openings(v) => v.len(),
+                        _ => 0,
+                    })
+                    .sum();
+
+                let log = |party: Party| {
+                    fs::read_to_string(format!("{path}_{}.txt", party.to_string().to_lowercase())).unwrap()
+                };
+                assert_eq!(log(corrupt).matches("*tampered-with* opened sharing").count(), sent, "seed {seed}");
+                assert!(sent > 0);
+                assert!(!log(honest).contains("*tampered-with*"), "seed {seed}");
+            }
         }
 
-        abort_msg
+        fs::remove_dir_all(&dir).unwrap();
     }
-    fn send_opening(&self, output: &mut String, s: &T) {
-        if self.corrupt && rand::random::<f32>() <= CORRUPTION_DEGREE {
-            // not part of the protocol! only here to simulate a  corrupt party
-            println!("(Corrupt {} tampering with sharing)", self.identity);
-            let s_t = s.tweaked();
-            self.tx_me_other.send(Msg::Singleton(log(
-                output,
-                false,
-                "other party",
-                "*tampered-with* opened sharing",
-                s_t.opened(self.other_party()),
-            )));
-        } else {
-            self.tx_me_other.send(Msg::Singleton(log(
-                output,
-                false,
-                "other party",
-                "opened sharing",
-                s.opened(self.other_party()),
-            )));
+
+    // what each party records as sent is what the other records as received, message for message and in the same
+    // phases, and nothing is recorded unless asked for
+    #[test]
+    fn recorded_views_are_symmetric() {
+        let (chain, chain_inputs, _) = squaring_chain();
+        let circuits = [
+            (String::from(CIRCUIT), inputs(), Q),
+            (String::from(EXAMPLE_4), example_4_inputs(), 31),
+            (chain, chain_inputs, Q),
+        ];
+
+        for (circuit, inputs, q) in circuits {
+            for authenticated in [false, true] {
+                let recorded = ProtocolOptions { authenticated, record_views: true, ..options(None) };
+                let result = run_beaver_protocol(&circuit, q, inputs.clone(), &recorded).unwrap();
+                let (view_p1, view_p2) = result.views.unwrap();
+
+                assert!(!view_p1.sent.is_empty() && !view_p2.sent.is_empty());
+                assert_eq!(view_p1.sent, view_p2.received, "{circuit}");
+                assert_eq!(view_p2.sent, view_p1.received, "{circuit}");
+                // the run ends with the exchange of the transcript digests
+                assert!(matches!(view_p1.sent.last(), Some((Phase::OutputProcessing, TranscriptEntry::Digest(_)))));
+
+                let unrecorded = ProtocolOptions { authenticated, ..options(None) };
+                assert!(run_beaver_protocol(&circuit, q, inputs.clone(), &unrecorded).unwrap().views.is_none());
+            }
         }
     }
-    fn receive_opening(&self, output: &mut String, own_s: &T) -> Result<u32, String> {
-        match self.rx_other_me.recv() {
-            Ok(Msg::Singleton(s)) => {
-                let new_s = T::add(
-                    own_s,
-                    &log(output, true, "other party", "opened sharing", s),
-                    self.q,
-                );
-                if T::authenticate(&new_s, self.key, self.q, self.identity) {
-                    Ok(new_s.value())
-                } else {
-                    Err(self.abort(
-                        output,
-                        &format!("Authentication failed for sharing {new_s}"),
-                    ))
+
+    // unauthenticated and authenticated runs agree on circuits mixing every kind of arithmetic gate, and the
+    // authenticated outputs returned are those of the cleartext evaluation
+    #[test]
+    fn cross_check_passes_on_honest_runs() {
+        let (chain, chain_inputs, _) = squaring_chain();
+        let circuits = [
+            (String::from(CIRCUIT), inputs(), Q),
+            (String::from(EXAMPLE_4), example_4_inputs(), 31),
+            (chain, chain_inputs, Q),
+            (String::from("0, P1, addc, 7 | 1, 0, mulc, 3 | 2, 1, mul, P2 | 3, 2, add, P1 & 3 & 2, 3"), inputs(), Q),
+            (String::from("0, P1, mul, P2 | 1, 0, mul, P1 & 0, 1 & 0, 1"), inputs(), MERSENNE31),
+        ];
+
+        for (circuit, inputs, q) in circuits {
+            let parsed: Circuit = circuit.parse().unwrap();
+            let cleartext = parsed.evaluate(&inputs, q).unwrap();
+
+            for seed in [None, Some(3)] {
+                let result = cross_check(&circuit, q, inputs.clone(), seed).unwrap();
+                for (id, v) in result.outputs_p1.iter().chain(&result.outputs_p2) {
+                    assert_eq!(cleartext[id], *v, "output {id} of {circuit}");
                 }
             }
-            _ => Err(self.abort(
-                output,
-                "Error during opening of sharing: Expected opened sharing",
-            )),
         }
+
+        // an error of either run is passed on: MACs need a prime modulus
+        let e = cross_check(CIRCUIT, 100, inputs(), Some(1)).err().unwrap().to_string();
+        assert!(e.contains("MACs require a prime field"), "{e}");
     }
-    fn process_inputs(
-        &self,
-        output: &mut String,
-        ids: &Vec<u32>,
-        singletons: &mut Vec<T>,
-        source: Party,
-        first: bool,
-    ) -> Result<HashMap<u32, T>, String> {
-        let mut sharing_hash = HashMap::new();
 
-        if source == self.identity {
-            let inputs = if first {&self.inputs_first} else {&self.inputs_second};
+    // over the ring of 8-bit integers, unauthenticated runs wrap around as machine integers do, whereas
+    // authenticated ones are rejected before they start, as are comparisons. q = 2 is prime and allowed
+    #[test]
+    fn power_of_two_moduli_are_unauthenticated_only() {
+        let (chain, _, _) = squaring_chain();
+        let inputs = Inputs { p1: (vec![255], vec![]), p2: (vec![], vec![130]), public: vec![] };
 
-            for (id, v) in ids.iter().zip(inputs.iter()) {
-                let a = singletons.pop().unwrap(); // cannot fail due to to sharing count
-                let a_open = self.receive_opening(output, &a)?;
-                let d = subtract_without_overflow(*v, a_open, self.q);
+        for q in [256, 1 << 16, 1 << 31] {
+            let inputs = Inputs { p1: (vec![q - 1], vec![]), p2: (vec![], vec![q / 2 + 2]), public: vec![] };
+            let cleartext = chain.parse::<Circuit>().unwrap().evaluate(&inputs, q).unwrap();
+            let result = run_beaver_protocol(&chain, q, inputs.clone(), &options(None)).unwrap();
+            assert_eq!(result.outputs_p1[&7], cleartext[&7], "q = {q}");
 
-                self.tx_me_other.send(Msg::Value(log(
-                    output,
-                    false,
-                    "other party",
-                    "delta for input processing",
-                    d,
-                )));
+            let authenticated = ProtocolOptions { authenticated: true, ..options(None) };
+            let e = run_beaver_protocol(&chain, q, inputs, &authenticated).err().unwrap().to_string();
+            assert!(e.contains(&format!("not yet implemented (q = {q} is a power of two)")), "{e}");
+        }
 
-                sharing_hash.insert(
-                    *id,
-                    T::addc(&a, d, self.s_k1, self.s_k2, self.q, self.identity),
-                );
-            }
-        } else {
-            for id in ids {
-                let a = singletons.pop().unwrap();
-                self.send_opening(output, &a);
+        // (255 * 130)^(2^7) mod 256, computed as machine integers
+        let expected = (0..7).fold(255u8.wrapping_mul(130), |x, _| x.wrapping_mul(x));
+        let result = run_beaver_protocol(&chain, 256, inputs, &options(None)).unwrap();
+        assert_eq!(result.outputs_p1[&7], expected as u32);
 
-                let d = match self.rx_other_me.recv() {
-                    Ok(Msg::Value(v)) => log(output, true, "other party", "delta for input processing", v),
-                    _ => return Err(self.abort(output, "Error during distribution of key sharings: expected delta for input processing")),
-                };
+        let ltz = unary_circuit("ltz, 4", 1);
+        let single = Inputs { p1: (vec![3], vec![]), p2: (vec![], vec![]), public: vec![] };
+        let e = run_beaver_protocol(&ltz, 256, single, &options(None)).err().unwrap().to_string();
+        assert!(e.contains("requires an odd modulus, not q = 256"), "{e}");
 
-                sharing_hash.insert(
-                    *id,
-                    T::addc(&a, d, self.s_k1, self.s_k2, self.q, self.identity),
-                );
+        let inputs = Inputs { p1: (vec![1], vec![]), p2: (vec![], vec![1]), public: vec![] };
+        let authenticated = ProtocolOptions { authenticated: true, ..options(None) };
+        assert_eq!(run_beaver_protocol(&chain, 2, inputs, &authenticated).unwrap().outputs_p1[&7], 1);
+    }
+
+    // a circuit of additions and multiplications by constants runs as well without dealer triples, with the same
+    // outputs and messages between the parties, whereas any gate consuming triples stops the run before it starts
+    #[test]
+    fn runs_without_dealer_triples_need_no_multiplications() {
+        let dir = std::env::temp_dir().join(format!("beaver_no_dealer_triples_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("run").to_str().unwrap().to_string();
+
+        let additive = "0, P1, addc, 7 | 1, 0, mulc, 3 | 2, 1, add, P2 | 3, 2, add, P1 & 3 & 2, 3";
+        let cleartext = additive.parse::<Circuit>().unwrap().evaluate(&inputs(), Q).unwrap();
+
+        for authenticated in [false, true] {
+            let options = |no_dealer_triples| ProtocolOptions {
+                authenticated,
+                no_dealer_triples,
+                seed: Some(1),
+                output_path: Some(path.clone()),
+                ..Default::default()
+            };
+            let with = run_beaver_protocol(additive, Q, inputs(), &options(false)).unwrap();
+            let without = run_beaver_protocol(additive, Q, inputs(), &options(true)).unwrap();
+
+            assert_eq!(without.triples, 0);
+            assert_eq!(without.outputs_p1[&3], cleartext[&3]);
+            assert_eq!(without.outputs_p2, with.outputs_p2);
+            assert_eq!((without.stats.p1_to_p2, without.stats.p2_to_p1), (with.stats.p1_to_p2, with.stats.p2_to_p1));
+            for name in ["dealer", "p1", "p2"] {
+                let log = fs::read_to_string(format!("{path}_{name}.txt")).unwrap();
+                assert!(log.contains("**** Triple generation disabled"), "{name}: {log}");
             }
         }
 
-        Ok(sharing_hash)
-    }
-    fn process_gate_add(&self, s1: &T, s2: &T) -> T {
-        T::add(s1, s2, self.q)
+        let eq = "0, P1, eq, P2 | 1, 0, add, P1 & 0 & 1";
+        let ltz = "0, P1, add, P2 | 1, 0, ltz, 4 | 2, 1, add, P1 & 2 &";
+        for circuit in [CIRCUIT, eq, ltz] {
+            let options = ProtocolOptions { no_dealer_triples: true, ..options(None) };
+            let e = run_beaver_protocol(circuit, MERSENNE31, inputs(), &options).err().unwrap().to_string();
+            assert!(e.contains("circuit requires Beaver triples but dealer triples are disabled"), "{circuit}: {e}");
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
     }
-    fn process_gate_mul(
-        &self,
-        output: &mut String,
-        s1: &T,
-        s2: &T,
-        BeaverSharing(a, b, c): BeaverSharing<T>,
-    ) -> Result<T, String> {
-        let u = T::subtract(s1, &a, self.q);
-        let v = T::subtract(s2, &b, self.q);
 
-        self.send_opening(output, &u);
-        self.send_opening(output, &v);
+    // with a simulated latency, the sender of each message waits it out, so a run takes at least as long as the
+    // messages either party sends and at most as long as those of both, and thus grows with the depth of the circuit
+    #[test]
+    fn latency_grows_with_the_depth_of_the_circuit() {
+        let latency = Duration::from_millis(5);
+        let mut previous = Duration::ZERO;
 
-        let u_open = self.receive_opening(output, &u)?;
-        let v_open = self.receive_opening(output, &v)?;
+        for depth in [1, 4, 16] {
+            let circuit = std::iter::once(String::from("0, P1, mul, P2"))
+                .chain((1..depth).map(|i| format!("{i}, {}, mul, {}", i - 1, i - 1)))
+                .collect::<Vec<_>>()
+                .join(" | ")
+                + &format!(" & {} &", depth - 1);
+            let inputs = Inputs { p1: (vec![3], vec![]), p2: (vec![], vec![5]), public: vec![] };
+            let delayed = ProtocolOptions { latency: Some(latency), ..options(None) };
+            let result = run_beaver_protocol(&circuit, Q, inputs, &delayed).unwrap();
 
-        Ok(T::addc(
-            &T::add(
-                &T::mulc(&b, u_open, self.q),
-                &T::add(&T::mulc(&a, v_open, self.q), &c, self.q),
-                self.q,
-            ),
-            u_open * v_open,
-            self.s_k1,
-            self.s_k2,
-            self.q,
-            self.identity,
-        ))
-    }
-    fn process_gate_addc(&self, s: &T, c: u32) -> T {
-        T::addc(s, c, self.s_k1, self.s_k2, self.q, self.identity)
-    }
-    fn process_gate_mulc(&self, s: &T, c: u32) -> T {
-        T::mulc(s, c, self.q)
-    }
-    fn other_party(&self) -> Party {
-        match self.identity {
-            Party::P1 => Party::P2,
-            Party::P2 => Party::P1,
+            let (sent_p1, sent_p2) = (result.stats.p1_to_p2 as u32, result.stats.p2_to_p1 as u32);
+            assert!(result.elapsed >= latency * sent_p1.max(sent_p2), "depth {depth}: {:?}", result.elapsed);
+            assert!(result.elapsed <= latency * (sent_p1 + sent_p2) + Duration::from_secs(1), "depth {depth}");
+            assert!(result.elapsed > previous, "depth {depth}");
+            previous = result.elapsed;
         }
     }
-}
 
-pub fn run_beaver_protocol(
-    circuit_encoding: &str,
-    q: u32,
-    inputs_p1_first: Vec<u32>,
-    inputs_p1_second: Vec<u32>,
-    inputs_p2_first: Vec<u32>,
-    inputs_p2_second: Vec<u32>,
-    authenticated: bool,
-    corrupt: bool,
-    output_path: &str,
-) -> Result<(), String> {
-    if authenticated {
-        run_beaver_protocol_internal::<AuthSharing>(
-            circuit_encoding,
-            q,
-            inputs_p1_first,
-            inputs_p1_second,
-            inputs_p2_first,
-            inputs_p2_second,
-            corrupt,
-            output_path,
-        )
-    } else {
-        run_beaver_protocol_internal::<UnauthSharing>(
-            circuit_encoding,
-            q,
-            inputs_p1_first,
-            inputs_p1_second,
-            inputs_p2_first,
-            inputs_p2_second,
-            corrupt,
-            output_path,
-        )
-    }
-}
+    // the dealers log at their own level, leaving the party logs as they are at theirs, and the other way round
+    #[test]
+    fn dealer_log_level_is_independent_of_the_party_one() {
+        let dir = std::env::temp_dir().join(format!("beaver_dealer_log_level_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
 
-fn run_beaver_protocol_internal<T: Sharing + 'static>(
-    circuit_encoding: &str,
-    q: u32,
-    inputs_p1_first: Vec<u32>,
-    inputs_p1_second: Vec<u32>,
-    inputs_p2_first: Vec<u32>,
-    inputs_p2_second: Vec<u32>,
-    corrupt: bool,
-    output_path: &str,
-) -> Result<(), String> {
-    // TODO prime verification
-
-    let c1: Circuit = circuit_encoding.parse()?;
-    // the next two calls cannot fail if this line is reached
-    // the circuit is computed separately by each party to mimic execution in independent machines
-    let c2: Circuit = circuit_encoding.parse().unwrap();
-    let c3: Circuit = circuit_encoding.parse().unwrap();
+        let logs = |name: &str, log_level, dealer_log_level| -> Vec<Option<String>> {
+            let path = dir.join(name).to_str().unwrap().to_string();
+            let options = ProtocolOptions {
+                authenticated: true,
+                dealers: 2,
+                seed: Some(1),
+                log_level,
+                dealer_log_level,
+                output_path: Some(path.clone()),
+                ..Default::default()
+            };
+            run_beaver_protocol(CIRCUIT, Q, inputs(), &options).unwrap();
+            ["p1", "p2", "dealer", "dealer1"].map(|n| fs::read_to_string(format!("{path}_{n}.txt")).ok()).to_vec()
+        };
 
-    let (tx_d_p1, rx_d_p1) = mpsc::channel::<Msg<T>>();
-    let (tx_d_p2, rx_d_p2) = mpsc::channel::<Msg<T>>();
-    let (tx_p1_p2, rx_p1_p2) = mpsc::channel::<Msg<T>>();
-    let (tx_p2_p1, rx_p2_p1) = mpsc::channel::<Msg<T>>();
+        let normal = logs("normal", LogLevel::Normal, None);
+        let verbose = logs("verbose", LogLevel::Verbose, None);
+        assert!(normal.iter().chain(&verbose).all(Option::is_some));
 
-    let dealer = Dealer {
-        circuit: c1,
-        q,
-        tx_d_p1,
-        tx_d_p2,
-        log_path: format!("{}_dealer.txt", output_path),
-    };
+        // the party logs at one level and the dealer logs at the other
+        let silent_dealers = logs("silent_dealers", LogLevel::Normal, Some(LogLevel::Off));
+        assert_eq!(silent_dealers[..2], normal[..2]);
+        assert_eq!(silent_dealers[2..], [None, None]);
 
-    // if corrupt, randomly choose a party to be so; otherwise, no party is so
-    let p1_corrupt = corrupt && rand::random::<bool>();
-    let p2_corrupt = corrupt && !p1_corrupt;
-
-    let (input_ids_p1_first, input_ids_p1_second) = c2.get_inputs_p1();
-    if input_ids_p1_first.len() != inputs_p1_first.len() || input_ids_p1_second.len() != inputs_p1_second.len() {
-        return Err(String::from("Error: number of input values provided by P1 does not match the circuit's needs"));
-    }
-
-    let (input_ids_p2_first, input_ids_p2_second) = c3.get_inputs_p2();
-    if input_ids_p2_first.len() != inputs_p2_first.len() || input_ids_p2_second.len() != inputs_p2_second.len() {
-        return Err(String::from("Error: number of input values provided by P2 does not match the circuit's needs"));
-    }
-
-    let mut party1 = ProtocolParty {
-        identity: Party::P1,
-        q,
-        circuit: c2,
-        inputs_first: inputs_p1_first,
-        inputs_second: inputs_p1_second,
-        corrupt: p1_corrupt,
-        rx_d_me: rx_d_p1,
-        rx_other_me: rx_p2_p1,
-        tx_me_other: tx_p1_p2,
-        log_path: format!("{}_p1.txt", output_path),
-        key: 0,
-        s_k1: 0,
-        s_k2: 0,
-    };
+        let verbose_dealers = logs("verbose_dealers", LogLevel::Normal, Some(LogLevel::Verbose));
+        assert_eq!(verbose_dealers[..2], normal[..2]);
+        assert_eq!(verbose_dealers[2..], verbose[2..]);
+        assert!(verbose_dealers[2].as_ref().unwrap().contains("(v="));
 
-    let mut party2 = ProtocolParty {
-        identity: Party::P2,
-        circuit: c3,
-        q,
-        inputs_first: inputs_p2_first,
-        inputs_second: inputs_p2_second,
-        corrupt: p2_corrupt,
-        rx_d_me: rx_d_p2,
-        rx_other_me: rx_p1_p2,
-        tx_me_other: tx_p2_p1,
-        log_path: format!("{}_p2.txt", output_path),
-        key: 0,
-        s_k1: 0,
-        s_k2: 0,
-    };
+        let silent_parties = logs("silent_parties", LogLevel::Off, Some(LogLevel::Normal));
+        assert_eq!(silent_parties[..2], [None, None]);
+        assert_eq!(silent_parties[2..], normal[2..]);
+
+        assert!("Verbose".parse::<LogLevel>() == Ok(LogLevel::Verbose));
+        assert!("loud".parse::<LogLevel>().is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // a public value scaling the secret input of P1 and shifting that of P2 gives the outputs of the cleartext
+    // evaluation for any public values, including those above q / 2 which stand for negative ones
+    #[test]
+    fn public_inputs_scale_secret_inputs_as_in_cleartext() {
+        let circuit = "0, P1, mul, pub0 | 1, pub1, add, P2 | 2, 0, add, 1 | 3, pub0, mul, pub1 & 0, 2 & 2, 3";
+        let parsed: Circuit = circuit.parse().unwrap();
+        assert_eq!(parsed.num_public_inputs(), 2);
+
+        for public in [vec![0, 0], vec![1, 100], vec![7, 13], vec![Q - 1, Q - 2]] {
+            let inputs = Inputs { p1: (vec![3], vec![]), p2: (vec![], vec![5]), public };
+            let cleartext = parsed.evaluate(&inputs, Q).unwrap();
+            assert_eq!(cleartext[&0], inputs.public[0] * 3 % Q);
+
+            for authenticated in [false, true] {
+                let options = ProtocolOptions { authenticated, ..options(None) };
+                let result = run_beaver_protocol(circuit, Q, inputs.clone(), &options).unwrap();
+                for (id, v) in result.outputs_p1.iter().chain(&result.outputs_p2) {
+                    assert_eq!(*v, cleartext[id], "output {id} with public inputs {:?}", inputs.public);
+                }
+            }
+        }
 
-    let thread_dealer = thread::spawn(move || dealer.run());
+        let missing = Inputs { p1: (vec![3], vec![]), p2: (vec![], vec![5]), public: vec![2] };
+        let e = run_beaver_protocol(circuit, Q, missing, &options(None)).err().unwrap().to_string();
+        assert!(e.contains("1 public input values were provided, but the circuit needs 2"), "{e}");
+    }
 
-    let thread_p1 = thread::spawn(move || party1.run());
+    // parties built with ProtocolParty::new alone, with randomness and display names of their own, run against the
+    // dealer of a setup to the outputs of the cleartext evaluation
+    #[test]
+    fn parties_built_with_new_run_to_the_cleartext_outputs() {
+        fn construct_and_run<T: Sharing + 'static>(authenticated: bool) {
+            let options = ProtocolOptions { authenticated, ..options(None) };
+            let Setup { dealer, mut rx_dealers_p1, mut rx_dealers_p2, field, .. } =
+                setup::<T>(CIRCUIT, Q, inputs(), &options).unwrap();
+            #[cfg(debug_assertions)]
+            let debug_keys = dealer.debug_keys.clone();
+            let (rx_p1, rx_p2) = (rx_dealers_p1.remove(0), rx_dealers_p2.remove(0));
+            let dealer = DealerMachine { dealer, rx_p1, rx_p2, result: None };
 
-    let thread_p2 = thread::spawn(move || party2.run());
+            let Inputs { p1, p2, public } = inputs();
+            let party = |identity, inputs, (name, peer): (&str, &str), seed| {
+                let party = ProtocolParty::<T>::new(
+                    identity,
+                    CIRCUIT.parse().unwrap(),
+                    field,
+                    inputs,
+                    public.clone(),
+                    None,
+                    1,
+                    Vec::new(),
+                    (String::from(name), String::from(peer)),
+                    ChaCha20Rng::seed_from_u64(seed),
+                    &options,
+                    None,
+                    #[cfg(debug_assertions)]
+                    debug_keys.clone(),
+                );
+                assert_eq!((party.identity, party.key, party.name.as_str()), (identity, 0, name));
+                PartyMachine::new(party, false)
+            };
+            let p1 = party(Party::P1, p1, ("Alice", "Bob"), 11);
+            let p2 = party(Party::P2, p2, ("Bob", "Alice"), 12);
 
-    let mut err_str = String::new();
+            let cleartext = CIRCUIT.parse::<Circuit>().unwrap().evaluate(&inputs(), Q).unwrap();
+            let (r1, r2) = drive_machines((dealer, p1, p2));
+            assert_eq!(r1.unwrap(), HashMap::from([(0, cleartext[&0])]));
+            assert_eq!(r2.unwrap(), HashMap::from([(1, cleartext[&1])]));
+        }
 
-    if let Err(e) = thread_dealer.join().unwrap() {
-        err_str.push_str(&format!("Dealer: {e}\n"));
+        construct_and_run::<UnauthSharing>(false);
+        construct_and_run::<AuthSharing>(true);
     }
-    if let Err(e) = thread_p1.join().unwrap() {
-        err_str.push_str(&format!("P1: {e}\n"));
+
+    // the preprocessing of a circuit with one gate of each kind consuming correlated randomness, counted by hand
+    #[test]
+    fn preprocessing_estimates_match_hand_counts() {
+        let circuit = "0, P1, add, P2 | 1, P1, mulc, 8 | 2, 1, truncf, 3 | dot, 3, 0 1, 1 0 | 4, 3, ltz, 8 | \
+                       5, 0, eq, 2 & 4, 5 & 2";
+        let parsed: Circuit = circuit.parse().unwrap();
+
+        // 2 * (8 - 2) triples for the comparison and 32 - 1 for the equality test
+        let triples = 12 + 31;
+        // 2 * 2 + 1 for the dot product, 2 for the truncation, 8 bits for the comparison and 32 for the equality test
+        let other_sharings = 5 + 2 + 8 + 32;
+        for (authenticated, transcript_bytes) in [(false, 1448), (true, 4312)] {
+            let estimate = PreprocessingEstimate::new(&parsed, authenticated);
+            let key_sharings = 2;
+            let input_singletons = 3;
+            assert_eq!(
+                estimate,
+                PreprocessingEstimate { key_sharings, input_singletons, triples, other_sharings, transcript_bytes }
+            );
+            assert_eq!(
+                estimate.to_string(),
+                format!("This run will generate 43 triples, 3 input singletons, producing ~{transcript_bytes} bytes of \
+                         transcript.")
+            );
+
+            // as many triples as the dealer actually generates
+            let inputs = Inputs { p1: (vec![1, 2], vec![]), p2: (vec![], vec![1]), public: vec![] };
+            let options = ProtocolOptions { authenticated, ..options(None) };
+            assert_eq!(run_beaver_protocol(circuit, MERSENNE31, inputs, &options).unwrap().triples, triples);
+        }
+
+        // a circuit without any of those gates needs the keys and singletons alone
+        let estimate = PreprocessingEstimate::new(&"0, P1, add, P2 & 0 &".parse().unwrap(), true);
+        assert_eq!((estimate.triples, estimate.other_sharings, estimate.transcript_bytes), (0, 0, 4 * 2 * (2 + 3 * 2)));
     }
-    if let Err(e) = thread_p2.join().unwrap() {
-        err_str.push_str(&format!("P2: {e}\n"));
+
+    // a chain of 8 squarings takes a round per multiplication, and a tree of products a round per layer however many
+    // multiplications it holds. reveals, truncations and zero assertions add to the rounds but not the depth
+    #[test]
+    fn rounds_follow_the_interactive_depth_of_the_circuit() {
+        let (chain, chain_inputs, _) = squaring_chain();
+        let tree = "0, P1, mul, P2 | 1, P1, mul, P2 | 2, 0, mul, 1 | 3, 0, add, 1 & 2 & 3";
+        let tree_inputs = Inputs { p1: (vec![2, 3], vec![]), p2: (vec![], vec![4, 5]), public: vec![] };
+
+        for (circuit, inputs, depth) in [(chain.as_str(), chain_inputs, 8), (tree, tree_inputs, 2)] {
+            let parsed: Circuit = circuit.parse().unwrap();
+            assert_eq!(parsed.multiplicative_depth(), depth);
+            assert_eq!(parsed.interactive_depth(), depth);
+
+            for authenticated in [false, true] {
+                let options = ProtocolOptions { authenticated, ..options(None) };
+                let result = run_beaver_protocol(circuit, Q, inputs.clone(), &options).unwrap();
+                // no output is revealed to both parties, so there is no cross-check
+                let expected = RoundReport {
+                    key_opening: 1,
+                    triple_check: 0,
+                    input_processing: 2,
+                    gate_processing: depth,
+                    output_processing: 2,
+                };
+                assert_eq!(result.rounds, expected);
+                assert_eq!(result.rounds, RoundReport::new(&parsed));
+                assert_eq!(
+                    result.rounds.to_string(),
+                    format!(
+                        "Communication rounds: {} (key opening 1, input processing 2, gate processing {depth}, \
+                         output processing 2)",
+                        depth + 5
+                    )
+                );
+            }
+        }
+
+        // the depths of gates 0 to 4 are 1, 2, 2, 3 and 5 openings, but 1, 1, 1, 2 and 2 multiplications
+        let mixed = "0, P1, mul, P2 | 1, 0, reveal | 2, 0, truncf, 3 | 3, 2, mul, 1 | 4, 3, assert_zero & 3, 4 &";
+        let mixed: Circuit = mixed.parse().unwrap();
+        assert_eq!(mixed.interactive_depth(), 5);
+        assert_eq!(mixed.multiplicative_depth(), 2);
+        assert_eq!(RoundReport::new(&mixed).gate_processing, 5);
     }
 
-    err_str.pop();
+    // outputs which are gates taking only input wires, whose sharings come straight from input processing, are
+    // opened as any other. an output id which is not a gate at all is refused when parsing
+    #[test]
+    fn input_wire_gates_declared_as_outputs_are_opened() {
+        let circuit = "0, P1, add, P2 | 1, P2, mul, P1 | 2, P1, addc, 7 | 3, 0, mul, 1 & 0, 1, 2 & 1, 2, 3";
+        let inputs = Inputs { p1: (vec![3, 4], vec![5]), p2: (vec![6], vec![7]), public: vec![] };
+        let cleartext = circuit.parse::<Circuit>().unwrap().evaluate(&inputs, Q).unwrap();
 
-    if err_str.is_empty() {
-        Ok(())
-    } else {
-        Err(err_str)
+        for authenticated in [false, true] {
+            let options = ProtocolOptions { authenticated, ..options(None) };
+            let result = run_beaver_protocol(circuit, Q, inputs.clone(), &options).unwrap();
+            for (outputs, ids) in [(&result.outputs_p1, [0, 1, 2]), (&result.outputs_p2, [1, 2, 3])] {
+                let expected: HashMap<u32, u32> = ids.iter().map(|id| (*id, cleartext[id])).collect();
+                assert_eq!(*outputs, expected);
+            }
+        }
+
+        let e = run_beaver_protocol("0, P1, add, P2 & 0, 4 &", Q, inputs, &options(None)).err().unwrap().to_string();
+        assert!(e.contains("necessary gate 4 not found"), "{e}");
     }
-}
 
-fn log<T: Display>(output: &mut String, receive: bool, other: &str, desc: &str, value: T) -> T {
-    output.push_str(&format!(
-        "{} {other} {desc}: {value}\n",
-        if receive { "Received from" } else { "Sent to" }
-    ));
-    value
+    // gate 2 reveals a*b + 3 to both parties mid-run, and gates 3 and 5 use the revealed value r as a constant.
+    // the model computes d*r and d*r + e + r from the inputs directly, so that a different reveal would show
+    #[test]
+    fn revealed_values_feed_downstream_constants_as_in_a_cleartext_model() {
+        let circuit = "0, P1, mul, P2 | 1, 0, addc, 3 | 2, 1, reveal | 3, P2, mulc, R2 | 4, 3, add, P1 \
+            | 5, 4, addc, R2 & 5 & 3, 5";
+        let rng = &mut ChaCha20Rng::seed_from_u64(4);
+
+        for authenticated in [false, true] {
+            for _ in 0..10 {
+                let [a, b, d, e]: [u64; 4] = std::array::from_fn(|_| rng.gen_range(0..Q as u64));
+                let r = (a * b + 3) % Q as u64;
+                let model = |v: u64| (v % Q as u64) as u32;
+                let inputs = Inputs {
+                    p1: (vec![a as u32], vec![e as u32]),
+                    p2: (vec![d as u32], vec![b as u32]),
+                    public: vec![],
+                };
+
+                let options = ProtocolOptions { authenticated, ..options(None) };
+                let result = run_beaver_protocol(circuit, Q, inputs, &options).unwrap();
+                assert_eq!(result.outputs_p1, HashMap::from([(5, model(d * r + e + r))]), "a = {a}, b = {b}");
+                assert_eq!(result.outputs_p2, HashMap::from([(3, model(d * r)), (5, model(d * r + e + r))]));
+            }
+        }
+    }
 }
@@ -0,0 +1,202 @@
+// Sessions, in which several circuits are evaluated under the same MAC keys k1 and k2 instead of fresh ones for
+// each run. The longer the keys are in use, the more a compromise of them exposes, so refresh_keys replaces them
+// between evaluations and starts a new key epoch. Material persisted under the keys of an epoch (share state files,
+// checkpoints) is bound to it, and refused in any later one: it would fail the MAC checks under the new keys, and
+// would otherwise keep the old ones in use. The session stands in for the dealer in drawing the keys, which it
+// hands to the dealer of each run.
+
+use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+
+use crate::circuit::Inputs;
+use crate::error::BeaverError;
+use crate::field::{FieldOps, F32};
+use crate::protocol::{self, KeyEpoch, ProtocolOptions, ProtocolResult};
+use crate::shares::PartyShareState;
+use crate::sharing::Sharing;
+
+pub struct Session {
+    q: u32,
+    key_epoch: KeyEpoch,
+    rng: ChaCha20Rng,
+}
+
+impl Session {
+    // starts the session in epoch 1. if a seed is given, the keys of every epoch are derived from it
+    pub fn new(q: u32, seed: Option<u64>) -> Result<Self, BeaverError> {
+        if q < 2 {
+            return Err(BeaverError::Input(format!("Error: the modulus q should be at least 2, not {q}")));
+        }
+
+        let mut rng = match seed {
+            Some(seed) => ChaCha20Rng::seed_from_u64(seed),
+            None => ChaCha20Rng::from_entropy(),
+        };
+        let f = F32::new(q);
+        let keys = (f.random(&mut rng), f.random(&mut rng));
+
+        Ok(Session { q, key_epoch: KeyEpoch { epoch: 1, keys }, rng })
+    }
+
+    pub fn epoch(&self) -> u32 {
+        self.key_epoch.epoch
+    }
+
+    // draws new keys and moves on to the next epoch, from which on all material of earlier ones is refused
+    pub fn refresh_keys(&mut self) {
+        let f = F32::new(self.q);
+        let keys = (f.random(&mut self.rng), f.random(&mut self.rng));
+        self.key_epoch = KeyEpoch { epoch: self.key_epoch.epoch + 1, keys };
+    }
+
+    // runs the protocol with the keys of the current epoch, which the parties record in their logs and checkpoints
+    pub fn evaluate(
+        &self,
+        circuit_encoding: &str,
+        inputs: Inputs,
+        options: ProtocolOptions,
+    ) -> Result<ProtocolResult, BeaverError> {
+        let options = ProtocolOptions { key_epoch: Some(self.key_epoch), ..options };
+        protocol::run_beaver_protocol(circuit_encoding, self.q, inputs, &options)
+    }
+
+    // resumes an evaluation interrupted in the current epoch; the parties refuse checkpoints saved in another one
+    pub fn resume(&self, circuit_encoding: &str, options: ProtocolOptions) -> Result<ProtocolResult, BeaverError> {
+        let options = ProtocolOptions { key_epoch: Some(self.key_epoch), ..options };
+        protocol::resume_beaver_protocol(circuit_encoding, self.q, &options)
+    }
+
+    // checks that a share state was produced in the current epoch of the session, and can thus still be used
+    pub fn check_share_state<T: Sharing>(&self, state: &PartyShareState<T>) -> Result<(), BeaverError> {
+        if state.q != self.q {
+            return Err(BeaverError::Input(format!(
+                "Error: the share state is modulo {}, but the session is modulo {}",
+                state.q, self.q
+            )));
+        }
+
+        match state.epoch {
+            Some(epoch) if epoch == self.epoch() => Ok(()),
+            Some(epoch) => Err(BeaverError::Input(format!(
+                "Error: the share state is from key epoch {epoch}, but the session is in epoch {}; its shares are \
+                 bound to keys which have since been refreshed",
+                self.epoch()
+            ))),
+            None => Err(BeaverError::Input(format!(
+                "Error: the share state is not bound to any key epoch, but the session is in epoch {}",
+                self.epoch()
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::circuit::{Circuit, Party};
+    use crate::protocol::{CheckpointConfig, LogLevel};
+    use crate::shares::{read_share_state, write_share_state};
+    use crate::sharing::AuthSharing;
+
+    const CIRCUIT: &str = "0, P1, mul, P2 | 1, 0, add, P1 & 0 & 1";
+    const Q: u32 = 101;
+
+    fn inputs() -> Inputs {
+        Inputs { p1: (vec![3], vec![4]), p2: (vec![], vec![5]), public: vec![] }
+    }
+
+    fn cleartext() -> std::collections::HashMap<u32, u32> {
+        CIRCUIT.parse::<Circuit>().unwrap().evaluate(&inputs(), Q).unwrap()
+    }
+
+    fn outputs(r: ProtocolResult) -> std::collections::HashMap<u32, u32> {
+        r.outputs_p1.into_iter().chain(r.outputs_p2).collect()
+    }
+
+    #[test]
+    fn fresh_evaluations_verify_after_a_refresh() {
+        let dir = std::env::temp_dir().join(format!("beaver_session_refresh_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("run").to_str().unwrap().to_string();
+
+        let mut session = Session::new(Q, Some(3)).unwrap();
+        let keys = session.key_epoch.keys;
+        let options = || ProtocolOptions {
+            authenticated: true,
+            seed: Some(1),
+            log_level: LogLevel::Normal,
+            output_path: Some(path.clone()),
+            ..Default::default()
+        };
+
+        assert_eq!(outputs(session.evaluate(CIRCUIT, inputs(), options()).unwrap()), cleartext());
+        assert!(fs::read_to_string(format!("{path}_p1.txt")).unwrap().contains("Key epoch: 1\n"));
+
+        session.refresh_keys();
+        assert_eq!(session.epoch(), 2);
+        assert_ne!(session.key_epoch.keys, keys);
+
+        // the MAC checks of the run pass under the new keys
+        assert_eq!(outputs(session.evaluate(CIRCUIT, inputs(), options()).unwrap()), cleartext());
+        for party in ["p1", "p2"] {
+            let log = fs::read_to_string(format!("{path}_{party}.txt")).unwrap();
+            assert!(log.contains("Key epoch: 2\n"), "{log}");
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn share_states_of_earlier_epochs_are_refused() {
+        let dir = std::env::temp_dir().join(format!("beaver_session_state_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("p1.state").to_str().unwrap().to_string();
+
+        let mut session = Session::new(Q, Some(3)).unwrap();
+        let state: PartyShareState<AuthSharing> =
+            PartyShareState { q: Q, party: Party::P1, keys: (1, 2), epoch: Some(1), inputs: vec![], triples: vec![] };
+        write_share_state(&path, &state).unwrap();
+
+        let state = read_share_state::<AuthSharing>(&path).unwrap();
+        assert_eq!(state.epoch, Some(1));
+        assert!(session.check_share_state(&state).is_ok());
+
+        session.refresh_keys();
+        let e = session.check_share_state(&state).unwrap_err().to_string();
+        assert!(e.contains("from key epoch 1, but the session is in epoch 2"), "{e}");
+
+        let unbound = PartyShareState { epoch: None, ..state };
+        let e = session.check_share_state(&unbound).unwrap_err().to_string();
+        assert!(e.contains("not bound to any key epoch"), "{e}");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn checkpoints_of_earlier_epochs_are_refused() {
+        let dir = std::env::temp_dir().join(format!("beaver_session_checkpoint_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let checkpoint = CheckpointConfig { path: dir.join("run").to_str().unwrap().to_string(), interval: 1 };
+
+        let mut session = Session::new(Q, Some(3)).unwrap();
+        let options = |crash_at| ProtocolOptions {
+            authenticated: true,
+            seed: Some(1),
+            checkpoint: Some(checkpoint.clone()),
+            crash_at,
+            ..Default::default()
+        };
+
+        assert!(session.evaluate(CIRCUIT, inputs(), options(Some((Party::P1, 1)))).is_err());
+
+        // the checkpoints resume fine within the epoch they were saved in
+        assert_eq!(outputs(session.resume(CIRCUIT, options(None)).unwrap()), cleartext());
+
+        session.refresh_keys();
+        let e = session.resume(CIRCUIT, options(None)).err().unwrap().to_string();
+        assert!(e.contains("was saved in key epoch 1, but the run is in key epoch 2"), "{e}");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,755 @@
+// Share files, for exchanging the shares held by one party with other MPC engines. A share file is plain text with
+// a header followed by one record per wire:
+//
+//     beaver-shares <format version>
+//     modulus <q>
+//     sharing <additive | additive-mac>
+//     share <wire id> <party index: 1 or 2> <components>
+//
+// An additive share has a single component, the party's summand of the value. An additive-mac share has three: the
+// summand of the value and those of its MACs under k1 and k2 (see AuthSharing). All components are below q and each
+// wire appears at most once.
+//
+// Share state files hold the complete share state of one party before the evaluation of the gates, in the same
+// line-oriented style but with each sharing written as in the logs (see the Display implementations in sharing.rs),
+// so that they can be read and graded by hand:
+//
+//     beaver-state <format version>
+//     modulus <q>
+//     sharing <additive | additive-mac>
+//     party <1 | 2>
+//     keys <share of k1> <share of k2>
+//     epoch <key epoch of the keys, if produced in a session>
+//     input <p1_first | p1_second | p2_first | p2_second> <gate id> <sharing, e.g. (3) or (3, 14, 15)>
+//     triples <number of triples>
+//     triple <sharings of a, b and c, e.g. [(3), (1), (4)]>
+//     checksum <FNV-1a hash of the preceding chunk of triple records, in hexadecimal>
+//
+// Blank lines and comments from a # to the end of the line are ignored, and sharings may also be written in the
+// verbose form of the logs, e.g. (v=3, MAC_k1=14, MAC_k2=15). Each input wire appears at most once, and so does the
+// epoch. Triples are listed in the order in which they are consumed, in chunks of up to 1024 each followed by its
+// checksum, so that they can be streamed (see ShareStateStream) and a truncated file is caught.
+
+use std::{
+    collections::HashSet,
+    fmt, fs,
+    io::{BufRead, BufReader, Lines},
+    iter::Enumerate,
+    str::FromStr,
+};
+
+use crate::circuit::{InputWire, Party};
+use crate::error::BeaverError;
+use crate::sharing::{BeaverSharing, Sharing};
+use crate::utilities::Fnv1a;
+
+const FORMAT_VERSION: u32 = 1;
+const STATE_FORMAT_VERSION: u32 = 2;
+const TRIPLE_CHUNK: usize = 1024;
+
+const INPUT_SLOTS: [&str; 4] = ["p1_first", "p1_second", "p2_first", "p2_second"];
+
+fn party_index(party: Party) -> u32 {
+    match party {
+        Party::P1 => 1,
+        Party::P2 => 2,
+    }
+}
+
+pub fn export_shares<T: Sharing>(path: &str, q: u32, party: Party, shares: &[(u32, T)]) -> Result<(), BeaverError> {
+    let index = party_index(party);
+
+    let mut out = format!("beaver-shares {FORMAT_VERSION}\nmodulus {q}\nsharing {}\n", T::SCHEME);
+
+    for (id, s) in shares {
+        let c: Vec<String> = s.components().iter().map(|v| v.to_string()).collect();
+        out.push_str(&format!("share {id} {index} {}\n", c.join(" ")));
+    }
+
+    fs::write(path, out).map_err(|e| BeaverError::Io(format!("Failed to write share file {path}: {e}")))
+}
+
+// reads the shares of the given party, checking that they were produced for modulus q and the sharing scheme of T
+pub fn import_shares<T: Sharing>(path: &str, q: u32, party: Party) -> Result<Vec<(u32, T)>, BeaverError> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| BeaverError::Io(format!("Failed to read share file {path}: {e}")))?;
+
+    let invalid = |n: usize, msg: &str| BeaverError::Parse(format!("Invalid share file {path} at line {}: {msg}", n + 1));
+
+    let mut lines = contents.lines().enumerate().filter(|(_, l)| !l.trim().is_empty());
+
+    let mut header = |key: &str| -> Result<(usize, String), BeaverError> {
+        match lines.next() {
+            Some((n, l)) => match l.split_whitespace().collect::<Vec<&str>>()[..] {
+                [k, v] if k == key => Ok((n, v.to_string())),
+                _ => Err(invalid(n, &format!("expected {key}"))),
+            },
+            None => Err(BeaverError::Parse(format!("Invalid share file {path}: missing {key}"))),
+        }
+    };
+
+    let (n, version) = header("beaver-shares")?;
+    if version != FORMAT_VERSION.to_string() {
+        return Err(invalid(n, &format!("unsupported format version {version}")));
+    }
+
+    let (n, modulus) = header("modulus")?;
+    if modulus != q.to_string() {
+        return Err(invalid(n, &format!("the shares are modulo {modulus}, not {q}")));
+    }
+
+    let (n, scheme) = header("sharing")?;
+    if scheme != T::SCHEME {
+        return Err(invalid(n, &format!("the shares are {scheme}, not {}", T::SCHEME)));
+    }
+
+    let index = party_index(party).to_string();
+    let mut ids = HashSet::new();
+    let mut shares = Vec::new();
+
+    for (n, line) in lines {
+        let terms: Vec<&str> = line.split_whitespace().collect();
+
+        if terms.len() < 3 || terms[0] != "share" {
+            return Err(invalid(n, "expected share record"));
+        }
+
+        let id: u32 = terms[1].parse().map_err(|_| invalid(n, "invalid wire id"))?;
+        if terms[2] != index {
+            return Err(invalid(n, &format!("expected a share of {party}, found party index {}", terms[2])));
+        }
+        if !ids.insert(id) {
+            return Err(invalid(n, &format!("wire {id} appears more than once")));
+        }
+
+        let c: Vec<u32> = terms[3..]
+            .iter()
+            .map(|t| t.parse().map_err(|_| invalid(n, "invalid share component")))
+            .collect::<Result<_, BeaverError>>()?;
+
+        if c.iter().any(|v| *v >= q) {
+            return Err(invalid(n, &format!("share components should be below {q}")));
+        }
+
+        let s = T::from_components(&c).ok_or(invalid(n, &format!("wrong number of components for {scheme}")))?;
+        shares.push((id, s));
+    }
+
+    Ok(shares)
+}
+
+// the shares held by one party once the dealer is done: those of the MAC keys, of the input wires and of the
+// Beaver triples, the last one being consumed first as in the protocol
+pub struct PartyShareState<T: Sharing> {
+    pub q: u32,
+    pub party: Party,
+    pub keys: (u32, u32),
+    // key epoch of the session the shares were produced in, if any (see Session::check_share_state)
+    pub epoch: Option<u32>,
+    pub inputs: Vec<(InputWire, T)>,
+    pub triples: Vec<BeaverSharing<T>>,
+}
+
+impl<T: Sharing> fmt::Display for PartyShareState<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "beaver-state {STATE_FORMAT_VERSION}")?;
+        writeln!(f, "modulus {}", self.q)?;
+        writeln!(f, "sharing {}", T::SCHEME)?;
+        writeln!(f, "party {}", party_index(self.party))?;
+        writeln!(f, "keys {} {}", self.keys.0, self.keys.1)?;
+        if let Some(epoch) = self.epoch {
+            writeln!(f, "epoch {epoch}")?;
+        }
+
+        for (wire, s) in &self.inputs {
+            let slot = INPUT_SLOTS[2 * (wire.party == Party::P2) as usize + !wire.first as usize];
+            writeln!(f, "input {slot} {} {s}", wire.id)?;
+        }
+
+        writeln!(f, "triples {}", self.triples.len())?;
+
+        // in the order in which they are consumed, which is the reverse of that of the vector
+        let records: Vec<String> = self.triples.iter().rev().map(|t| format!("triple {t}")).collect();
+        for chunk in records.chunks(TRIPLE_CHUNK) {
+            for r in chunk {
+                writeln!(f, "{r}")?;
+            }
+            writeln!(f, "checksum {}", chunk_checksum(chunk.iter().map(String::as_str)))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<T: Sharing> FromStr for PartyShareState<T> {
+    type Err = String;
+
+    fn from_str(contents: &str) -> Result<Self, Self::Err> {
+        ShareStateStream::new(String::from("share state"), contents.as_bytes())
+            .and_then(ShareStateStream::into_state)
+            .map_err(|e| e.to_string())
+    }
+}
+
+// the checksum of a chunk of triple records, over their lines as written (without comments or surrounding
+// whitespace), each followed by a newline
+fn chunk_checksum<'a>(records: impl IntoIterator<Item = &'a str>) -> String {
+    let mut h = Fnv1a::new();
+    for r in records {
+        h.write(r.as_bytes());
+        h.write(b"\n");
+    }
+    format!("{:016x}", h.finish())
+}
+
+// the non-blank lines of a share state, without comments and along with their index
+struct Records<B: BufRead> {
+    lines: Enumerate<Lines<B>>,
+    // what is being read, for error messages
+    source: String,
+    // index of the last line read
+    position: usize,
+}
+
+impl<B: BufRead> Records<B> {
+    fn next(&mut self) -> Result<Option<(usize, String)>, BeaverError> {
+        for (n, line) in self.lines.by_ref() {
+            self.position = n;
+            let line = line.map_err(|e| BeaverError::Io(format!("Failed to read {}: {e}", self.source)))?;
+            let line = line.split('#').next().unwrap_or("").trim();
+            if !line.is_empty() {
+                return Ok(Some((n, line.to_string())));
+            }
+        }
+        Ok(None)
+    }
+
+    fn invalid(&self, n: usize, msg: &str) -> BeaverError {
+        BeaverError::Parse(format!("Invalid {}: line {}: {msg}", self.source, n + 1))
+    }
+
+    fn truncated(&self, msg: &str) -> BeaverError {
+        BeaverError::Parse(format!("Invalid {}: truncated file, {msg}", self.source))
+    }
+
+    // the values of the next record, which should have the given key
+    fn header(&mut self, key: &str) -> Result<(usize, Vec<String>), BeaverError> {
+        match self.next()? {
+            Some((n, l)) => match l.split_whitespace().collect::<Vec<&str>>()[..] {
+                [k, ref v @ ..] if k == key => Ok((n, v.iter().map(|v| v.to_string()).collect())),
+                _ => Err(self.invalid(n, &format!("expected {key}"))),
+            },
+            None => Err(BeaverError::Parse(format!("Invalid {}: missing {key}", self.source))),
+        }
+    }
+}
+
+// a share state read lazily, so that states with huge numbers of triples can be used without holding them all in
+// memory: the header and the input sharings are read upfront, and the triples one chunk at a time as they are
+// consumed, in the order in which they are consumed. each chunk is checked against its checksum before any of its
+// triples is handed out, so that a truncated or corrupted file fails as soon as the damaged chunk is reached rather
+// than with a bad triple. after an error, the stream ends
+pub struct ShareStateStream<T: Sharing, B: BufRead> {
+    pub q: u32,
+    pub party: Party,
+    pub keys: (u32, u32),
+    pub epoch: Option<u32>,
+    pub inputs: Vec<(InputWire, T)>,
+    records: Records<B>,
+    // number of triples announced by the state, and of those handed out so far
+    num_triples: usize,
+    consumed: usize,
+    // the triples of the current chunk not handed out yet, the next one last
+    chunk: Vec<BeaverSharing<T>>,
+    failed: bool,
+}
+
+impl<T: Sharing> ShareStateStream<T, BufReader<fs::File>> {
+    // checks that the shares are of the sharing scheme of T, but not that they belong to any particular run
+    pub fn open(path: &str) -> Result<Self, BeaverError> {
+        let file = fs::File::open(path)
+            .map_err(|e| BeaverError::Io(format!("Failed to read share state file {path}: {e}")))?;
+        ShareStateStream::new(format!("share state file {path}"), BufReader::new(file))
+    }
+}
+
+impl<T: Sharing, B: BufRead> ShareStateStream<T, B> {
+    fn new(source: String, reader: B) -> Result<Self, BeaverError> {
+        let mut records = Records { lines: reader.lines().enumerate(), source, position: 0 };
+
+        let (n, version) = records.header("beaver-state")?;
+        if version != [STATE_FORMAT_VERSION.to_string()] {
+            return Err(records.invalid(n, &format!("unsupported format version {}", version.join(" "))));
+        }
+
+        let (n, modulus) = records.header("modulus")?;
+        let q = match &modulus[..] {
+            [m] => m
+                .parse::<u32>()
+                .ok()
+                .filter(|q| *q >= 2)
+                .ok_or(records.invalid(n, &format!("invalid modulus {m}")))?,
+            _ => return Err(records.invalid(n, "expected a single modulus")),
+        };
+
+        let (n, scheme) = records.header("sharing")?;
+        if scheme != [T::SCHEME] {
+            return Err(records.invalid(n, &format!("the shares are {}, not {}", scheme.join(" "), T::SCHEME)));
+        }
+
+        let (n, index) = records.header("party")?;
+        let party = match &index[..] {
+            [i] if i == "1" => Party::P1,
+            [i] if i == "2" => Party::P2,
+            _ => return Err(records.invalid(n, "the party index should be 1 or 2")),
+        };
+
+        let (n, keys) = records.header("keys")?;
+        let keys = match keys.iter().map(|k| k.parse::<u32>().ok().filter(|k| *k < q)).collect::<Vec<_>>()[..] {
+            [Some(k1), Some(k2)] => (k1, k2),
+            _ => return Err(records.invalid(n, &format!("expected the shares of k1 and k2, below {q}"))),
+        };
+
+        let mut epoch = None;
+        let mut wires = HashSet::new();
+        let mut inputs = Vec::new();
+
+        // the epoch and the input sharings, up to the number of triples
+        let num_triples = loop {
+            let Some((n, line)) = records.next()? else {
+                return Err(BeaverError::Parse(format!("Invalid {}: missing triples", records.source)));
+            };
+            // the sharing starts at the first bracket, as its display contains spaces
+            let (head, sharing) = line.split_at(line.find(['(', '[']).unwrap_or(line.len()));
+
+            match head.split_whitespace().collect::<Vec<&str>>()[..] {
+                ["epoch", e] => {
+                    if epoch.is_some() {
+                        return Err(records.invalid(n, "the epoch appears more than once"));
+                    }
+                    epoch = Some(e.parse().map_err(|_| records.invalid(n, &format!("invalid key epoch {e}")))?);
+                }
+                ["input", slot, id] => {
+                    let slot = INPUT_SLOTS
+                        .iter()
+                        .position(|s| *s == slot)
+                        .ok_or(records.invalid(n, &format!("unknown input slot {slot}")))?;
+                    let wire = InputWire {
+                        party: if slot < 2 { Party::P1 } else { Party::P2 },
+                        first: slot % 2 == 0,
+                        id: id.parse().map_err(|_| records.invalid(n, "invalid gate id"))?,
+                    };
+                    if !wires.insert(wire) {
+                        return Err(records.invalid(n, &format!("the {wire} appears more than once")));
+                    }
+                    let s: T = parse_sharing(sharing)
+                        .ok_or(records.invalid(n, &format!("invalid {} sharing", T::SCHEME)))?;
+                    if s.components().iter().any(|v| *v >= q) {
+                        return Err(records.invalid(n, &format!("share components should be below {q}")));
+                    }
+                    inputs.push((wire, s));
+                }
+                ["triples", count] => {
+                    break count.parse().map_err(|_| records.invalid(n, &format!("invalid number of triples {count}")))?
+                }
+                _ => return Err(records.invalid(n, "expected epoch, input or triples record")),
+            }
+        };
+
+        let mut stream = ShareStateStream {
+            q,
+            party,
+            keys,
+            epoch,
+            inputs,
+            records,
+            num_triples,
+            consumed: 0,
+            chunk: Vec::new(),
+            failed: false,
+        };
+        if num_triples == 0 {
+            stream.check_end()?;
+        }
+
+        Ok(stream)
+    }
+
+    pub fn num_triples(&self) -> usize {
+        self.num_triples
+    }
+
+    // index of the last line read, which grows with the chunks read rather than with the size of the state
+    pub fn position(&self) -> usize {
+        self.records.position
+    }
+
+    // reads the remaining triples into a complete share state
+    pub fn into_state(mut self) -> Result<PartyShareState<T>, BeaverError> {
+        let mut triples = self.by_ref().collect::<Result<Vec<_>, _>>()?;
+        triples.reverse();
+
+        let ShareStateStream { q, party, keys, epoch, inputs, .. } = self;
+        Ok(PartyShareState { q, party, keys, epoch, inputs, triples })
+    }
+
+    // reads and checks the next chunk of triples, which must all be there along with their checksum
+    fn read_chunk(&mut self) -> Result<(), BeaverError> {
+        let size = TRIPLE_CHUNK.min(self.num_triples - self.consumed);
+        let mut lines = Vec::with_capacity(size);
+
+        while lines.len() < size {
+            match self.records.next()? {
+                Some((n, line)) if line.starts_with("triple ") || line.starts_with("triple[") => lines.push((n, line)),
+                Some((n, _)) => return Err(self.records.invalid(n, "expected triple record")),
+                None => {
+                    let read = self.consumed + lines.len();
+                    let msg = format!("it ends after {read} of the {} triples announced", self.num_triples);
+                    return Err(self.records.truncated(&msg));
+                }
+            }
+        }
+
+        let expected = chunk_checksum(lines.iter().map(|(_, l)| l.as_str()));
+        match self.records.next()? {
+            Some((n, line)) => match line.split_whitespace().collect::<Vec<&str>>()[..] {
+                ["checksum", c] if c == expected => (),
+                ["checksum", _] => {
+                    return Err(self.records.invalid(n, "the triples of the chunk do not match its checksum"));
+                }
+                _ => return Err(self.records.invalid(n, "expected the checksum of the chunk")),
+            },
+            None => return Err(self.records.truncated("it ends before the checksum of its last chunk of triples")),
+        }
+
+        for (n, line) in lines.into_iter().rev() {
+            let t: BeaverSharing<T> = parse_triple(&line["triple".len()..])
+                .ok_or(self.records.invalid(n, &format!("invalid triple of {} sharings", T::SCHEME)))?;
+            if t.components().iter().any(|v| *v >= self.q) {
+                return Err(self.records.invalid(n, &format!("share components should be below {}", self.q)));
+            }
+            self.chunk.push(t);
+        }
+
+        if self.consumed + size == self.num_triples {
+            self.check_end()?;
+        }
+
+        Ok(())
+    }
+
+    fn check_end(&mut self) -> Result<(), BeaverError> {
+        match self.records.next()? {
+            Some((n, _)) => {
+                Err(self.records.invalid(n, &format!("more than the {} triples announced", self.num_triples)))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl<T: Sharing, B: BufRead> Iterator for ShareStateStream<T, B> {
+    type Item = Result<BeaverSharing<T>, BeaverError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        if self.chunk.is_empty() && self.consumed < self.num_triples {
+            if let Err(e) = self.read_chunk() {
+                self.failed = true;
+                return Some(Err(e));
+            }
+        }
+
+        let t = self.chunk.pop()?;
+        self.consumed += 1;
+        Some(Ok(t))
+    }
+}
+
+// a sharing as displayed, e.g. (3, 14, 15), with optionally labelled components as in (v=3, MAC_k1=14, MAC_k2=15)
+fn parse_sharing<T: Sharing>(s: &str) -> Option<T> {
+    let inner = s.trim().strip_prefix('(')?.strip_suffix(')')?.trim();
+    let c = match inner {
+        "" => Vec::new(),
+        _ => inner
+            .split(',')
+            .map(|t| t.rsplit('=').next().unwrap_or(t).trim().parse().ok())
+            .collect::<Option<Vec<u32>>>()?,
+    };
+    T::from_components(&c)
+}
+
+// a triple as displayed, e.g. [(3), (1), (4)]
+fn parse_triple<T: Sharing>(s: &str) -> Option<BeaverSharing<T>> {
+    let inner = s.trim().strip_prefix('[')?.strip_suffix(']')?.trim();
+    let sharings = inner
+        .split_inclusive(')')
+        .map(|p| parse_sharing(p.trim_start_matches([',', ' '])))
+        .collect::<Option<Vec<T>>>()?;
+    match <[T; 3]>::try_from(sharings) {
+        Ok([a, b, c]) => Some(BeaverSharing(a, b, c)),
+        Err(_) => None,
+    }
+}
+
+pub fn write_share_state<T: Sharing>(path: &str, state: &PartyShareState<T>) -> Result<(), BeaverError> {
+    fs::write(path, state.to_string())
+        .map_err(|e| BeaverError::Io(format!("Failed to write share state file {path}: {e}")))
+}
+
+// reads the whole state at once; see ShareStateStream to read its triples lazily
+pub fn read_share_state<T: Sharing>(path: &str) -> Result<PartyShareState<T>, BeaverError> {
+    ShareStateStream::open(path)?.into_state()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+    use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+
+    use super::*;
+    use crate::sharing::{AuthSharing, UnauthSharing};
+
+    const Q: u32 = 2147483647;
+
+    // a state of P1 with the given number of random authenticated triples
+    fn state(num_triples: usize) -> PartyShareState<AuthSharing> {
+        let rng = &mut ChaCha20Rng::seed_from_u64(7);
+        let triples = (0..num_triples)
+            .map(|_| {
+                let c: Vec<u32> = (0..9).map(|_| rng.gen_range(0..Q)).collect();
+                BeaverSharing::from_components(&c).unwrap()
+            })
+            .collect();
+        PartyShareState { q: Q, party: Party::P1, keys: (3, 5), epoch: None, inputs: Vec::new(), triples }
+    }
+
+    fn temp_path(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("beaver_{name}_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("p1.state").to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn truncated_state_fails_cleanly() {
+        let path = temp_path("truncated_state");
+        let original = state(10_000);
+        let contents = original.to_string();
+
+        // cut in the middle of a chunk, at the end of a chunk and right after the checksum of one
+        let third_chunk = contents.match_indices("checksum").nth(2).unwrap().0;
+        let cuts = [contents.len() * 3 / 5, third_chunk, third_chunk + "checksum".len() + 18];
+
+        for (cut, msg) in cuts.into_iter().zip([
+            "truncated file, it ends after",
+            "truncated file, it ends before the checksum",
+            "truncated file, it ends after 3072 of the 10000 triples announced",
+        ]) {
+            fs::write(&path, &contents[..cut]).unwrap();
+
+            let results: Vec<_> = ShareStateStream::<AuthSharing, _>::open(&path).unwrap().collect();
+            let (last, triples) = results.split_last().unwrap();
+
+            // every triple handed out comes from a complete chunk, and is the one in the original state
+            assert_eq!(triples.len() % TRIPLE_CHUNK, 0);
+            for (t, expected) in triples.iter().zip(original.triples.iter().rev()) {
+                assert_eq!(t.as_ref().unwrap().components(), expected.components());
+            }
+            let e = last.as_ref().err().unwrap().to_string();
+            assert!(e.contains(msg), "{e}");
+
+            assert!(read_share_state::<AuthSharing>(&path).is_err());
+        }
+
+        // altering a triple is caught by the checksum of its chunk before any of its triples is handed out
+        let tampered = contents.replacen("triple [(", "triple [(1", 1);
+        fs::write(&path, tampered).unwrap();
+        let mut stream = ShareStateStream::<AuthSharing, _>::open(&path).unwrap();
+        let e = stream.next().unwrap().err().unwrap().to_string();
+        assert!(e.contains("do not match its checksum"), "{e}");
+        assert!(stream.next().is_none());
+
+        fs::remove_dir_all(std::path::Path::new(&path).parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn triples_are_loaded_incrementally() {
+        let path = temp_path("incremental_state");
+        let original = state(100_000);
+        write_share_state(&path, &original).unwrap();
+
+        let mut stream = ShareStateStream::<AuthSharing, _>::open(&path).unwrap();
+        assert_eq!(stream.num_triples(), 100_000);
+        let header = stream.position();
+
+        // a single chunk is read for the first triple, and the next one only once the first is used up
+        let first = stream.next().unwrap().unwrap();
+        assert_eq!(first.components(), original.triples.last().unwrap().components());
+        assert_eq!(stream.position(), header + TRIPLE_CHUNK + 1);
+        assert_eq!(stream.chunk.len(), TRIPLE_CHUNK - 1);
+
+        for _ in 1..TRIPLE_CHUNK {
+            stream.next().unwrap().unwrap();
+        }
+        assert_eq!(stream.position(), header + TRIPLE_CHUNK + 1);
+        stream.next().unwrap().unwrap();
+        assert_eq!(stream.position(), header + 2 * (TRIPLE_CHUNK + 1));
+
+        // the rest of the state reads as the original one
+        let rest = stream.into_state().unwrap();
+        assert_eq!(rest.triples.len(), 100_000 - TRIPLE_CHUNK - 1);
+        for (t, expected) in rest.triples.iter().zip(&original.triples) {
+            assert_eq!(t.components(), expected.components());
+        }
+
+        fs::remove_dir_all(std::path::Path::new(&path).parent().unwrap()).unwrap();
+    }
+
+    // exports random shares of the given party for a few scattered wires, and checks that they import unchanged
+    fn round_trip<T: Sharing>(path: &str, party: Party, num_components: usize) {
+        let rng = &mut ChaCha20Rng::seed_from_u64(11);
+        let shares: Vec<(u32, T)> = [0, 7, 3, 1000, u32::MAX]
+            .into_iter()
+            .map(|id| {
+                let c: Vec<u32> = (0..num_components).map(|_| rng.gen_range(0..Q)).collect();
+                (id, T::from_components(&c).unwrap())
+            })
+            .collect();
+
+        export_shares(path, Q, party, &shares).unwrap();
+        let imported = import_shares::<T>(path, Q, party).unwrap();
+
+        assert_eq!(imported.len(), shares.len());
+        for ((id, s), (expected_id, expected)) in imported.iter().zip(&shares) {
+            assert_eq!(id, expected_id);
+            assert_eq!(s.components(), expected.components());
+        }
+    }
+
+    #[test]
+    fn exported_shares_import_unchanged() {
+        let path = temp_path("share_file");
+
+        for party in [Party::P1, Party::P2] {
+            round_trip::<UnauthSharing>(&path, party, 1);
+            round_trip::<AuthSharing>(&path, party, 3);
+        }
+
+        // an empty share file is valid too
+        export_shares::<AuthSharing>(&path, Q, Party::P1, &[]).unwrap();
+        assert!(import_shares::<AuthSharing>(&path, Q, Party::P1).unwrap().is_empty());
+
+        fs::remove_dir_all(std::path::Path::new(&path).parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn mismatched_share_files_are_refused() {
+        let path = temp_path("share_file_mismatch");
+        let shares = [(4, AuthSharing::from_components(&[1, 2, 3]).unwrap())];
+        export_shares(&path, Q, Party::P1, &shares).unwrap();
+
+        let e = import_shares::<AuthSharing>(&path, 101, Party::P1).err().unwrap().to_string();
+        assert!(e.contains("at line 2: the shares are modulo 2147483647, not 101"), "{e}");
+
+        let e = import_shares::<UnauthSharing>(&path, Q, Party::P1).err().unwrap().to_string();
+        assert!(e.contains("at line 3: the shares are additive-mac, not additive"), "{e}");
+
+        let e = import_shares::<AuthSharing>(&path, Q, Party::P2).err().unwrap().to_string();
+        assert!(e.contains("at line 4: expected a share of P2, found party index 1"), "{e}");
+
+        // components must be below the modulus the file claims
+        fs::write(&path, "beaver-shares 1\nmodulus 101\nsharing additive\nshare 0 1 101\n").unwrap();
+        let e = import_shares::<UnauthSharing>(&path, 101, Party::P1).err().unwrap().to_string();
+        assert!(e.contains("at line 4: share components should be below 101"), "{e}");
+
+        fs::remove_dir_all(std::path::Path::new(&path).parent().unwrap()).unwrap();
+    }
+
+    // a state of P2 in a session, with an input in every slot and random sharings with the given number of components
+    fn full_state<T: Sharing>(num_components: usize, num_triples: usize) -> PartyShareState<T> {
+        let rng = &mut ChaCha20Rng::seed_from_u64(13);
+        let mut sharing = || T::from_components(&(0..num_components).map(|_| rng.gen_range(0..Q)).collect::<Vec<_>>());
+        let inputs = [(Party::P1, true, 0), (Party::P1, false, 5), (Party::P2, true, 2), (Party::P2, false, 9)]
+            .into_iter()
+            .map(|(party, first, id)| (InputWire { party, first, id }, sharing().unwrap()))
+            .collect();
+        let triples = (0..num_triples)
+            .map(|_| BeaverSharing(sharing().unwrap(), sharing().unwrap(), sharing().unwrap()))
+            .collect();
+        PartyShareState { q: Q, party: Party::P2, keys: (Q - 1, 0), epoch: Some(4), inputs, triples }
+    }
+
+    fn assert_same_state<T: Sharing>(state: &PartyShareState<T>, expected: &PartyShareState<T>) {
+        assert_eq!((state.q, state.party, state.keys), (expected.q, expected.party, expected.keys));
+        assert_eq!(state.epoch, expected.epoch);
+        assert_eq!(state.inputs.len(), expected.inputs.len());
+        for ((wire, s), (expected_wire, expected_s)) in state.inputs.iter().zip(&expected.inputs) {
+            assert_eq!(wire, expected_wire);
+            assert_eq!(s.components(), expected_s.components());
+        }
+        assert_eq!(state.triples.len(), expected.triples.len());
+        for (t, expected_t) in state.triples.iter().zip(&expected.triples) {
+            assert_eq!(t.components(), expected_t.components());
+        }
+    }
+
+    fn text_round_trip<T: Sharing>(num_components: usize) {
+        // spanning several chunks, the last of which is partial
+        let original = full_state::<T>(num_components, 2 * TRIPLE_CHUNK + 5);
+        let text = original.to_string();
+
+        let parsed: PartyShareState<T> = text.parse().unwrap();
+        assert_same_state(&parsed, &original);
+        assert_eq!(parsed.to_string(), text);
+
+        // as do the empty state and one outside of any session
+        let empty = PartyShareState::<T> { epoch: None, inputs: Vec::new(), triples: Vec::new(), ..original };
+        let parsed: PartyShareState<T> = empty.to_string().parse().unwrap();
+        assert_same_state(&parsed, &empty);
+    }
+
+    #[test]
+    fn share_states_read_back_as_written() {
+        text_round_trip::<UnauthSharing>(1);
+        text_round_trip::<AuthSharing>(3);
+
+        // through a file too
+        let path = temp_path("state_round_trip");
+        let original = full_state::<AuthSharing>(3, 10);
+        write_share_state(&path, &original).unwrap();
+        assert_same_state(&read_share_state(&path).unwrap(), &original);
+        fs::remove_dir_all(std::path::Path::new(&path).parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn hand_written_share_states_are_read() {
+        // comments, blank lines, extra spaces and the verbose form of the logs for the inputs
+        let original = full_state::<AuthSharing>(3, 2);
+        let mut text = String::from("# written by hand\n\n");
+        for line in original.to_string().lines() {
+            let line = match line.strip_prefix("input ") {
+                Some(record) => {
+                    let (prefix, _) = record.rsplit_once(" (").unwrap();
+                    let slot = INPUT_SLOTS.iter().position(|s| prefix.starts_with(s)).unwrap();
+                    format!("input  {prefix}   {:#}  # slot {slot}", original.inputs[slot].1)
+                }
+                None => line.to_string(),
+            };
+            text.push_str(&format!("{line}\n\n"));
+        }
+        assert!(text.contains("(v="), "{text}");
+
+        // the triple records are covered by the checksums as written, so they are kept as they are
+        let parsed: PartyShareState<AuthSharing> = text.parse().unwrap();
+        assert_same_state(&parsed, &original);
+
+        let unauth: PartyShareState<UnauthSharing> = "beaver-state 2\nmodulus 101\nsharing additive\nparty 1\n\
+                                                      keys 3 4\ninput p2_second 7 (100)\ntriples 0\n"
+            .parse()
+            .unwrap();
+        assert_eq!(unauth.inputs[0].0, InputWire { party: Party::P2, first: false, id: 7 });
+        assert_eq!(unauth.inputs[0].1.components(), vec![100]);
+    }
+}
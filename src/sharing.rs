@@ -4,12 +4,19 @@ use std::fmt::{self, Display};
 use rand_chacha::ChaCha20Rng;
 
 use crate::circuit::*;
-use crate::utilities;
-use crate::utilities::safe_gen_mod;
-use crate::utilities::subtract_without_overflow;
+use crate::field::{FieldOps, ModField, Reducer};
 
-pub struct UnauthSharing(u32);
+// an additive sharing over any field context: the protocol runs over ModField, whose sharings implement Sharing,
+// while the generic operations below also serve wider elements such as those of F64
+pub struct UnauthSharing<F: FieldOps = ModField>(F::Elem);
 
+// MAC model: the dealer samples two global keys k1 and k2 and gives each party additive sharings of both. During
+// key opening, P2 sends its share of k1 to P1 and P1 sends its share of k2 to P2, so that P1 alone learns k1 and
+// P2 alone learns k2. A sharing of x is then a triple (x_i, m1_i, m2_i) held by each party i, where the m1_i add
+// up to k1 * x and the m2_i to k2 * x. A party opening its share to P1 sends (x_i, m1_i, 0): P1 adds it to its own
+// share and checks m1 == k1 * x under the key only it knows, while P2's sharing of the MAC under k2 stays hidden
+// from P1 (and vice versa when opening to P2). Thus each component of the MAC is only ever checked by the party
+// owning the corresponding key, and a forged opening passes the check with probability 1/q.
 pub struct AuthSharing (
     UnauthSharing,
     UnauthSharing,
@@ -17,55 +24,73 @@ pub struct AuthSharing (
 );
 
 pub trait Sharing: Sized + Display + Send {
+    // name of the sharing scheme in share files
+    const SCHEME: &'static str;
     // the key is only used if the implementing type corresponds to an authenticated sharing
-    fn share(v: u32, k1: u32, k2: u32, rng: &mut ChaCha20Rng, q: u32) -> (Self, Self);
-    fn beaver_share(k1: u32, k2: u32, q: u32, rng: &mut ChaCha20Rng) -> (BeaverSharing<Self>, BeaverSharing<Self>);
-    fn add(s1: &Self, s2: &Self, q: u32) -> Self;
+    fn share(v: u32, k1: u32, k2: u32, rng: &mut ChaCha20Rng, f: &ModField) -> (Self, Self);
+    fn beaver_share(k1: u32, k2: u32, f: &ModField, rng: &mut ChaCha20Rng) -> (BeaverSharing<Self>, BeaverSharing<Self>);
+    fn add(s1: &Self, s2: &Self, f: &ModField) -> Self;
     // addc adds the given constant - regardless of which party calls it
-    fn addc(s: &Self, c: u32, k1: u32, k2: u32, q: u32, party: Party) -> Self;
+    fn addc(s: &Self, c: u32, k1: u32, k2: u32, f: &ModField, party: Party) -> Self;
+    // the party's share of a value both parties know, built locally as that constant added to a sharing of 0
+    fn public(v: u32, k1: u32, k2: u32, f: &ModField, party: Party) -> Self;
     // componentwise multiplication, not protocol for multiplication of gates
-    fn mulc(s: &Self, c: u32, q: u32) -> Self;
-    fn complement(&self, q: u32) -> Self;
-    fn subtract(s1: &Self, s2: &Self, q: u32) -> Self {
-        Self::add(s1, &s2.complement(q), q)
+    fn mulc(s: &Self, c: u32, f: &ModField) -> Self;
+    fn complement(&self, f: &ModField) -> Self;
+    // overridden by the sharings with data, which subtract componentwise without building the complement
+    fn subtract(s1: &Self, s2: &Self, f: &ModField) -> Self {
+        Self::add(s1, &s2.complement(f), f)
     }
-    fn authenticate(&self, key: u32, q: u32, party: Party) -> bool;
+    fn authenticate(&self, key: u32, f: &ModField, party: Party) -> bool;
     fn opened(&self, to: Party) -> Self;
     fn value(&self) -> u32;
+    // the MAC the given party checks the value against, for sharings which have one
+    fn mac(&self, party: Party) -> Option<u32>;
     //used only to simulate corrupt parties
     fn tweaked(&self) -> Self;
+    // the values making up the sharing, in a fixed order, for serialisation
+    fn components(&self) -> Vec<u32>;
+    fn from_components(c: &[u32]) -> Option<Self>;
 }
 
 impl Sharing for UnauthSharing {
-    fn share(v: u32, _k1: u32, _k2: u32, rng: &mut ChaCha20Rng, q: u32) -> (Self, Self) {
-        let r = utilities::safe_gen_mod(rng, q);
-        (Self(r), Self(subtract_without_overflow(v, r, q)))
+    const SCHEME: &'static str = "additive";
+    fn share(v: u32, _k1: u32, _k2: u32, rng: &mut ChaCha20Rng, f: &ModField) -> (Self, Self) {
+        Self::share_in(f, v, rng)
     }
-    fn beaver_share(_k1: u32, _k2: u32, q: u32, rng: &mut ChaCha20Rng) -> (BeaverSharing<Self>, BeaverSharing<Self>) {
-        let a = safe_gen_mod(rng, q);
-        let b = safe_gen_mod(rng, q);
-        let (a1, a2) = Self::share(a, 0, 0, rng, q); // key not used in unauth sharings
-        let (b1, b2) = Self::share(b, 0, 0, rng, q);
-        let (c1, c2) = Self::share(utilities::mul_without_overflow(a, b, q), 0, 0, rng, q);
+    // the dealer generates triples by the million, hence the Reducer
+    fn beaver_share(_k1: u32, _k2: u32, f: &ModField, rng: &mut ChaCha20Rng) -> (BeaverSharing<Self>, BeaverSharing<Self>) {
+        let f = Reducer::new(f.q);
+        let a = f.random(rng);
+        let b = f.random(rng);
+        let (a1, a2) = Self::share_in(&f, a, rng); // key not used in unauth sharings
+        let (b1, b2) = Self::share_in(&f, b, rng);
+        let (c1, c2) = Self::share_in(&f, f.mul(a, b), rng);
 
         (BeaverSharing(a1, b1, c1), BeaverSharing(a2, b2, c2))
     }
-    fn add(&Self(v1): &Self, &Self(v2): &Self, q: u32) -> Self {
-        Self(utilities::add_without_overflow(v1, v2, q))
+    fn add(s1: &Self, s2: &Self, f: &ModField) -> Self {
+        s1.add_in(s2, f)
     }
-    fn addc(&Self(v): &Self, c: u32, _k1: u32, _k2: u32, q: u32, party: Party) -> Self {
+    fn addc(&Self(v): &Self, c: u32, _k1: u32, _k2: u32, f: &ModField, party: Party) -> Self {
         match party {
-            Party::P1 => Self(utilities::add_without_overflow(v, c, q)),
+            Party::P1 => Self(f.add(v, c)),
             Party::P2 => Self(v),
         }
     }
-    fn mulc(&Self(v): &Self, c: u32, q: u32) -> Self {
-        Self(utilities::mul_without_overflow(v, c, q) as u32)
+    fn public(v: u32, k1: u32, k2: u32, f: &ModField, party: Party) -> Self {
+        Self::addc(&Self(0), v, k1, k2, f, party)
     }
-    fn complement(&self, q: u32) -> Self {
-        Self(subtract_without_overflow(0, self.0, q))
+    fn mulc(s: &Self, c: u32, f: &ModField) -> Self {
+        s.mulc_in(c, f)
     }
-    fn authenticate(&self, _key: u32, _q: u32, _party: Party) -> bool {
+    fn complement(&self, f: &ModField) -> Self {
+        self.neg_in(f)
+    }
+    fn subtract(s1: &Self, s2: &Self, f: &ModField) -> Self {
+        s1.sub_in(s2, f)
+    }
+    fn authenticate(&self, _key: u32, _f: &ModField, _party: Party) -> bool {
         true
     }
     fn opened(&self, _to: Party) -> Self {
@@ -74,65 +99,115 @@ impl Sharing for UnauthSharing {
     fn value(&self) -> u32 {
         self.0
     }
+    fn mac(&self, _party: Party) -> Option<u32> {
+        None
+    }
     fn tweaked(&self) -> Self {
         Self(self.0 + 1)
     }
+    fn components(&self) -> Vec<u32> {
+        vec![self.0]
+    }
+    fn from_components(c: &[u32]) -> Option<Self> {
+        match c {
+            [v] => Some(Self(*v)),
+            _ => None,
+        }
+    }
+}
+
+impl<F: FieldOps> UnauthSharing<F> {
+    pub fn new(v: F::Elem) -> Self {
+        Self(v)
+    }
+    // share in any field context over the same elements, so that callers sharing many values can pick a faster one
+    pub fn share_in<G: FieldOps<Elem = F::Elem>>(f: &G, v: F::Elem, rng: &mut ChaCha20Rng) -> (Self, Self) {
+        let r = f.random(rng);
+        (Self(r), Self(f.sub(v, r)))
+    }
+    pub fn add_in(&self, other: &Self, f: &F) -> Self {
+        Self(f.add(self.0, other.0))
+    }
+    pub fn sub_in(&self, other: &Self, f: &F) -> Self {
+        Self(f.sub(self.0, other.0))
+    }
+    pub fn mulc_in(&self, c: F::Elem, f: &F) -> Self {
+        Self(f.mul(self.0, c))
+    }
+    pub fn neg_in(&self, f: &F) -> Self {
+        Self(f.neg(self.0))
+    }
+    // the value shared by this sharing and the other party's
+    pub fn open_in(&self, other: &Self, f: &F) -> F::Elem {
+        f.add(self.0, other.0)
+    }
 }
 
-impl fmt::Display for UnauthSharing {
+impl<F: FieldOps> fmt::Display for UnauthSharing<F>
+where
+    F::Elem: Display,
+{
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "({})", self.0)
     }
 }
 
 impl Sharing for AuthSharing{
-    fn share(v: u32, k1: u32, k2: u32, rng: &mut ChaCha20Rng, q: u32) -> (Self, Self) {
-        
-        let (x1, x2) = UnauthSharing::share(v, 0, 0, rng, q);
-        let (x11, x12) = UnauthSharing::share(utilities::mul_without_overflow(v, k1, q), 0, 0, rng, q);
-        let (x21, x22) = UnauthSharing::share(utilities::mul_without_overflow(v, k2, q), 0, 0, rng, q);
-
-        (Self(x1, x11, x21), Self(x2, x12, x22))
+    const SCHEME: &'static str = "additive-mac";
+    fn share(v: u32, k1: u32, k2: u32, rng: &mut ChaCha20Rng, f: &ModField) -> (Self, Self) {
+        Self::share_in(f, v, k1, k2, rng)
     }
-    fn beaver_share(k1: u32, k2: u32, q: u32, rng: &mut ChaCha20Rng) -> (BeaverSharing<Self>, BeaverSharing<Self>) {
-        let a = utilities::safe_gen_mod(rng, q);
-        let b = utilities::safe_gen_mod(rng, q);
+    // see UnauthSharing::beaver_share
+    fn beaver_share(k1: u32, k2: u32, f: &ModField, rng: &mut ChaCha20Rng) -> (BeaverSharing<Self>, BeaverSharing<Self>) {
+        let f = Reducer::new(f.q);
+        let a = f.random(rng);
+        let b = f.random(rng);
 
-        let (a1, a2) = Self::share(a, k1, k2, rng, q);
-        let (b1, b2) = Self::share(b, k1, k2, rng, q);
-        let (c1, c2) = Self::share(utilities::mul_without_overflow(a, b, q), k1, k2, rng, q);
+        let (a1, a2) = Self::share_in(&f, a, k1, k2, rng);
+        let (b1, b2) = Self::share_in(&f, b, k1, k2, rng);
+        let (c1, c2) = Self::share_in(&f, f.mul(a, b), k1, k2, rng);
 
         (BeaverSharing(a1, b1, c1), BeaverSharing(a2, b2, c2))
     }
-    fn add(Self(s_1, x1_1, x2_1): &Self, Self(s_2, x1_2, x2_2): &Self, q: u32) -> Self {
+    fn add(Self(s_1, x1_1, x2_1): &Self, Self(s_2, x1_2, x2_2): &Self, f: &ModField) -> Self {
         Self(
-            UnauthSharing::add(s_1, s_2, q),
-            UnauthSharing::add(x1_1, x1_2, q),
-            UnauthSharing::add(x2_1, x2_2, q),
+            UnauthSharing::add(s_1, s_2, f),
+            UnauthSharing::add(x1_1, x1_2, f),
+            UnauthSharing::add(x2_1, x2_2, f),
         )
     }
-    fn addc(s: &Self, c: u32, k1: u32, k2: u32, q: u32, party: Party) -> Self {
+    fn addc(s: &Self, c: u32, k1: u32, k2: u32, f: &ModField, party: Party) -> Self {
         Self::add(s, &Self(
             UnauthSharing(if party == Party::P1 {c} else {0}),
-            UnauthSharing(utilities::mul_without_overflow(k1, c, q)),
-            UnauthSharing(utilities::mul_without_overflow(k2, c, q)),
-        ), q)
+            UnauthSharing(f.mul(k1, c)),
+            UnauthSharing(f.mul(k2, c)),
+        ), f)
+    }
+    fn public(v: u32, k1: u32, k2: u32, f: &ModField, party: Party) -> Self {
+        Self::addc(&Self(UnauthSharing(0), UnauthSharing(0), UnauthSharing(0)), v, k1, k2, f, party)
     }
-    fn mulc(s: &Self, c: u32, q: u32) -> Self {
+    fn mulc(s: &Self, c: u32, f: &ModField) -> Self {
         Self(
-            UnauthSharing::mulc(&s.0, c, q),
-            UnauthSharing::mulc(&s.1, c, q),
-            UnauthSharing::mulc(&s.2, c, q),
+            UnauthSharing::mulc(&s.0, c, f),
+            UnauthSharing::mulc(&s.1, c, f),
+            UnauthSharing::mulc(&s.2, c, f),
         )
     }
-    fn complement(&self, q: u32) -> Self {
-        Self(self.0.complement(q), self.1.complement(q), self.2.complement(q))
+    fn complement(&self, f: &ModField) -> Self {
+        Self(self.0.complement(f), self.1.complement(f), self.2.complement(f))
     }
-    fn authenticate(&self, key: u32, q: u32, party: Party) -> bool {
+    fn subtract(s1: &Self, s2: &Self, f: &ModField) -> Self {
+        Self(
+            UnauthSharing::subtract(&s1.0, &s2.0, f),
+            UnauthSharing::subtract(&s1.1, &s2.1, f),
+            UnauthSharing::subtract(&s1.2, &s2.2, f),
+        )
+    }
+    fn authenticate(&self, key: u32, f: &ModField, party: Party) -> bool {
         match party {
             // avoid subtraction to prevent overflow of unsigned
-            Party::P1 => self.1.0 % q == utilities::mul_without_overflow(self.0.0, key, q),
-            Party::P2 => self.2.0 % q == utilities::mul_without_overflow(self.0.0, key, q),
+            Party::P1 => self.1.0 % f.q == f.mul(self.0.0, key),
+            Party::P2 => self.2.0 % f.q == f.mul(self.0.0, key),
         }  
     }
     fn opened(&self, to: Party) -> Self {
@@ -145,18 +220,112 @@ impl Sharing for AuthSharing{
     fn value(&self) -> u32 {
         self.0.0
     }
+    fn mac(&self, party: Party) -> Option<u32> {
+        match party {
+            Party::P1 => Some(self.1.0),
+            Party::P2 => Some(self.2.0),
+        }
+    }
+    // the MACs are left untouched, as a corrupt party does not know the key needed to fix them
     fn tweaked(&self) -> Self {
         Self(
             self.0.tweaked(),
             UnauthSharing(self.1.0),
-            UnauthSharing(self.1.0),
+            UnauthSharing(self.2.0),
         )
     }
+    fn components(&self) -> Vec<u32> {
+        vec![self.0.0, self.1.0, self.2.0]
+    }
+    fn from_components(c: &[u32]) -> Option<Self> {
+        match c {
+            [v, x1, x2] => Some(Self(UnauthSharing(*v), UnauthSharing(*x1), UnauthSharing(*x2))),
+            _ => None,
+        }
+    }
 }
 
+impl AuthSharing {
+    fn share_in<F: FieldOps<Elem = u32>>(f: &F, v: u32, k1: u32, k2: u32, rng: &mut ChaCha20Rng) -> (Self, Self) {
+        let (x1, x2) = UnauthSharing::share_in(f, v, rng);
+        let (x11, x12) = UnauthSharing::share_in(f, f.mul(v, k1), rng);
+        let (x21, x22) = UnauthSharing::share_in(f, f.mul(v, k2), rng);
+
+        (Self(x1, x11, x21), Self(x2, x12, x22))
+    }
+    // the components labelled, for readers unfamiliar with the structure of authenticated sharings
+    pub fn fmt_verbose(&self) -> String {
+        format!("(v={}, MAC_k1={}, MAC_k2={})", self.0.0, self.1.0, self.2.0)
+    }
+}
+
+// the alternate form {:#} is the verbose one, which the sharings below pass on to their components
 impl fmt::Display for AuthSharing {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "({}, {}, {})", self.0.0, self.1.0, self.2.0)
+        if f.alternate() {
+            write!(f, "{}", self.fmt_verbose())
+        } else {
+            write!(f, "({}, {}, {})", self.0.0, self.1.0, self.2.0)
+        }
+    }
+}
+
+// a sharing carrying no data whose operations are all no-ops. running the protocol with it measures the
+// cost of the topology traversal, wire lookups and channel messaging alone, without any field arithmetic
+#[cfg(feature = "benchmarking")]
+pub struct NullSharing;
+
+#[cfg(feature = "benchmarking")]
+impl Sharing for NullSharing {
+    const SCHEME: &'static str = "null";
+    fn share(_v: u32, _k1: u32, _k2: u32, _rng: &mut ChaCha20Rng, _f: &ModField) -> (Self, Self) {
+        (Self, Self)
+    }
+    fn beaver_share(_k1: u32, _k2: u32, _f: &ModField, _rng: &mut ChaCha20Rng) -> (BeaverSharing<Self>, BeaverSharing<Self>) {
+        (BeaverSharing(Self, Self, Self), BeaverSharing(Self, Self, Self))
+    }
+    fn add(_s1: &Self, _s2: &Self, _f: &ModField) -> Self {
+        Self
+    }
+    fn addc(_s: &Self, _c: u32, _k1: u32, _k2: u32, _f: &ModField, _party: Party) -> Self {
+        Self
+    }
+    fn public(_v: u32, _k1: u32, _k2: u32, _f: &ModField, _party: Party) -> Self {
+        Self
+    }
+    fn mulc(_s: &Self, _c: u32, _f: &ModField) -> Self {
+        Self
+    }
+    fn complement(&self, _f: &ModField) -> Self {
+        Self
+    }
+    fn authenticate(&self, _key: u32, _f: &ModField, _party: Party) -> bool {
+        true
+    }
+    fn opened(&self, _to: Party) -> Self {
+        Self
+    }
+    fn value(&self) -> u32 {
+        0
+    }
+    fn mac(&self, _party: Party) -> Option<u32> {
+        None
+    }
+    fn tweaked(&self) -> Self {
+        Self
+    }
+    fn components(&self) -> Vec<u32> {
+        vec![]
+    }
+    fn from_components(c: &[u32]) -> Option<Self> {
+        if c.is_empty() { Some(Self) } else { None }
+    }
+}
+
+#[cfg(feature = "benchmarking")]
+impl fmt::Display for NullSharing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "()")
     }
 }
 
@@ -164,8 +333,305 @@ impl fmt::Display for AuthSharing {
 // the Sharing functionality is not required for Beaver triple sharings 
 pub struct BeaverSharing<T: Sharing> (pub T, pub T, pub T);
 
+impl<T: Sharing> BeaverSharing<T> {
+    pub fn components(&self) -> Vec<u32> {
+        let mut c = self.0.components();
+        c.extend(self.1.components());
+        c.extend(self.2.components());
+        c
+    }
+    // the three sharings of a triple have the same number of components
+    pub fn from_components(c: &[u32]) -> Option<Self> {
+        if !c.len().is_multiple_of(3) {
+            return None;
+        }
+        let n = c.len() / 3;
+        Some(BeaverSharing(
+            T::from_components(&c[..n])?,
+            T::from_components(&c[n..2 * n])?,
+            T::from_components(&c[2 * n..])?,
+        ))
+    }
+}
+
 impl<T: Sharing> fmt::Display for BeaverSharing<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "[{}, {}, {}]", self.0, self.1, self.2)
+        let show = shower(f);
+        write!(f, "[{}, {}, {}]", show(&self.0), show(&self.1), show(&self.2))
+    }
+}
+
+// formats sharings in the same form (plain or alternate) as the formatter
+fn shower<T: Sharing>(f: &fmt::Formatter<'_>) -> impl Fn(&T) -> String {
+    let alternate = f.alternate();
+    move |s| if alternate { format!("{s:#}") } else { s.to_string() }
+}
+
+// sharings of random vectors a and b together with one of their inner product c = <a, b>, consumed by a dot
+// gate of the same length
+pub struct InnerProductSharing<T: Sharing>(pub Vec<T>, pub Vec<T>, pub T);
+
+impl<T: Sharing> InnerProductSharing<T> {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    // components of a, then of b, then of c
+    pub fn components(&self) -> Vec<u32> {
+        let mut c: Vec<u32> = self.0.iter().chain(&self.1).flat_map(|s| s.components()).collect();
+        c.extend(self.2.components());
+        c
+    }
+    // all 2 * len + 1 sharings have the same number of components
+    pub fn from_components(len: usize, c: &[u32]) -> Option<Self> {
+        if !c.len().is_multiple_of(2 * len + 1) {
+            return None;
+        }
+        let n = c.len() / (2 * len + 1);
+        let sharing = |i: usize| T::from_components(&c[i * n..(i + 1) * n]);
+        Some(InnerProductSharing(
+            (0..len).map(sharing).collect::<Option<Vec<T>>>()?,
+            (len..2 * len).map(sharing).collect::<Option<Vec<T>>>()?,
+            sharing(2 * len)?,
+        ))
+    }
+}
+
+impl<T: Sharing> fmt::Display for InnerProductSharing<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = shower(f);
+        let join = |v: &Vec<T>| v.iter().map(&show).collect::<Vec<String>>().join(", ");
+        write!(f, "[[{}], [{}], {}]", join(&self.0), join(&self.1), show(&self.2))
+    }
+}
+
+// sharings of a random r and of r' = r / 2^f (rounded down), consumed by a truncation gate with shift f
+pub struct TruncationSharing<T: Sharing>(pub T, pub T);
+
+impl<T: Sharing> TruncationSharing<T> {
+    pub fn components(&self) -> Vec<u32> {
+        let mut c = self.0.components();
+        c.extend(self.1.components());
+        c
+    }
+    pub fn from_components(c: &[u32]) -> Option<Self> {
+        if !c.len().is_multiple_of(2) {
+            return None;
+        }
+        let n = c.len() / 2;
+        Some(TruncationSharing(T::from_components(&c[..n])?, T::from_components(&c[n..])?))
+    }
+}
+
+impl<T: Sharing> fmt::Display for TruncationSharing<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = shower(f);
+        write!(f, "[{}, {}]", show(&self.0), show(&self.1))
+    }
+}
+
+// sharings of random bits r_0, ..., r_(k - 2) and of a random high part h, making up r = h * 2^(k - 1) + sum(r_i * 2^i),
+// consumed by a comparison gate of bit length k
+pub struct ComparisonSharing<T: Sharing>(pub Vec<T>, pub T);
+
+impl<T: Sharing> ComparisonSharing<T> {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    // components of the bits, then of the high part
+    pub fn components(&self) -> Vec<u32> {
+        let mut c: Vec<u32> = self.0.iter().flat_map(|s| s.components()).collect();
+        c.extend(self.1.components());
+        c
+    }
+    // all len + 1 sharings have the same number of components
+    pub fn from_components(len: usize, c: &[u32]) -> Option<Self> {
+        if !c.len().is_multiple_of(len + 1) {
+            return None;
+        }
+        let n = c.len() / (len + 1);
+        let sharing = |i: usize| T::from_components(&c[i * n..(i + 1) * n]);
+        Some(ComparisonSharing((0..len).map(sharing).collect::<Option<Vec<T>>>()?, sharing(len)?))
+    }
+}
+
+impl<T: Sharing> fmt::Display for ComparisonSharing<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = shower(f);
+        let join = self.0.iter().map(&show).collect::<Vec<String>>().join(", ");
+        write!(f, "[[{}], {}]", join, show(&self.1))
+    }
+}
+
+// sharings of the bits r_0, ..., r_31 of a uniformly random r modulo q, consumed by an equality test
+pub struct EqualitySharing<T: Sharing>(pub Vec<T>);
+
+impl<T: Sharing> EqualitySharing<T> {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    pub fn components(&self) -> Vec<u32> {
+        self.0.iter().flat_map(|s| s.components()).collect()
+    }
+    // all len sharings have the same number of components
+    pub fn from_components(len: usize, c: &[u32]) -> Option<Self> {
+        if len == 0 || !c.len().is_multiple_of(len) {
+            return None;
+        }
+        let n = c.len() / len;
+        Some(EqualitySharing((0..len).map(|i| T::from_components(&c[i * n..(i + 1) * n])).collect::<Option<Vec<T>>>()?))
+    }
+}
+
+impl<T: Sharing> fmt::Display for EqualitySharing<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = shower(f);
+        write!(f, "[{}]", self.0.iter().map(show).collect::<Vec<String>>().join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+    use rand_chacha::rand_core::SeedableRng;
+
+    use super::*;
+    use crate::field::F64;
+
+    // the direct subtraction against the default one, add(s1, &s2.complement(f)), on random sharings of random values
+    fn check_subtract<T: Sharing>(seed: u64) {
+        let rng = &mut ChaCha20Rng::seed_from_u64(seed);
+        let random_q = (0..8).map(|_| rng.gen_range(2..=u32::MAX)).collect::<Vec<u32>>();
+
+        for q in [2, 3, 7, 101, 2147483647, u32::MAX - 4, u32::MAX].into_iter().chain(random_q) {
+            let f = &ModField::new(q);
+            let (k1, k2) = (rng.gen_range(0..q), rng.gen_range(0..q));
+            for _ in 0..100 {
+                let (v1, v2) = (rng.gen_range(0..q), rng.gen_range(0..q));
+                let (s1, t1) = T::share(v1, k1, k2, rng, f);
+                let (s2, t2) = T::share(v2, k1, k2, rng, f);
+                // also the sharing of 0 and subtraction from itself
+                for (a, b) in [(&s1, &s2), (&t1, &t2), (&s2, &s1), (&s1, &s1), (&s1, &T::share(0, k1, k2, rng, f).0)] {
+                    assert_eq!(
+                        T::subtract(a, b, f).components(),
+                        T::add(a, &b.complement(f), f).components(),
+                        "q = {q}, {a} - {b}"
+                    );
+                }
+                // the result opens to v1 - v2
+                let (d1, d2) = (T::subtract(&s1, &s2, f), T::subtract(&t1, &t2, f));
+                assert_eq!(T::add(&d1, &d2, f).value(), f.sub(v1, v2));
+            }
+        }
+    }
+
+    #[test]
+    fn unauth_subtract_matches_complement() {
+        check_subtract::<UnauthSharing>(1);
+    }
+
+    #[test]
+    fn auth_subtract_matches_complement() {
+        check_subtract::<AuthSharing>(2);
+    }
+
+    // the generic operations of UnauthSharing over F64, with moduli whose elements do not fit in a u32: the shares
+    // of two values open to them, and the operations on shares open to the same operations on the values
+    #[test]
+    fn unauth_sharing_over_f64() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(3);
+
+        for q in [(1u64 << 61) - 1, u64::MAX - 58, u64::MAX] {
+            let f = &F64::new(q);
+            for _ in 0..100 {
+                let (v1, v2, c) = (rng.gen_range(0..q), rng.gen_range(0..q), rng.gen_range(0..q));
+                let (s1, t1) = UnauthSharing::<F64>::share_in(f, v1, rng);
+                let (s2, t2) = UnauthSharing::<F64>::share_in(f, v2, rng);
+
+                assert_eq!(s1.open_in(&t1, f), v1);
+                assert_eq!(s1.add_in(&s2, f).open_in(&t1.add_in(&t2, f), f), f.add(v1, v2));
+                assert_eq!(s1.sub_in(&s2, f).open_in(&t1.sub_in(&t2, f), f), f.sub(v1, v2));
+                assert_eq!(s1.mulc_in(c, f).open_in(&t1.mulc_in(c, f), f), f.mul(v1, c));
+                assert_eq!(s1.neg_in(f).open_in(&t1.neg_in(f), f), f.neg(v1));
+            }
+        }
+
+        // displayed as the u32 sharings are
+        assert_eq!(UnauthSharing::<F64>::new(u64::MAX).to_string(), format!("({})", u64::MAX));
+    }
+
+    // each party reconstructs k1 or k2 from its own share and the one the other party opens to it, then adds the
+    // opening of a sharing to its own share: the MAC under its key checks out against the reconstructed key, while
+    // the MAC under the other key, zeroed by opened, does not. this holds for sums of sharings as well
+    #[test]
+    fn opened_then_added_sharings_authenticate_under_the_reconstructed_key() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(4);
+
+        for q in [101, 2147483647, u32::MAX] {
+            let f = &ModField::new(q);
+            for _ in 0..100 {
+                let (k1, k2) = (rng.gen_range(0..q), rng.gen_range(0..q));
+                let (k1_1, k1_2) = UnauthSharing::share(k1, 0, 0, rng, f);
+                let (k2_1, k2_2) = UnauthSharing::share(k2, 0, 0, rng, f);
+                // P2 opens its share of k1 to P1, and P1 its share of k2 to P2
+                let key_p1 = UnauthSharing::add(&k1_1, &k1_2, f).value();
+                let key_p2 = UnauthSharing::add(&k2_2, &k2_1, f).value();
+                assert_eq!((key_p1, key_p2), (k1, k2));
+
+                let (v, w) = (rng.gen_range(1..q), rng.gen_range(1..q));
+                let (s1, s2) = AuthSharing::share(v, k1, k2, rng, f);
+                let (t1, t2) = AuthSharing::share(w, k1, k2, rng, f);
+                let (u1, u2) = (AuthSharing::add(&s1, &t1, f), AuthSharing::add(&s2, &t2, f));
+
+                for (own, other, value) in [(&s1, &s2, v), (&u1, &u2, f.add(v, w))] {
+                    let at_p1 = AuthSharing::add(own, &other.opened(Party::P1), f);
+                    let at_p2 = AuthSharing::add(other, &own.opened(Party::P2), f);
+                    assert_eq!((at_p1.value(), at_p2.value()), (value, value));
+
+                    assert!(at_p1.authenticate(key_p1, f, Party::P1));
+                    assert!(at_p2.authenticate(key_p2, f, Party::P2));
+                    // each party only gets the other party's share of the MAC under its own key, whereas its own
+                    // share of the other MAC alone matches with probability 1 / q, which is not negligible for 101
+                    if q > 101 {
+                        assert!(!at_p1.authenticate(key_p2, f, Party::P2));
+                        assert!(!at_p2.authenticate(key_p1, f, Party::P1));
+                    }
+
+                    // an opening with its value tampered with, but not its MAC, fails the check if the key is not 0
+                    let tampered = AuthSharing::add(own, &other.opened(Party::P1).tweaked(), f);
+                    assert_eq!(tampered.authenticate(key_p1, f, Party::P1), k1 == 0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn alternate_form_labels_the_components_of_authenticated_sharings() {
+        let s = AuthSharing(UnauthSharing(3), UnauthSharing(14), UnauthSharing(15));
+        assert_eq!(s.to_string(), "(3, 14, 15)");
+        assert_eq!(s.fmt_verbose(), "(v=3, MAC_k1=14, MAC_k2=15)");
+        assert_eq!(format!("{s:#}"), s.fmt_verbose());
+
+        // composite sharings pass the form on to each of their components
+        let t = |v| AuthSharing(UnauthSharing(v), UnauthSharing(v + 1), UnauthSharing(v + 2));
+        let triple = BeaverSharing(t(1), t(4), t(7));
+        assert_eq!(triple.to_string(), "[(1, 2, 3), (4, 5, 6), (7, 8, 9)]");
+        assert_eq!(
+            format!("{triple:#}"),
+            "[(v=1, MAC_k1=2, MAC_k2=3), (v=4, MAC_k1=5, MAC_k2=6), (v=7, MAC_k1=8, MAC_k2=9)]"
+        );
+
+        // unauthenticated sharings have nothing to label
+        let u = BeaverSharing(UnauthSharing::<ModField>(1), UnauthSharing(2), UnauthSharing(3));
+        assert_eq!(u.to_string(), "[(1), (2), (3)]");
+        assert_eq!(format!("{u:#}"), u.to_string());
     }
 }
@@ -0,0 +1,155 @@
+use rand_chacha::{rand_core::{RngCore, SeedableRng}, ChaCha20Rng};
+
+use crate::circuit::*;
+use crate::error::BeaverError;
+use crate::protocol::{self, CorruptionConfig, ProtocolOptions};
+use crate::field::{FieldOps, F32};
+
+pub struct DetectionStats {
+    pub trials: u32,
+    // runs aborted by the honest party because of a failed MAC check
+    pub detected: u32,
+    // runs which finished but produced at least one wrong output (tampering went unnoticed)
+    pub wrong_output: u32,
+    // runs which finished with the correct outputs (tampering was never triggered)
+    pub correct_output: u32,
+    // probability that a run is detected according to the number of openings and the corruption degree
+    pub expected_detection_rate: f64,
+}
+
+impl DetectionStats {
+    pub fn detection_rate(&self) -> f64 {
+        self.detected as f64 / self.trials as f64
+    }
+}
+
+// runs the authenticated protocol `trials` times with a corrupt party on random inputs and classifies the
+// outcome of each run. all randomness (inputs, dealer, choice and behaviour of the corrupt party) is derived
+// from `seed`, so that the statistics are reproducible
+pub fn measure_detection_rate(
+    circuit_encoding: &str,
+    q: u32,
+    corruption: CorruptionConfig,
+    trials: u32,
+    seed: u64,
+) -> Result<DetectionStats, BeaverError> {
+    let circuit: Circuit = circuit_encoding.parse().map_err(BeaverError::Parse)?;
+    let rng = &mut ChaCha20Rng::seed_from_u64(seed);
+
+    let expected_detection_rate = expected_detection_rate(&circuit, q, corruption.degree);
+    let cost = protocol::estimate_cost(&circuit, true);
+
+    let mut stats = DetectionStats {
+        trials,
+        detected: 0,
+        wrong_output: 0,
+        correct_output: 0,
+        expected_detection_rate,
+    };
+
+    let arity = circuit.input_arity();
+
+    for _ in 0..trials {
+        let mut random_inputs = |n: usize| -> Vec<u32> {
+            (0..n).map(|_| F32::new(q).random(rng)).collect()
+        };
+
+        let inputs = Inputs {
+            p1: (random_inputs(arity.p1_first), random_inputs(arity.p1_second)),
+            p2: (random_inputs(arity.p2_first), random_inputs(arity.p2_second)),
+            public: random_inputs(circuit.num_public_inputs()),
+        };
+
+        let expected_p1 = circuit.evaluate_outputs(&inputs, q, Party::P1).map_err(BeaverError::Input)?;
+        let expected_p2 = circuit.evaluate_outputs(&inputs, q, Party::P2).map_err(BeaverError::Input)?;
+
+        let options = ProtocolOptions {
+            authenticated: true,
+            corruption: Some(CorruptionConfig {
+                degree: corruption.degree,
+                party: corruption.party,
+            }),
+            seed: Some(rng.next_u64()),
+            ..Default::default()
+        };
+
+        match protocol::run_beaver_protocol(circuit_encoding, q, inputs, &options) {
+            Ok(result) => {
+                // tampering does not change which messages are sent, so every finished run matches the estimate
+                for sent in [result.stats.p1_to_p2, result.stats.p2_to_p1] {
+                    debug_assert_eq!(sent, cost.messages.total(), "messages sent differ from the estimate");
+                }
+                debug_assert_eq!(result.stats.dealer_to_p1, cost.dealer_messages, "dealer messages differ from the estimate");
+
+                if result.outputs_p1 == expected_p1 && result.outputs_p2 == expected_p2 {
+                    stats.correct_output += 1;
+                } else {
+                    stats.wrong_output += 1;
+                }
+            }
+            Err(e) if e.is_authentication_failure() => stats.detected += 1,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(stats)
+}
+
+// the corrupt party (either one with equal probability) tampers at least once with probability
+// 1 - (1 - degree)^n, where n is the number of openings it sends, and each tampering goes unnoticed
+// with probability 1/q
+fn expected_detection_rate(circuit: &Circuit, q: u32, degree: f32) -> f64 {
+    let muls = circuit.num_beaver_triples();
+    let arity = circuit.input_arity();
+
+    // P1 opens the masks of P2's inputs, its half of each multiplication and P2's outputs, and vice versa
+    let openings_p1 = arity.p2_first + arity.p2_second + 2 * muls + circuit.get_outputs(Party::P2).len();
+    let openings_p2 = arity.p1_first + arity.p1_second + 2 * muls + circuit.get_outputs(Party::P1).len();
+
+    let tamper_rate = |n: usize| 1.0 - (1.0 - degree as f64).powi(n as i32);
+
+    (tamper_rate(openings_p1) + tamper_rate(openings_p2)) / 2.0 * (1.0 - 1.0 / q as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // P1 and P2 multiply their inputs, and P1 adds its second one to the product
+    const CIRCUIT: &str = "0, P1, mul, P2 | 1, 0, add, P1 & 0 & 1";
+    const Q: u32 = 101;
+
+    fn measure(degree: f32, trials: u32) -> DetectionStats {
+        measure_detection_rate(CIRCUIT, Q, CorruptionConfig { degree, party: None }, trials, 7).unwrap()
+    }
+
+    // a party tampering with every opening is caught unless the key of the other party is 0
+    #[test]
+    fn full_corruption_is_almost_always_detected() {
+        let stats = measure(1.0, 60);
+
+        assert_eq!(stats.expected_detection_rate, 1.0 - 1.0 / Q as f64);
+        assert_eq!(stats.detected + stats.wrong_output + stats.correct_output, 60);
+        assert_eq!(stats.correct_output, 0);
+        assert!(stats.detection_rate() >= 0.95, "detected {} of 60", stats.detected);
+    }
+
+    #[test]
+    fn honest_runs_are_never_detected() {
+        let stats = measure(0.0, 20);
+
+        assert_eq!(stats.expected_detection_rate, 0.0);
+        assert_eq!((stats.detected, stats.wrong_output, stats.correct_output), (0, 0, 20));
+    }
+
+    // P1 sends 4 openings (P2's second input, its half of the multiplication and P2's output) and P2 5 (P1's two
+    // inputs, its half of the multiplication and P1's output)
+    #[test]
+    fn expected_detection_rate_counts_the_openings() {
+        let circuit: Circuit = CIRCUIT.parse().unwrap();
+        let expected = ((1.0 - 0.9f64.powi(4)) + (1.0 - 0.9f64.powi(5))) / 2.0 * (1.0 - 1.0 / Q as f64);
+
+        assert!((expected_detection_rate(&circuit, Q, 0.1) - expected).abs() < 1e-6);
+        assert!((measure(0.1, 1).expected_detection_rate - expected).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,176 @@
+// Seeded generators of random field elements, sharings, triples and circuits, for checking properties of the
+// arithmetic and the protocol over many generated cases rather than a few hand-picked ones. The generators only
+// draw from the RNG they are given, so check runs each case from its own seed and reports it on failure.
+
+use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+
+use crate::benchcircuits::{self, BenchCircuit};
+use crate::field::{FieldOps, ModField};
+pub use crate::benchcircuits::CircuitShape;
+use crate::sharing::*;
+
+// a uniformly random element of Z_q
+pub fn element(rng: &mut ChaCha20Rng, q: u32) -> u32 {
+    ModField::new(q).random(rng)
+}
+
+// a pair of MAC keys, as drawn by the dealer
+pub fn keys(rng: &mut ChaCha20Rng, q: u32) -> (u32, u32) {
+    (element(rng, q), element(rng, q))
+}
+
+// the shares of k1 and k2 held by P1 and P2, which are what addc takes rather than the keys themselves
+pub fn key_shares(rng: &mut ChaCha20Rng, (k1, k2): (u32, u32), q: u32) -> ((u32, u32), (u32, u32)) {
+    let (sk11, sk12) = unauth_sharing(rng, k1, q);
+    let (sk21, sk22) = unauth_sharing(rng, k2, q);
+    ((sk11.value(), sk21.value()), (sk12.value(), sk22.value()))
+}
+
+// the sharings held by P1 and P2 of the given value
+pub fn unauth_sharing(rng: &mut ChaCha20Rng, v: u32, q: u32) -> (UnauthSharing, UnauthSharing) {
+    UnauthSharing::share(v, 0, 0, rng, &ModField::new(q))
+}
+
+pub fn auth_sharing(rng: &mut ChaCha20Rng, v: u32, (k1, k2): (u32, u32), q: u32) -> (AuthSharing, AuthSharing) {
+    AuthSharing::share(v, k1, k2, rng, &ModField::new(q))
+}
+
+// a Beaver triple of either sharing type, with the keys only used by authenticated sharings
+pub fn triple<T: Sharing>(rng: &mut ChaCha20Rng, (k1, k2): (u32, u32), q: u32) -> (BeaverSharing<T>, BeaverSharing<T>) {
+    T::beaver_share(k1, k2, &ModField::new(q), rng)
+}
+
+// the value the sharings of both parties reconstruct to
+pub fn reconstruct<T: Sharing>(s1: &T, s2: &T, q: u32) -> u32 {
+    T::add(s1, s2, &ModField::new(q)).value()
+}
+
+// a random valid circuit of the given shape over Z_q (see benchcircuits::random)
+pub fn circuit(rng: &mut ChaCha20Rng, shape: &CircuitShape, q: u32) -> BenchCircuit {
+    benchcircuits::random(rng, shape, q)
+}
+
+// runs the property on the given number of cases, the i-th one with an RNG seeded by seed + i, and reports the
+// first case it fails on with the seed reproducing it
+pub fn check(seed: u64, cases: usize, mut property: impl FnMut(&mut ChaCha20Rng) -> Result<(), String>) -> Result<(), String> {
+    for i in 0..cases as u64 {
+        let case_seed = seed.wrapping_add(i);
+        property(&mut ChaCha20Rng::seed_from_u64(case_seed))
+            .map_err(|e| format!("Error: property failed for case {i} (seed {case_seed}): {e}"))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::Party;
+
+    // a small prime, a 31-bit one and the largest prime below 2^32
+    const MODULI: [u32; 4] = [2, 101, 2147483647, 4294967291];
+
+    fn modulus(rng: &mut ChaCha20Rng) -> u32 {
+        MODULI[element(rng, MODULI.len() as u32) as usize]
+    }
+
+    // both parties' sharings of v under the given keys
+    fn share<T: Sharing>(rng: &mut ChaCha20Rng, v: u32, (k1, k2): (u32, u32), q: u32) -> (T, T) {
+        T::share(v, k1, k2, rng, &ModField::new(q))
+    }
+
+    fn expect(what: &str, got: u32, expected: u32) -> Result<(), String> {
+        match got == expected {
+            true => Ok(()),
+            false => Err(format!("{what} reconstructs to {got}, not {expected}")),
+        }
+    }
+
+    fn linearity<T: Sharing>(rng: &mut ChaCha20Rng) -> Result<(), String> {
+        let q = modulus(rng);
+        let f = ModField::new(q);
+        let keys = keys(rng, q);
+        let ((sk11, sk21), (sk12, sk22)) = key_shares(rng, keys, q);
+        let (a, b, c) = (element(rng, q), element(rng, q), element(rng, q));
+        let ((a1, a2), (b1, b2)) = (share::<T>(rng, a, keys, q), share::<T>(rng, b, keys, q));
+
+        let sum = (T::add(&a1, &b1, &f), T::add(&a2, &b2, &f));
+        expect("a + b", reconstruct(&sum.0, &sum.1, q), f.add(a, b))?;
+
+        let difference = (T::subtract(&a1, &b1, &f), T::subtract(&a2, &b2, &f));
+        expect("a - b", reconstruct(&difference.0, &difference.1, q), f.sub(a, b))?;
+
+        // each party adds the constant with its own shares of the keys
+        let shifted = (T::addc(&a1, c, sk11, sk21, &f, Party::P1), T::addc(&a2, c, sk12, sk22, &f, Party::P2));
+        expect("a + c", reconstruct(&shifted.0, &shifted.1, q), f.add(a, c))?;
+
+        let scaled = (T::mulc(&a1, c, &f), T::mulc(&a2, c, &f));
+        expect("a * c", reconstruct(&scaled.0, &scaled.1, q), f.mul(a, c))?;
+
+        let (t1, t2) = triple::<T>(rng, keys, q);
+        let (ta, tb) = (reconstruct(&t1.0, &t2.0, q), reconstruct(&t1.1, &t2.1, q));
+        expect("the product of the triple", reconstruct(&t1.2, &t2.2, q), f.mul(ta, tb))
+    }
+
+    #[test]
+    fn local_operations_are_linear() {
+        check(1, 500, linearity::<UnauthSharing>).unwrap();
+        check(2, 500, linearity::<AuthSharing>).unwrap();
+    }
+
+    // the MACs of the result of any chain of local operations on authenticated sharings are those of its value, so
+    // that opening it passes the checks of both parties, whereas a tampered opening fails them
+    #[test]
+    fn local_operations_preserve_macs() {
+        check(3, 500, |rng| {
+            let q = modulus(rng);
+            let f = ModField::new(q);
+            let (k1, k2) = keys(rng, q);
+            let ((sk11, sk21), (sk12, sk22)) = key_shares(rng, (k1, k2), q);
+            let (a, b) = (element(rng, q), element(rng, q));
+            let ((a1, a2), (b1, b2)) = (auth_sharing(rng, a, (k1, k2), q), auth_sharing(rng, b, (k1, k2), q));
+            let (t1, t2) = triple::<AuthSharing>(rng, (k1, k2), q);
+            let (c, d) = (element(rng, q), element(rng, q));
+
+            let chain = |a: &AuthSharing, b: &AuthSharing, t: &BeaverSharing<AuthSharing>, (sk1, sk2), party| {
+                let s = AuthSharing::add(&AuthSharing::mulc(a, c, &f), b, &f);
+                let s = AuthSharing::addc(&s, d, sk1, sk2, &f, party);
+                AuthSharing::subtract(&AuthSharing::add(&s, &t.2, &f), &t.0, &f)
+            };
+            let s1 = chain(&a1, &b1, &t1, (sk11, sk21), Party::P1);
+            let s2 = chain(&a2, &b2, &t2, (sk12, sk22), Party::P2);
+
+            // each party adds the other one's opening to its own sharing and checks it with its key
+            let at_p1 = AuthSharing::add(&s1, &s2.opened(Party::P1), &f);
+            let at_p2 = AuthSharing::add(&s2, &s1.opened(Party::P2), &f);
+            if !at_p1.authenticate(k1, &f, Party::P1) || !at_p2.authenticate(k2, &f, Party::P2) {
+                return Err(format!("the MACs of {s1} and {s2} do not match their value under keys ({k1}, {k2})"));
+            }
+
+            // a zero key, which is drawn half of the time over Z_2, lets any tampering through
+            let tampered = AuthSharing::add(&s1, &s2.tweaked().opened(Party::P1), &f);
+            if k1 != 0 && tampered.authenticate(k1, &f, Party::P1) {
+                return Err(format!("the tampered opening of {s2} passes the check of P1 under key {k1}"));
+            }
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    // the generators themselves are deterministic given the seed, which check relies on to report failing cases
+    #[test]
+    fn generators_are_reproducible() {
+        let draw = |seed| {
+            let rng = &mut ChaCha20Rng::seed_from_u64(seed);
+            let keys = keys(rng, 101);
+            let v = element(rng, 101);
+            let (s1, s2) = auth_sharing(rng, v, keys, 101);
+            let c = circuit(rng, &CircuitShape::default(), 101).encoding;
+            (keys, s1.components(), s2.components(), c)
+        };
+        assert_eq!(draw(5), draw(5));
+        assert_ne!(draw(5), draw(6));
+
+        let e = check(10, 5, |rng| if element(rng, 2) == 1 { Err(String::from("odd")) } else { Ok(()) }).unwrap_err();
+        assert!(e.contains("(seed 1"), "{e}");
+    }
+}
@@ -1,54 +1,1343 @@
 
+use std::{collections::HashSet, num::IntErrorKind};
+
 use rand_chacha::ChaCha20Rng;
-use rand_core::RngCore;
+use rand_core::{RngCore, SeedableRng};
+
+use crate::error::{ParseVecError, ParseVecErrorKind};
+use crate::field::{FieldOps, F32, F64};
+
+// the arithmetic helpers on u32 values below are wrappers around F32, deprecated now that all callers carry a
+// FieldOps context instead of a bare modulus. they take any u32 operands, reduced or not, and never overflow: the
+// largest intermediate value, (2^32 - 1)^2, fits in the u64 they compute in
 
+// a uniformly random value in [0, q) for any q >= 1 (q = 0, which no run accepts, would divide by zero)
+#[deprecated(note = "use F32::random")]
 pub fn safe_gen_mod( rng: &mut ChaCha20Rng, q: u32) -> u32 {
-    (rng.next_u64() % q as u64) as u32 // cryptographically safer than generating an u32 due to bias
+    F32::new(q).random(rng)
+}
+
+// a 256-bit seed written as 64 hexadecimal characters (in either case), e.g. taken from a configuration file
+pub fn seed_from_hex(s: &str) -> Result<[u8; 32], String> {
+    let s = s.trim();
+
+    if s.chars().count() != 64 {
+        return Err(format!("Invalid seed: expected 64 hexadecimal characters, found {}", s.chars().count()));
+    }
+    if let Some((i, c)) = s.chars().enumerate().find(|(_, c)| !c.is_ascii_hexdigit()) {
+        return Err(format!("Invalid seed: '{c}' at position {} is not a hexadecimal character", i + 1));
+    }
+
+    let mut seed = [0u8; 32];
+    for (i, byte) in seed.iter_mut().enumerate() {
+        // the string is known to consist of 64 ASCII hexadecimal characters
+        *byte = u8::from_str_radix(&s[2 * i..2 * i + 2], 16).unwrap();
+    }
+    Ok(seed)
+}
+
+pub fn rng_from_hex_seed(s: &str) -> Result<ChaCha20Rng, String> {
+    seed_from_hex(s).map(ChaCha20Rng::from_seed)
+}
+
+// the seed of the sub-RNG with the given label, e.g. "dealer", derived from a master seed so that the streams of
+// different labels are independent while each label always gets the same one. ChaCha20 keyed with the current seed
+// serves as a PRF: the label is absorbed 8 bytes at a time, each block selecting the stream whose first 32 bytes
+// become the next seed, and its length last, from a later position in the stream, so that labels differing only
+// in trailing zero bytes do not collide
+pub fn derive_subseed(master: &[u8; 32], label: &str) -> [u8; 32] {
+    let step = |seed: [u8; 32], stream: u64, word_pos: u128| {
+        let mut rng = ChaCha20Rng::from_seed(seed);
+        rng.set_stream(stream);
+        rng.set_word_pos(word_pos);
+        let mut next = [0u8; 32];
+        rng.fill_bytes(&mut next);
+        next
+    };
+
+    let mut seed = *master;
+    for block in label.as_bytes().chunks(8) {
+        let mut padded = [0u8; 8];
+        padded[..block.len()].copy_from_slice(block);
+        seed = step(seed, u64::from_le_bytes(padded), 0);
+    }
+    step(seed, label.len() as u64, 16)
+}
+
+// 64-bit FNV-1a (offset basis 0xcbf29ce484222325, prime 0x100000001b3), used for the transcript digests. unlike
+// std's DefaultHasher, whose algorithm is unspecified and may change between releases, it gives the same digest for
+// the same bytes on every platform and build. it is not collision resistant, so it only guards against accidental
+// divergence, not a party forging messages
+#[derive(Clone, Copy, Debug)]
+pub struct Fnv1a(u64);
+
+impl Fnv1a {
+    pub fn new() -> Self {
+        Fnv1a(0xcbf29ce484222325)
+    }
+    pub fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 = (self.0 ^ *b as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// rejection sampling: the 2^64 mod q smallest u64 values are discarded, so that the remaining ones (a multiple
+// of q) reduce to every residue equally often. each draw is rejected with probability below min(q / 2^64, 1 / 2),
+// so the loop takes fewer than two draws on average, and a single one for all practical purposes if q fits in a u32
+pub fn gen_below(rng: &mut ChaCha20Rng, q: u64) -> u64 {
+    let rejected = q.wrapping_neg() % q;
+    loop {
+        let v = rng.next_u64();
+        if v >= rejected {
+            return v % q;
+        }
+    }
+}
+
+// the samplers below are built on gen_below, and fail rather than loop when asked for something impossible
+
+// a uniformly random value in [1, q), e.g. to mask a value that must stay invertible
+pub fn gen_nonzero_mod(rng: &mut ChaCha20Rng, q: u32) -> Result<u32, String> {
+    match q {
+        0 | 1 => Err(format!("Error: there are no nonzero elements modulo q = {q}")),
+        _ => Ok(1 + gen_below(rng, q as u64 - 1) as u32),
+    }
+}
+
+// n distinct uniformly random values in [0, q), in ascending order, e.g. evaluation points
+pub fn gen_distinct_mod(rng: &mut ChaCha20Rng, n: usize, q: u32) -> Result<Vec<u32>, String> {
+    if n > q as usize {
+        return Err(format!("Error: cannot draw {n} distinct elements modulo q = {q}"));
+    }
+    Ok(sample_subset(n, q as usize, rng)?.into_iter().map(|i| i as u32).collect())
+}
+
+// a uniformly random subset of k elements of [0, n), in ascending order. Floyd's algorithm takes exactly k draws,
+// whatever the ratio of k to n
+pub fn sample_subset(k: usize, n: usize, rng: &mut ChaCha20Rng) -> Result<Vec<usize>, String> {
+    if k > n {
+        return Err(format!("Error: cannot draw a subset of {k} elements out of {n}"));
+    }
+
+    let mut chosen = HashSet::with_capacity(k);
+    for j in n - k..n {
+        let t = gen_below(rng, j as u64 + 1) as usize;
+        if !chosen.insert(t) {
+            chosen.insert(j);
+        }
+    }
 
+    let mut subset: Vec<usize> = chosen.into_iter().collect();
+    subset.sort_unstable();
+    Ok(subset)
 }
 
+// Fisher-Yates: position i is swapped with a uniformly random one among the first i + 1, so that all n! orders are
+// equally likely. two parties shuffling with RNGs from the same seed obtain the same order
+pub fn shuffle<T>(v: &mut [T], rng: &mut ChaCha20Rng) {
+    for i in (1..v.len()).rev() {
+        v.swap(i, gen_below(rng, i as u64 + 1) as usize);
+    }
+}
+
+// a uniformly random permutation of [0, n)
+pub fn random_permutation(n: usize, rng: &mut ChaCha20Rng) -> Vec<usize> {
+    let mut p: Vec<usize> = (0..n).collect();
+    shuffle(&mut p, rng);
+    p
+}
+
+// the reordering of v whose i-th element is v[p[i]]
+pub fn apply_permutation<T: Clone>(v: &[T], p: &[usize]) -> Result<Vec<T>, String> {
+    let mut seen = vec![false; v.len()];
+    if p.len() != v.len() || !p.iter().all(|i| *i < v.len() && !std::mem::replace(&mut seen[*i], true)) {
+        return Err(format!("Error: not a permutation of the {} positions of the vector", v.len()));
+    }
+    Ok(p.iter().map(|i| v[*i].clone()).collect())
+}
+
+#[deprecated(note = "use F32::add")]
 pub fn add_without_overflow(a: u32, b: u32, q: u32) -> u32 {
-    ((a as u64 + b as u64) % q as u64) as u32
+    F32::new(q).add(a, b)
 }
 
+#[deprecated(note = "use F32::sub")]
 pub fn subtract_without_overflow(a: u32, b: u32, q: u32) -> u32 {
-    (a as i64 - b as i64).rem_euclid(q as i64) as u32
+    F32::new(q).sub(a, b)
 }
 
+#[deprecated(note = "use F32::mul")]
 pub fn mul_without_overflow(a: u32, b: u32, q: u32) -> u32 {
-    ((a as u64 * b as u64) % q as u64) as u32
+    F32::new(q).mul(a, b)
+}
+
+// elementwise versions of the above over slices, for batched operations. the in-place variants overwrite their
+// first argument and allocate nothing, and all of them fail if the lengths of two slices differ
+
+pub fn add_vec(a: &[u32], b: &[u32], q: u32) -> Result<Vec<u32>, String> {
+    let mut out = a.to_vec();
+    add_vec_in_place(&mut out, b, q)?;
+    Ok(out)
 }
 
+pub fn sub_vec(a: &[u32], b: &[u32], q: u32) -> Result<Vec<u32>, String> {
+    let mut out = a.to_vec();
+    sub_vec_in_place(&mut out, b, q)?;
+    Ok(out)
+}
+
+pub fn mul_vec(a: &[u32], b: &[u32], q: u32) -> Result<Vec<u32>, String> {
+    let mut out = a.to_vec();
+    mul_vec_in_place(&mut out, b, q)?;
+    Ok(out)
+}
+
+// multiplication of every element by the constant c
+pub fn mulc_vec(a: &[u32], c: u32, q: u32) -> Vec<u32> {
+    let mut out = a.to_vec();
+    mulc_vec_in_place(&mut out, c, q);
+    out
+}
+
+pub fn reduce_vec(a: &[u32], q: u32) -> Vec<u32> {
+    let mut out = a.to_vec();
+    reduce_vec_in_place(&mut out, q);
+    out
+}
+
+pub fn add_vec_in_place(a: &mut [u32], b: &[u32], q: u32) -> Result<(), String> {
+    zip_in_place(a, b, |x, y| F32::new(q).add(x, y))
+}
+
+pub fn sub_vec_in_place(a: &mut [u32], b: &[u32], q: u32) -> Result<(), String> {
+    zip_in_place(a, b, |x, y| F32::new(q).sub(x, y))
+}
+
+pub fn mul_vec_in_place(a: &mut [u32], b: &[u32], q: u32) -> Result<(), String> {
+    zip_in_place(a, b, |x, y| F32::new(q).mul(x, y))
+}
+
+pub fn mulc_vec_in_place(a: &mut [u32], c: u32, q: u32) {
+    a.iter_mut().for_each(|x| *x = F32::new(q).mul(*x, c));
+}
+
+pub fn reduce_vec_in_place(a: &mut [u32], q: u32) {
+    a.iter_mut().for_each(|x| *x %= q);
+}
+
+// number of elements processed together, so that the compiler can unroll and vectorize each chunk. with a modulus
+// only known at runtime the remainders still dominate, so expect a modest gain over a plain loop
+const LANES: usize = 8;
+
+fn zip_in_place(a: &mut [u32], b: &[u32], op: impl Fn(u32, u32) -> u32) -> Result<(), String> {
+    if a.len() != b.len() {
+        return Err(format!("Error: vectors of lengths {} and {} cannot be combined elementwise", a.len(), b.len()));
+    }
+
+    let (mut chunks_a, mut chunks_b) = (a.chunks_exact_mut(LANES), b.chunks_exact(LANES));
+    for (ca, cb) in (&mut chunks_a).zip(&mut chunks_b) {
+        for (x, y) in ca.iter_mut().zip(cb) {
+            *x = op(*x, *y);
+        }
+    }
+    for (x, y) in chunks_a.into_remainder().iter_mut().zip(chunks_b.remainder()) {
+        *x = op(*x, *y);
+    }
+
+    Ok(())
+}
+
+// the residue of any i32, including i32::MIN. panics if q = 0, which runs of the protocol reject upfront
 pub fn modulo(a: i32, q: u32) -> u32 {
-    ((a as i64).rem_euclid(q as i64)) as u32
+    modulo_i64(a as i64, q as u64).expect("the modulus should be positive") as u32
 }
 
-pub fn str_u32_to_vec_u32(s: &str) -> Result<Vec<u32>, String> {
+// the residue of any i64 modulo any u64, computed in i128 so that neither i64::MIN nor a modulus above i64::MAX
+// overflows. None if q = 0, while q = 1 maps everything to 0
+pub fn modulo_i64(a: i64, q: u64) -> Option<u64> {
+    (q > 0).then(|| (a as i128).rem_euclid(q as i128) as u64)
+}
 
-    let mut ns = Vec::new();
-    for n_str in s.chars().filter(|c| !c.is_whitespace()).collect::<String>().split(",") {
-        if n_str.len() > 0 {
-            match n_str.parse::<u32>() {
-                Ok(n) => {ns.push(n);},
-                Err(_) => return Err(format!("Invalid sequence of u32: {s}"))
+// (g, x, y) with g = gcd(a, b) = a * x + b * y
+pub fn ext_gcd(a: u64, b: u64) -> (u64, i128, i128) {
+    let (mut r0, mut r1) = (a as i128, b as i128);
+    let (mut x0, mut x1) = (1, 0);
+    let (mut y0, mut y1) = (0, 1);
+
+    while r1 != 0 {
+        let quotient = r0 / r1;
+        (r0, r1) = (r1, r0 - quotient * r1);
+        (x0, x1) = (x1, x0 - quotient * x1);
+        (y0, y1) = (y1, y0 - quotient * y1);
+    }
+
+    (r0 as u64, x0, y0)
+}
+
+// the inverse of a modulo q, which exists iff they are coprime
+#[deprecated(note = "use F32::inv")]
+pub fn inv_mod(a: u32, q: u32) -> Option<u32> {
+    F32::new(q).inv(a)
+}
+
+// a / b modulo q, if b is invertible
+#[deprecated(note = "use F32::inv and F32::mul")]
+pub fn div_mod(a: u32, b: u32, q: u32) -> Option<u32> {
+    let f = F32::new(q);
+    f.inv(b).map(|b_inv| f.mul(a, b_inv))
+}
+
+// the residues of value modulo each of the moduli, e.g. to run one instance of the protocol per modulus
+pub fn crt_split(value: u128, moduli: &[u64]) -> Result<Vec<u64>, String> {
+    if moduli.contains(&0) {
+        return Err(String::from("Error: the moduli of a CRT split should be positive"));
+    }
+    Ok(moduli.iter().map(|m| (value % *m as u128) as u64).collect())
+}
+
+// the unique value below the product of the moduli with the given (residue, modulus) pairs, which requires the
+// moduli to be pairwise coprime and their product to fit in a u128. the value is built one modulus at a time: if x
+// is the combination of the pairs so far, with product M, the next pair (r, m) gives x + M * ((r - x) / M mod m)
+pub fn crt_combine(residues: &[(u64, u64)]) -> Result<u128, String> {
+    for (i, (r, m)) in residues.iter().enumerate() {
+        if *m == 0 || r >= m {
+            return Err(format!("Error: residue {r} is not below its modulus {m}"));
+        }
+        if let Some((_, other)) = residues[..i].iter().find(|(_, other)| ext_gcd(*m, *other).0 != 1) {
+            return Err(format!("Error: moduli {other} and {m} are not coprime"));
+        }
+    }
+
+    let (mut x, mut product) = (0u128, 1u128);
+
+    for (r, m) in residues {
+        let next_product = product
+            .checked_mul(*m as u128)
+            .ok_or(String::from("Error: the product of the moduli does not fit in 128 bits"))?;
+
+        // the inverse exists as the moduli are coprime, and the new x stays below the new product
+        let diff = F64::new(*m).sub(*r, (x % *m as u128) as u64);
+        let inv = F64::new(*m).inv((product % *m as u128) as u64).unwrap();
+        x += product * F64::new(*m).mul(diff, inv) as u128;
+
+        product = next_product;
+    }
+
+    Ok(x)
+}
+
+// deterministic Miller-Rabin test: the first 12 primes as witnesses are enough for every n < 2^64
+pub fn is_prime(n: u64) -> bool {
+    const WITNESSES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+
+    if n < 2 {
+        return false;
+    }
+    if let Some(p) = WITNESSES.iter().find(|p| n.is_multiple_of(**p)) {
+        return n == *p;
+    }
+
+    // n - 1 = d * 2^s with d odd
+    let s = (n - 1).trailing_zeros();
+    let d = (n - 1) >> s;
+
+    WITNESSES.iter().all(|a| {
+        let mut x = F64::new(n).pow(*a, d);
+        if x == 1 || x == n - 1 {
+            return true;
+        }
+        for _ in 1..s {
+            x = F64::new(n).mul(x, x);
+            if x == n - 1 {
+                return true;
             }
         }
+        false
+    })
+}
+
+// the smallest prime which is at least n, if there is one below 2^64
+pub fn next_prime(n: u64) -> Option<u64> {
+    (n..=u64::MAX).find(|c| is_prime(*c))
+}
+
+// statistical security (in bits) of the masking of values opened by truncation gates
+pub const TRUNCATION_SECURITY: u32 = 8;
+
+// number of bits of the random mask of equality tests, which covers any u32 modulus so that their cost does not
+// depend on q
+pub const EQUALITY_BITS: u32 = 32;
+
+// truncation gates take signed inputs of absolute value below 2^(k - 1), where k is the largest value such that
+// masking an input (shifted to [0, 2^k)) with a random value of k + TRUNCATION_SECURITY bits never wraps around q
+pub fn truncation_bits(q: u32) -> u32 {
+    q.checked_ilog2().unwrap_or(0).saturating_sub(1 + TRUNCATION_SECURITY)
+}
+
+// named primes which can be given instead of a literal modulus. larger standard primes (such as the BN254 scalar
+// field) do not fit in a u32
+const PRIME_PRESETS: [(&str, u32); 5] = [
+    ("mersenne13", 8191),
+    ("mersenne17", 131071),
+    ("mersenne19", 524287),
+    ("mersenne31", 2147483647),
+    ("largest-u16", 65521),
+];
+
+pub fn prime_preset(name: &str) -> Option<u32> {
+    PRIME_PRESETS
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, q)| *q)
+}
+
+// a literal u32 or the name of a preset
+pub fn parse_modulus(s: &str) -> Result<u32, String> {
+    let s = s.trim();
+
+    match s.parse::<u32>() {
+        Ok(q) => Ok(q),
+        Err(_) => prime_preset(s).ok_or(format!(
+            "Invalid modulus {s}: expected a u32 or one of the presets {}",
+            PRIME_PRESETS.iter().map(|(n, _)| *n).collect::<Vec<&str>>().join(", ")
+        )),
     }
+}
 
-    Ok(ns)
+// fixed-point encoding of reals with f fractional bits: x is represented by round(x * 2^f) mod q, so that negative
+// values wrap around. sums of encodings encode the sum at the same scale, but the product of two encodings has
+// scale 2^(2f), i.e. must be decoded with 2f fractional bits (and no truncation is performed). for the decoding to
+// be meaningful, |x| * 2^f must stay below q / 2
+pub fn encode_fixed(x: f64, f: u32, q: u32) -> u32 {
+    let scaled = (x * 2f64.powi(f as i32)).round() as i64;
+    scaled.rem_euclid(q as i64) as u32
 }
 
-pub fn str_i32_to_vec_u32(s: &str, q: u32) -> Result<Vec<u32>, String> {
+// the representative of v modulo q in (-q / 2, q / 2], i.e. with residues above q / 2 read as negative
+pub fn centered(v: u32, q: u32) -> i64 {
+    let v = v % q;
+    if v > q / 2 { v as i64 - q as i64 } else { v as i64 }
+}
 
-    let mut ns = Vec::new();
-    for n_str in s.chars().filter(|c| !c.is_whitespace()).collect::<String>().split(",") {
-        if n_str.len() > 0 {
-            match n_str.parse::<i32>() {
-                Ok(n) => {ns.push(modulo(n, q));},
-                Err(_) => return Err(format!("Invalid sequence of i32: {s}"))
+// inverse of encode_fixed
+pub fn decode_fixed(v: u32, f: u32, q: u32) -> f64 {
+    centered(v, q) as f64 / 2f64.powi(f as i32)
+}
+
+// a list of reals, separated as in list_entries, each encoded with f fractional bits. values too large to be
+// decoded back are rejected
+pub fn str_f64_to_vec_u32(s: &str, f: u32, q: u32) -> Result<Vec<u32>, ParseVecError> {
+    let bound = (q / 2) as f64 / 2f64.powi(f as i32);
+
+    list_entries(s)
+        .into_iter()
+        .map(|(position, token)| {
+            let kind = match token.parse::<f64>() {
+                Ok(x) if x.abs() <= bound => return Ok(encode_fixed(x, f, q)),
+                Ok(_) => ParseVecErrorKind::OutOfRange(format!("[-{bound}, {bound}]")),
+                Err(_) => ParseVecErrorKind::NotANumber(10),
+            };
+            Err(ParseVecError { line: s.to_string(), position, token: token.to_string(), kind })
+        })
+        .collect()
+}
+
+// both parsers accept the forms described in parse_int_list
+pub fn str_u32_to_vec_u32(s: &str) -> Result<Vec<u32>, ParseVecError> {
+    let ns = parse_int_list(s, 0, u32::MAX as i64)?;
+    Ok(ns.into_iter().map(|(_, _, n)| n as u32).collect())
+}
+
+// a list of output ids, each with whether it was marked as signed with a leading -, e.g. 3, -7. ranges and
+// repetitions are allowed as in parse_int_list, with both bounds negative for a range of signed ids
+pub fn str_to_output_list(s: &str) -> Result<Vec<(u32, bool)>, ParseVecError> {
+    let ns = parse_int_list(s, -(u32::MAX as i64), u32::MAX as i64)?;
+    ns.into_iter()
+        .map(|(position, token, n)| {
+            let signed = token.starts_with('-');
+            if signed && n > 0 {
+                return Err(ParseVecError {
+                    line: s.to_string(),
+                    position,
+                    token: token.to_string(),
+                    kind: ParseVecErrorKind::OutOfRange(String::from("as the range mixes signed and unsigned output ids")),
+                });
             }
+            Ok((n.unsigned_abs() as u32, signed))
+        })
+        .collect()
+}
+
+// q = 0 is reported as the first entry being out of range
+pub fn str_i32_to_vec_u32(s: &str, q: u32) -> Result<Vec<u32>, ParseVecError> {
+    let ns = parse_int_list(s, i32::MIN as i64, i32::MAX as i64)?;
+    ns.into_iter()
+        .map(|(position, token, n)| match modulo_i64(n, q as u64) {
+            Some(r) => Ok(r as u32),
+            None => Err(ParseVecError {
+                line: s.to_string(),
+                position,
+                token: token.to_string(),
+                kind: ParseVecErrorKind::OutOfRange(String::from("for q = 0")),
+            }),
+        })
+        .collect()
+}
+
+// the most values a list of integers may expand to, far more than any circuit takes as inputs or outputs
+const MAX_LIST_LEN: usize = 1 << 22;
+
+// a list of integers in [min, max], with entries as described in list_entries, each value with the position and entry
+// it comes from. besides single values, an entry may be a value repeated a number of times, e.g. 0*16, or a non-empty
+// inclusive range, e.g. 1..=5, as long as the list has at most MAX_LIST_LEN values in total. values may be written in
+// hexadecimal or binary, as in parse_integer
+fn parse_int_list(s: &str, min: i64, max: i64) -> Result<Vec<(usize, &str, i64)>, ParseVecError> {
+    let mut ns = Vec::new();
+
+    for (position, token) in list_entries(s) {
+        match parse_int_token(token, min, max, MAX_LIST_LEN - ns.len()) {
+            Ok(values) => ns.extend(values.into_iter().map(|v| (position, token, v))),
+            Err(kind) => return Err(ParseVecError { line: s.to_string(), position, token: token.to_string(), kind }),
         }
     }
 
     Ok(ns)
 }
+
+// the non-empty entries of a list separated by commas, semicolons or whitespace, each with the (1-based) position
+// in s where it starts, so that errors can point to it
+fn list_entries(s: &str) -> Vec<(usize, &str)> {
+    let mut entries = Vec::new();
+    let mut start = 0;
+
+    // a trailing separator flushes the last entry
+    for (i, c) in s.char_indices().chain([(s.len(), ',')]) {
+        if c != ',' && c != ';' && !c.is_whitespace() {
+            continue;
+        }
+        if i > start {
+            entries.push((s[..start].chars().count() + 1, &s[start..i]));
+        }
+        start = i + c.len_utf8();
+    }
+
+    entries
+}
+
+// at most room values, so that a short entry such as 0*4294967295 cannot exhaust the memory
+fn parse_int_token(token: &str, min: i64, max: i64, room: usize) -> Result<Vec<i64>, ParseVecErrorKind> {
+    let too_long = |n: u64| match n > room as u64 {
+        true => Err(ParseVecErrorKind::TooLong(MAX_LIST_LEN)),
+        false => Ok(()),
+    };
+
+    if let Some((v, count)) = token.split_once('*') {
+        let count = integer(count, 0, u32::MAX as i64)?;
+        let v = integer(v, min, max)?;
+        too_long(count as u64)?;
+        Ok(vec![v; count as usize])
+    } else if let Some((first, last)) = token.split_once("..=") {
+        let range = integer(first, min, max)?..=integer(last, min, max)?;
+        if range.is_empty() {
+            return Err(ParseVecErrorKind::EmptyRange);
+        }
+        too_long((range.end() - range.start()) as u64 + 1)?;
+        Ok(range.collect())
+    } else {
+        too_long(1)?;
+        Ok(vec![integer(token, min, max)?])
+    }
+}
+
+// an integer in [min, max] with an optional sign, written in decimal or, with a 0x or 0b prefix after the sign, in
+// hexadecimal or binary. the errors tell digits which are invalid in the base apart from values out of range
+pub fn parse_integer(s: &str, min: i64, max: i64) -> Result<i64, String> {
+    integer(s, min, max).map_err(|kind| format!("{s}: {kind}"))
+}
+
+fn integer(s: &str, min: i64, max: i64) -> Result<i64, ParseVecErrorKind> {
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(d) => (true, d),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let (radix, digits) = match digits.get(..2) {
+        Some("0x" | "0X") => (16, &digits[2..]),
+        Some("0b" | "0B") => (2, &digits[2..]),
+        _ => (10, digits),
+    };
+
+    if digits.is_empty() {
+        return Err(ParseVecErrorKind::EmptyToken);
+    }
+    // from_str_radix would accept a second sign
+    if digits.starts_with(['+', '-']) {
+        return Err(ParseVecErrorKind::NotANumber(radix));
+    }
+
+    let out_of_range = || ParseVecErrorKind::OutOfRange(format!("[{min}, {max}]"));
+
+    let magnitude = match u64::from_str_radix(digits, radix) {
+        Ok(m) => m as i128,
+        Err(e) if *e.kind() == IntErrorKind::PosOverflow => return Err(out_of_range()),
+        Err(_) => return Err(ParseVecErrorKind::NotANumber(radix)),
+    };
+    let value = if negative { -magnitude } else { magnitude };
+
+    match (min as i128..=max as i128).contains(&value) {
+        true => Ok(value as i64),
+        false => Err(out_of_range()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_Q: u32 = u32::MAX;
+
+    #[test]
+    fn fnv1a_reference_digests() {
+        let digest = |chunks: &[&[u8]]| {
+            let mut h = Fnv1a::new();
+            for c in chunks {
+                h.write(c);
+            }
+            h.finish()
+        };
+
+        assert_eq!(digest(&[]), 0xcbf29ce484222325);
+        assert_eq!(digest(&[b"a"]), 0xaf63dc4c8601ec8c);
+        assert_eq!(digest(&[b"foobar"]), 0x85944171f73967e8);
+        // the digest only depends on the bytes, not on how they are split
+        assert_eq!(digest(&[b"foo", b"", b"bar"]), 0x85944171f73967e8);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn addition_wraps_at_q() {
+        assert_eq!(add_without_overflow(5, 2, 7), 0);
+        assert_eq!(add_without_overflow(6, 6, 7), 5);
+        assert_eq!(add_without_overflow(MAX_Q - 1, MAX_Q - 1, MAX_Q), MAX_Q - 2);
+        // unreduced operands
+        assert_eq!(add_without_overflow(u32::MAX, u32::MAX, 10), 0);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn subtraction_of_larger_value() {
+        assert_eq!(subtract_without_overflow(2, 5, 7), 4);
+        assert_eq!(subtract_without_overflow(0, 1, 7), 6);
+        assert_eq!(subtract_without_overflow(0, MAX_Q - 1, MAX_Q), 1);
+        assert_eq!(subtract_without_overflow(3, 3, 7), 0);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn multiplication_near_max_modulus() {
+        // (q - 1)^2 = 1 and (q - 2)(q - 1) = 2 modulo q, through intermediates above u32::MAX
+        assert_eq!(mul_without_overflow(MAX_Q - 1, MAX_Q - 1, MAX_Q), 1);
+        assert_eq!(mul_without_overflow(MAX_Q - 2, MAX_Q - 1, MAX_Q), 2);
+        assert_eq!(
+            mul_without_overflow(u32::MAX, u32::MAX, MAX_Q - 4),
+            ((u32::MAX as u128 * u32::MAX as u128) % (MAX_Q - 4) as u128) as u32
+        );
+    }
+
+    #[test]
+    fn modulo_of_i32_min() {
+        assert_eq!(modulo(i32::MIN, 7), (-(2i64.pow(31))).rem_euclid(7) as u32);
+        assert_eq!(modulo(i32::MIN, MAX_Q), MAX_Q - 2u32.pow(31));
+        assert_eq!(modulo(i32::MIN, 2u32.pow(31)), 0);
+        assert_eq!(modulo(-1, 7), 6);
+        assert_eq!(modulo(i32::MAX, 1), 0);
+    }
+
+    fn kind_at(s: &str, q: u32) -> (usize, String, ParseVecErrorKind) {
+        let e = str_i32_to_vec_u32(s, q).unwrap_err();
+        (e.position, e.token, e.kind)
+    }
+
+    #[test]
+    fn accepted_list_forms() {
+        let q = 1000;
+        // separators, including repeated and trailing ones
+        assert_eq!(str_i32_to_vec_u32("1, 2;3 \t4,", q).unwrap(), vec![1, 2, 3, 4]);
+        assert_eq!(str_i32_to_vec_u32("  ", q).unwrap(), Vec::<u32>::new());
+        // signs, reduced modulo q
+        assert_eq!(str_i32_to_vec_u32("+5, -1, -0", q).unwrap(), vec![5, 999, 0]);
+        // hexadecimal and binary, in either case and with a sign
+        assert_eq!(str_i32_to_vec_u32("0x1f, 0X1F, 0b101, 0B11, -0x1", q).unwrap(), vec![31, 31, 5, 3, 999]);
+        // repetitions, including of a hexadecimal value and none at all
+        assert_eq!(str_i32_to_vec_u32("7*3, 0x2*2, 9*0, -1*1", q).unwrap(), vec![7, 7, 7, 2, 2, 999]);
+        // ranges, including single-valued and negative ones
+        assert_eq!(str_i32_to_vec_u32("1..=4, 6..=6, -2..=0", q).unwrap(), vec![1, 2, 3, 4, 6, 998, 999, 0]);
+        // the bounds of i32
+        assert_eq!(str_i32_to_vec_u32("-2147483648, 2147483647", MAX_Q).unwrap(), vec![MAX_Q - 2u32.pow(31), 2147483647]);
+        // unsigned lists up to u32::MAX, and signed output ids
+        assert_eq!(str_u32_to_vec_u32("4294967295, 0..=2").unwrap(), vec![u32::MAX, 0, 1, 2]);
+        assert_eq!(str_to_output_list("3, -7, -2..=-1").unwrap(), vec![(3, false), (7, true), (2, true), (1, true)]);
+        // reals in fixed point
+        assert_eq!(str_f64_to_vec_u32("1.5, -0.25", 2, 101).unwrap(), vec![6, 100]);
+    }
+
+    #[test]
+    fn rejected_list_forms() {
+        let q = 1000;
+        assert_eq!(kind_at("1, x", q), (4, String::from("x"), ParseVecErrorKind::NotANumber(10)));
+        assert_eq!(kind_at("0b102", q), (1, String::from("0b102"), ParseVecErrorKind::NotANumber(2)));
+        assert_eq!(kind_at("0xg", q), (1, String::from("0xg"), ParseVecErrorKind::NotANumber(16)));
+        assert_eq!(kind_at("+-1", q).2, ParseVecErrorKind::NotANumber(10));
+        assert_eq!(kind_at("0x-1", q).2, ParseVecErrorKind::NotANumber(16));
+        assert_eq!(kind_at("1, 0x", q), (4, String::from("0x"), ParseVecErrorKind::EmptyToken));
+        assert_eq!(kind_at("3*", q).2, ParseVecErrorKind::EmptyToken);
+        assert_eq!(kind_at("1..=", q).2, ParseVecErrorKind::EmptyToken);
+        assert_eq!(kind_at("5..=1", q).2, ParseVecErrorKind::EmptyRange);
+        assert_eq!(kind_at("2147483648", q).2, ParseVecErrorKind::OutOfRange(String::from("[-2147483648, 2147483647]")));
+        assert_eq!(kind_at("1*-1", q).2, ParseVecErrorKind::OutOfRange(String::from("[0, 4294967295]")));
+        assert_eq!(kind_at("1", 0).2, ParseVecErrorKind::OutOfRange(String::from("for q = 0")));
+        assert!(str_u32_to_vec_u32("-1").is_err());
+        assert!(str_to_output_list("-1..=2").is_err());
+        assert!(str_f64_to_vec_u32("100", 2, 101).is_err());
+    }
+
+    #[test]
+    fn oversized_expansions_are_rejected() {
+        let too_long = || ParseVecErrorKind::TooLong(MAX_LIST_LEN);
+        assert_eq!(kind_at("0*4294967295", 7), (1, String::from("0*4294967295"), too_long()));
+        assert_eq!(kind_at("1, 0..=2147483647", 7), (4, String::from("0..=2147483647"), too_long()));
+        assert_eq!(str_u32_to_vec_u32("0..=4294967295").unwrap_err().kind, too_long());
+        // the limit applies to the list as a whole, and the list may reach it exactly
+        let max = MAX_LIST_LEN;
+        assert_eq!(str_i32_to_vec_u32(&format!("0*{max}"), 7).unwrap().len(), max);
+        assert_eq!(kind_at(&format!("0*{}, 1..=2", max - 1), 7).2, too_long());
+        assert_eq!(kind_at(&format!("0*{max}, 1"), 7), (max.to_string().len() + 5, String::from("1"), too_long()));
+    }
+
+    #[test]
+    fn primality_of_small_values_matches_a_sieve() {
+        const N: usize = 10_000;
+        let mut sieve = vec![true; N];
+        sieve[0] = false;
+        sieve[1] = false;
+        for i in 2..N {
+            if sieve[i] {
+                (2 * i..N).step_by(i).for_each(|j| sieve[j] = false);
+            }
+        }
+
+        for (n, prime) in sieve.iter().enumerate() {
+            assert_eq!(is_prime(n as u64), *prime, "{n}");
+        }
+        // the witnesses themselves are prime
+        assert!([2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37].into_iter().all(is_prime));
+
+        assert_eq!(next_prime(0), Some(2));
+        assert_eq!(next_prime(2), Some(2));
+        assert_eq!(next_prime(14), Some(17));
+        assert_eq!(next_prime(7908), Some(7919));
+    }
+
+    #[test]
+    fn pseudoprimes_are_composite() {
+        // Carmichael numbers, which pass the Fermat test for every base coprime to them
+        let carmichael = [561, 1105, 1729, 2465, 2821, 6601, 8911, 41041, 825265, 321197185, 5394826801];
+        for n in carmichael {
+            assert!(!is_prime(n), "{n}");
+        }
+        // strong pseudoprimes to base 2, to bases 2 to 7 and to every base up to 37 but 29, 31 and 37
+        for n in [2047, 3215031751, 3825123056546413051] {
+            assert!(!is_prime(n), "{n}");
+        }
+        assert_eq!(3825123056546413051, 149491u64 * 747451 * 34233211);
+    }
+
+    #[test]
+    fn primality_near_2_to_the_63() {
+        let two_63 = 1u64 << 63;
+
+        // 2^63 - 25 and 2^63 + 29 are the primes around 2^63
+        assert!(is_prime(two_63 - 25) && is_prime(two_63 + 29));
+        assert!((two_63 - 24..two_63 + 29).all(|n| !is_prime(n)));
+        assert_eq!(next_prime(two_63 - 24), Some(two_63 + 29));
+        assert_eq!(next_prime(two_63 - 25), Some(two_63 - 25));
+
+        // products of two primes near 2^32, and Mersenne primes
+        assert!(!is_prime(2147483647 * 4294967291) && !is_prime(4294967291 * 4294967291));
+        assert!(is_prime(2147483647) && is_prime((1 << 61) - 1));
+
+        // the largest prime below 2^64, after which there is none
+        assert!(is_prime(u64::MAX - 58));
+        assert_eq!(next_prime(u64::MAX - 58), Some(u64::MAX - 58));
+        assert_eq!(next_prime(u64::MAX - 57), None);
+    }
+
+    #[test]
+    fn ext_gcd_gives_bezout_coefficients() {
+        for a in 0..60u64 {
+            for b in 0..60u64 {
+                let (g, x, y) = ext_gcd(a, b);
+                assert_eq!(a as i128 * x + b as i128 * y, g as i128, "a = {a}, b = {b}");
+                // the greatest common divisor, by brute force (gcd(0, 0) = 0)
+                let expected = (1..=a.max(b)).rev().find(|d| a % d == 0 && b % d == 0).unwrap_or(0);
+                assert_eq!(g, expected, "a = {a}, b = {b}");
+            }
+        }
+        let (g, x, y) = ext_gcd(u64::MAX, u64::MAX - 1);
+        assert_eq!((g, u64::MAX as i128 * x + (u64::MAX - 1) as i128 * y), (1, 1));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn inverses_and_divisions_modulo_small_primes() {
+        for q in [2, 3, 5, 7, 11, 13, 101, 257] {
+            assert_eq!(inv_mod(0, q), None);
+            assert_eq!(div_mod(1, 0, q), None);
+
+            for a in 1..q {
+                let inv = inv_mod(a, q).unwrap();
+                assert!(inv < q);
+                assert_eq!(a * inv % q, 1, "q = {q}, a = {a}");
+                // unreduced operands
+                assert_eq!(inv_mod(a + q, q), Some(inv));
+
+                for b in 0..q {
+                    let quotient = div_mod(b, a, q).unwrap();
+                    assert_eq!(quotient * a % q, b, "q = {q}, {b} / {a}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn only_units_modulo_composites_are_invertible() {
+        for q in [4, 6, 9, 12, 15, 100, 256, 1001] {
+            for a in 0..q {
+                let coprime = ext_gcd(a as u64, q as u64).0 == 1;
+                match inv_mod(a, q) {
+                    Some(inv) => {
+                        assert!(coprime, "q = {q}, a = {a}");
+                        assert_eq!(a * inv % q, 1);
+                    }
+                    None => assert!(!coprime, "q = {q}, a = {a}"),
+                }
+                assert_eq!(div_mod(1, a, q).is_some(), coprime);
+            }
+        }
+    }
+
+    // a * inv(a) = 1 for random values and moduli up to u32::MAX, whose products do not fit in a u32
+    #[test]
+    #[allow(deprecated)]
+    fn product_with_the_inverse_is_one() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(5);
+        for q in [2147483647, 4294967291, u32::MAX, u32::MAX - 1] {
+            for _ in 0..1000 {
+                let a = rng.next_u32() % q;
+                let product = |inv: u32| (a as u64 * inv as u64 % q as u64) as u32;
+                match inv_mod(a, q) {
+                    Some(inv) => assert_eq!(product(inv), 1, "q = {q}, a = {a}"),
+                    None => assert_ne!(ext_gcd(a as u64, q as u64).0, 1),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn crt_examples_computed_by_hand() {
+        // 23 is 2 mod 3, 3 mod 5 and 2 mod 7
+        assert_eq!(crt_split(23, &[3, 5, 7]).unwrap(), [2, 3, 2]);
+        assert_eq!(crt_combine(&[(2, 3), (3, 5), (2, 7)]).unwrap(), 23);
+        // of 2, 11, 20 and 29, the values below 36 which are 2 mod 9, only 29 is 1 mod 4
+        assert_eq!(crt_combine(&[(1, 4), (2, 9)]).unwrap(), 29);
+        assert_eq!(crt_combine(&[(2, 9), (1, 4)]).unwrap(), 29);
+        // values at or above the product of the moduli come back reduced
+        assert_eq!(crt_split(105 + 23, &[3, 5, 7]).unwrap(), [2, 3, 2]);
+        assert_eq!(crt_combine(&[(0, 3), (0, 5), (0, 7)]).unwrap(), 0);
+        assert_eq!(crt_combine(&[(2, 3), (4, 5), (6, 7)]).unwrap(), 104);
+        assert_eq!(crt_combine(&[]).unwrap(), 0);
+
+        assert!(crt_split(1, &[3, 0]).is_err());
+        assert!(crt_combine(&[(3, 3), (1, 5)]).unwrap_err().contains("residue 3 is not below its modulus 3"));
+        assert!(crt_combine(&[(1, 6), (2, 5), (1, 9)]).unwrap_err().contains("moduli 6 and 9 are not coprime"));
+        let large = [(0, u64::MAX), (0, u64::MAX - 1), (0, u64::MAX - 2)];
+        assert!(crt_combine(&large).unwrap_err().contains("does not fit in 128 bits"));
+    }
+
+    #[test]
+    fn crt_round_trip_under_three_31_bit_primes() {
+        let moduli = [2147483647, 2147483629, 2147483587];
+        let product = moduli.iter().map(|m| *m as u128).product::<u128>();
+        let rng = &mut ChaCha20Rng::seed_from_u64(6);
+
+        let random = (0..1000).map(|_| ((rng.next_u64() as u128) << 64 | rng.next_u64() as u128) % product);
+        for value in [0, 1, product - 1, moduli[0] as u128].into_iter().chain(random) {
+            let residues = crt_split(value, &moduli).unwrap();
+            let pairs: Vec<(u64, u64)> = residues.into_iter().zip(moduli).collect();
+            assert_eq!(crt_combine(&pairs).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn shuffles_are_reproducible_from_the_seed() {
+        let shuffled = |seed: u64| {
+            let mut v: Vec<u32> = (0..20).collect();
+            shuffle(&mut v, &mut ChaCha20Rng::seed_from_u64(seed));
+            v
+        };
+        assert_eq!(shuffled(7), shuffled(7));
+        assert_ne!(shuffled(7), shuffled(8));
+
+        // shuffling a vector moves its elements as the permutation drawn from the same seed says
+        let v: Vec<char> = "permutation".chars().collect();
+        let mut w = v.clone();
+        shuffle(&mut w, &mut ChaCha20Rng::seed_from_u64(9));
+        let p = random_permutation(v.len(), &mut ChaCha20Rng::seed_from_u64(9));
+        assert_eq!(apply_permutation(&v, &p).unwrap(), w);
+
+        // nothing to shuffle
+        let mut empty: [u8; 0] = [];
+        shuffle(&mut empty, &mut ChaCha20Rng::seed_from_u64(1));
+        assert_eq!(random_permutation(1, &mut ChaCha20Rng::seed_from_u64(1)), [0]);
+    }
+
+    #[test]
+    fn random_permutations_are_valid_and_uniform() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(10);
+        for n in 0..50 {
+            let mut p = random_permutation(n, rng);
+            p.sort_unstable();
+            assert_eq!(p, (0..n).collect::<Vec<usize>>());
+        }
+
+        // each of the 6 orders of 3 elements about 1000 times out of 6000
+        let mut counts = std::collections::HashMap::new();
+        for _ in 0..6000 {
+            *counts.entry(random_permutation(3, rng)).or_insert(0) += 1;
+        }
+        assert_eq!(counts.len(), 6);
+        assert!(counts.values().all(|c| (850..1150).contains(c)), "{counts:?}");
+    }
+
+    #[test]
+    fn only_permutations_are_applied() {
+        let v = ["a", "b", "c", "d"];
+        assert_eq!(apply_permutation(&v, &[2, 0, 3, 1]).unwrap(), ["c", "a", "d", "b"]);
+        assert_eq!(apply_permutation(&v, &[0, 1, 2, 3]).unwrap(), v);
+        assert!(apply_permutation::<u8>(&[], &[]).unwrap().is_empty());
+
+        // too short, too long, out of range and repeated
+        for p in [vec![0, 1, 2], vec![0, 1, 2, 3, 0], vec![0, 1, 2, 4], vec![0, 1, 1, 3]] {
+            let e = apply_permutation(&v, &p).unwrap_err();
+            assert!(e.contains("not a permutation of the 4 positions"), "{p:?}: {e}");
+        }
+    }
+
+    #[test]
+    fn nonzero_values_are_uniform() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(11);
+
+        // each of 1 to 6 about 1000 times out of 6000, and never 0
+        let mut counts = [0; 7];
+        for _ in 0..6000 {
+            counts[gen_nonzero_mod(rng, 7).unwrap() as usize] += 1;
+        }
+        assert_eq!(counts[0], 0);
+        assert!(counts[1..].iter().all(|c| (850..1150).contains(c)), "{counts:?}");
+
+        assert_eq!(gen_nonzero_mod(rng, 2), Ok(1));
+        assert!((0..1000).all(|_| gen_nonzero_mod(rng, u32::MAX).unwrap() != 0));
+        for q in [0, 1] {
+            assert!(gen_nonzero_mod(rng, q).unwrap_err().contains("no nonzero elements"));
+        }
+    }
+
+    #[test]
+    fn subsets_are_distinct_sorted_and_uniform() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(12);
+
+        for (k, n) in [(0, 0), (0, 5), (1, 1), (3, 10), (10, 10), (50, 1000)] {
+            let subset = sample_subset(k, n, rng).unwrap();
+            assert_eq!(subset.len(), k);
+            assert!(subset.windows(2).all(|w| w[0] < w[1]) && subset.iter().all(|i| *i < n), "{subset:?}");
+        }
+
+        // each of the 10 subsets of 2 elements out of 5 about 1000 times out of 10000
+        let mut counts = std::collections::HashMap::new();
+        for _ in 0..10_000 {
+            *counts.entry(sample_subset(2, 5, rng).unwrap()).or_insert(0) += 1;
+        }
+        assert_eq!(counts.len(), 10);
+        assert!(counts.values().all(|c| (850..1150).contains(c)), "{counts:?}");
+
+        let values = gen_distinct_mod(rng, 100, 101).unwrap();
+        assert_eq!(values.len(), 100);
+        assert!(values.windows(2).all(|w| w[0] < w[1]) && values.iter().all(|v| *v < 101));
+        assert_eq!(gen_distinct_mod(rng, 7, 7).unwrap(), [0, 1, 2, 3, 4, 5, 6]);
+        assert!(gen_distinct_mod(rng, 0, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn unsatisfiable_samples_fail() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(13);
+        assert!(sample_subset(6, 5, rng).unwrap_err().contains("cannot draw a subset of 6 elements out of 5"));
+        assert!(sample_subset(1, 0, rng).is_err());
+        assert!(gen_distinct_mod(rng, 8, 7).unwrap_err().contains("cannot draw 8 distinct elements modulo q = 7"));
+        assert!(gen_distinct_mod(rng, 1, 0).is_err());
+    }
+
+    #[test]
+    fn hex_seeds_are_parsed_in_either_case() {
+        let hex = "00112233445566778899aabbccddeeff00112233445566778899AABBCCDDEEFF";
+        let seed = seed_from_hex(&format!("  {hex}\n")).unwrap();
+        assert_eq!(seed[..16], (0..16).map(|i| 0x11 * i).collect::<Vec<u8>>());
+        assert_eq!(seed[..16], seed[16..]);
+        assert_eq!(seed_from_hex(&hex.to_lowercase()).unwrap(), seed);
+
+        assert!(seed_from_hex(&hex[1..]).unwrap_err().contains("found 63"));
+        assert!(seed_from_hex(&format!("{hex}0")).unwrap_err().contains("found 65"));
+        let e = seed_from_hex(&format!("{}g{}", &hex[..10], &hex[11..])).unwrap_err();
+        assert!(e.contains("'g' at position 11"), "{e}");
+        // 64 characters, but not 64 bytes
+        assert!(seed_from_hex(&"é".repeat(64)).unwrap_err().contains("is not a hexadecimal character"));
+
+        let mut a = rng_from_hex_seed(hex).unwrap();
+        assert_eq!(a.next_u64(), ChaCha20Rng::from_seed(seed).next_u64());
+    }
+
+    #[test]
+    fn labels_give_independent_reproducible_streams() {
+        let master = seed_from_hex(&"5a".repeat(32)).unwrap();
+        let stream = |master: &[u8; 32], label: &str| {
+            let mut rng = ChaCha20Rng::from_seed(derive_subseed(master, label));
+            (0..4).map(|_| rng.next_u64()).collect::<Vec<u64>>()
+        };
+
+        // the same label always gives the same stream
+        assert_eq!(stream(&master, "dealer"), stream(&master, "dealer"));
+
+        // labels differing in a character, in case, in length or only in trailing zero bytes, including across the
+        // 8-byte blocks they are absorbed in, all give different streams
+        let labels = ["", "\0", "\0\0", "dealer", "Dealer", "dealer1", "dealer2", "p1", "p2", "12345678", "12345678\0"];
+        let streams: HashSet<Vec<u64>> = labels.iter().map(|l| stream(&master, l)).collect();
+        assert_eq!(streams.len(), labels.len());
+        assert!(labels.iter().all(|l| derive_subseed(&master, l) != master));
+
+        // and another master seed gives other streams for the same labels
+        let other = seed_from_hex(&"5b".repeat(32)).unwrap();
+        assert!(labels.iter().all(|l| stream(&master, l) != stream(&other, l)));
+    }
+
+    // the vector operations against plain loops in u64, over lengths around multiples of LANES and unreduced values
+    #[test]
+    fn vector_operations_match_scalar_loops() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(14);
+
+        for q in [2, 7, 101, 2147483647, u32::MAX] {
+            for n in (0..=2 * LANES + 1).chain([100, 1001]) {
+                let a: Vec<u32> = (0..n).map(|_| rng.next_u32()).collect();
+                let b: Vec<u32> = (0..n).map(|_| rng.next_u32()).collect();
+                let c = rng.next_u32();
+                let q64 = q as u64;
+
+                let scalar = |op: fn(u64, u64, u64) -> u64| -> Vec<u32> {
+                    a.iter().zip(&b).map(|(x, y)| op(*x as u64 % q64, *y as u64 % q64, q64) as u32).collect()
+                };
+                assert_eq!(add_vec(&a, &b, q).unwrap(), scalar(|x, y, q| (x + y) % q), "q = {q}, n = {n}");
+                assert_eq!(sub_vec(&a, &b, q).unwrap(), scalar(|x, y, q| (x + q - y) % q), "q = {q}, n = {n}");
+                assert_eq!(mul_vec(&a, &b, q).unwrap(), scalar(|x, y, q| x * y % q), "q = {q}, n = {n}");
+
+                let mulc: Vec<u32> = a.iter().map(|x| ((*x as u64 % q64) * (c as u64 % q64) % q64) as u32).collect();
+                assert_eq!(mulc_vec(&a, c, q), mulc);
+                assert_eq!(reduce_vec(&a, q), a.iter().map(|x| x % q).collect::<Vec<u32>>());
+
+                // the in-place variants give the same results
+                let mut in_place = a.clone();
+                add_vec_in_place(&mut in_place, &b, q).unwrap();
+                assert_eq!(in_place, add_vec(&a, &b, q).unwrap());
+            }
+        }
+
+        for (a, b) in [(vec![1, 2], vec![1]), (vec![], vec![1])] {
+            let e = add_vec(&a, &b, 7).unwrap_err();
+            assert!(e.contains(&format!("lengths {} and {}", a.len(), b.len())), "{e}");
+            assert!(mul_vec_in_place(&mut a.clone(), &b, 7).is_err());
+        }
+    }
+
+    // counts the allocations of each thread, so that the tests running in parallel do not disturb one another
+    struct CountingAlloc;
+
+    thread_local! {
+        static ALLOCATIONS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    }
+
+    unsafe impl std::alloc::GlobalAlloc for CountingAlloc {
+        unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+            // the counter may already be gone while a thread exits
+            let _ = ALLOCATIONS.try_with(|a| a.set(a.get() + 1));
+            std::alloc::System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+            std::alloc::System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static GLOBAL: CountingAlloc = CountingAlloc;
+
+    #[test]
+    fn in_place_vector_operations_allocate_nothing() {
+        let a: Vec<u32> = (0..1000).collect();
+        let b: Vec<u32> = (0..1000).rev().collect();
+        let mut x = a.clone();
+        let q = 2147483647;
+
+        let before = ALLOCATIONS.with(|a| a.get());
+        add_vec_in_place(&mut x, &b, q).unwrap();
+        sub_vec_in_place(&mut x, &b, q).unwrap();
+        mul_vec_in_place(&mut x, &b, q).unwrap();
+        mulc_vec_in_place(&mut x, 3, q);
+        reduce_vec_in_place(&mut x, 101);
+        assert_eq!(ALLOCATIONS.with(|a| a.get()), before);
+
+        // while the other variants allocate their result, and only it
+        let before = ALLOCATIONS.with(|a| a.get());
+        let y = add_vec(&a, &b, q).unwrap();
+        assert_eq!(ALLOCATIONS.with(|a| a.get()), before + 1);
+        assert_eq!(y.len(), 1000);
+    }
+
+    #[test]
+    fn modulo_i64_at_the_boundaries() {
+        let extremes = [i64::MIN, i64::MIN + 1, -1, 0, 1, i64::MAX - 1, i64::MAX];
+
+        // no residue modulo 0, and only 0 modulo 1
+        for a in extremes {
+            assert_eq!(modulo_i64(a, 0), None);
+            assert_eq!(modulo_i64(a, 1), Some(0));
+        }
+
+        // i64::MIN = -2^63 is 0 modulo every power of two up to 2^63, and 2^63 - 1 is -1 modulo 2^63
+        for k in 1..=63 {
+            assert_eq!(modulo_i64(i64::MIN, 1 << k), Some(0));
+        }
+        assert_eq!(modulo_i64(i64::MIN, 3), Some(1));
+        assert_eq!(modulo_i64(i64::MAX, 1 << 63), Some(i64::MAX as u64));
+        assert_eq!(modulo_i64(-1, 1 << 63), Some((1 << 63) - 1));
+
+        // moduli above i64::MAX leave non-negative values as they are and wrap negative ones once
+        for q in [(1 << 63) + 1, u64::MAX - 1, u64::MAX] {
+            assert_eq!(modulo_i64(i64::MAX, q), Some(i64::MAX as u64));
+            assert_eq!(modulo_i64(0, q), Some(0));
+            assert_eq!(modulo_i64(-1, q), Some(q - 1));
+            assert_eq!(modulo_i64(i64::MIN, q), Some(q - (1 << 63)));
+        }
+
+        // the residue r is below q and a - r a multiple of q, for small values and moduli
+        for q in 1..=20u64 {
+            for a in -100..=100i64 {
+                let r = modulo_i64(a, q).unwrap();
+                assert!(r < q && (a - r as i64) % q as i64 == 0, "a = {a}, q = {q}");
+            }
+        }
+    }
+
+    #[test]
+    fn values_below_tiny_moduli_are_uniform() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(15);
+        assert!((0..100).all(|_| gen_below(rng, 1) == 0));
+
+        // each residue within 15% of its expected count
+        for q in [2, 3, 5, 7] {
+            let draws = 2000 * q as usize;
+            let mut counts = vec![0; q as usize];
+            for _ in 0..draws {
+                counts[gen_below(rng, q) as usize] += 1;
+            }
+            assert!(counts.iter().all(|c| (1700..2300).contains(c)), "q = {q}: {counts:?}");
+        }
+    }
+
+    // 2^63 + 1 rejects almost half of the draws, the worst case, and the others hardly any
+    #[test]
+    fn values_below_moduli_near_the_maximum_are_drawn() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(16);
+        for q in [1 << 63, (1 << 63) + 1, u64::MAX - 1, u64::MAX] {
+            let draws: Vec<u64> = (0..10_000).map(|_| gen_below(rng, q)).collect();
+            assert!(draws.iter().all(|v| *v < q));
+            // about half of them in the upper half
+            let upper = draws.iter().filter(|v| **v >= q / 2).count();
+            assert!((4700..5300).contains(&upper), "q = {q}: {upper}");
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn safe_gen_mod_stays_below_q() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(17);
+        assert!((0..100).all(|_| safe_gen_mod(rng, 1) == 0));
+
+        // every residue of a tiny modulus comes up, and none outside
+        for q in [2, 3, 10] {
+            let draws: HashSet<u32> = (0..1000).map(|_| safe_gen_mod(rng, q)).collect();
+            assert_eq!(draws, (0..q).collect());
+        }
+
+        // huge moduli, with values above 2^31 and up to u32::MAX - 1
+        for q in [2147483647, 1 << 31, u32::MAX - 1, u32::MAX] {
+            let draws: Vec<u32> = (0..10_000).map(|_| safe_gen_mod(rng, q)).collect();
+            assert!(draws.iter().all(|v| *v < q));
+            assert!(draws.iter().any(|v| *v >= q / 2) && draws.iter().any(|v| *v < q / 2));
+        }
+    }
+
+    #[test]
+    fn fixed_point_round_trips() {
+        let q = 2147483647;
+        for f in [0, 4, 8, 16] {
+            let scale = 2f64.powi(f as i32);
+            // multiples of 2^-f within the bound come back exactly, however they wrap around q
+            for k in [0, 1, -1, 3, -7, 1000, -1000, (q / 2) as i64, -((q / 2) as i64)] {
+                let x = k as f64 / scale;
+                assert_eq!(decode_fixed(encode_fixed(x, f, q), f, q), x, "f = {f}, x = {x}");
+            }
+            // other values to the nearest multiple
+            for x in [0.1, -0.1, 1.2345, -6.789] {
+                assert!((decode_fixed(encode_fixed(x, f, q), f, q) - x).abs() <= 0.5 / scale, "f = {f}, x = {x}");
+            }
+        }
+
+        // negative values wrap around q
+        assert_eq!(encode_fixed(-1.0, 0, 101), 100);
+        assert_eq!(encode_fixed(-0.5, 1, 101), 100);
+        assert_eq!(encode_fixed(1.25, 2, 101), 5);
+        assert_eq!(decode_fixed(100, 2, 101), -0.25);
+        assert_eq!(decode_fixed(50, 0, 101), 50.0);
+        assert_eq!(decode_fixed(51, 0, 101), -50.0);
+    }
+
+    // reals added by the protocol as their fixed-point encodings, and decoded from the outputs
+    #[test]
+    fn fixed_point_addition_circuit() {
+        use crate::circuit::Inputs;
+        use crate::protocol::{run_beaver_protocol, LogLevel, ProtocolOptions};
+
+        let (q, f) = (2147483647, 8);
+        let circuit = "0, P1, add, P2 | 1, 0, add, P1 | 2, 1, addc, 0 & 1 & 1, 2";
+        for (x, y, z) in [(1.5, -2.25, 0.125), (-100.0, 0.00390625, -0.5), (3.0, 4.0, 5.0)] {
+            let encode = |v: &[f64]| v.iter().map(|v| encode_fixed(*v, f, q)).collect::<Vec<u32>>();
+            let inputs = Inputs { p1: (encode(&[x]), encode(&[z])), p2: (vec![], encode(&[y])), public: vec![] };
+
+            for authenticated in [false, true] {
+                let options =
+                    ProtocolOptions { authenticated, seed: Some(1), log_level: LogLevel::Off, ..Default::default() };
+                let result = run_beaver_protocol(circuit, q, inputs.clone(), &options).unwrap();
+                assert_eq!(decode_fixed(result.outputs_p1[&1], f, q), x + y + z);
+                assert_eq!(decode_fixed(result.outputs_p2[&2], f, q), x + y + z);
+            }
+        }
+    }
+
+    #[test]
+    fn mixed_base_vectors() {
+        let q = 2147483647;
+        // 31 in every base and case, with signs, and negative values reduced modulo q
+        let parsed = str_i32_to_vec_u32("31, 0x1f, 0X1F, 0b11111, +0B11111, -0x1f, -0b1, 0x7ffffffe", q).unwrap();
+        assert_eq!(parsed, [31, 31, 31, 31, 31, q - 31, q - 1, q - 1]);
+        assert_eq!(str_u32_to_vec_u32("0xffffffff 0b0; 10, 0x0a").unwrap(), [u32::MAX, 0, 10, 10]);
+        // repetitions and ranges across bases
+        assert_eq!(str_i32_to_vec_u32("0x2*0b11, 0b1..=0x3", q).unwrap(), [2, 2, 2, 1, 2, 3]);
+
+        assert_eq!(parse_integer("-0x80000000", i32::MIN as i64, i32::MAX as i64), Ok(i32::MIN as i64));
+        assert_eq!(parse_integer("0b", 0, 10), Err(String::from("0b: missing digits")));
+
+        // digits invalid in the base are told apart from values out of range
+        let q = 1000;
+        assert_eq!(kind_at("0x1f, 0b12", q), (7, String::from("0b12"), ParseVecErrorKind::NotANumber(2)));
+        assert_eq!(kind_at("0b1, 0x1g", q), (6, String::from("0x1g"), ParseVecErrorKind::NotANumber(16)));
+        assert_eq!(kind_at("0b1, 1a", q).2, ParseVecErrorKind::NotANumber(10));
+        let out_of_range = ParseVecErrorKind::OutOfRange(String::from("[-2147483648, 2147483647]"));
+        assert_eq!(kind_at("0x80000000", q).2, out_of_range);
+        assert_eq!(kind_at("0b1, -0x80000001", q), (6, String::from("-0x80000001"), out_of_range));
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_entry() {
+        let q = 1000;
+        // a trailing comma is a separator, but not after a repetition or range missing its second part
+        assert_eq!(str_i32_to_vec_u32("1, 2,", q).unwrap(), vec![1, 2]);
+        assert_eq!(kind_at("1, 2*,", q), (4, String::from("2*"), ParseVecErrorKind::EmptyToken));
+        assert_eq!(kind_at("1, 2..=,", q), (4, String::from("2..="), ParseVecErrorKind::EmptyToken));
+        // letters embedded in a number, in the count of a repetition and in a range bound
+        assert_eq!(kind_at("10, 2a3, 4", q), (5, String::from("2a3"), ParseVecErrorKind::NotANumber(10)));
+        assert_eq!(kind_at("1;2;3*x", q), (5, String::from("3*x"), ParseVecErrorKind::NotANumber(10)));
+        assert_eq!(kind_at("1..=9, 0x1..=0xz", q), (8, String::from("0x1..=0xz"), ParseVecErrorKind::NotANumber(16)));
+        assert_eq!(str_u32_to_vec_u32("7 7 l7").unwrap_err().position, 5);
+        assert_eq!(str_f64_to_vec_u32("0.5, 1.5e", 2, 101).unwrap_err().kind, ParseVecErrorKind::NotANumber(10));
+
+        // positions count characters rather than bytes
+        assert_eq!(kind_at("1,\u{a0}x", q).0, 4);
+
+        let e = str_i32_to_vec_u32("1, 2, 3a, 4", q).unwrap_err();
+        assert_eq!(e.to_string(), "entry \"3a\" at position 7: not a number\n1, 2, 3a, 4\n      ^");
+        // long lines are cut to a window around the entry
+        let line = format!("{}x{}", "1, ".repeat(20), ", 2".repeat(20));
+        let e = str_i32_to_vec_u32(&line, q).unwrap_err();
+        let lines: Vec<String> = e.to_string().lines().map(String::from).collect();
+        assert_eq!(lines[0], "entry \"x\" at position 61: not a number");
+        assert!(lines[1].starts_with("...") && lines[1].ends_with("..."), "{}", lines[1]);
+        assert_eq!(lines[1].chars().nth(lines[2].len() - 1), Some('x'));
+    }
+
+    #[test]
+    fn moduli_are_literals_or_presets() {
+        let presets =
+            [("mersenne13", 8191), ("mersenne17", 131071), ("mersenne19", 524287), ("mersenne31", 2147483647)];
+        for (name, q) in presets.into_iter().chain([("largest-u16", 65521)]) {
+            assert_eq!(prime_preset(name), Some(q));
+            assert_eq!(parse_modulus(name), Ok(q));
+            assert!(is_prime(q as u64), "{name}");
+        }
+        // the mersenne presets are 2^n - 1, and largest-u16 is the largest prime below 2^16
+        for (name, q) in presets {
+            let n: u32 = name["mersenne".len()..].parse().unwrap();
+            assert_eq!(q, (1u32 << n) - 1);
+        }
+        assert_eq!(next_prime(65521 + 1).unwrap(), 65537);
+
+        // names are case-insensitive and surrounding whitespace is ignored, but not inner whitespace
+        assert_eq!(parse_modulus("MERSENNE31"), Ok(2147483647));
+        assert_eq!(parse_modulus(" Largest-U16\n"), Ok(65521));
+        assert_eq!(prime_preset("mersenne 31"), None);
+        assert_eq!(prime_preset("mersenne61"), None);
+
+        // literals take precedence and are not checked for primality here
+        assert_eq!(parse_modulus("101"), Ok(101));
+        assert_eq!(parse_modulus(" 4294967295 "), Ok(u32::MAX));
+        assert_eq!(parse_modulus("100"), Ok(100));
+
+        let expected = |s: &str| {
+            format!(
+                "Invalid modulus {s}: expected a u32 or one of the presets \
+                 mersenne13, mersenne17, mersenne19, mersenne31, largest-u16"
+            )
+        };
+        for s in ["bn254", "4294967296", "-1", "0x65", "mersenne"] {
+            assert_eq!(parse_modulus(s), Err(expected(s)));
+        }
+        assert_eq!(parse_modulus("  goldilocks "), Err(expected("goldilocks")));
+        assert_eq!(parse_modulus(""), Err(expected("")));
+    }
+}
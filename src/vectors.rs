@@ -0,0 +1,295 @@
+// Known-answer test vectors: each vector fixes a circuit, its inputs, the mode and the seed of a run together
+// with the outputs each party must obtain. A vector file consists of vectors separated by blank lines, each of
+// them made of `<field>: <value>` lines (lines starting with `#` are ignored):
+//
+//     name: mul_auth
+//     circuit: 0, P1, mul, P2 & 0 & 0
+//     q: 7
+//     p1_first: 3
+//     p1_second:
+//     p2_first:
+//     p2_second: 6
+//     authenticated: true
+//     seed: 1
+//     outputs_p1: 0=4
+//     outputs_p2: 0=4
+//
+// The circuit and the input vectors follow the same format as the input file, and the expected outputs are
+// comma-separated `<gate id>=<value>` pairs. Circuits with pub<i> gate inputs also take a `public:` line with the
+// public inputs, which is otherwise optional.
+//
+// Beyond the outputs, the execution logs of a seeded run can be compared with golden ones, such as those of the
+// mixed_auth vector in test_vectors/golden/mixed_auth, which must be regenerated whenever the logs change on purpose.
+
+use std::{collections::HashMap, fs};
+
+use crate::circuit::Inputs;
+use crate::error::BeaverError;
+use crate::protocol::{self, ProtocolOptions};
+use crate::utilities;
+
+pub struct TestVector {
+    pub name: String,
+    pub circuit: String,
+    pub q: u32,
+    pub inputs: Inputs,
+    pub authenticated: bool,
+    pub seed: u64,
+    pub outputs_p1: HashMap<u32, u32>,
+    pub outputs_p2: HashMap<u32, u32>,
+}
+
+pub struct VectorOutcome {
+    pub name: String,
+    // the reason for the failure, if the vector did not pass
+    pub result: Result<(), String>,
+}
+
+// runs every vector in the file through the protocol and reports whether it produced the expected outputs
+pub fn run_test_vectors(path: &str) -> Result<Vec<VectorOutcome>, BeaverError> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| BeaverError::Io(format!("Error reading test vector file {path}: {e}")))?;
+
+    Ok(parse_test_vectors(&contents)?
+        .into_iter()
+        .map(|v| VectorOutcome {
+            result: run_test_vector(&v),
+            name: v.name,
+        })
+        .collect())
+}
+
+pub fn run_test_vector(vector: &TestVector) -> Result<(), String> {
+    let options = ProtocolOptions {
+        authenticated: vector.authenticated,
+        seed: Some(vector.seed),
+        ..Default::default()
+    };
+
+    let result = protocol::run_beaver_protocol(&vector.circuit, vector.q, vector.inputs.clone(), &options)
+        .map_err(|e| e.to_string())?;
+
+    if result.outputs_p1 != vector.outputs_p1 {
+        return Err(format!(
+            "P1 obtained {} instead of {}",
+            format_outputs(&result.outputs_p1),
+            format_outputs(&vector.outputs_p1)
+        ));
+    }
+
+    if result.outputs_p2 != vector.outputs_p2 {
+        return Err(format!(
+            "P2 obtained {} instead of {}",
+            format_outputs(&result.outputs_p2),
+            format_outputs(&vector.outputs_p2)
+        ));
+    }
+
+    Ok(())
+}
+
+// the execution logs of a run, as written for the partial path <path> to <path>_<name>.txt
+const TRANSCRIPTS: [&str; 3] = ["dealer", "p1", "p2"];
+
+// runs the vector with its seed, writing the logs of the dealer and both parties for the partial path, and
+// returns the name and contents of each of them. the logs of two such runs are identical, so that they can be
+// compared with golden ones recorded earlier to catch unintended changes in the protocol
+pub fn record_transcripts(vector: &TestVector, path: &str) -> Result<Vec<(String, String)>, BeaverError> {
+    let options = ProtocolOptions {
+        authenticated: vector.authenticated,
+        seed: Some(vector.seed),
+        output_path: Some(path.to_string()),
+        ..Default::default()
+    };
+
+    protocol::run_beaver_protocol(&vector.circuit, vector.q, vector.inputs.clone(), &options)?;
+
+    TRANSCRIPTS
+        .iter()
+        .map(|name| {
+            let file = format!("{path}_{name}.txt");
+            fs::read_to_string(&file)
+                .map(|contents| (name.to_string(), contents))
+                .map_err(|e| BeaverError::Io(format!("Error reading transcript {file}: {e}")))
+        })
+        .collect()
+}
+
+// writes each transcript to the file <name>.txt in the golden directory, e.g. after an intended change
+pub fn save_golden_transcripts(golden_dir: &str, transcripts: &[(String, String)]) -> Result<(), BeaverError> {
+    for (name, contents) in transcripts {
+        let file = format!("{golden_dir}/{name}.txt");
+        fs::write(&file, contents).map_err(|e| BeaverError::Io(format!("Error writing golden transcript {file}: {e}")))?;
+    }
+
+    Ok(())
+}
+
+// compares each transcript with the file <name>.txt in the golden directory, reporting the first line which
+// differs
+pub fn assert_transcript_matches(golden_dir: &str, actual: &[(String, String)]) -> Result<(), String> {
+    for (name, contents) in actual {
+        let file = format!("{golden_dir}/{name}.txt");
+        let golden = fs::read_to_string(&file).map_err(|e| format!("Error reading golden transcript {file}: {e}"))?;
+
+        let (mut golden_lines, mut actual_lines) = (golden.lines(), contents.lines());
+
+        for n in 1.. {
+            match (golden_lines.next(), actual_lines.next()) {
+                (None, None) => break,
+                (g, a) if g == a => (),
+                (g, a) => {
+                    return Err(format!(
+                        "Transcript {name} differs from {file} at line {n}: expected {}, found {}",
+                        g.map_or(String::from("end of file"), |l| format!("\"{l}\"")),
+                        a.map_or(String::from("end of file"), |l| format!("\"{l}\""))
+                    ))
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn parse_test_vectors(s: &str) -> Result<Vec<TestVector>, BeaverError> {
+    let mut vectors = Vec::new();
+    let mut fields: HashMap<String, String> = HashMap::new();
+    let mut start = 0;
+
+    for (n, line) in s.lines().chain(std::iter::once("")).enumerate() {
+        let line = line.trim();
+
+        if line.starts_with('#') {
+            continue;
+        }
+
+        if line.is_empty() {
+            if !fields.is_empty() {
+                vectors.push(parse_test_vector(&fields).map_err(|e| {
+                    BeaverError::Parse(format!("Invalid test vector starting at line {}: {e}", start + 1))
+                })?);
+                fields.clear();
+            }
+            continue;
+        }
+
+        if fields.is_empty() {
+            start = n;
+        }
+
+        match line.split_once(':') {
+            Some((k, v)) => {
+                fields.insert(k.trim().to_string(), v.trim().to_string());
+            }
+            None => {
+                return Err(BeaverError::Parse(format!(
+                    "Invalid test vector line {}, expected <field>: <value>: {line}",
+                    n + 1
+                )))
+            }
+        }
+    }
+
+    Ok(vectors)
+}
+
+fn parse_test_vector(fields: &HashMap<String, String>) -> Result<TestVector, String> {
+    let field = |k: &str| -> Result<&String, String> {
+        fields.get(k).ok_or(format!("missing field {k}"))
+    };
+
+    let q = utilities::parse_modulus(field("q")?)?;
+
+    let seed: u64 = field("seed")?
+        .parse()
+        .map_err(|_| format!("could not parse seed: {}", field("seed").unwrap()))?;
+
+    let authenticated = match field("authenticated")?.as_str() {
+        "true" => true,
+        "false" => false,
+        other => return Err(format!("expected \"true\" or \"false\" for authenticated: {other}")),
+    };
+
+    let inputs = |k: &str| utilities::str_i32_to_vec_u32(field(k)?, q).map_err(|e| format!("invalid {k}: {e}"));
+
+    Ok(TestVector {
+        name: field("name")?.clone(),
+        circuit: field("circuit")?.clone(),
+        q,
+        inputs: Inputs {
+            p1: (inputs("p1_first")?, inputs("p1_second")?),
+            p2: (inputs("p2_first")?, inputs("p2_second")?),
+            public: if fields.contains_key("public") { inputs("public")? } else { Vec::new() },
+        },
+        authenticated,
+        seed,
+        outputs_p1: parse_outputs(field("outputs_p1")?)?,
+        outputs_p2: parse_outputs(field("outputs_p2")?)?,
+    })
+}
+
+fn parse_outputs(s: &str) -> Result<HashMap<u32, u32>, String> {
+    let mut outputs = HashMap::new();
+
+    for pair in s.split(',').map(|p| p.trim()).filter(|p| !p.is_empty()) {
+        match pair.split_once('=') {
+            Some((id, v)) => match (id.trim().parse(), v.trim().parse()) {
+                (Ok(id), Ok(v)) => {
+                    outputs.insert(id, v);
+                }
+                _ => return Err(format!("invalid output pair: {pair}")),
+            },
+            None => return Err(format!("expected <gate id>=<value>: {pair}")),
+        }
+    }
+
+    Ok(outputs)
+}
+
+fn format_outputs(outputs: &HashMap<u32, u32>) -> String {
+    let mut pairs: Vec<(&u32, &u32)> = outputs.iter().collect();
+    pairs.sort();
+
+    format!(
+        "{{{}}}",
+        pairs
+            .iter()
+            .map(|(id, v)| format!("{id}={v}"))
+            .collect::<Vec<String>>()
+            .join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KNOWN_ANSWERS: &str = "test_vectors/known_answers.txt";
+
+    #[test]
+    fn known_answers_pass() {
+        let outcomes = run_test_vectors(KNOWN_ANSWERS).unwrap();
+        assert!(!outcomes.is_empty());
+
+        let failures: Vec<String> = outcomes
+            .iter()
+            .filter_map(|o| o.result.as_ref().err().map(|e| format!("{}: {e}", o.name)))
+            .collect();
+        assert!(failures.is_empty(), "failed vectors:\n{}", failures.join("\n"));
+    }
+
+    // the canonical circuit of the golden transcripts, recorded with its fixed seed
+    #[test]
+    fn mixed_auth_transcripts_match_golden() {
+        let vectors = parse_test_vectors(&fs::read_to_string(KNOWN_ANSWERS).unwrap()).unwrap();
+        let vector = vectors.iter().find(|v| v.name == "mixed_auth").unwrap();
+
+        let dir = std::env::temp_dir().join(format!("beaver_golden_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let transcripts = record_transcripts(vector, dir.join("mixed_auth").to_str().unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_transcript_matches("test_vectors/golden/mixed_auth", &transcripts.unwrap()).unwrap();
+    }
+}